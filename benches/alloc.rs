@@ -0,0 +1,406 @@
+//! Allocation audit for the client's hot paths.
+//!
+//! Counts heap allocations made by the client thread while connecting,
+//! doing small text round trips, and receiving large binary messages, and
+//! exits non-zero when a scenario exceeds its recorded baseline by more than
+//! [`TOLERANCE`]. The echo server runs on its own thread and is not counted.
+//!
+//! Run with `cargo bench --bench alloc`; add `--features no-alloc-handshake`
+//! to also check that the fixed-buffer handshake makes no allocations. After an intentional change, update
+//! the baselines from the printed counts.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use fastwebsockets::{Frame, OpCode, Role, WebSocket};
+use monoio::net::{TcpListener, TcpStream};
+use monoio::time::TimeDriver;
+use monoio::{Buildable, Driver, RuntimeBuilder};
+use monoio_compat::{AsyncReadExt, AsyncWriteExt, StreamWrapper};
+use sha1::{Digest, Sha1};
+use websockets_monoio::{Message, WsClient};
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Allowed growth over a baseline before the audit fails.
+const TOLERANCE: f64 = 0.25;
+
+/// Recorded allocation counts (allocations plus reallocations) per scenario,
+/// the higher of the io_uring and legacy drivers.
+const CONNECT_BASELINE: u64 = 40;
+const SMALL_TEXT_BASELINE: u64 = 2_004;
+const LARGE_BINARY_BASELINE: u64 = 108;
+/// `http_upgrade::fixed` must not allocate at all.
+#[cfg(feature = "no-alloc-handshake")]
+const FIXED_HANDSHAKE_BASELINE: u64 = 0;
+
+const SMALL_TEXT_ROUND_TRIPS: usize = 1000;
+const LARGE_BINARY_MESSAGES: usize = 100;
+const LARGE_BINARY_SIZE: usize = 256 * 1024;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn record() {
+    if COUNTING.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Allocations made on this thread while `f` runs.
+async fn count<T>(f: impl Future<Output = T>) -> (T, u64) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.with(|c| c.set(true));
+    let out = f.await;
+    COUNTING.with(|c| c.set(false));
+    (out, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+struct Scenario {
+    name: &'static str,
+    count: u64,
+    baseline: u64,
+}
+
+impl Scenario {
+    fn limit(&self) -> u64 {
+        (self.baseline as f64 * (1.0 + TOLERANCE)) as u64
+    }
+}
+
+async fn run_scenarios(addr: SocketAddr) -> Result<Vec<Scenario>> {
+    let url = format!("ws://{addr}/alloc");
+
+    let (client, connect) = count(WsClient::connect(&url, &[])).await;
+    let mut client = client.context("websocket connect")?;
+
+    let text = "x".repeat(32);
+    let (result, small_text) = count(async {
+        for _ in 0..SMALL_TEXT_ROUND_TRIPS {
+            client.send(Message::Text(text.clone())).await?;
+            match client.recv().await? {
+                Message::Text(echo) if echo.len() == text.len() => {}
+                other => bail!("unexpected echo {other:?}"),
+            }
+        }
+        Ok(())
+    })
+    .await;
+    result?;
+
+    // Sent with `send_owned` so the request side reuses one buffer.
+    let mut buf = vec![b'x'; LARGE_BINARY_SIZE];
+    let (result, large_binary) = count(async {
+        for _ in 0..LARGE_BINARY_MESSAGES {
+            buf = client
+                .send_owned(OpCode::Binary, std::mem::take(&mut buf))
+                .await?;
+            match client.recv().await? {
+                Message::Binary(data) if data.len() == LARGE_BINARY_SIZE => {}
+                other => bail!("unexpected echo of {} bytes", other.payload().len()),
+            }
+        }
+        Ok(())
+    })
+    .await;
+    result?;
+
+    let _ = client.send(Message::Close(None)).await;
+
+    #[allow(unused_mut)]
+    let mut scenarios = vec![
+        Scenario {
+            name: "connect",
+            count: connect,
+            baseline: CONNECT_BASELINE,
+        },
+        Scenario {
+            name: "small_text_round_trips",
+            count: small_text,
+            baseline: SMALL_TEXT_BASELINE,
+        },
+        Scenario {
+            name: "large_binary_receives",
+            count: large_binary,
+            baseline: LARGE_BINARY_BASELINE,
+        },
+    ];
+    #[cfg(feature = "no-alloc-handshake")]
+    scenarios.push(fixed_handshake().await?);
+    Ok(scenarios)
+}
+
+/// Key generation and the whole upgrade of `http_upgrade::fixed` against
+/// an in-memory server.
+#[cfg(feature = "no-alloc-handshake")]
+async fn fixed_handshake() -> Result<Scenario> {
+    use websockets_monoio::http_upgrade::fixed::{FixedHandshake, FixedKey};
+
+    // The thread's RNG allocates its state on first use.
+    let _ = FixedKey::generate();
+    let mut duplex = UpgradeDuplex::new();
+    let (result, count) = count(async {
+        let key = FixedKey::generate();
+        let mut handshake = FixedHandshake::<512, 512>::new();
+        let response = handshake
+            .run(&mut duplex, "edge.example", "/alloc", &key, &[])
+            .await?;
+        anyhow::ensure!(response.trailing.is_empty(), "unexpected trailing bytes");
+        Ok(())
+    })
+    .await;
+    result?;
+    Ok(Scenario {
+        name: "fixed_handshake",
+        count,
+        baseline: FIXED_HANDSHAKE_BASELINE,
+    })
+}
+
+/// In-memory server for one upgrade: records the request and answers it
+/// with a 101 built in fixed buffers, so the audit only sees the client.
+#[cfg(feature = "no-alloc-handshake")]
+struct UpgradeDuplex {
+    request: [u8; 512],
+    request_len: usize,
+    response: [u8; 256],
+    response_len: usize,
+    response_read: usize,
+}
+
+#[cfg(feature = "no-alloc-handshake")]
+impl UpgradeDuplex {
+    fn new() -> Self {
+        Self {
+            request: [0; 512],
+            request_len: 0,
+            response: [0; 256],
+            response_len: 0,
+            response_read: 0,
+        }
+    }
+
+    /// Build the response once the request is complete.
+    fn answer(&mut self) -> std::io::Result<()> {
+        let request = &self.request[..self.request_len];
+        let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidData);
+        let start = request
+            .windows(19)
+            .position(|w| w == b"Sec-WebSocket-Key: ")
+            .ok_or_else(invalid)?
+            + 19;
+        let key = request.get(start..start + 24).ok_or_else(invalid)?;
+        let mut sha1 = Sha1::new();
+        sha1.update(key);
+        sha1.update(WS_GUID.as_bytes());
+        let mut accept = [0u8; 28];
+        BASE64
+            .encode_slice(sha1.finalize(), &mut accept)
+            .map_err(|_| invalid())?;
+        let mut len = 0;
+        for part in [
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+              Upgrade: websocket\r\nSec-WebSocket-Accept: "
+                .as_slice(),
+            &accept,
+            b"\r\n\r\n",
+        ] {
+            self.response[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        self.response_len = len;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "no-alloc-handshake")]
+impl monoio_compat::AsyncRead for UpgradeDuplex {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let request = &self.request[..self.request_len];
+        if self.response_len == 0 && request.windows(4).any(|w| w == b"\r\n\r\n") {
+            self.answer()?;
+        }
+        let pending = &self.response[self.response_read..self.response_len];
+        let n = pending.len().min(buf.remaining());
+        buf.put_slice(&pending[..n]);
+        self.response_read += n;
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "no-alloc-handshake")]
+impl monoio_compat::AsyncWrite for UpgradeDuplex {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let start = self.request_len;
+        let n = buf.len().min(self.request.len() - start);
+        self.request[start..start + n].copy_from_slice(&buf[..n]);
+        self.request_len += n;
+        std::task::Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+fn run_on<D>(driver: &str, addr: SocketAddr) -> Result<bool>
+where
+    D: Buildable + Driver + 'static,
+{
+    let mut runtime = Buildable::build(RuntimeBuilder::<TimeDriver<D>>::new())?;
+    let scenarios = runtime.block_on(run_scenarios(addr))?;
+    let mut ok = true;
+    for s in scenarios {
+        let verdict = if s.count <= s.limit() { "ok" } else { "FAIL" };
+        println!(
+            "alloc/{}/{driver}: {} allocations (baseline {}, limit {}) {verdict}",
+            s.name,
+            s.count,
+            s.baseline,
+            s.limit()
+        );
+        ok &= s.count <= s.limit();
+    }
+    Ok(ok)
+}
+
+fn main() -> Result<ExitCode> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut runtime = RuntimeBuilder::<monoio::LegacyDriver>::new()
+            .build()
+            .expect("failed to build server runtime");
+        runtime.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("bind echo server");
+            tx.send(listener.local_addr().expect("local addr")).unwrap();
+            while let Ok((stream, _)) = listener.accept().await {
+                monoio::spawn(async move {
+                    if let Err(err) = handle_connection(stream).await {
+                        eprintln!("alloc echo connection error: {err:#}");
+                    }
+                });
+            }
+        });
+    });
+    let addr = rx.recv()?;
+
+    let mut ok = true;
+    #[cfg(target_os = "linux")]
+    if monoio::utils::detect_uring() {
+        ok &= run_on::<monoio::IoUringDriver>("io_uring", addr)?;
+    }
+    ok &= run_on::<monoio::LegacyDriver>("legacy", addr)?;
+
+    Ok(if ok {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("allocation counts exceed the recorded baselines");
+        ExitCode::FAILURE
+    })
+}
+
+async fn handle_connection(stream: TcpStream) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let mut stream = StreamWrapper::new(stream);
+    let mut header_bytes = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            bail!("unexpected eof during websocket handshake");
+        }
+        header_bytes.extend_from_slice(&buf[..read]);
+        if header_bytes.windows(4).any(|window| window == b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let header_text =
+        std::str::from_utf8(&header_bytes).context("handshake bytes were not valid utf-8")?;
+    let sec_key = header_text
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("Sec-WebSocket-Key")
+                .then(|| value.trim())
+        })
+        .context("handshake missing Sec-WebSocket-Key header")?;
+    let mut sha1 = Sha1::new();
+    sha1.update(sec_key.as_bytes());
+    sha1.update(WS_GUID.as_bytes());
+    let accept = BASE64.encode(sha1.finalize());
+
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+         Upgrade: websocket\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut ws = WebSocket::after_handshake(stream, Role::Server);
+    ws.set_auto_close(true);
+    ws.set_writev(false);
+    while let Ok(frame) = ws.read_frame().await {
+        match frame.opcode {
+            OpCode::Text | OpCode::Binary => {
+                ws.write_frame(Frame::new(true, frame.opcode, None, frame.payload))
+                    .await?;
+            }
+            OpCode::Close => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
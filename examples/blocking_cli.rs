@@ -0,0 +1,42 @@
+//! Send one message and print the reply, without an async runtime in sight.
+//!
+//! ```text
+//! cargo run --example blocking_cli -- wss://echo.websocket.org/ "ping"
+//! ```
+//!
+//! Exits non-zero if connecting fails or no reply arrives within 5 seconds.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use websockets_monoio::{Message, WsClientBuilder, blocking};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let url = args.next().context("usage: blocking_cli <url> <message>")?;
+    let text = args.next().context("usage: blocking_cli <url> <message>")?;
+
+    let builder = WsClientBuilder::new().connect_timeout(Duration::from_secs(5));
+    let mut client = blocking::connect(&url, &builder)?;
+    client.send(Message::Text(text))?;
+
+    // Skip anything that is not a data message, e.g. a server greeting.
+    loop {
+        match client.recv(Duration::from_secs(5))? {
+            Message::Text(reply) => {
+                println!("{reply}");
+                break;
+            }
+            Message::Binary(reply) => {
+                println!("<{} bytes>", reply.len());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(close) = client.close()? {
+        eprintln!("closed: {} {}", close.code, close.reason);
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! A blocking client for scripts that send a message or two and exit.
+//!
+//! [`connect`] builds a single-threaded monoio runtime on the legacy
+//! (epoll/kqueue) driver with timers enabled, and every method of the
+//! returned [`BlockingWsClient`] blocks the calling thread while driving it.
+//! Use [`WsClient`] directly in anything long-running.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use websockets_monoio::{Message, WsClientBuilder, blocking};
+//!
+//! fn main() -> Result<(), websockets_monoio::WsError> {
+//!     let mut client = blocking::connect("wss://echo.websocket.org/", &WsClientBuilder::new())?;
+//!     client.send(Message::Text("status".into()))?;
+//!     println!("{:?}", client.recv(Duration::from_secs(5))?);
+//!     client.close()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Panics
+//!
+//! Every function here panics when called from async code running on a
+//! monoio runtime, whether it was started by `#[monoio::main]`, a
+//! `RuntimeBuilder` or a blocking client (from a callback such as
+//! [`on_event`](WsClientBuilder::on_event)): blocking there would stall
+//! every other task on the thread. Runtimes of other crates cannot be
+//! detected, so keep these calls out of async code altogether.
+
+use std::cell::Cell;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use monoio::time::TimeDriver;
+use monoio::{LegacyDriver, Runtime, RuntimeBuilder};
+
+use crate::message::{CloseFrame, Message};
+use crate::time::TimedOut;
+use crate::{WsClient, WsClientBuilder, WsError};
+
+/// How long [`BlockingWsClient::close`] waits for the server's Close.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const IN_ASYNC_CONTEXT: &str = "websockets_monoio::blocking cannot be used from within an async \
+                                runtime; use WsClient there instead";
+
+/// What monoio's `Runtime::block_on` panics with inside another runtime.
+const MONOIO_NESTED: &str = "Can not start a runtime inside a runtime";
+
+thread_local! {
+    /// Set while a blocking call drives its runtime on this thread.
+    static DRIVING: Cell<bool> = const { Cell::new(false) };
+}
+
+type BlockingRuntime = Runtime<TimeDriver<LegacyDriver>>;
+
+/// Run `future` to completion on `runtime`, blocking the thread.
+fn block_on<F: Future>(runtime: &mut BlockingRuntime, future: F) -> F::Output {
+    // A blocking call nested in another one would start a second runtime;
+    // monoio panics on that itself, but without saying what to do instead.
+    assert!(!DRIVING.get(), "{IN_ASYNC_CONTEXT}");
+    DRIVING.set(true);
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            DRIVING.set(false);
+        }
+    }
+    let _reset = Reset;
+    // monoio keeps its runtime context private, so a foreign monoio runtime
+    // on this thread only shows up as its own assertion in `block_on`,
+    // which fires before the future is first polled.
+    match std::panic::catch_unwind(AssertUnwindSafe(|| runtime.block_on(future))) {
+        Ok(output) => output,
+        Err(panic) if panic.downcast_ref::<&str>() == Some(&MONOIO_NESTED) => {
+            panic!("{IN_ASYNC_CONTEXT}")
+        }
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+/// Connect to `url` with the options in `opts`, blocking until the
+/// handshake completes.
+pub fn connect(url: &str, opts: &WsClientBuilder) -> Result<BlockingWsClient, WsError> {
+    assert!(!DRIVING.get(), "{IN_ASYNC_CONTEXT}");
+    let mut runtime = RuntimeBuilder::<LegacyDriver>::new()
+        .enable_timer()
+        .build()?;
+    let client = block_on(&mut runtime, opts.connect(url))?;
+    Ok(BlockingWsClient {
+        client: Some(client),
+        runtime,
+    })
+}
+
+/// A [`WsClient`] with its own runtime, created with [`connect`].
+///
+/// Dropping it without [`close`](Self::close) drops the connection without
+/// a closing handshake.
+pub struct BlockingWsClient {
+    /// Only `None` while being dropped.
+    client: Option<WsClient>,
+    runtime: BlockingRuntime,
+}
+
+impl BlockingWsClient {
+    /// Send `message`, blocking until it is written and flushed.
+    pub fn send(&mut self, message: Message) -> Result<(), WsError> {
+        let client = self.client.as_mut().expect("client present until drop");
+        block_on(&mut self.runtime, async {
+            client.send(message).await?;
+            client.flush().await
+        })
+    }
+
+    /// Receive the next message, failing with [`WsError::Timeout`] if none
+    /// arrives within `timeout`. A timed-out call loses nothing, so it can
+    /// simply be retried.
+    pub fn recv(&mut self, timeout: Duration) -> Result<Message, WsError> {
+        let client = self.client.as_mut().expect("client present until drop");
+        block_on(&mut self.runtime, async {
+            monoio::time::timeout(timeout, client.recv())
+                .await
+                .map_err(|_| WsError::Timeout(TimedOut::after(timeout)))?
+        })
+    }
+
+    /// Close with code 1000 and wait up to [`CLOSE_TIMEOUT`] for the
+    /// server's answer, discarding messages that arrive in between.
+    ///
+    /// Returns the server's Close, or `None` if it hung up without one or
+    /// did not answer in time.
+    pub fn close(mut self) -> Result<Option<CloseFrame>, WsError> {
+        let mut client = self.client.take().expect("client present until drop");
+        block_on(&mut self.runtime, async move {
+            let close = Message::Close(Some(CloseFrame {
+                code: 1000,
+                reason: String::new(),
+            }));
+            let sent = match client.send(close).await {
+                Ok(()) => client.flush().await,
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(()) => Ok(client
+                    .consume_until_close_with_timeout(CLOSE_TIMEOUT)
+                    .await
+                    .ok()),
+                Err(WsError::ClosedByPeer(close)) => Ok(Some(close)),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    pub fn client(&self) -> &WsClient {
+        self.client.as_ref().expect("client present until drop")
+    }
+
+    /// The underlying client, e.g. to change settings that take effect
+    /// without awaiting.
+    pub fn client_mut(&mut self) -> &mut WsClient {
+        self.client.as_mut().expect("client present until drop")
+    }
+}
+
+impl Drop for BlockingWsClient {
+    fn drop(&mut self) {
+        // The transport cancels in-flight I/O when dropped, which needs the
+        // runtime's driver.
+        if let Some(client) = self.client.take()
+            && !DRIVING.get()
+        {
+            block_on(&mut self.runtime, async move { drop(client) });
+        }
+    }
+}
+
+impl fmt::Debug for BlockingWsClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingWsClient")
+            .field("url", &self.client.as_ref().map(|c| c.info().url()))
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,132 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::WsError;
+
+/// Bytes charged per connection for its fixed buffers: the `StreamWrapper`
+/// read and write buffers plus the `fastwebsockets` read buffer (8 KiB each).
+/// [`WsClientBuilder::buffer_size`](crate::WsClientBuilder::buffer_size)
+/// changes the first two.
+pub const CONNECTION_BUFFER_BYTES: usize = 3 * STREAM_BUFFER_BYTES;
+
+/// Default size of each `StreamWrapper` buffer.
+pub(crate) const STREAM_BUFFER_BYTES: usize = 8 * 1024;
+
+/// A memory limit shared by every connection on one monoio thread.
+///
+/// Connections charge their fixed buffers against the budget at connect time
+/// and charge fragmented messages while they are being reassembled; a
+/// [`WsReconnectClient`](crate::WsReconnectClient) also charges the messages
+/// in its outbound queue. Once the limit would be exceeded, new connects
+/// fail fast, and reassembly and queueing are refused with
+/// [`WsError::MemoryBudget`]. Clones share the same accounting.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    limit: usize,
+    used: Cell<usize>,
+    rejections: Cell<u64>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit_bytes: usize) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                limit: limit_bytes,
+                used: Cell::new(0),
+                rejections: Cell::new(0),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Bytes currently reserved across all connections.
+    pub fn used(&self) -> usize {
+        self.inner.used.get()
+    }
+
+    pub fn available(&self) -> usize {
+        self.inner.limit.saturating_sub(self.used())
+    }
+
+    /// Number of reservations refused so far.
+    pub fn rejections(&self) -> u64 {
+        self.inner.rejections.get()
+    }
+
+    /// Reserve `bytes`, released when the returned [`Reservation`] is dropped.
+    pub fn try_reserve(&self, bytes: usize) -> Result<Reservation, WsError> {
+        self.charge(bytes)?;
+        Ok(Reservation {
+            budget: self.clone(),
+            bytes,
+        })
+    }
+
+    fn charge(&self, bytes: usize) -> Result<(), WsError> {
+        let used = self.used();
+        if bytes > self.inner.limit.saturating_sub(used) {
+            self.inner.rejections.set(self.inner.rejections.get() + 1);
+            return Err(WsError::MemoryBudget {
+                requested: bytes,
+                used,
+                limit: self.inner.limit,
+            });
+        }
+        self.inner.used.set(used + bytes);
+        Ok(())
+    }
+
+    fn release(&self, bytes: usize) {
+        self.inner.used.set(self.used().saturating_sub(bytes));
+    }
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+/// Bytes held against a [`MemoryBudget`]; returned to the budget on drop.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Grow the reservation by `additional` bytes.
+    pub fn grow(&mut self, additional: usize) -> Result<(), WsError> {
+        self.budget.charge(additional)?;
+        self.bytes += additional;
+        Ok(())
+    }
+
+    /// Return `bytes` of the reservation to the budget.
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.budget.release(bytes);
+        self.bytes -= bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
@@ -0,0 +1,326 @@
+//! Cancelling connects, receives and reconnect loops from one handle.
+//!
+//! A [`CancellationToken`] given to a builder with
+//! [`WsClientBuilder::cancellation`] is watched by everything that can wait
+//! on the network: connecting (DNS, TCP, TLS, the upgrade and the first
+//! message), [`WsClient::recv`] and the keepalive inside it, the
+//! [`BackgroundPing`] task, retry and reconnect backoff sleeps, the pacing
+//! hook and [`drain_cancellable`]. Once it is cancelled they resolve with
+//! [`WsError::Cancelled`] at their next poll instead of racing a shutdown
+//! signal at every call site.
+//!
+//! A connection that sees the cancellation is left as its [`OnCancel`]
+//! says: poisoned, or closed with 1001 (going away) first. Either way every
+//! later `send` and `recv` on it fails with [`WsError::Cancelled`].
+//!
+//! Tokens form a tree. [`child`](CancellationToken::child) tokens are
+//! cancelled with their parent but can be cancelled alone, so one fleet-wide
+//! token can stop everything while per-connection children stop one. Each
+//! connection from a builder watches its own child of the builder's token,
+//! returned by [`WsClient::cancellation_token`]. Tokens are `Rc`-based, like
+//! the rest of a monoio thread's state; use one tree per thread.
+//!
+//! Cancelling a connect in the middle of the TLS handshake:
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//! use websockets_monoio::cancel::{CancellationToken, OnCancel};
+//! use websockets_monoio::{WsClientBuilder, WsError};
+//!
+//! // Accepts TCP connections and never answers the ClientHello.
+//! let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+//! let addr = listener.local_addr().unwrap();
+//! std::thread::spawn(move || {
+//!     let _held: Vec<_> = listener.incoming().collect();
+//! });
+//!
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .enable_timer()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let shutdown = CancellationToken::new();
+//!     let builder = WsClientBuilder::new().cancellation(&shutdown, OnCancel::Poison);
+//!
+//!     let canceller = shutdown.clone();
+//!     monoio::spawn(async move {
+//!         monoio::time::sleep(Duration::from_millis(100)).await;
+//!         canceller.cancel();
+//!     });
+//!     let started = Instant::now();
+//!     let result = builder.connect(&format!("wss://localhost:{}/", addr.port())).await;
+//!     assert!(matches!(result, Err(WsError::Cancelled)));
+//!     assert!(started.elapsed() < Duration::from_secs(1));
+//! });
+//! ```
+//!
+//! In the middle of a `recv`, closing the connection, and in the middle of
+//! a reconnect backoff:
+//!
+//! ```
+//! # use std::io::{Read, Write};
+//! # use base64::Engine;
+//! # use sha1::Digest;
+//! use std::time::{Duration, Instant};
+//! use websockets_monoio::cancel::{CancellationToken, OnCancel};
+//! use websockets_monoio::reconnect::Backoff;
+//! use websockets_monoio::{Message, WsClientBuilder, WsError, WsReconnectClient};
+//!
+//! # // Completes each upgrade, sends "hello" and hands the socket back.
+//! # let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+//! # let addr = listener.local_addr().unwrap();
+//! # let (sockets, accepted) = std::sync::mpsc::channel();
+//! # std::thread::spawn(move || {
+//! #     for socket in listener.incoming() {
+//! #         let mut socket = socket.unwrap();
+//! #         let mut request = Vec::new();
+//! #         let mut byte = [0u8];
+//! #         while !request.ends_with(b"\r\n\r\n") {
+//! #             socket.read_exact(&mut byte).unwrap();
+//! #             request.push(byte[0]);
+//! #         }
+//! #         let request = String::from_utf8(request).unwrap();
+//! #         let key = request
+//! #             .lines()
+//! #             .filter_map(|line| line.split_once(':'))
+//! #             .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+//! #             .unwrap()
+//! #             .1;
+//! #         let mut sha1 = sha1::Sha1::new();
+//! #         sha1.update(key.trim().as_bytes());
+//! #         sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+//! #         let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+//! #         write!(
+//! #             socket,
+//! #             "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+//! #              Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+//! #         )
+//! #         .unwrap();
+//! #         socket.write_all(b"\x81\x05hello").unwrap();
+//! #         sockets.send(socket).unwrap();
+//! #     }
+//! # });
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .enable_timer()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let url = format!("ws://{addr}/");
+//!
+//!     // Mid-recv: the server is silent after its greeting.
+//!     let fleet = CancellationToken::new();
+//!     let builder = WsClientBuilder::new().cancellation(&fleet, OnCancel::Close);
+//!     let mut client = builder.connect(&url).await.unwrap();
+//!     let mut server = accepted.recv().unwrap();
+//!     assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+//!
+//!     let connection = client.cancellation_token().unwrap().clone();
+//!     monoio::spawn(async move {
+//!         monoio::time::sleep(Duration::from_millis(100)).await;
+//!         connection.cancel();
+//!     });
+//!     let started = Instant::now();
+//!     assert!(matches!(client.recv().await, Err(WsError::Cancelled)));
+//!     assert!(started.elapsed() < Duration::from_secs(1));
+//!     assert!(!client.is_usable());
+//!     assert!(matches!(client.send(Message::Text("late".into())).await, Err(WsError::Cancelled)));
+//!     // Only this connection's child token was cancelled.
+//!     assert!(!fleet.is_cancelled());
+//!
+//!     // OnCancel::Close sent a masked Close with 1001.
+//!     let mut close = [0u8; 8];
+//!     server.read_exact(&mut close).unwrap();
+//!     assert_eq!(close[0], 0x88);
+//!     let code = [close[6] ^ close[2], close[7] ^ close[3]];
+//!     assert_eq!(u16::from_be_bytes(code), 1001);
+//!
+//!     // Mid-backoff: the server closes and the next attempt is a minute off.
+//!     let mut backoff = Backoff::default();
+//!     backoff.initial = Duration::from_secs(60);
+//!     let builder = builder.reconnect_backoff(backoff);
+//!     let mut reconnecting = WsReconnectClient::connect(builder, &url).await.unwrap();
+//!     let mut server = accepted.recv().unwrap();
+//!     server.write_all(b"\x88\x02\x03\xe8").unwrap();
+//!     assert_eq!(reconnecting.recv().await.unwrap(), Message::Text("hello".into()));
+//!     assert!(matches!(reconnecting.recv().await.unwrap(), Message::Close(_)));
+//!
+//!     let canceller = fleet.clone();
+//!     monoio::spawn(async move {
+//!         monoio::time::sleep(Duration::from_millis(300)).await;
+//!         canceller.cancel();
+//!     });
+//!     let started = Instant::now();
+//!     assert!(matches!(reconnecting.recv().await, Err(WsError::Cancelled)));
+//!     assert!(started.elapsed() < Duration::from_secs(2));
+//! });
+//! ```
+//!
+//! [`WsClientBuilder::cancellation`]: crate::WsClientBuilder::cancellation
+//! [`WsClient::recv`]: crate::WsClient::recv
+//! [`WsClient::cancellation_token`]: crate::WsClient::cancellation_token
+//! [`BackgroundPing`]: crate::BackgroundPing
+//! [`drain_cancellable`]: crate::drain::drain_cancellable
+//! [`WsError::Cancelled`]: crate::WsError::Cancelled
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::pin::{Pin, pin};
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// What a connection does when its token is cancelled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnCancel {
+    /// Leave the connection where it stands: it is marked unusable and
+    /// nothing more is written, so the peer only notices once the client
+    /// is dropped.
+    #[default]
+    Poison,
+    /// Send a Close with 1001 (going away) first, best effort and without
+    /// waiting for the peer's.
+    Close,
+}
+
+/// A clonable handle that cancels everything watching it, and its
+/// children.
+///
+/// Clones share one state: cancelling any of them cancels all.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    node: Rc<Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    cancelled: Cell<bool>,
+    /// Slots of the [`Cancelled`] futures waiting on this token.
+    wakers: RefCell<Vec<Option<Waker>>>,
+    children: RefCell<Vec<Weak<Node>>>,
+}
+
+impl Node {
+    fn cancel(&self) {
+        if self.cancelled.replace(true) {
+            return;
+        }
+        for waker in self.wakers.take().into_iter().flatten() {
+            waker.wake();
+        }
+        for child in self.children.take() {
+            if let Some(child) = child.upgrade() {
+                child.cancel();
+            }
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token cancelled together with this one, which can also be
+    /// cancelled on its own without affecting this one.
+    pub fn child(&self) -> Self {
+        let child = Self::new();
+        if self.is_cancelled() {
+            child.cancel();
+        } else {
+            let mut children = self.node.children.borrow_mut();
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Rc::downgrade(&child.node));
+        }
+        child
+    }
+
+    /// Cancel this token and its children. Cancelling again does nothing.
+    pub fn cancel(&self) {
+        self.node.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.cancelled.get()
+    }
+
+    /// Resolves once the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            node: &self.node,
+            slot: None,
+        }
+    }
+
+    /// Run `future` until it completes, or drop it and return `None` once the
+    /// token is cancelled. The token is checked first, so a cancelled token
+    /// never starts the future.
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut cancelled = self.cancelled();
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            if Pin::new(&mut cancelled).poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            future.as_mut().poll(cx).map(Some)
+        })
+        .await
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+pub struct Cancelled<'a> {
+    node: &'a Node,
+    slot: Option<usize>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.node.cancelled.get() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.node.wakers.borrow_mut();
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => match wakers.iter().position(Option::is_none) {
+                Some(free) => free,
+                None => {
+                    wakers.push(None);
+                    wakers.len() - 1
+                }
+            },
+        };
+        wakers[slot] = Some(cx.waker().clone());
+        drop(wakers);
+        self.slot = Some(slot);
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        // The slots are already gone once the token is cancelled.
+        if let Some(slot) = self.slot
+            && let Some(waker) = self.node.wakers.borrow_mut().get_mut(slot)
+        {
+            *waker = None;
+        }
+    }
+}
+
+impl fmt::Debug for Cancelled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cancelled")
+            .field("cancelled", &self.node.cancelled.get())
+            .finish()
+    }
+}
@@ -1,18 +1,132 @@
-use anyhow::Result;
-use fastwebsockets::{Role, WebSocket};
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::{Pin, pin};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use fastwebsockets::{Frame, OpCode, Payload, Role, WebSocket, WebSocketError};
 use monoio::net::TcpStream;
+#[cfg(target_os = "linux")]
+use monoio::net::UnixStream;
 use monoio_compat::{AsyncRead, AsyncWrite, StreamWrapper};
+use monoio_rustls::{ClientTlsStream, TlsConnector};
 
-use crate::http_upgrade::{generate_client_key, read_response, write_request};
-use crate::tls::{connect_wss, default_connector};
-use crate::url::{Scheme, parse_ws_or_wss};
+use crate::WsError;
+use crate::budget::{CONNECTION_BUFFER_BYTES, MemoryBudget, Reservation, STREAM_BUFFER_BYTES};
+use crate::cancel::{CancellationToken, OnCancel};
+use crate::codec::{Codec, CodecClient};
+use crate::conflate::{ConflatingClient, Conflator};
+use crate::context::ContextMap;
+use crate::event::{EventSink, WsEvent};
+use crate::fairness::{
+    FairnessState, ProcessingBudget, ReadFairness, wake_after_others, yield_to_others,
+    yield_to_timers,
+};
+use crate::gate::GatedStream;
+use crate::handoff::TransferOptions;
+use crate::http_upgrade::{
+    DEFAULT_MAX_RESPONSE_HEADER, HeaderPair, SimpleRequest, SimpleResponse, UpgradeErr,
+    UpgradeResponse, format_extension, generate_client_key, http_exchange,
+    read_response_with_limit, write_request,
+};
+use crate::info::{ConnectionInfo, TlsInfo, redact_url};
+use crate::keepalive::Keepalive;
+use crate::message::{CloseFrame, Message, PreparedMessage};
+use crate::middleware::Middleware;
+use crate::middleware::intercept::{
+    Direction, InterceptAction, Interceptor, InterceptorChain, InterceptorStats,
+};
+use crate::ping::{BackgroundPing, PingShared};
+use crate::policy::EndpointPolicy;
+use crate::preset::Preset;
+use crate::probe::{ProbeError, ProbeReport};
+use crate::proxy::{Proxy, ProxyScheme, http_connect};
+use crate::reconnect::{Backoff, Jitter, Pacing, Policy, ReconnectPolicy, RetryConfig};
+use crate::sequence::{RecoveryPolicy, SequenceGap, SequencedClient};
+use crate::split::{SplitShared, WsReader, WsWriter};
+use crate::stats::{ConnectionStats, ConnectionStatsSnapshot, HISTOGRAM_BUCKETS, Histogram};
+use crate::summary::{RttSamples, SessionSummary};
+use crate::text;
+use crate::time::{Clock, ConnectPhase, Deadline, PhaseClock, SystemClock, TimedOut};
+use crate::tls::{default_connector, tls_handshake};
+use crate::transform::{OnTransformError, TransformError, Transforms};
+use crate::transport::{Opaque, Transport};
+use crate::url::{OwnedWsUrl, Scheme, WsUrl, parse_ws_or_wss, parse_ws_or_wss_lenient};
+use crate::watchdog::{StallWatchdog, WatchdogShared};
 
-/// A unified IO stream that can be plain TCP or TLS over TCP, both wrapped
-/// in `monoio_compat::StreamWrapper` to provide AsyncRead/AsyncWrite.
+/// A unified IO stream that can be plain TCP, TLS over TCP or (on Linux) an
+/// abstract Unix socket, each wrapped in `monoio_compat::StreamWrapper` to
+/// provide AsyncRead/AsyncWrite, or a caller-supplied [`Transport`].
 #[allow(clippy::large_enum_variant)]
 pub enum AnyStream {
     Plain(StreamWrapper<TcpStream>),
     Tls(StreamWrapper<monoio_rustls::ClientTlsStream<TcpStream>>),
+    /// TLS to the origin inside TLS to an `https://` proxy.
+    TlsOverTls(Box<StreamWrapper<ClientTlsStream<ClientTlsStream<TcpStream>>>>),
+    /// A Linux abstract-namespace Unix socket.
+    #[cfg(target_os = "linux")]
+    AbstractUnix(StreamWrapper<UnixStream>),
+    /// A transport from [`WsClientBuilder::connect_over`] or
+    /// [`WsClient::connect_via_stream_factory`].
+    Custom(Box<dyn Transport>),
+}
+
+/// Anything readable and writable with the tokio traits, e.g. a stream
+/// for [`WsClient::connect_via_stream_factory`]. Implemented for every such
+/// type; implement [`Transport`] instead to report a peer label or
+/// encryption.
+pub trait TokioIo: AsyncRead + AsyncWrite + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> TokioIo for T {}
+
+impl AnyStream {
+    /// Replace the `StreamWrapper` with one with `read` and `write` byte
+    /// buffers. Whatever its buffers hold is lost, and it must have no read
+    /// or write in progress, so callers check the [`StreamState`] first.
+    fn rebuffer(&mut self, read: usize, write: usize) -> std::io::Result<()> {
+        fn rewrap<T>(stream: StreamWrapper<T>, read: usize, write: usize) -> StreamWrapper<T> {
+            StreamWrapper::new_with_buffer_size(stream.into_inner(), read, write)
+        }
+
+        let placeholder = AnyStream::Custom(Box::new(Opaque(tokio::io::empty())));
+        *self = match std::mem::replace(self, placeholder) {
+            AnyStream::Plain(s) => AnyStream::Plain(rewrap(s, read, write)),
+            AnyStream::Tls(s) => AnyStream::Tls(rewrap(s, read, write)),
+            AnyStream::TlsOverTls(s) => AnyStream::TlsOverTls(Box::new(rewrap(*s, read, write))),
+            #[cfg(target_os = "linux")]
+            AnyStream::AbstractUnix(s) => AnyStream::AbstractUnix(rewrap(s, read, write)),
+            AnyStream::Custom(s) => {
+                *self = AnyStream::Custom(s);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "custom transports have no buffers to resize",
+                ));
+            }
+        };
+        Ok(())
+    }
+
+    /// Whether the connection to the origin is encrypted. For a
+    /// [`Custom`](Self::Custom) stream, what its [`Transport::is_secure`]
+    /// reports.
+    pub fn is_tls(&self) -> bool {
+        match self {
+            AnyStream::Plain(_) => false,
+            AnyStream::Tls(_) | AnyStream::TlsOverTls(_) => true,
+            #[cfg(target_os = "linux")]
+            AnyStream::AbstractUnix(_) => false,
+            AnyStream::Custom(s) => s.is_secure(),
+        }
+    }
 }
 
 impl monoio_compat::AsyncRead for AnyStream {
@@ -25,6 +139,12 @@ impl monoio_compat::AsyncRead for AnyStream {
             match self.get_unchecked_mut() {
                 AnyStream::Plain(s) => core::pin::Pin::new_unchecked(s).poll_read(cx, buf),
                 AnyStream::Tls(s) => core::pin::Pin::new_unchecked(s).poll_read(cx, buf),
+                #[cfg(target_os = "linux")]
+                AnyStream::AbstractUnix(s) => core::pin::Pin::new_unchecked(s).poll_read(cx, buf),
+                AnyStream::TlsOverTls(s) => {
+                    core::pin::Pin::new_unchecked(&mut **s).poll_read(cx, buf)
+                }
+                AnyStream::Custom(s) => core::pin::Pin::new(&mut **s).poll_read(cx, buf),
             }
         }
     }
@@ -40,10 +160,52 @@ impl monoio_compat::AsyncWrite for AnyStream {
             match self.get_unchecked_mut() {
                 AnyStream::Plain(s) => core::pin::Pin::new_unchecked(s).poll_write(cx, buf),
                 AnyStream::Tls(s) => core::pin::Pin::new_unchecked(s).poll_write(cx, buf),
+                #[cfg(target_os = "linux")]
+                AnyStream::AbstractUnix(s) => core::pin::Pin::new_unchecked(s).poll_write(cx, buf),
+                AnyStream::TlsOverTls(s) => {
+                    core::pin::Pin::new_unchecked(&mut **s).poll_write(cx, buf)
+                }
+                AnyStream::Custom(s) => core::pin::Pin::new(&mut **s).poll_write(cx, buf),
+            }
+        }
+    }
+
+    fn poll_write_vectored(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> core::task::Poll<Result<usize, std::io::Error>> {
+        unsafe {
+            match self.get_unchecked_mut() {
+                AnyStream::Plain(s) => {
+                    core::pin::Pin::new_unchecked(s).poll_write_vectored(cx, bufs)
+                }
+                AnyStream::Tls(s) => core::pin::Pin::new_unchecked(s).poll_write_vectored(cx, bufs),
+                #[cfg(target_os = "linux")]
+                AnyStream::AbstractUnix(s) => {
+                    core::pin::Pin::new_unchecked(s).poll_write_vectored(cx, bufs)
+                }
+                AnyStream::TlsOverTls(s) => {
+                    core::pin::Pin::new_unchecked(&mut **s).poll_write_vectored(cx, bufs)
+                }
+                AnyStream::Custom(s) => core::pin::Pin::new(&mut **s).poll_write_vectored(cx, bufs),
             }
         }
     }
 
+    /// Whether the transport gathers slices itself. `StreamWrapper` does
+    /// not, so only [`Custom`](Self::Custom) transports can.
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            AnyStream::Plain(s) => s.is_write_vectored(),
+            AnyStream::Tls(s) => s.is_write_vectored(),
+            #[cfg(target_os = "linux")]
+            AnyStream::AbstractUnix(s) => s.is_write_vectored(),
+            AnyStream::TlsOverTls(s) => s.is_write_vectored(),
+            AnyStream::Custom(s) => s.is_write_vectored(),
+        }
+    }
+
     fn poll_flush(
         self: core::pin::Pin<&mut Self>,
         cx: &mut core::task::Context<'_>,
@@ -52,6 +214,10 @@ impl monoio_compat::AsyncWrite for AnyStream {
             match self.get_unchecked_mut() {
                 AnyStream::Plain(s) => core::pin::Pin::new_unchecked(s).poll_flush(cx),
                 AnyStream::Tls(s) => core::pin::Pin::new_unchecked(s).poll_flush(cx),
+                #[cfg(target_os = "linux")]
+                AnyStream::AbstractUnix(s) => core::pin::Pin::new_unchecked(s).poll_flush(cx),
+                AnyStream::TlsOverTls(s) => core::pin::Pin::new_unchecked(&mut **s).poll_flush(cx),
+                AnyStream::Custom(s) => core::pin::Pin::new(&mut **s).poll_flush(cx),
             }
         }
     }
@@ -64,65 +230,3768 @@ impl monoio_compat::AsyncWrite for AnyStream {
             match self.get_unchecked_mut() {
                 AnyStream::Plain(s) => core::pin::Pin::new_unchecked(s).poll_shutdown(cx),
                 AnyStream::Tls(s) => core::pin::Pin::new_unchecked(s).poll_shutdown(cx),
+                #[cfg(target_os = "linux")]
+                AnyStream::AbstractUnix(s) => core::pin::Pin::new_unchecked(s).poll_shutdown(cx),
+                AnyStream::TlsOverTls(s) => {
+                    core::pin::Pin::new_unchecked(&mut **s).poll_shutdown(cx)
+                }
+                AnyStream::Custom(s) => core::pin::Pin::new(&mut **s).poll_shutdown(cx),
             }
         }
     }
 }
 
-/// Exposed stream type used by `WsClient`.
-pub type WsStream = AnyStream;
+/// A clonable handle to a stream, shared by the `WebSocket` and the
+/// [`WsClient`] so the client can reach the transport directly.
+///
+/// Each poll borrows the stream only for its duration, so handles never hold
+/// a borrow across an await point. The same holds for [`with`](Self::with),
+/// which is how the client swaps the transport's buffers while the
+/// `WebSocket` keeps its own handle.
+///
+/// Writes and flushes that fail with a transient error (`Interrupted`, or
+/// `WouldBlock` from a stream that does not register for readiness) are
+/// retried here, so only errors that end the connection reach the caller.
+pub struct SharedStream<S>(Rc<Shared<S>>);
+
+pub(crate) struct Shared<S> {
+    stream: RefCell<S>,
+    state: StreamState,
+}
+
+/// What the transport may be holding, as seen from the polls through the
+/// [`SharedStream`] handles.
+#[derive(Debug, Default)]
+pub(crate) struct StreamState {
+    /// Bytes accepted by writes since the last completed flush.
+    unflushed: Cell<usize>,
+    /// Most `unflushed` has been.
+    unflushed_high_water: Cell<usize>,
+    /// A flush was started and has not completed.
+    flushing: Cell<bool>,
+    read_in_flight: Cell<bool>,
+    /// The last read filled the caller's buffer, so the transport may have
+    /// more buffered.
+    read_buffered: Cell<bool>,
+}
+
+impl StreamState {
+    pub(crate) fn unflushed(&self) -> usize {
+        self.unflushed.get()
+    }
+
+    pub(crate) fn unflushed_high_water(&self) -> usize {
+        self.unflushed_high_water.get()
+    }
+
+    pub(crate) fn flushing(&self) -> bool {
+        self.flushing.get()
+    }
+
+    /// Whether the transport may hold received bytes not yet read, or is in
+    /// the middle of a read.
+    pub(crate) fn read_pending(&self) -> bool {
+        self.read_in_flight.get() || self.read_buffered.get()
+    }
+}
+
+/// A [`SharedStream`] handle that does not keep the stream alive.
+pub(crate) type WeakStream<S> = Weak<Shared<S>>;
+
+impl<S> SharedStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self(Rc::new(Shared {
+            stream: RefCell::new(stream),
+            state: StreamState::default(),
+        }))
+    }
+
+    /// Run `f` with exclusive access to the stream.
+    pub fn with<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.0.stream.borrow_mut())
+    }
+
+    pub(crate) fn state(&self) -> &StreamState {
+        &self.0.state
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakStream<S> {
+        Rc::downgrade(&self.0)
+    }
+
+    pub(crate) fn upgrade(weak: &WeakStream<S>) -> Option<Self> {
+        weak.upgrade().map(Self)
+    }
+
+    fn written(&self, poll: Poll<std::io::Result<usize>>) -> Poll<std::io::Result<usize>> {
+        if let Poll::Ready(Ok(n)) = poll {
+            let state = &self.0.state;
+            let unflushed = state.unflushed.get() + n;
+            state.unflushed.set(unflushed);
+            state
+                .unflushed_high_water
+                .set(state.unflushed_high_water.get().max(unflushed));
+        }
+        poll
+    }
+
+    fn flushed(&self, poll: Poll<std::io::Result<()>>) -> Poll<std::io::Result<()>> {
+        let state = &self.0.state;
+        state.flushing.set(poll.is_pending());
+        if let Poll::Ready(Ok(())) = poll {
+            state.unflushed.set(0);
+        }
+        poll
+    }
+}
+
+impl<S> Clone for SharedStream<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SharedStream<S> {
+    fn poll_read(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = core::pin::Pin::new(&mut *self.0.stream.borrow_mut()).poll_read(cx, buf);
+        let state = &self.0.state;
+        state.read_in_flight.set(poll.is_pending());
+        if poll.is_ready() {
+            state
+                .read_buffered
+                .set(buf.filled().len() > before && buf.remaining() == 0);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SharedStream<S> {
+    fn poll_write(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        buf: &[u8],
+    ) -> core::task::Poll<Result<usize, std::io::Error>> {
+        self.written(retry_transient(cx, |cx| {
+            core::pin::Pin::new(&mut *self.0.stream.borrow_mut()).poll_write(cx, buf)
+        }))
+    }
+
+    fn poll_write_vectored(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> core::task::Poll<Result<usize, std::io::Error>> {
+        self.written(retry_transient(cx, |cx| {
+            core::pin::Pin::new(&mut *self.0.stream.borrow_mut()).poll_write_vectored(cx, bufs)
+        }))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.stream.borrow().is_write_vectored()
+    }
+
+    fn poll_flush(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Result<(), std::io::Error>> {
+        self.flushed(retry_transient(cx, |cx| {
+            core::pin::Pin::new(&mut *self.0.stream.borrow_mut()).poll_flush(cx)
+        }))
+    }
+
+    fn poll_shutdown(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Result<(), std::io::Error>> {
+        self.flushed(core::pin::Pin::new(&mut *self.0.stream.borrow_mut()).poll_shutdown(cx))
+    }
+}
+
+/// Exposed stream type used by `WsClient`: the shared transport behind a
+/// [`GatedStream`], which keeps `read_frame` safe to cancel.
+pub type WsStream = GatedStream<SharedStream<AnyStream>>;
 
 pub struct WsClient {
     pub ws: WebSocket<WsStream>,
+    io: SharedStream<AnyStream>,
+    events: Option<EventSink>,
+    budget: Option<MemoryBudget>,
+    buffers: Option<Reservation>,
+    /// Read and write buffer sizes of the transport's `StreamWrapper`.
+    buffer_sizes: (usize, usize),
+    partial: Option<PartialMessage>,
+    keepalive: Option<KeepaliveState>,
+    /// Consulted when the keepalive times out.
+    watchdog: Option<Rc<WatchdogShared>>,
+    /// This connection's child of the builder's token.
+    cancel: Option<(CancellationToken, OnCancel)>,
+    info: ConnectionInfo,
+    /// The server's `101` response, without the bytes read past it.
+    handshake: UpgradeResponse,
+    /// Cleared once a send or receive fails or the peer's Close arrives.
+    usable: bool,
+    stats: ConnectionStats,
+    ping_reply: Option<PingReply>,
+    on_pong: Option<PongHook>,
+    reserved: Rc<ReservedOpcodes>,
+    /// Heartbeat or ping reply still being written when a `recv` was cancelled.
+    outbox: Option<PendingWrite>,
+    role: Role,
+    middleware: Middlewares,
+    /// The peer's Close, recorded as soon as it is read.
+    peer_close: Option<CloseFrame>,
+    /// Messages read while looking for the peer's Close after a failed write.
+    inbox: VecDeque<Message>,
+    write_lock: Rc<WriteLock>,
+    /// Pong bookkeeping of the latest [`BackgroundPing`].
+    pings: Option<Rc<PingShared>>,
+    fairness: FairnessState,
+    processing: ProcessingBudget,
+    /// A large Text message whose validation was interrupted by a yield.
+    pending_text: Option<PendingText>,
+    /// Reject Text messages that are not ASCII.
+    ascii_text: bool,
+    /// State shared with the [`WsWriter`] after [`split`](Self::split).
+    split: Option<Rc<SplitShared>>,
+    /// Scratch space for masking [`PreparedMessage`]s.
+    prepared: Vec<u8>,
+    /// Whether frames are written with vectored writes.
+    writev: bool,
+    /// Whether frames are masked as a client; see
+    /// [`WsClientBuilder::unmasked_client_frames`].
+    mask: bool,
+    interceptors: InterceptorChain,
+    transforms: Transforms,
+    /// Inbound messages skipped because their transform failed.
+    transform_drops: u64,
+    context: ContextMap,
+    /// The message accepted by the builder's `await_first_message` check.
+    first_message: Option<Message>,
+    /// Options recorded for [`WsClient::handoff_descriptor`].
+    pub(crate) transfer: TransferOptions,
+    /// Session number reported in the [`SessionSummary`].
+    generation: u64,
+    /// Round trips of keepalive and background pings.
+    rtt: RttSamples,
+    /// Set once the session has ended and its summary was emitted.
+    session_end: Option<Box<SessionSummary>>,
 }
 
-impl WsClient {
-    /// Connect to a `ws://` or `wss://` URL and complete the WebSocket handshake.
-    pub async fn connect(url: &str, extra_headers: &[(&str, &str)]) -> Result<Self> {
-        let u = parse_ws_or_wss(url)?;
+struct KeepaliveState {
+    config: Keepalive,
+    next_heartbeat: Instant,
+    last_alive: Instant,
+    /// When the latest Ping heartbeat went out, until a Pong arrives.
+    ping_sent: Option<Instant>,
+}
+
+/// An encoded frame written straight to the transport, tracking progress so a
+/// cancelled write resumes where it stopped instead of leaving half a frame
+/// on the wire.
+pub(crate) struct PendingWrite {
+    bytes: Vec<u8>,
+    written: usize,
+}
 
-        // Establish underlying transport (TCP or TLS over TCP)
-        let mut stream = match u.scheme {
-            Scheme::Ws => {
-                let tcp = TcpStream::connect((u.host, u.port)).await?;
-                AnyStream::Plain(StreamWrapper::new(tcp))
+impl PendingWrite {
+    pub(crate) fn frame(message: Message, mask: bool) -> Self {
+        let mut frame = message.into_frame();
+        if mask {
+            frame.mask();
+        }
+        let mut bytes = Vec::new();
+        let len = frame.write(&mut bytes).len();
+        bytes.truncate(len);
+        Self { bytes, written: 0 }
+    }
+
+    /// Finish writing `outbox`, if any, and clear it.
+    pub(crate) async fn flush(
+        outbox: &mut Option<PendingWrite>,
+        io: &mut SharedStream<AnyStream>,
+        lock: &WriteLock,
+    ) -> Result<(), WsError> {
+        if let Some(pending) = outbox {
+            let _write = lock.lock().await;
+            pending.write_to(io).await?;
+            *outbox = None;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn write_to(
+        &mut self,
+        io: &mut SharedStream<AnyStream>,
+    ) -> Result<(), WsError> {
+        use monoio_compat::AsyncWriteExt;
+
+        while self.written < self.bytes.len() {
+            let n = io.write(&self.bytes[self.written..]).await?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
             }
-            Scheme::Wss => {
-                let connector = default_connector();
-                let tls = connect_wss(u.host, u.port, connector).await?;
-                AnyStream::Tls(StreamWrapper::new(tls))
+            self.written += n;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes frame writes from the client and its [`BackgroundPing`] task,
+/// so the chunks of a large frame never have a ping interleaved with them.
+///
+/// Release wakes every waiter; the losers of the race simply wait again.
+#[derive(Default)]
+pub(crate) struct WriteLock {
+    held: Cell<bool>,
+    waiters: RefCell<Vec<Waker>>,
+}
+
+pub(crate) struct WriteGuard<'a>(&'a WriteLock);
+
+impl WriteLock {
+    pub(crate) async fn lock(&self) -> WriteGuard<'_> {
+        poll_fn(|cx| {
+            if self.held.replace(true) {
+                self.waiters.borrow_mut().push(cx.waker().clone());
+                return Poll::Pending;
             }
+            Poll::Ready(())
+        })
+        .await;
+        WriteGuard(self)
+    }
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.0.held.set(false);
+        for waker in self.0.waiters.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Computes the Pong payload for a received Ping; `None` sends no Pong.
+type PingReplyFn = dyn Fn(&[u8], &ContextMap) -> Option<Vec<u8>>;
+
+#[derive(Clone)]
+struct PingReply(Rc<PingReplyFn>);
+
+impl std::fmt::Debug for PingReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PingReply(..)")
+    }
+}
+
+/// Observes every Pong read by `recv`.
+type PongFn = dyn Fn(&[u8], &ContextMap);
+
+#[derive(Clone)]
+struct PongHook(Rc<PongFn>);
+
+impl std::fmt::Debug for PongHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PongHook(..)")
+    }
+}
+
+/// Decides whether a connection's first message makes it ready.
+type FirstMessageFn = dyn Fn(&Message, &ContextMap) -> Result<(), String>;
+
+#[derive(Clone)]
+struct FirstMessage {
+    timeout: Duration,
+    validate: Rc<FirstMessageFn>,
+}
+
+impl std::fmt::Debug for FirstMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FirstMessage")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What the gate reported about frames with a reserved opcode.
+#[derive(Debug, Default)]
+struct ReservedOpcodes {
+    /// The last one handed on to `fastwebsockets`, which fails the read.
+    rejected: Cell<Option<u8>>,
+    skipped: Cell<u64>,
+}
+
+#[derive(Clone)]
+struct SharedClock(Rc<dyn Clock>);
+
+impl std::fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clock(..)")
+    }
+}
+
+#[derive(Clone)]
+struct Connector(TlsConnector);
+
+impl std::fmt::Debug for Connector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TlsConnector(..)")
+    }
+}
+
+#[derive(Clone, Default)]
+struct Middlewares(Vec<Rc<dyn Middleware>>);
+
+impl std::fmt::Debug for Middlewares {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Middlewares({})", self.0.len())
+    }
+}
+
+/// A fragmented data message that is still being reassembled.
+struct PartialMessage {
+    opcode: OpCode,
+    payload: Vec<u8>,
+    reservation: Option<Reservation>,
+}
+
+/// A complete Text message whose UTF-8 is validated a chunk at a time.
+struct PendingText {
+    payload: Vec<u8>,
+    /// Length of the prefix known to be valid, always at a char boundary.
+    valid: usize,
+    /// Held until the message is delivered, like the fragments' reservation.
+    _reservation: Option<Reservation>,
+}
+
+/// Configures and opens a [`WsClient`].
+///
+/// ```no_run
+/// use websockets_monoio::{MemoryBudget, WsClientBuilder};
+///
+/// # async fn run() -> Result<(), websockets_monoio::WsError> {
+/// let budget = MemoryBudget::new(64 * 1024 * 1024);
+/// let client = WsClientBuilder::new()
+///     .header("Authorization", "Bearer token")
+///     .memory_budget(budget.clone())
+///     .connect("wss://example.com/feed")
+///     .await?;
+/// println!("{} bytes in use", budget.used());
+/// # drop(client);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WsClientBuilder {
+    extra_headers: Vec<(String, String)>,
+    subprotocols: Vec<String>,
+    extensions: Vec<String>,
+    memory_budget: Option<MemoryBudget>,
+    pub(crate) events: Option<EventSink>,
+    keepalive: Option<Keepalive>,
+    watchdog: Option<Rc<WatchdogShared>>,
+    cancellation: Option<(CancellationToken, OnCancel)>,
+    custom_request: Option<CustomRequest>,
+    pre_upgrade: Option<PreUpgradeHook>,
+    max_response_header: Option<usize>,
+    unmasked: bool,
+    inter_arrival_buckets: Option<[Duration; HISTOGRAM_BUCKETS]>,
+    clock: Option<SharedClock>,
+    ping_reply: Option<PingReply>,
+    on_pong: Option<PongHook>,
+    skip_reserved: bool,
+    ascii_text: bool,
+    strip_url_fragment: bool,
+    withhold_url_credentials: bool,
+    first_message: Option<FirstMessage>,
+    middleware: Middlewares,
+    interceptors: Vec<Interceptor>,
+    transforms: Transforms,
+    proxy: Option<Proxy>,
+    tls_connector: Option<Connector>,
+    hooks: ConnectHooks,
+    read_fairness: ReadFairness,
+    processing_budget: ProcessingBudget,
+    connect_timeout: Option<Duration>,
+    vectored_writes: Option<bool>,
+    preset: Option<Preset>,
+    pub(crate) backoff: Backoff,
+    pub(crate) reconnect_policy: Option<Policy>,
+    pub(crate) reconnect_jitter: Jitter,
+    pub(crate) jitter_seed: Option<u64>,
+    pub(crate) reconnect_pacing: Option<Pacing>,
+    pub(crate) outbound_ttl: Option<Duration>,
+}
+
+/// Observes each address a connection is about to dial.
+type TcpConnectFn = dyn Fn(SocketAddr, &ContextMap) + Send + Sync;
+/// Observes the TLS session once the handshake with the server completes.
+type TlsConnectFn = dyn Fn(&TlsInfo, &ContextMap) + Send + Sync;
+
+/// How [`WsClientBuilder::open`] gets the stream the upgrade runs over.
+enum Dial {
+    /// Dial the URL's host, or the proxy.
+    Url,
+    /// Use a transport the caller opened.
+    Over(Box<AnyStream>),
+    /// TLS to this address, presenting the URL's host.
+    TlsTerminator(SocketAddr),
+}
+
+/// Fire-and-forget observers of the connect phases, and options for the
+/// sockets they dial.
+#[derive(Clone, Default)]
+struct ConnectHooks {
+    tcp: Option<Arc<TcpConnectFn>>,
+    tls: Option<Arc<TlsConnectFn>>,
+    #[cfg(target_os = "linux")]
+    so_mark: Option<u32>,
+    #[cfg(all(target_os = "linux", feature = "tcp-user-timeout"))]
+    tcp_user_timeout: Option<Duration>,
+    nodelay: Option<bool>,
+    /// `StreamWrapper` read and write buffer size.
+    buffer_size: Option<usize>,
+    policy: Option<Rc<dyn EndpointPolicy>>,
+    /// Handed to the hooks, and to the connections dialed.
+    context: ContextMap,
+}
+
+impl std::fmt::Debug for ConnectHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("ConnectHooks");
+        s.field("tcp", &self.tcp.is_some())
+            .field("tls", &self.tls.is_some())
+            .field("policy", &self.policy.is_some())
+            .field("nodelay", &self.nodelay)
+            .field("buffer_size", &self.buffer_size)
+            .field("context", &self.context);
+        #[cfg(target_os = "linux")]
+        s.field("so_mark", &self.so_mark);
+        #[cfg(all(target_os = "linux", feature = "tcp-user-timeout"))]
+        s.field("tcp_user_timeout", &self.tcp_user_timeout);
+        s.finish()
+    }
+}
+
+impl ConnectHooks {
+    /// Connect to `host:port`. With a TCP hook, an endpoint policy or a
+    /// deadline the name is resolved here, so the hook sees each address
+    /// before it is dialed, the policy vets the addresses that are dialed,
+    /// and the lookup is timed as its own phase; addresses are tried in
+    /// order. `target` is the URL when dialing it directly rather than a
+    /// proxy.
+    async fn connect_tcp(
+        &self,
+        host: &str,
+        port: u16,
+        target: Option<&WsUrl<'_>>,
+        clock: &mut PhaseClock,
+    ) -> Result<TcpStream, WsError> {
+        use std::net::ToSocketAddrs;
+
+        let policy = target.zip(self.policy.as_deref());
+        if self.tcp.is_none()
+            && policy.is_none()
+            && !self.configures_socket()
+            && !clock.splits_resolve()
+        {
+            let tcp = TcpStream::connect((host, port)).await?;
+            self.set_nodelay(&tcp)?;
+            return Ok(tcp);
+        }
+        let addrs: Vec<SocketAddr> = clock
+            .run(ConnectPhase::Resolve, async {
+                (host, port).to_socket_addrs().map(Iterator::collect)
+            })
+            .await?;
+        if let Some((url, policy)) = policy {
+            policy.check(url, &addrs)?;
+        }
+        clock
+            .run(ConnectPhase::Connect, async {
+                let mut last_error = None;
+                for addr in addrs {
+                    if let Some(hook) = &self.tcp {
+                        hook(addr, &self.context);
+                    }
+                    match self.connect_addr(addr).await {
+                        Ok(tcp) => return Ok(tcp),
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "could not resolve to any address",
+                    )
+                }))
+            })
+            .await
+    }
+
+    /// The host to ask a proxy to tunnel to. The proxy resolves the target,
+    /// so with an endpoint policy the name is resolved here as well, the
+    /// policy vets the addresses, and the tunnel goes to the first of them
+    /// rather than to a name the proxy could resolve differently.
+    async fn proxy_target<'u>(
+        &self,
+        u: &'u WsUrl<'_>,
+        clock: &mut PhaseClock,
+    ) -> Result<Cow<'u, str>, WsError> {
+        use std::net::ToSocketAddrs;
+
+        let Some(policy) = self.policy.as_deref() else {
+            return Ok(Cow::Borrowed(&*u.host));
         };
+        let addrs: Vec<SocketAddr> = clock
+            .run(ConnectPhase::Resolve, async {
+                (&*u.host, u.port).to_socket_addrs().map(Iterator::collect)
+            })
+            .await?;
+        policy.check(u, &addrs)?;
+        match addrs.first() {
+            Some(addr) => Ok(Cow::Owned(addr.ip().to_string())),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+            .into()),
+        }
+    }
 
-        // HTTP Upgrade handshake
-        let key = generate_client_key();
-        write_request(
-            &mut stream,
-            u.host,
-            u.path_and_query,
-            &key.sec_websocket_key,
-            extra_headers,
-        )
-        .await?;
-        read_response(&mut stream, &key.expected_accept).await?;
+    /// Whether sockets need options set around connecting.
+    fn configures_socket(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.so_mark.is_some() {
+            return true;
+        }
+        #[cfg(all(target_os = "linux", feature = "tcp-user-timeout"))]
+        if self.tcp_user_timeout.is_some() {
+            return true;
+        }
+        false
+    }
 
-        // Switch to WebSocket
-        let mut ws = WebSocket::after_handshake(stream, Role::Client);
-        ws.set_auto_close(true);
-        ws.set_auto_pong(true);
-        if matches!(u.scheme, Scheme::Wss) {
-            // TLS backends generally buffer writes, so gathering is less effective.
-            ws.set_writev(false);
+    async fn connect_addr(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
+        #[cfg(target_os = "linux")]
+        let tcp = match self.so_mark {
+            Some(mark) => connect_marked(addr, mark).await?,
+            None => TcpStream::connect(addr).await?,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let tcp = TcpStream::connect(addr).await?;
+        #[cfg(all(target_os = "linux", feature = "tcp-user-timeout"))]
+        if let Some(timeout) = self.tcp_user_timeout {
+            use std::os::fd::{AsRawFd, BorrowedFd};
+
+            // SAFETY: `tcp` owns the descriptor and outlives the borrow.
+            let fd = unsafe { BorrowedFd::borrow_raw(tcp.as_raw_fd()) };
+            socket2::SockRef::from(&fd).set_tcp_user_timeout(Some(timeout))?;
         }
+        self.set_nodelay(&tcp)?;
+        Ok(tcp)
+    }
 
-        Ok(Self { ws })
+    fn set_nodelay(&self, tcp: &TcpStream) -> std::io::Result<()> {
+        match self.nodelay {
+            Some(nodelay) => tcp.set_nodelay(nodelay),
+            None => Ok(()),
+        }
     }
 
-    pub fn into_inner(self) -> WebSocket<WsStream> {
-        self.ws
+    /// Wrap a connected stream with the configured buffer size.
+    fn wrap<T>(&self, stream: T) -> StreamWrapper<T> {
+        match self.buffer_size {
+            Some(size) => StreamWrapper::new_with_buffer_size(stream, size, size),
+            None => StreamWrapper::new(stream),
+        }
     }
 }
 
-// Convenience trait bound if you want to reuse upgrade for different streams.
-pub trait TokioIo: AsyncRead + AsyncWrite + Unpin {}
-impl<T: AsyncRead + AsyncWrite + Unpin> TokioIo for T {}
+/// Connect from a socket carrying `SO_MARK` `mark`. The mark has to be set
+/// before `connect`, which `TcpStream::connect` does not allow, so the socket
+/// is created here and handed to monoio while the connect is in progress.
+#[cfg(target_os = "linux")]
+async fn connect_marked(addr: SocketAddr, mark: u32) -> std::io::Result<TcpStream> {
+    use socket2::{Domain, Protocol, SockRef, Socket, Type};
+    use std::os::fd::{AsRawFd, BorrowedFd};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_mark(mark)?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&addr.into()) {
+        Ok(()) => {}
+        Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(e) => return Err(e),
+    }
+    let tcp = TcpStream::from_std(socket.into())?;
+    tcp.writable(false).await?;
+    // SAFETY: `tcp` owns the descriptor and outlives the borrow.
+    let fd = unsafe { BorrowedFd::borrow_raw(tcp.as_raw_fd()) };
+    match SockRef::from(&fd).take_error()? {
+        Some(e) => Err(e),
+        None => Ok(tcp),
+    }
+}
+
+/// Produces the raw upgrade request from the URL, the `Sec-WebSocket-Key`
+/// and the handshake time.
+type RequestFn = dyn Fn(&WsUrl<'_>, &str, SystemTime) -> Vec<u8> + Send + Sync;
+
+#[derive(Clone)]
+struct CustomRequest(Arc<RequestFn>);
+
+impl std::fmt::Debug for CustomRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomRequest(..)")
+    }
+}
+
+/// The connection of a [`WsClientBuilder::pre_upgrade`] hook, before the
+/// upgrade request is sent on it.
+#[derive(Clone)]
+pub struct PreUpgrade {
+    /// Lent to each exchange, and taken back for the upgrade when the hook
+    /// completes.
+    stream: Rc<RefCell<Option<AnyStream>>>,
+    host: Rc<str>,
+}
+
+impl PreUpgrade {
+    /// Send `request` and read the response; see [`http_exchange`] for what
+    /// is supported.
+    ///
+    /// Fails with [`UpgradeErr::Exchange`] when called while another
+    /// exchange is in progress or after the hook has completed. Dropping the
+    /// returned future before it completes loses the connection, and the
+    /// connect fails.
+    pub async fn exchange(&self, request: &SimpleRequest) -> Result<SimpleResponse, WsError> {
+        let taken = self.stream.borrow_mut().take();
+        let Some(mut stream) = taken else {
+            return Err(UpgradeErr::Exchange("the connection is not available").into());
+        };
+        let result = http_exchange(&mut stream, &self.host, request).await;
+        *self.stream.borrow_mut() = Some(stream);
+        Ok(result?)
+    }
+}
+
+impl std::fmt::Debug for PreUpgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PreUpgrade({})", self.host)
+    }
+}
+
+/// Extra upgrade request headers from a pre-upgrade hook.
+type PreUpgradeFuture = Pin<Box<dyn Future<Output = Result<Vec<(String, String)>, WsError>>>>;
+type PreUpgradeFn = dyn Fn(PreUpgrade) -> PreUpgradeFuture;
+
+#[derive(Clone)]
+struct PreUpgradeHook(Rc<PreUpgradeFn>);
+
+impl std::fmt::Debug for PreUpgradeHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreUpgradeHook(..)")
+    }
+}
+
+impl PreUpgradeHook {
+    /// Run the hook on `stream` and hand the stream back with the headers.
+    async fn run(
+        &self,
+        stream: AnyStream,
+        host: &str,
+    ) -> Result<(AnyStream, Vec<(String, String)>), WsError> {
+        let handle = PreUpgrade {
+            stream: Rc::new(RefCell::new(Some(stream))),
+            host: host.into(),
+        };
+        let headers = (self.0)(handle.clone()).await?;
+        let stream = handle.stream.borrow_mut().take();
+        let stream = stream.ok_or(UpgradeErr::Exchange("an exchange did not complete"))?;
+        Ok((stream, headers))
+    }
+}
+
+impl WsClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a header to the upgrade request.
+    ///
+    /// Headers from this and [`extra_headers`](Self::extra_headers) are sent
+    /// in the order they were added, after the handshake headers. Repeating a
+    /// name (several `Cookie` lines, say) sends every occurrence in order;
+    /// nothing is merged or replaced.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Append several headers to the upgrade request, in iteration order.
+    ///
+    /// Takes borrowed slices such as `&[("Cookie", "a=1")]` as well as owned
+    /// or computed pairs, e.g. a `Vec<(String, String)>` or an iterator of
+    /// `(&str, String)`. Ordering and repeated names behave as for
+    /// [`header`](Self::header).
+    pub fn extra_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: HeaderPair,
+    {
+        self.extra_headers.extend(
+            headers
+                .into_iter()
+                .map(|h| (h.name().to_owned(), h.value().to_owned())),
+        );
+        self
+    }
+
+    /// Offer a subprotocol in the `Sec-WebSocket-Protocol` request header.
+    ///
+    /// Offers accumulate in call order in a single comma-separated header.
+    /// The server must select one of them, or one offered with
+    /// [`header`](Self::header), or none; anything else fails the connect
+    /// with [`UpgradeErr::UnofferedProtocol`]. The server's choice is
+    /// [`ConnectionInfo::protocol`].
+    pub fn subprotocol(mut self, name: &str) -> Self {
+        self.subprotocols.push(name.to_owned());
+        self
+    }
+
+    /// Offer an extension in the `Sec-WebSocket-Extensions` request header.
+    ///
+    /// Offers accumulate in call order and are sent comma-separated, with
+    /// parameters formatted as `name; param; param=value` (RFC 6455 §9.1).
+    /// Negotiating an extension does not implement it: the accepted value is
+    /// reported in [`ConnectionInfo::extensions`].
+    pub fn with_extension(mut self, name: &str, params: &[(&str, Option<&str>)]) -> Self {
+        self.extensions.push(format_extension(name, params));
+        self
+    }
+
+    /// Offer a pre-formatted extension entry, sent as-is.
+    pub fn with_extension_raw(mut self, raw_value: &str) -> Self {
+        self.extensions.push(raw_value.to_owned());
+        self
+    }
+
+    /// Charge this connection's buffers against a shared [`MemoryBudget`],
+    /// and, for a [`WsReconnectClient`](crate::WsReconnectClient), the
+    /// payloads waiting in its outbound queue.
+    pub fn memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// Receive [`WsEvent`]s raised while connecting and on the open
+    /// connection, each with the builder's [`context`](Self::context).
+    pub fn on_event(mut self, f: impl Fn(&WsEvent, &ContextMap) + 'static) -> Self {
+        let mut sink = EventSink::new(f);
+        sink.set_context(self.hooks.context.clone());
+        self.events = Some(sink);
+        self
+    }
+
+    /// Attach `value` under `key` to every connection this builder opens,
+    /// replacing an earlier value for `key`. The [`ContextMap`] is passed to
+    /// event sinks, connect hooks, Ping and Pong hooks, interceptors,
+    /// middleware and the [`await_first_message`](Self::await_first_message)
+    /// check, and returned by [`WsClient::context`]. Connections made by a
+    /// [`WsReconnectClient`](crate::WsReconnectClient) or a
+    /// [`WsPool`](crate::WsPool) from this builder share it.
+    pub fn context(mut self, key: &'static str, value: impl Any + fmt::Debug) -> Self {
+        self.hooks.context.insert(key, value);
+        if let Some(events) = &mut self.events {
+            events.set_context(self.hooks.context.clone());
+        }
+        self
+    }
+
+    /// Send heartbeats and enforce liveness while [`WsClient::recv`] is waiting.
+    pub fn keepalive(mut self, keepalive: Keepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Check `watchdog` when the [`keepalive`](Self::keepalive) times out,
+    /// setting `local_stall` on the [`WsError::KeepaliveTimeout`] if the
+    /// thread stalled while the liveness proof was awaited. The watchdog
+    /// must run on the thread the connections do.
+    pub fn stall_watchdog(mut self, watchdog: &StallWatchdog) -> Self {
+        self.watchdog = Some(watchdog.shared());
+        self
+    }
+
+    /// Stop connects, receives, backoff sleeps and background pings of
+    /// connections from this builder once `token` is cancelled, failing them
+    /// with [`WsError::Cancelled`]. Each connection watches a child of
+    /// `token`, see [`WsClient::cancellation_token`], and is left as
+    /// `on_cancel` says. See the [`cancel`](crate::cancel) module.
+    pub fn cancellation(mut self, token: &CancellationToken, on_cancel: OnCancel) -> Self {
+        self.cancellation = Some((token.clone(), on_cancel));
+        self
+    }
+
+    /// Replace the generated upgrade request with the bytes returned by
+    /// `builder`, which receives the parsed URL, the generated
+    /// `Sec-WebSocket-Key` and the handshake time.
+    ///
+    /// The bytes are sent verbatim, so they must form a complete HTTP request
+    /// including the terminating blank line. Headers set with
+    /// [`header`](Self::header) are not added. The response is still validated
+    /// against the key, so the request must carry it in `Sec-WebSocket-Key`.
+    ///
+    /// The time comes from the builder's [`clock`](Self::clock) and is the
+    /// one recorded in [`ConnectionInfo::handshake_at`], so a signature over
+    /// a timestamp can use it and match what the client reports.
+    pub fn with_custom_http_request(mut self, builder: Box<RequestFn>) -> Self {
+        self.custom_request = Some(CustomRequest(Arc::from(builder)));
+        self
+    }
+
+    /// Run `hook` on the connection after TCP and TLS are established but
+    /// before the upgrade request, e.g. to POST a signed body to an auth
+    /// endpoint and forward the token it returns. The hook gets a
+    /// [`PreUpgrade`] to make any number of plain HTTP exchanges on the same
+    /// connection, one at a time, and returns headers to add to the upgrade
+    /// request. With
+    /// [`with_custom_http_request`](Self::with_custom_http_request) those
+    /// headers are dropped.
+    ///
+    /// The hook counts against [`connect_timeout`](Self::connect_timeout) as
+    /// part of the upgrade phase. Its error fails the connect as is.
+    ///
+    /// ```no_run
+    /// use websockets_monoio::WsClientBuilder;
+    /// use websockets_monoio::http_upgrade::SimpleRequest;
+    ///
+    /// # async fn run() -> Result<(), websockets_monoio::WsError> {
+    /// let client = WsClientBuilder::new()
+    ///     .pre_upgrade(|conn| async move {
+    ///         let auth = SimpleRequest::new("POST", "/auth")
+    ///             .header("Content-Type", "application/json")
+    ///             .body(r#"{"key":"k","sig":"s"}"#);
+    ///         let response = conn.exchange(&auth).await?;
+    ///         let token = String::from_utf8_lossy(&response.body).into_owned();
+    ///         Ok(vec![("X-Auth-Token".to_owned(), token)])
+    ///     })
+    ///     .connect("wss://venue.example/stream")
+    ///     .await?;
+    /// # drop(client);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pre_upgrade<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(PreUpgrade) -> Fut + 'static,
+        Fut: Future<Output = Result<Vec<(String, String)>, WsError>> + 'static,
+    {
+        self.pre_upgrade = Some(PreUpgradeHook(Rc::new(move |conn| Box::pin(hook(conn)))));
+        self
+    }
+
+    /// Read wall-clock time from `clock` instead of the system clock, e.g. a
+    /// [`FrozenClock`](crate::time::FrozenClock) in tests. See [`Clock`] for
+    /// where it is used.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(SharedClock(Rc::new(clock)));
+        self
+    }
+
+    /// How many messages or bytes [`WsClient::recv`] may return back to back,
+    /// without waiting for I/O, before yielding to other tasks. See
+    /// [`ReadFairness`] for the defaults.
+    pub fn read_fairness(mut self, fairness: ReadFairness) -> Self {
+        self.read_fairness = fairness;
+        self
+    }
+
+    /// From what size [`WsClient::recv`] validates a Text message's UTF-8 in
+    /// chunks, yielding to other tasks in between. See [`ProcessingBudget`]
+    /// for the defaults.
+    pub fn processing_budget(mut self, budget: ProcessingBudget) -> Self {
+        self.processing_budget = budget;
+        self
+    }
+
+    /// Call `cb` with each resolved address just before it is dialed, e.g. to
+    /// log which IP a name resolved to. With a [`proxy`](Self::proxy) these
+    /// are the proxy's addresses. If an address fails, `cb` is called again
+    /// for the next one.
+    pub fn with_tcp_connect_cb(mut self, cb: Box<TcpConnectFn>) -> Self {
+        self.hooks.tcp = Some(Arc::from(cb));
+        self
+    }
+
+    /// Call `cb` with the negotiated session once the TLS handshake with the
+    /// server completes. Not called for `ws://` URLs or for the TLS session
+    /// to an `https://` proxy.
+    pub fn with_tls_connect_cb(mut self, cb: Box<TlsConnectFn>) -> Self {
+        self.hooks.tls = Some(Arc::from(cb));
+        self
+    }
+
+    /// Set `SO_MARK` to `mark` on every TCP socket this builder dials (the
+    /// proxy's when one is set), so the kernel routes it by the policy rules
+    /// matching that mark, e.g. to keep it out of a VPN tunnel. Setting a
+    /// mark needs `CAP_NET_ADMIN`; without it the connect fails with
+    /// `PermissionDenied`.
+    #[cfg(target_os = "linux")]
+    pub fn with_so_mark(mut self, mark: u32) -> Self {
+        self.hooks.so_mark = Some(mark);
+        self
+    }
+
+    /// Set `TCP_USER_TIMEOUT` on every TCP socket this builder dials: the
+    /// kernel aborts the connection once sent data has gone unacknowledged
+    /// for `timeout` (rounded down to milliseconds), and the next read or
+    /// write fails with `TimedOut`.
+    ///
+    /// Only data in flight is timed, so an idle connection is never torn
+    /// down by this alone. Pair it with `SO_KEEPALIVE`, or with
+    /// [`keepalive`](Self::keepalive) or [`WsClient::background_ping`], to
+    /// have something to acknowledge. With `SO_KEEPALIVE` on, the user timeout
+    /// also overrides its probe count: the connection is dropped when probes
+    /// have been unanswered for `timeout`, rather than after
+    /// `TCP_KEEPCNT` probes.
+    #[cfg(all(target_os = "linux", feature = "tcp-user-timeout"))]
+    pub fn with_tcp_user_timeout(mut self, timeout: Duration) -> Self {
+        self.hooks.tcp_user_timeout = Some(timeout);
+        self
+    }
+
+    /// Answer incoming Pings with the payload returned by `f` instead of
+    /// echoing them, or send no Pong when it returns `None`.
+    ///
+    /// The hook runs as [`WsClient::recv`] reads the Ping, before the Pong is
+    /// queued. A reply longer than 125 bytes, the control frame limit, fails
+    /// that `recv` with [`WsError::PingReplyTooLarge`]. The Ping is still
+    /// returned by `recv`, and Close frames are answered automatically as
+    /// usual. Pings read directly from [`WsClient::ws`] get no reply.
+    pub fn on_ping_reply(
+        mut self,
+        f: impl Fn(&[u8], &ContextMap) -> Option<Vec<u8>> + 'static,
+    ) -> Self {
+        self.ping_reply = Some(PingReply(Rc::new(f)));
+        self
+    }
+
+    /// Call `f` with the payload of every Pong [`WsClient::recv`] reads.
+    ///
+    /// Pongs are reported whether they answer one of the client's Pings or
+    /// arrive unsolicited, as some servers send them for their own keepalive
+    /// (RFC 6455 §5.5.3). Either way a Pong updates
+    /// [`ConnectionStats::last_received`], counts as liveness for
+    /// [`Keepalive::ping`], and is returned by `recv`. It only yields a
+    /// [`BackgroundPing::last_rtt`] sample if it carries the sequence number
+    /// of that task's latest Ping, so unsolicited Pongs never skew the RTT.
+    pub fn on_pong(mut self, f: impl Fn(&[u8], &ContextMap) + 'static) -> Self {
+        self.on_pong = Some(PongHook(Rc::new(f)));
+        self
+    }
+
+    /// Drop frames with a reserved opcode (`0x3` to `0x7`, `0xB` to `0xF`)
+    /// instead of failing the connection.
+    ///
+    /// By default such a frame is a protocol error: `recv` closes the
+    /// connection with code 1002 and fails with
+    /// [`WsError::ReservedOpcode`]. With `skip` set, each one is dropped
+    /// whole, counted in [`WsClient::reserved_frames_skipped`], and reported
+    /// as [`WsEvent::ReservedOpcodeSkipped`]. Meant for servers known to emit
+    /// such frames, e.g. during maintenance windows.
+    pub fn skip_reserved_opcodes(mut self, skip: bool) -> Self {
+        self.skip_reserved = skip;
+        self
+    }
+
+    /// Assert that the server only sends ASCII text, as most exchanges do
+    /// for their JSON.
+    ///
+    /// Every Text message is then checked for ASCII, which is cheaper than
+    /// validating UTF-8, and one that is not is a policy violation: `recv`
+    /// closes the connection with 1008 and fails with
+    /// [`WsError::NonAsciiText`] at the first non-ASCII byte. Without it,
+    /// Text is validated as UTF-8 with an ASCII fast path; see
+    /// [`text`].
+    pub fn ascii_text(mut self, enabled: bool) -> Self {
+        self.ascii_text = enabled;
+        self
+    }
+
+    /// Make connecting wait for the server's first data message and pass it
+    /// to `validate`, for endpoints that greet every connection with a
+    /// welcome or info message.
+    ///
+    /// Connect returns once `validate` accepts the message, which stays
+    /// available from [`WsClient::first_message`]; Pings and Pongs before it
+    /// are handled as usual. If `validate` returns `Err(reason)` the
+    /// connection is closed with code 1008 and connect fails with
+    /// [`WsError::FirstMessageRejected`]. If no message arrives within
+    /// `timeout` it is closed with code 1000 and connect fails with
+    /// [`WsError::FirstMessageTimeout`]; a Close from the server fails it
+    /// with [`WsError::ClosedByPeer`]. The wait counts against
+    /// [`connect_timeout`](Self::connect_timeout) and deadlines, and runs
+    /// again on every reconnect. The runtime must be built with the timer
+    /// enabled.
+    pub fn await_first_message(
+        mut self,
+        timeout: Duration,
+        validate: impl Fn(&Message, &ContextMap) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.first_message = Some(FirstMessage {
+            timeout,
+            validate: Rc::new(validate),
+        });
+        self
+    }
+
+    /// Run `middleware` on every frame and message received through
+    /// [`WsClient::recv`]. Middleware runs in registration order.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.0.push(Rc::new(middleware));
+        self
+    }
+
+    /// Run `f` on every Text and Binary message before [`WsClient::send`]
+    /// writes it, e.g. to add a sequence number or sign it. Interceptors run
+    /// in registration order, each seeing what the previous one produced; a
+    /// [`Replace`](InterceptAction::Replace) hands its message to the next
+    /// one and a [`Drop`](InterceptAction::Drop) ends the chain, with `send`
+    /// returning `Ok(())`.
+    ///
+    /// An `Err(reason)` fails the send with [`WsError::Intercept`] and leaves
+    /// the connection usable. Interceptors get no access to the client, so
+    /// they cannot send or receive themselves. Messages sent with
+    /// [`WsClient::send_owned`] and control messages bypass them.
+    pub fn intercept_outbound(
+        mut self,
+        name: &str,
+        f: impl Fn(&mut Message, &ContextMap) -> Result<InterceptAction, String> + 'static,
+    ) -> Self {
+        self.interceptors
+            .push(Interceptor::new(name, Direction::Outbound, f));
+        self
+    }
+
+    /// Run `f` on every Text and Binary message before [`WsClient::recv`]
+    /// returns it, e.g. to verify a signature, with the same ordering rules
+    /// as [`intercept_outbound`](Self::intercept_outbound). Dropped messages
+    /// are skipped; an `Err(reason)` discards the message and fails that
+    /// `recv` with [`WsError::Intercept`], leaving the connection usable.
+    /// Runs before [`Middleware::before_deliver`].
+    pub fn intercept_inbound(
+        mut self,
+        name: &str,
+        f: impl Fn(&mut Message, &ContextMap) -> Result<InterceptAction, String> + 'static,
+    ) -> Self {
+        self.interceptors
+            .push(Interceptor::new(name, Direction::Inbound, f));
+        self
+    }
+
+    /// Rewrite the opcode and payload of every Text and Binary message
+    /// received, e.g. to decrypt it; see the [`transform`](crate::transform)
+    /// module. Runs once the message is reassembled, before UTF-8
+    /// validation and the [inbound interceptors](Self::intercept_inbound).
+    /// An opcode other than Text is delivered as Binary.
+    ///
+    /// When `f` fails, `on_error` decides whether the message is skipped
+    /// and counted in [`WsClient::transform_drops`] or the connection is
+    /// closed with 1007 and `recv` fails with [`WsError::Transform`].
+    /// Replaces an earlier inbound transform.
+    pub fn transform_inbound(
+        mut self,
+        f: impl Fn(OpCode, Vec<u8>) -> Result<(OpCode, Vec<u8>), TransformError> + 'static,
+        on_error: OnTransformError,
+    ) -> Self {
+        self.transforms.inbound = Some((Rc::new(f), on_error));
+        self
+    }
+
+    /// Rewrite the opcode and payload of every Text and Binary message
+    /// [`WsClient::send`] writes, e.g. to encrypt it. Runs after the
+    /// [outbound interceptors](Self::intercept_outbound), right before the
+    /// message is framed. An opcode other than Text is sent as Binary.
+    ///
+    /// When `f` fails, the send fails with [`WsError::Transform`] without
+    /// writing anything and the connection stays usable. The same paths
+    /// that bypass interceptors, such as [`WsClient::send_owned`] and
+    /// [`WsWriter`], bypass the transform. Replaces an earlier outbound
+    /// transform.
+    pub fn transform_outbound(
+        mut self,
+        f: impl Fn(OpCode, Vec<u8>) -> Result<(OpCode, Vec<u8>), TransformError> + 'static,
+    ) -> Self {
+        self.transforms.outbound = Some(Rc::new(f));
+        self
+    }
+
+    /// Tunnel the connection through an HTTP proxy with `CONNECT`.
+    ///
+    /// Works for `ws://` and `wss://` targets through both `http://` and
+    /// `https://` proxies. With an `https://` proxy and a `wss://` target,
+    /// the origin's TLS session runs inside the one to the proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Use `connector` for TLS, to the origin and to an `https://` proxy,
+    /// instead of the default one trusting the `webpki-roots` certificates.
+    pub fn tls_connector(mut self, connector: TlsConnector) -> Self {
+        self.tls_connector = Some(Connector(connector));
+        self
+    }
+
+    /// Backoff used by [`WsReconnectClient`](crate::reconnect::WsReconnectClient)
+    /// between reconnect attempts.
+    pub fn reconnect_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Decide per disconnect whether and when
+    /// [`WsReconnectClient`](crate::reconnect::WsReconnectClient) reconnects,
+    /// e.g. to stop on an exchange's auth-failure close code. Replaces the
+    /// [`DefaultReconnectPolicy`](crate::reconnect::DefaultReconnectPolicy)
+    /// over [`reconnect_backoff`](Self::reconnect_backoff).
+    pub fn reconnect_policy(mut self, policy: impl ReconnectPolicy + 'static) -> Self {
+        self.reconnect_policy = Some(Policy(Rc::new(policy)));
+        self
+    }
+
+    /// How the reconnect delays of the policy are randomized;
+    /// [`Jitter::Decorrelated`] by default. Every wait is reported as
+    /// [`WsEvent::ReconnectScheduled`].
+    pub fn reconnect_jitter(mut self, jitter: Jitter) -> Self {
+        self.reconnect_jitter = jitter;
+        self
+    }
+
+    /// Seed the reconnect jitter with `seed` instead of the
+    /// [process seed](crate::reconnect::process_jitter_seed). Every client
+    /// from this builder then draws the same schedule, so this is for tests
+    /// and simulations.
+    pub fn jitter_seed(mut self, seed: u64) -> Self {
+        self.jitter_seed = Some(seed);
+        self
+    }
+
+    /// Await `f(attempt)` before each reconnect attempt, after the backoff,
+    /// e.g. to ask a fleet-wide coordinator for a token so reconnects are
+    /// spread out. A hook that does not finish within `timeout` is
+    /// abandoned, reported as [`WsEvent::ReconnectPacingTimedOut`], and the
+    /// attempt goes ahead.
+    pub fn reconnect_pacing<F, Fut>(mut self, timeout: Duration, f: F) -> Self
+    where
+        F: Fn(u32) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.reconnect_pacing = Some(Pacing {
+            f: Rc::new(move |attempt| Box::pin(f(attempt))),
+            timeout,
+        });
+        self
+    }
+
+    /// Default time to live for messages queued on a
+    /// [`WsReconnectClient`](crate::reconnect::WsReconnectClient); messages
+    /// still queued after `ttl` are dropped. Unset by default.
+    pub fn outbound_ttl(mut self, ttl: Duration) -> Self {
+        self.outbound_ttl = Some(ttl);
+        self
+    }
+
+    /// Vet every connect this builder makes with `policy`, e.g.
+    /// [`DenyPrivateNetworks`](crate::policy::DenyPrivateNetworks) for URLs
+    /// from untrusted configuration. A veto fails the connect with
+    /// [`WsError::Policy`] before anything is dialed. See
+    /// [`EndpointPolicy`] for when it runs.
+    pub fn endpoint_policy(mut self, policy: impl EndpointPolicy + 'static) -> Self {
+        self.hooks.policy = Some(Rc::new(policy));
+        self
+    }
+
+    /// Set `TCP_NODELAY` on every TCP socket this builder dials. Unset by
+    /// default, which leaves the system default (Nagle's algorithm on).
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.hooks.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Size of the transport's read and write buffers, each. Defaults to
+    /// 8 KiB; sizes below 1 KiB are raised to 1 KiB.
+    ///
+    /// Writes larger than the buffer go out in several submissions, so
+    /// larger buffers suit large messages. A
+    /// [`memory_budget`](Self::memory_budget) is charged for both buffers.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.hooks.buffer_size = Some(bytes.max(1024));
+        self
+    }
+
+    /// Drop a `#fragment` from the URLs this builder connects to instead of
+    /// failing with
+    /// [`UrlError::FragmentNotAllowed`](crate::url::UrlError::FragmentNotAllowed);
+    /// see [`parse_ws_or_wss_lenient`]. Off by default. Either way the
+    /// upgrade request never carries a fragment.
+    pub fn strip_url_fragment(mut self, strip: bool) -> Self {
+        self.strip_url_fragment = strip;
+        self
+    }
+
+    /// Parse `user:password@` in the URLs this builder connects to but do
+    /// not send it. By default it is sent as `Authorization: Basic`, unless
+    /// an `Authorization` header is set with [`header`](Self::header). It
+    /// never reaches DNS or the `Host` header either way.
+    pub fn withhold_url_credentials(mut self, withhold: bool) -> Self {
+        self.withhold_url_credentials = withhold;
+        self
+    }
+
+    /// Fail the upgrade with [`UpgradeErr::Oversized`] when the server's
+    /// response headers exceed `bytes`. Defaults to
+    /// [`DEFAULT_MAX_RESPONSE_HEADER`]; raise it for servers that set many
+    /// or large cookies. The whole header block is buffered during the
+    /// upgrade.
+    pub fn max_response_header(mut self, bytes: usize) -> Self {
+        self.max_response_header = Some(bytes);
+        self
+    }
+
+    /// **Non-conformant:** send client frames without masking.
+    ///
+    /// RFC 6455 §5.1 requires every client frame to be masked and a server
+    /// to close the connection when one is not, so with `unmasked` set a
+    /// standard server will drop the connection on the first frame. Only
+    /// for private links where both ends are under your control and the
+    /// server is known to accept unmasked client frames; masking protects
+    /// intermediaries such as caching proxies, so never use it across a
+    /// network you do not own. Off by default. Saves the XOR pass over each
+    /// payload and, for borrowed payloads, the copy made to mask it.
+    pub fn unmasked_client_frames(mut self, unmasked: bool) -> Self {
+        self.unmasked = unmasked;
+        self
+    }
+
+    /// Bucket bounds for [`ConnectionStats::inter_arrival`] instead of
+    /// [`DEFAULT_INTER_ARRIVAL_BOUNDS`](crate::stats::DEFAULT_INTER_ARRIVAL_BOUNDS).
+    pub fn inter_arrival_buckets(mut self, bounds: [Duration; HISTOGRAM_BUCKETS]) -> Self {
+        self.inter_arrival_buckets = Some(bounds);
+        self
+    }
+
+    /// Fail [`connect`](Self::connect) with [`WsError::Timeout`] when
+    /// resolution, TCP connect, TLS and the upgrade (plus the wait set with
+    /// [`await_first_message`](Self::await_first_message)) take longer than
+    /// `timeout` together. With
+    /// [`connect_with_deadline`](Self::connect_with_deadline) the earlier of
+    /// the two applies. The runtime must be built with the timer enabled.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Whether frames larger than 1 KiB go out as one vectored write of
+    /// header and payload rather than copied into one buffer first. On by
+    /// default for transports that gather writes themselves, which are only
+    /// [`Transport`]s implementing `poll_write_vectored` and
+    /// `is_write_vectored`: TCP, TLS and Unix connections go through
+    /// `monoio_compat::StreamWrapper`'s write buffer, which does not, so
+    /// they always copy and this has no effect on them.
+    pub fn vectored_writes(mut self, enabled: bool) -> Self {
+        self.vectored_writes = Some(enabled);
+        self
+    }
+
+    /// Apply [`Preset::LatencySensitive`].
+    pub fn latency_sensitive(self) -> Self {
+        Preset::LatencySensitive.apply(self)
+    }
+
+    /// Apply [`Preset::Throughput`].
+    pub fn throughput(self) -> Self {
+        Preset::Throughput.apply(self)
+    }
+
+    /// Apply [`Preset::LongLivedFeed`].
+    pub fn long_lived_feed(self) -> Self {
+        Preset::LongLivedFeed.apply(self)
+    }
+
+    pub(crate) fn with_preset(mut self, preset: Preset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// The preset last applied to this builder, if any.
+    pub fn preset(&self) -> Option<Preset> {
+        self.preset
+    }
+
+    /// Cap the delay between reconnect attempts. Defaults to 60 seconds, so
+    /// exponential growth never produces multi-day waits.
+    pub fn max_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.backoff.max_interval = interval;
+        self
+    }
+
+    /// Connect to the first reachable server in `urls`, trying them in order
+    /// and retrying each as configured in `config`.
+    ///
+    /// Returns the client with the URL it is connected to. When every attempt
+    /// fails, [`WsError::AllFailed`] carries the last error for each URL.
+    /// Retry sleeps use `monoio::time`, so the runtime must be built with the
+    /// timer enabled.
+    pub async fn connect_list_with_retry(
+        &self,
+        urls: &[OwnedWsUrl],
+        config: RetryConfig,
+    ) -> Result<(WsClient, OwnedWsUrl), WsError> {
+        let mut failures = Vec::with_capacity(urls.len());
+        for url in urls {
+            let target = url.to_string_with_credentials();
+            let mut attempt = 0;
+            let error = loop {
+                match self.connect(&target).await {
+                    Ok(client) => return Ok((client, url.clone())),
+                    Err(WsError::Cancelled) => return Err(WsError::Cancelled),
+                    Err(e) if attempt + 1 >= config.attempts_per_url => break e,
+                    Err(_) => {
+                        self.sleep(config.backoff.delay(attempt)).await?;
+                        attempt += 1;
+                    }
+                }
+            };
+            failures.push((url.clone(), error));
+        }
+        Err(WsError::AllFailed(failures))
+    }
+
+    /// Connect to a `ws://` or `wss://` URL with the configured options.
+    pub async fn connect(&self, url: &str) -> Result<WsClient, WsError> {
+        self.connect_timed(url, &mut PhaseClock::default()).await
+    }
+
+    /// Complete the WebSocket handshake over `transport` with the configured
+    /// options, instead of dialing `url`.
+    ///
+    /// `url` only supplies the `Host` header and the request path. Options
+    /// that apply to dialing (proxy, TLS connector, socket options, connect
+    /// hooks) are not used; the rest, such as keepalives, events, the
+    /// upgrade hooks and the connect timeout, are. A `wss://` URL does not
+    /// add TLS, so `transport` has to be encrypted itself. It is used once:
+    /// a [`WsReconnectClient`](crate::WsReconnectClient) or a
+    /// [`WsPool`](crate::WsPool) from this builder dials `url` instead. See
+    /// the [`transport`](crate::transport) module.
+    pub async fn connect_over(
+        &self,
+        url: &str,
+        transport: impl Transport + 'static,
+    ) -> Result<WsClient, WsError> {
+        self.establish(
+            url,
+            Dial::Over(Box::new(AnyStream::Custom(Box::new(transport)))),
+            &mut PhaseClock::default(),
+        )
+        .await
+    }
+
+    /// Connect to a `ws://` backend behind a TLS terminator with the
+    /// configured options; see [`WsClient::connect_via_tls_terminator`].
+    ///
+    /// The builder's [TLS connector](Self::tls_connector) verifies the
+    /// terminator's certificate against the backend's host name. The proxy
+    /// is not used.
+    pub async fn connect_via_tls_terminator(
+        &self,
+        backend_ws_url: &str,
+        tls_terminator_addr: SocketAddr,
+    ) -> Result<WsClient, WsError> {
+        self.establish(
+            backend_ws_url,
+            Dial::TlsTerminator(tls_terminator_addr),
+            &mut PhaseClock::default(),
+        )
+        .await
+    }
+
+    /// [`connect`](Self::connect) within the caller's `deadline`, returning
+    /// the client with the budget that is left.
+    ///
+    /// Resolution, TCP connect, TLS and the upgrade all draw on the same
+    /// deadline, so the remainder can bound whatever the caller does next,
+    /// such as waiting for a subscription ack. If it runs out,
+    /// [`WsError::Timeout`] names the phase and the budget left when each
+    /// phase started. DNS lookups block the thread and cannot be cut short;
+    /// one that overruns fails as soon as it returns. The runtime must be
+    /// built with the timer enabled.
+    pub async fn connect_with_deadline(
+        &self,
+        url: &str,
+        deadline: Deadline,
+    ) -> Result<(WsClient, Duration), WsError> {
+        let client = self
+            .connect_timed(url, &mut PhaseClock::new(Some(deadline)))
+            .await?;
+        Ok((client, deadline.remaining()))
+    }
+
+    pub(crate) async fn connect_timed(
+        &self,
+        url: &str,
+        clock: &mut PhaseClock,
+    ) -> Result<WsClient, WsError> {
+        self.establish(url, Dial::Url, clock).await
+    }
+
+    /// Connect as `dial` says, unless the
+    /// [`cancellation`](Self::cancellation) token stops it.
+    async fn establish(
+        &self,
+        url: &str,
+        dial: Dial,
+        clock: &mut PhaseClock,
+    ) -> Result<WsClient, WsError> {
+        let open = self.open(url, dial, clock);
+        match &self.cancellation {
+            Some((token, _)) => token
+                .run_until_cancelled(open)
+                .await
+                .unwrap_or(Err(WsError::Cancelled)),
+            None => open.await,
+        }
+    }
+
+    /// Sleep for `delay` between attempts, cut short by the
+    /// [`cancellation`](Self::cancellation) token.
+    pub(crate) async fn sleep(&self, delay: Duration) -> Result<(), WsError> {
+        let sleep = monoio::time::sleep(delay);
+        match &self.cancellation {
+            Some((token, _)) => token
+                .run_until_cancelled(sleep)
+                .await
+                .ok_or(WsError::Cancelled),
+            None => {
+                sleep.await;
+                Ok(())
+            }
+        }
+    }
+
+    /// The builder's cancellation token, if any.
+    pub(crate) fn cancel_token(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref().map(|(token, _)| token)
+    }
+
+    async fn open(
+        &self,
+        url: &str,
+        dial: Dial,
+        clock: &mut PhaseClock,
+    ) -> Result<WsClient, WsError> {
+        let mut u = if self.strip_url_fragment {
+            parse_ws_or_wss_lenient(url)?
+        } else {
+            parse_ws_or_wss(url)?
+        };
+        if self.withhold_url_credentials {
+            u.userinfo = None;
+        }
+        if let Some(policy) = &self.hooks.policy {
+            policy.check(&u, &[])?;
+        }
+        if let Some(timeout) = self.connect_timeout {
+            clock.limit(Deadline::after(timeout));
+        }
+
+        // Fail fast before dialing if the budget cannot cover the buffers.
+        let buffers = match &self.memory_budget {
+            Some(budget) => Some(
+                budget
+                    .try_reserve(self.connection_buffer_bytes())
+                    .inspect_err(|e| emit_budget_event(&self.events, e))?,
+            ),
+            None => None,
+        };
+
+        let extensions = self.extensions.join(", ");
+        let mut headers: Vec<(&str, &str)> = self
+            .extra_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        if !extensions.is_empty() {
+            headers.push(("Sec-WebSocket-Extensions", &extensions));
+        }
+        let mut info = ConnectionInfo::new(url);
+        let connector = match &self.tls_connector {
+            Some(Connector(connector)) => connector,
+            None => default_connector(),
+        };
+        let stream = match dial {
+            Dial::Over(stream) => *stream,
+            Dial::Url => {
+                WsClient::dial(
+                    &u,
+                    self.proxy.as_ref(),
+                    connector,
+                    &self.hooks,
+                    &mut info,
+                    clock,
+                )
+                .await?
+            }
+            Dial::TlsTerminator(addr) => {
+                let started = Instant::now();
+                let tcp = clock
+                    .run(ConnectPhase::Connect, self.hooks.connect_addr(addr))
+                    .await?;
+                record_tcp(&mut info, &tcp, started);
+                WsClient::start_tls(tcp, &u.host, connector, &self.hooks, &mut info, clock).await?
+            }
+        };
+        let (stream, hook_headers) = match &self.pre_upgrade {
+            Some(hook) => {
+                clock
+                    .run(ConnectPhase::Upgrade, hook.run(stream, &u.host_header()))
+                    .await?
+            }
+            None => (stream, Vec::new()),
+        };
+        headers.extend(hook_headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let custom = self.custom_request.as_ref();
+        let subprotocols: Vec<&str> = self.subprotocols.iter().map(String::as_str).collect();
+        let wall_clock: &dyn Clock = match &self.clock {
+            Some(SharedClock(c)) => c.as_ref(),
+            None => &SystemClock,
+        };
+        let mut client = clock
+            .run(
+                ConnectPhase::Upgrade,
+                WsClient::handshake(
+                    stream,
+                    &u,
+                    &subprotocols,
+                    &headers,
+                    custom,
+                    self.max_response_header
+                        .unwrap_or(DEFAULT_MAX_RESPONSE_HEADER),
+                    wall_clock,
+                    info,
+                    self.events.as_ref(),
+                ),
+            )
+            .await
+            .inspect_err(|e| emit_accept_event(&self.events, e))?;
+        client.events = self.events.clone();
+        client.context = self.hooks.context.clone();
+        client.fairness = FairnessState::new(self.read_fairness);
+        client.processing = self.processing_budget;
+        client.ascii_text = self.ascii_text;
+        client.budget = self.memory_budget.clone();
+        client.buffers = buffers;
+        if let Some(size) = self.hooks.buffer_size {
+            client.buffer_sizes = (size, size);
+        }
+        client.keepalive = self.keepalive.clone().map(|config| {
+            let now = Instant::now();
+            KeepaliveState {
+                next_heartbeat: now + config.interval,
+                last_alive: now,
+                ping_sent: None,
+                config,
+            }
+        });
+        client.watchdog = self.watchdog.clone();
+        client.cancel = self
+            .cancellation
+            .as_ref()
+            .map(|(token, on_cancel)| (token.child(), *on_cancel));
+        if let Some(reply) = &self.ping_reply {
+            client.ws.set_auto_pong(false);
+            client.ping_reply = Some(reply.clone());
+        }
+        client.on_pong = self.on_pong.clone();
+        if self.unmasked {
+            client.mask = false;
+            client.ws.set_auto_apply_mask(false);
+        }
+        if let Some(bounds) = self.inter_arrival_buckets {
+            client.stats.inter_arrival = Histogram::new(bounds);
+        }
+        client.middleware = self.middleware.clone();
+        client.interceptors = InterceptorChain::new(&self.interceptors);
+        client.transforms = self.transforms.clone();
+        if let Some(enabled) = self.vectored_writes
+            && client.writev
+        {
+            client.writev = enabled;
+            client.ws.set_writev(enabled);
+        }
+        client.info.preset = self.preset;
+        client.transfer = TransferOptions {
+            extra_headers: self.extra_headers.clone(),
+            subprotocols: self.subprotocols.clone(),
+            extensions: self.extensions.clone(),
+            tcp_nodelay: self.hooks.nodelay,
+            buffer_size: self.hooks.buffer_size,
+            connect_timeout: self.connect_timeout,
+            vectored_writes: self.vectored_writes,
+            max_response_header: self.max_response_header,
+            unmasked_client_frames: self.unmasked,
+            skip_reserved_opcodes: self.skip_reserved,
+            ascii_text: self.ascii_text,
+            strip_url_fragment: self.strip_url_fragment,
+            withhold_url_credentials: self.withhold_url_credentials,
+        };
+        if self.skip_reserved {
+            client.skip_reserved_opcodes();
+        }
+        if let Some(first) = &self.first_message {
+            let result = clock
+                .run(ConnectPhase::FirstMessage, client.await_first(first))
+                .await;
+            match result {
+                Ok(message) => client.first_message = Some(message),
+                Err(e) => {
+                    let code = match e {
+                        WsError::FirstMessageRejected { .. } => CloseFrame::POLICY_VIOLATION,
+                        _ => 1000,
+                    };
+                    let close = Message::Close(Some(CloseFrame {
+                        code,
+                        reason: String::new(),
+                    }));
+                    let _ = client.send(close).await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(client)
+    }
+
+    /// Charge `bytes` to the memory budget, if there is one, reporting a
+    /// refusal as [`WsEvent::MemoryBudgetExceeded`].
+    pub(crate) fn reserve(&self, bytes: usize) -> Result<Option<Reservation>, WsError> {
+        self.memory_budget
+            .as_ref()
+            .map(|budget| budget.try_reserve(bytes))
+            .transpose()
+            .inspect_err(|e| emit_budget_event(&self.events, e))
+    }
+
+    /// Bytes a connection from this builder charges to a memory budget.
+    fn connection_buffer_bytes(&self) -> usize {
+        match self.hooks.buffer_size {
+            Some(size) => CONNECTION_BUFFER_BYTES - 2 * STREAM_BUFFER_BYTES + 2 * size,
+            None => CONNECTION_BUFFER_BYTES,
+        }
+    }
+}
+
+impl WsClient {
+    /// Connect to a `ws://` or `wss://` URL and complete the WebSocket handshake.
+    ///
+    /// Use [`WsClientBuilder`] for anything beyond extra headers.
+    ///
+    /// # Runtime drivers
+    ///
+    /// The client runs on any monoio driver: `IoUringDriver`, `LegacyDriver`
+    /// (epoll/kqueue) and `FusionDriver`, which picks io_uring when the kernel
+    /// supports it. `benches/perf.rs` runs the connect and round-trip cases
+    /// under both drivers, and `tests/drivers.rs` runs a round trip on each
+    /// of the three. Differences worth knowing about:
+    ///
+    /// - Both transports go through `monoio_compat::StreamWrapper`, whose read
+    ///   and write buffers are [`WsClientBuilder::buffer_size`] each (8 KiB by
+    ///   default) on every driver. Frames larger than that are written in
+    ///   several submissions, so await each write to completion rather than
+    ///   assuming one frame is one syscall.
+    /// - `StreamWrapper` does not implement vectored writes on any driver, so
+    ///   frames are copied into its write buffer; only custom
+    ///   [`Transport`]s that gather writes get vectored frame writes (see
+    ///   [`WsClientBuilder::vectored_writes`]).
+    /// - Timers are not enabled by `#[monoio::main]` by default. Enable them with
+    ///   `#[monoio::main(timer_enabled = true)]` or `RuntimeBuilder::enable_timer`
+    ///   before wrapping this call in `monoio::time::timeout`.
+    pub async fn connect(url: &str, extra_headers: &[(&str, &str)]) -> Result<Self, WsError> {
+        WsClientBuilder::new()
+            .extra_headers(extra_headers)
+            .connect(url)
+            .await
+    }
+
+    /// [`connect`](Self::connect), failing with [`WsError::Timeout`] if the
+    /// whole connect takes longer than `timeout`. The error names the phase
+    /// that was running, as with [`WsClientBuilder::connect_timeout`].
+    ///
+    /// The runtime must be built with the timer enabled. TCP connect, TLS
+    /// and the upgrade run inside the returned future and spawn no tasks, so
+    /// on timeout they are dropped where they stand and the socket is
+    /// closed. Only DNS resolution, which blocks, cannot be interrupted; see
+    /// [`WsClientBuilder::connect_with_deadline`].
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use std::time::Duration;
+    /// use websockets_monoio::{ConnectPhase, WsClient, WsError};
+    ///
+    /// // Accepts connections and never answers, like a wedged server. Each
+    /// // socket reports when the client closes it.
+    /// let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let addr = listener.local_addr().unwrap();
+    /// let (closed, closes) = std::sync::mpsc::channel();
+    /// std::thread::spawn(move || {
+    ///     for socket in listener.incoming() {
+    ///         let closed = closed.clone();
+    ///         std::thread::spawn(move || {
+    ///             let mut socket = socket.unwrap();
+    ///             while socket.read(&mut [0u8; 1024]).unwrap_or(0) > 0 {}
+    ///             closed.send(()).unwrap();
+    ///         });
+    ///     }
+    /// });
+    ///
+    /// let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+    ///     .enable_timer()
+    ///     .build()
+    ///     .unwrap();
+    /// runtime.block_on(async {
+    ///     // Stuck in the upgrade, then in the TLS handshake.
+    ///     for url in [format!("ws://{addr}/"), format!("wss://localhost:{}/", addr.port())] {
+    ///         let timeout = Duration::from_millis(200);
+    ///         let result = WsClient::connect_with_timeout(&url, &[], timeout).await;
+    ///         let Err(WsError::Timeout(e)) = result else { panic!("connected") };
+    ///         assert!(e.limit() <= timeout);
+    ///         assert!(matches!(e.phase(), Some(ConnectPhase::Upgrade | ConnectPhase::Tls)));
+    ///         closes.recv_timeout(Duration::from_secs(5)).expect("socket closed");
+    ///     }
+    /// });
+    /// ```
+    pub async fn connect_with_timeout(
+        url: &str,
+        extra_headers: &[(&str, &str)],
+        timeout: Duration,
+    ) -> Result<Self, WsError> {
+        WsClientBuilder::new()
+            .extra_headers(extra_headers)
+            .connect_timeout(timeout)
+            .connect(url)
+            .await
+    }
+
+    /// [`connect_with_timeout`](Self::connect_with_timeout) with the timeout
+    /// in milliseconds.
+    ///
+    /// Meant for FFI wrappers: `Duration` has no C representation, while a
+    /// `u64` maps to `uint64_t` in `cbindgen` output, so an exported function
+    /// can pass the C caller's timeout straight through. The wrapper still has
+    /// to drive the future on a monoio runtime with the timer enabled, and
+    /// the client stays on that runtime's thread.
+    pub async fn connect_timeout_ms(
+        url: &str,
+        headers: &[(&str, &str)],
+        timeout_ms: u64,
+    ) -> Result<Self, WsError> {
+        Self::connect_with_timeout(url, headers, Duration::from_millis(timeout_ms)).await
+    }
+
+    /// [`connect`](Self::connect) from a socket with `SO_MARK` set to `mark`,
+    /// see [`WsClientBuilder::with_so_mark`].
+    #[cfg(target_os = "linux")]
+    pub async fn connect_with_so_mark(
+        mark: u32,
+        url: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Self, WsError> {
+        WsClientBuilder::new()
+            .extra_headers(headers)
+            .with_so_mark(mark)
+            .connect(url)
+            .await
+    }
+
+    /// Check that `url` can be reached with the builder's options (proxy, TLS
+    /// config, headers and so on): connect, close gracefully at once, and
+    /// report what was negotiated.
+    ///
+    /// Meant for pre-flight checks. On failure, [`ProbeError::kind`] says
+    /// where it failed (DNS, TCP, TLS, a 401/403, another status, a malformed
+    /// handshake) with the underlying error attached. The close waits at most
+    /// [`PROBE_CLOSE_TIMEOUT`](crate::probe::PROBE_CLOSE_TIMEOUT) for the
+    /// server's answer, so the runtime must be built with the timer enabled.
+    pub async fn probe(url: &str, opts: &WsClientBuilder) -> Result<ProbeReport, ProbeError> {
+        crate::probe::probe(opts, url).await
+    }
+
+    /// [`connect`](Self::connect), retried up to `max_attempts` times in
+    /// total with a fixed `backoff` between attempts.
+    ///
+    /// A lighter alternative to
+    /// [`WsReconnectClient`](crate::reconnect::WsReconnectClient) when only the
+    /// initial connect needs retrying. Fails with
+    /// [`WsError::MaxRetriesExceeded`] carrying the error of every attempt.
+    /// Sleeps use `monoio::time`, so the runtime must be built with the
+    /// timer enabled.
+    pub async fn connect_with_retries(
+        url: &str,
+        extra_headers: &[(&str, &str)],
+        max_attempts: u32,
+        backoff: Duration,
+    ) -> Result<Self, WsError> {
+        let mut attempts = 0;
+        let mut earlier_errors = Vec::new();
+        loop {
+            attempts += 1;
+            match Self::connect(url, extra_headers).await {
+                Ok(client) => return Ok(client),
+                Err(e) if attempts >= max_attempts => {
+                    return Err(WsError::MaxRetriesExceeded {
+                        attempts,
+                        earlier_errors,
+                        last_error: Box::new(e),
+                    });
+                }
+                Err(e) => {
+                    earlier_errors.push(e);
+                    monoio::time::sleep(backoff).await;
+                }
+            }
+        }
+    }
+
+    /// [`WsClientBuilder::connect_list_with_retry`] with default options.
+    pub async fn connect_list_with_retry(
+        urls: &[OwnedWsUrl],
+        config: RetryConfig,
+    ) -> Result<(Self, OwnedWsUrl), WsError> {
+        WsClientBuilder::new()
+            .connect_list_with_retry(urls, config)
+            .await
+    }
+
+    /// Connect to a `ws://` backend that sits behind a TLS terminator.
+    ///
+    /// TLS is established with `tls_terminator_addr` (e.g. nginx or Envoy)
+    /// instead of resolving the backend host. The host from `backend_ws_url` is
+    /// used for SNI and the `Host` header, and its path and query form the
+    /// upgrade request, so the terminator can route to the plaintext backend.
+    pub async fn connect_via_tls_terminator(
+        backend_ws_url: &str,
+        tls_terminator_addr: SocketAddr,
+        extra_headers: &[(&str, &str)],
+    ) -> Result<Self, WsError> {
+        WsClientBuilder::new()
+            .extra_headers(extra_headers)
+            .connect_via_tls_terminator(backend_ws_url, tls_terminator_addr)
+            .await
+    }
+
+    /// Connect to a server on the Linux abstract Unix socket `socket_name`
+    /// and complete the WebSocket handshake.
+    ///
+    /// Abstract sockets have no filesystem entry. The name is given without
+    /// the leading NUL (one is ignored if present), e.g. `"my-service"` for
+    /// what `ss -x` shows as `@my-service`. The upgrade requests `/` with
+    /// `Host: localhost`, and [`ConnectionInfo::url`] reads
+    /// `unix-abstract:@my-service`.
+    #[cfg(target_os = "linux")]
+    pub async fn connect_abstract_unix(
+        socket_name: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Self, WsError> {
+        let name = socket_name.strip_prefix('\0').unwrap_or(socket_name);
+        // Not a URL, but what `as_str()` and `Display` show for the
+        // connection in logs and hooks.
+        let label = format!("unix-abstract:@{name}");
+        let mut info = ConnectionInfo::new(&label);
+        let started = Instant::now();
+        let unix = UnixStream::connect(format!("\0{name}")).await?;
+        info.timings.tcp = started.elapsed();
+        let u = WsUrl {
+            scheme: Scheme::Ws,
+            userinfo: None,
+            host: "localhost".into(),
+            port: 80,
+            path_and_query: "/".into(),
+            original: &label,
+        };
+        let stream = AnyStream::AbstractUnix(StreamWrapper::new(unix));
+        Self::handshake(
+            stream,
+            &u,
+            &[],
+            headers,
+            None,
+            DEFAULT_MAX_RESPONSE_HEADER,
+            &SystemClock,
+            info,
+            None,
+        )
+        .await
+    }
+
+    /// Open a transport with `factory` and complete the WebSocket handshake
+    /// over it.
+    ///
+    /// The factory establishes whatever the connection runs on (a socket
+    /// from elsewhere, shared memory, an in-process pipe, ...) and is called
+    /// exactly once; its error is returned as is. `url` only supplies the
+    /// `Host` header and the request path: no TCP connection or TLS is set
+    /// up, so for a `wss://` URL the factory has to return an encrypted
+    /// stream. The stream is boxed as [`AnyStream::Custom`]; the other
+    /// connect paths are unaffected. [`WsClientBuilder::connect_over`] does
+    /// the same with the builder's options and a [`Transport`].
+    pub async fn connect_via_stream_factory<F, Fut, S>(
+        factory: F,
+        url: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Self, WsError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<S, WsError>>,
+        S: TokioIo + 'static,
+    {
+        let u = parse_ws_or_wss(url)?;
+        let mut info = ConnectionInfo::new(url);
+        let started = Instant::now();
+        let stream = factory().await?;
+        info.timings.tcp = started.elapsed();
+        Self::handshake(
+            AnyStream::Custom(Box::new(Opaque(stream))),
+            &u,
+            &[],
+            headers,
+            None,
+            DEFAULT_MAX_RESPONSE_HEADER,
+            &SystemClock,
+            info,
+            None,
+        )
+        .await
+    }
+
+    /// Resize the transport's write buffer to `bytes` (at least 1 KiB), e.g.
+    /// after finding that the application sends much larger frames than
+    /// [`WsClientBuilder::buffer_size`] was set for.
+    ///
+    /// The buffer lives in the `StreamWrapper` inside the `WebSocket`'s
+    /// stream. The client reaches it through its own [`SharedStream`]
+    /// handle, which borrows the transport only for this call, and replaces
+    /// the wrapper with one of the new size. The read buffer is replaced
+    /// along with it, so that is only safe while both are empty: if bytes
+    /// have been written but not flushed, or received bytes may be waiting
+    /// in the read buffer, this fails with [`WsError::PendingData`] and
+    /// nothing changes. Await [`flush`](Self::flush) first. With a
+    /// [`MemoryBudget`] the difference is charged first. Transports from
+    /// [`connect_via_stream_factory`](Self::connect_via_stream_factory) have
+    /// no such buffer and fail with `Unsupported`.
+    ///
+    /// ```
+    /// # use std::io::{Read, Write};
+    /// # use base64::Engine;
+    /// # use sha1::Digest;
+    /// use std::time::Duration;
+    /// use websockets_monoio::{Message, WsClientBuilder, WsError};
+    ///
+    /// # let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// # let addr = listener.local_addr().unwrap();
+    /// # std::thread::spawn(move || {
+    /// #     let (mut socket, _) = listener.accept().unwrap();
+    /// #     let mut request = Vec::new();
+    /// #     let mut byte = [0u8];
+    /// #     while !request.ends_with(b"\r\n\r\n") {
+    /// #         socket.read_exact(&mut byte).unwrap();
+    /// #         request.push(byte[0]);
+    /// #     }
+    /// #     let request = String::from_utf8(request).unwrap();
+    /// #     let key = request
+    /// #         .lines()
+    /// #         .filter_map(|line| line.split_once(':'))
+    /// #         .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+    /// #         .unwrap()
+    /// #         .1;
+    /// #     let mut sha1 = sha1::Sha1::new();
+    /// #     sha1.update(key.trim().as_bytes());
+    /// #     sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    /// #     let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+    /// #     write!(
+    /// #         socket,
+    /// #         "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+    /// #          Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    /// #     )
+    /// #     .unwrap();
+    /// #     while socket.read(&mut [0u8; 1024]).unwrap_or(0) > 0 {}
+    /// # });
+    /// # monoio::RuntimeBuilder::<monoio::LegacyDriver>::new().enable_timer().build().unwrap().block_on(async {
+    /// let mut client = WsClientBuilder::new()
+    ///     .connect(&format!("ws://{addr}/"))
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// // An unflushed send blocks resizing either buffer.
+    /// client.send(Message::Text("hello".into())).await.unwrap();
+    /// assert!(matches!(client.set_read_buffer_cap(64 * 1024), Err(WsError::PendingData)));
+    /// assert!(matches!(client.set_write_buffer_cap(64 * 1024), Err(WsError::PendingData)));
+    /// client.flush().await.unwrap();
+    /// client.set_write_buffer_cap(64 * 1024).unwrap();
+    /// client.set_read_buffer_cap(64 * 1024).unwrap();
+    ///
+    /// // So does a read left in progress by a cancelled `recv`.
+    /// let wait = monoio::time::timeout(Duration::from_millis(50), client.recv()).await;
+    /// assert!(wait.is_err());
+    /// assert!(matches!(client.set_write_buffer_cap(4096), Err(WsError::PendingData)));
+    /// assert!(matches!(client.set_read_buffer_cap(4096), Err(WsError::PendingData)));
+    /// # });
+    /// ```
+    pub fn set_write_buffer_cap(&mut self, bytes: usize) -> Result<(), WsError> {
+        let (read, _) = self.buffer_sizes;
+        self.resize_buffers(read, bytes.max(1024))
+    }
+
+    /// Resize the transport's read buffer to `bytes` (at least 1 KiB), like
+    /// [`set_write_buffer_cap`](Self::set_write_buffer_cap), and with the
+    /// same conditions.
+    ///
+    /// A read in progress (a `recv` was cancelled while waiting) also counts
+    /// as pending data, as does a last read that filled the receive buffer,
+    /// since the buffer may then hold more. Calling it again after the next
+    /// `recv` usually succeeds.
+    pub fn set_read_buffer_cap(&mut self, bytes: usize) -> Result<(), WsError> {
+        let (_, write) = self.buffer_sizes;
+        self.resize_buffers(bytes.max(1024), write)
+    }
+
+    /// Replace both transport buffers, which throws away whatever they hold,
+    /// so only while they are empty.
+    fn resize_buffers(&mut self, read: usize, write: usize) -> Result<(), WsError> {
+        let state = self.io.state();
+        if state.unflushed() > 0 || state.flushing() || state.read_pending() {
+            return Err(WsError::PendingData);
+        }
+        let old = self.buffer_sizes.0 + self.buffer_sizes.1;
+        let new = read + write;
+        if let Some(reservation) = &mut self.buffers {
+            reservation.grow(new.saturating_sub(old))?;
+        }
+        let result = self.io.with(|s| s.rebuffer(read, write));
+        // Give back what was charged, or what the smaller buffers free.
+        let refund = match result {
+            Ok(()) => old.saturating_sub(new),
+            Err(_) => new.saturating_sub(old),
+        };
+        if let Some(reservation) = &mut self.buffers {
+            reservation.shrink(refund);
+        }
+        result?;
+        self.buffer_sizes = (read, write);
+        Ok(())
+    }
+
+    /// Negotiated connection properties, fixed at connect time.
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// The server's `101` response: every header it sent, plus the
+    /// subprotocol and extensions it selected. Unlike [`info`](Self::info)
+    /// this may hold session cookies or tokens, so avoid logging it whole.
+    /// [`trailing`](UpgradeResponse::trailing) is always empty; those bytes
+    /// were the start of the WebSocket stream.
+    ///
+    /// ```
+    /// # use std::io::{Read, Write};
+    /// # use base64::Engine;
+    /// # use sha1::Digest;
+    /// use websockets_monoio::WsClientBuilder;
+    ///
+    /// # let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// # let addr = listener.local_addr().unwrap();
+    /// # std::thread::spawn(move || {
+    /// #     let (mut socket, _) = listener.accept().unwrap();
+    /// #     let mut request = Vec::new();
+    /// #     let mut byte = [0u8];
+    /// #     while !request.ends_with(b"\r\n\r\n") {
+    /// #         socket.read_exact(&mut byte).unwrap();
+    /// #         request.push(byte[0]);
+    /// #     }
+    /// #     let request = String::from_utf8(request).unwrap();
+    /// #     let key = request
+    /// #         .lines()
+    /// #         .filter_map(|line| line.split_once(':'))
+    /// #         .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+    /// #         .unwrap()
+    /// #         .1;
+    /// #     let mut sha1 = sha1::Sha1::new();
+    /// #     sha1.update(key.trim().as_bytes());
+    /// #     sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    /// #     let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+    /// #     write!(
+    /// #         socket,
+    /// #         "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+    /// #          Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\
+    /// #          Sec-WebSocket-Protocol: feed.v2\r\nSet-Cookie: session=abc\r\n\r\n"
+    /// #     )
+    /// #     .unwrap();
+    /// #     socket.read(&mut [0u8; 64]).ok();
+    /// # });
+    /// # monoio::RuntimeBuilder::<monoio::LegacyDriver>::new().enable_timer().build().unwrap().block_on(async {
+    /// let client = WsClientBuilder::new()
+    ///     .subprotocol("feed.v2")
+    ///     .connect(&format!("ws://{addr}/"))
+    ///     .await
+    ///     .unwrap();
+    /// let response = client.handshake_response();
+    /// assert_eq!(response.protocol.as_deref(), Some("feed.v2"));
+    /// assert_eq!(response.get("set-cookie"), Some("session=abc"));
+    /// assert_eq!(response.headers.len(), 5);
+    /// # });
+    /// ```
+    pub fn handshake_response(&self) -> &UpgradeResponse {
+        &self.handshake
+    }
+
+    /// The message accepted by
+    /// [`WsClientBuilder::await_first_message`] while connecting. It is not
+    /// returned by `recv`.
+    pub fn first_message(&self) -> Option<&Message> {
+        self.first_message.as_ref()
+    }
+
+    /// Wait for the first data message and run the builder's check on it.
+    async fn await_first(&mut self, first: &FirstMessage) -> Result<Message, WsError> {
+        let wait = async {
+            loop {
+                match self.recv().await? {
+                    Message::Close(close) => {
+                        return Err(WsError::ClosedByPeer(close.unwrap_or(CloseFrame {
+                            code: CloseFrame::NO_STATUS_RECEIVED,
+                            reason: String::new(),
+                        })));
+                    }
+                    message @ (Message::Text(_) | Message::Binary(_)) => return Ok(message),
+                    Message::Ping(_) | Message::Pong(_) => {}
+                }
+            }
+        };
+        let message = monoio::time::timeout(first.timeout, wait)
+            .await
+            .map_err(|_| WsError::FirstMessageTimeout(first.timeout))??;
+        match (first.validate)(&message, &self.context) {
+            Ok(()) => Ok(message),
+            Err(reason) => Err(WsError::FirstMessageRejected { reason, message }),
+        }
+    }
+
+    /// Establish the underlying transport (TCP or TLS over TCP).
+    async fn dial(
+        u: &WsUrl<'_>,
+        proxy: Option<&Proxy>,
+        connector: &TlsConnector,
+        hooks: &ConnectHooks,
+        info: &mut ConnectionInfo,
+        clock: &mut PhaseClock,
+    ) -> Result<AnyStream, WsError> {
+        let Some(proxy) = proxy else {
+            let started = Instant::now();
+            let tcp = hooks.connect_tcp(&u.host, u.port, Some(u), clock).await?;
+            record_tcp(info, &tcp, started);
+            return match u.scheme {
+                Scheme::Ws => Ok(AnyStream::Plain(hooks.wrap(tcp))),
+                Scheme::Wss => Self::start_tls(tcp, &u.host, connector, hooks, info, clock).await,
+            };
+        };
+
+        // Vetted before the proxy is dialed, so a denied target costs nothing.
+        let target = hooks.proxy_target(u, clock).await?;
+        // The tunnel counts towards the TCP phase; `tls` times the origin only.
+        let started = Instant::now();
+        let mut tcp = hooks
+            .connect_tcp(&proxy.host, proxy.port, None, clock)
+            .await?;
+        match proxy.scheme {
+            ProxyScheme::Http => {
+                let tunnel = http_connect(&mut tcp, &target, u.port, proxy.authorization());
+                clock.run(ConnectPhase::Connect, tunnel).await?;
+                record_tunnel(info, &tcp, started, &target, u.port);
+                match u.scheme {
+                    Scheme::Ws => Ok(AnyStream::Plain(hooks.wrap(tcp))),
+                    Scheme::Wss => {
+                        Self::start_tls(tcp, &u.host, connector, hooks, info, clock).await
+                    }
+                }
+            }
+            ProxyScheme::Https => {
+                record_tunnel(info, &tcp, started, &target, u.port);
+                let tunnel = clock
+                    .run(ConnectPhase::Connect, async {
+                        let (mut tunnel, _) = tls_handshake(tcp, &proxy.host, connector).await?;
+                        http_connect(&mut tunnel, &target, u.port, proxy.authorization()).await?;
+                        Ok::<_, WsError>(tunnel)
+                    })
+                    .await?;
+                info.timings.tcp = started.elapsed();
+                match u.scheme {
+                    Scheme::Ws => Ok(AnyStream::Tls(hooks.wrap(tunnel))),
+                    Scheme::Wss => {
+                        let started = Instant::now();
+                        let (tls, tls_info) = clock
+                            .run(ConnectPhase::Tls, tls_handshake(tunnel, &u.host, connector))
+                            .await?;
+                        info.timings.tls = Some(started.elapsed());
+                        if let Some(hook) = &hooks.tls {
+                            hook(&tls_info, &hooks.context);
+                        }
+                        info.tls = Some(tls_info);
+                        Ok(AnyStream::TlsOverTls(Box::new(hooks.wrap(tls))))
+                    }
+                }
+            }
+        }
+    }
+
+    async fn start_tls(
+        tcp: TcpStream,
+        server_name: &str,
+        connector: &TlsConnector,
+        hooks: &ConnectHooks,
+        info: &mut ConnectionInfo,
+        clock: &mut PhaseClock,
+    ) -> Result<AnyStream, WsError> {
+        let started = Instant::now();
+        let (tls, tls_info) = clock
+            .run(
+                ConnectPhase::Tls,
+                tls_handshake(tcp, server_name, connector),
+            )
+            .await?;
+        info.timings.tls = Some(started.elapsed());
+        if let Some(hook) = &hooks.tls {
+            hook(&tls_info, &hooks.context);
+        }
+        info.tls = Some(tls_info);
+        Ok(AnyStream::Tls(hooks.wrap(tls)))
+    }
+
+    /// Run the HTTP upgrade over an established transport and wrap it.
+    /// `events` hears about unusual header casing in the response.
+    #[allow(clippy::too_many_arguments)]
+    async fn handshake(
+        stream: AnyStream,
+        u: &WsUrl<'_>,
+        subprotocols: &[&str],
+        extra_headers: &[(&str, &str)],
+        custom_request: Option<&CustomRequest>,
+        max_header: usize,
+        clock: &dyn Clock,
+        mut info: ConnectionInfo,
+        events: Option<&EventSink>,
+    ) -> Result<Self, WsError> {
+        // Parsed URLs are valid; this catches a `WsUrl` built by hand.
+        u.validate()?;
+        let started = Instant::now();
+        info.handshake_at = clock.now();
+        info.peer_label = stream.peer_label();
+        // The request goes through the shared handle too, so it gets the
+        // same retries on transient write errors as frames do.
+        let io = SharedStream::new(stream);
+        let mut stream = io.clone();
+        let key = generate_client_key();
+        match custom_request {
+            Some(CustomRequest(build)) => {
+                use monoio_compat::AsyncWriteExt;
+
+                let request = build(u, &key.sec_websocket_key, info.handshake_at);
+                stream.write_all(&request).await?;
+                stream.flush().await?;
+            }
+            None => {
+                // Credentials from the URL, unless the caller set their own.
+                let authorization = u.basic_authorization().filter(|_| {
+                    !extra_headers
+                        .iter()
+                        .any(|(name, _)| name.eq_ignore_ascii_case("Authorization"))
+                });
+                let mut headers = extra_headers.to_vec();
+                if let Some(authorization) = &authorization {
+                    headers.push(("Authorization", authorization));
+                }
+                write_request(
+                    &mut stream,
+                    &u.host_header(),
+                    &u.path_and_query,
+                    &key.sec_websocket_key,
+                    subprotocols,
+                    &headers,
+                )
+                .await?;
+            }
+        }
+        // Offers made with `header` count too. A custom request offers what
+        // it likes, so the choice is not checked.
+        let mut offered = subprotocols.to_vec();
+        if !offered.is_empty() {
+            offered.extend(
+                extra_headers
+                    .iter()
+                    .filter(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Protocol"))
+                    .flat_map(|(_, value)| value.split(',').map(str::trim)),
+            );
+        }
+        if custom_request.is_some() {
+            offered.clear();
+        }
+        let mut response =
+            read_response_with_limit(&mut stream, &key, &offered, max_header).await?;
+        let unusual: Vec<String> = response.unusual_casing().map(str::to_owned).collect();
+        if let Some(events) = events
+            && !unusual.is_empty()
+        {
+            events.emit(&WsEvent::UnusualHeaderCasing { names: unusual });
+        }
+        info.timings.upgrade = started.elapsed();
+        info.protocol = response.protocol.clone();
+        info.extensions = response.extensions.clone();
+        let trailing = std::mem::take(&mut response.trailing);
+
+        // Switch to WebSocket
+        // Gathering only pays off when the transport writes the slices in
+        // one call; otherwise the header and payload would go out separately.
+        let writev = stream.is_write_vectored();
+        drop(stream);
+        // Frames that arrived with the response are the start of the stream.
+        let mut gate = GatedStream::with_buffered(io.clone(), trailing);
+        // Reserved opcodes fail the read in `fastwebsockets`; note which one
+        // for the error.
+        let reserved = Rc::new(ReservedOpcodes::default());
+        let rejected = reserved.clone();
+        gate.on_reserved_opcode(move |opcode| {
+            rejected.rejected.set(Some(opcode));
+            false
+        });
+        let ws = new_websocket(gate, Role::Client, writev, true, true);
+
+        Ok(Self {
+            ws,
+            io,
+            events: None,
+            budget: None,
+            buffers: None,
+            buffer_sizes: (STREAM_BUFFER_BYTES, STREAM_BUFFER_BYTES),
+            partial: None,
+            keepalive: None,
+            watchdog: None,
+            cancel: None,
+            info,
+            handshake: response,
+            usable: true,
+            stats: ConnectionStats::new(),
+            ping_reply: None,
+            on_pong: None,
+            reserved,
+            outbox: None,
+            role: Role::Client,
+            middleware: Middlewares::default(),
+            peer_close: None,
+            inbox: VecDeque::new(),
+            write_lock: Rc::default(),
+            pings: None,
+            fairness: FairnessState::default(),
+            processing: ProcessingBudget::default(),
+            pending_text: None,
+            ascii_text: false,
+            split: None,
+            prepared: Vec::new(),
+            writev,
+            mask: true,
+            interceptors: InterceptorChain::default(),
+            transforms: Transforms::default(),
+            transform_drops: 0,
+            context: ContextMap::new(),
+            first_message: None,
+            transfer: TransferOptions {
+                extra_headers: extra_headers
+                    .iter()
+                    .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect(),
+                ..TransferOptions::default()
+            },
+            generation: 0,
+            rtt: RttSamples::default(),
+            session_end: None,
+        })
+    }
+
+    /// Wait until everything written so far has left the transport's
+    /// buffers, including a heartbeat interrupted by a cancelled `recv`.
+    ///
+    /// Sends complete once the frame is in the transport's write buffer;
+    /// the transport pushes it out on its own, so this is only needed to
+    /// confirm that it did: before an operation that requires empty
+    /// buffers, such as [`set_write_buffer_cap`](Self::set_write_buffer_cap),
+    /// or to reset [`pending_write_bytes`](Self::pending_write_bytes) after
+    /// a batch of sends. It returns once the local socket has taken the
+    /// data, so against a peer that stops reading it waits, once the socket
+    /// buffers have filled up, until the peer resumes.
+    pub async fn flush(&mut self) -> Result<(), WsError> {
+        PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await?;
+        let lock = self.write_lock.clone();
+        let _write = lock.lock().await;
+        self.ws.flush().await?;
+        Ok(())
+    }
+
+    /// Bytes handed to the transport since the last completed flush:
+    /// frames from every send path, heartbeats and ping replies included.
+    ///
+    /// Sends do not flush, so this keeps growing until [`flush`](Self::flush)
+    /// confirms the bytes left. A sender that sends a batch and then flushes
+    /// can read it before the next batch as a cheap congestion signal, one
+    /// level above TCP: if the previous batch is still unconfirmed, conflate
+    /// instead of queueing more. The most it has been is reported as
+    /// [`ConnectionStatsSnapshot::pending_write_high_water`].
+    pub fn pending_write_bytes(&self) -> usize {
+        self.io.state().unflushed()
+    }
+
+    /// Whether bytes were written since the last completed flush.
+    pub fn needs_flush(&self) -> bool {
+        self.pending_write_bytes() > 0
+    }
+
+    /// Whether a flush was started and has not completed yet, e.g. a
+    /// [`flush`](Self::flush) that timed out while the peer was not reading.
+    pub fn is_flushing(&self) -> bool {
+        self.io.state().flushing()
+    }
+
+    /// Send a complete message as a single frame.
+    ///
+    /// Once the peer's Close has been received, including when a write fails
+    /// because the peer left right after sending it, sends fail with
+    /// [`WsError::ClosedByPeer`] carrying its code and reason.
+    pub async fn send(&mut self, message: Message) -> Result<(), WsError> {
+        if self.cancel_requested() {
+            return Err(self.cancelled().await);
+        }
+        self.check_peer_close()?;
+        let Some(message) = self.intercept(Direction::Outbound, message)? else {
+            return Ok(());
+        };
+        let frame = self.transform_outbound(message)?;
+        let len = frame.payload.len();
+        let lock = self.write_lock.clone();
+        let write = lock.lock().await;
+        if let Err(e) = self.ws.write_frame(frame).await {
+            drop(write);
+            return Err(self.write_failed(e.into()).await);
+        }
+        self.stats.record_sent(len);
+        Ok(())
+    }
+
+    /// The Close frame received from the peer, if any. A Close without a
+    /// status is reported with code [`CloseFrame::NO_STATUS_RECEIVED`].
+    pub fn peer_close(&self) -> Option<&CloseFrame> {
+        self.peer_close.as_ref()
+    }
+
+    fn check_peer_close(&self) -> Result<(), WsError> {
+        match &self.peer_close {
+            Some(close) => Err(WsError::ClosedByPeer(close.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Mark the connection unusable and report the peer's Close in place of
+    /// the transport error when there is one.
+    ///
+    /// A peer that closes while we are writing usually leaves its Close
+    /// unread, and the write then fails with a broken pipe or reset. In that
+    /// case whatever it sent before leaving is read to find the Close; data
+    /// messages read on the way are kept for [`recv`](Self::recv).
+    async fn write_failed(&mut self, e: WsError) -> WsError {
+        self.usable = false;
+        if self.peer_close.is_none() && peer_gone(&e) {
+            // Replies cannot be written any more and would fail the read.
+            self.ws.set_auto_close(false);
+            self.ws.set_auto_pong(false);
+            while let Ok(message) = self.recv_message().await {
+                let closed = matches!(message, Message::Close(_));
+                self.inbox.push_back(message);
+                if closed {
+                    break;
+                }
+            }
+            self.ws.set_auto_close(self.split.is_none());
+            self.ws.set_auto_pong(self.auto_pong());
+        }
+        let e = match &self.peer_close {
+            Some(close) => WsError::ClosedByPeer(close.clone()),
+            None => e,
+        };
+        self.end_session(Some(&e));
+        e
+    }
+
+    /// This connection's cancellation token, a child of the one given to
+    /// [`WsClientBuilder::cancellation`]: cancelling it stops only this
+    /// connection.
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancel.as_ref().map(|(token, _)| token)
+    }
+
+    fn cancel_requested(&self) -> bool {
+        self.cancellation_token()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// End the connection as its [`OnCancel`] says, the first time only, and
+    /// return [`WsError::Cancelled`].
+    pub(crate) async fn cancelled(&mut self) -> WsError {
+        let close = matches!(self.cancel, Some((_, OnCancel::Close)));
+        if self.usable && close && self.peer_close.is_none() {
+            let close = Message::Close(Some(CloseFrame {
+                code: CloseFrame::GOING_AWAY,
+                reason: String::new(),
+            }));
+            // Best effort: finish an interrupted heartbeat, then the Close.
+            let flushed =
+                PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await;
+            if flushed.is_ok() {
+                self.outbox = Some(PendingWrite::frame(close, self.masks()));
+                let _ = PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await;
+            }
+        }
+        if self.usable {
+            let _ = self.track::<()>(Err(WsError::Cancelled));
+        }
+        WsError::Cancelled
+    }
+
+    /// Whether the connection can still be used: `false` once a
+    /// [`send`](Self::send) or [`recv`](Self::recv) has failed or the peer's
+    /// Close has been received. Reads and writes made directly on
+    /// [`ws`](Self::ws) are not tracked.
+    pub fn is_usable(&self) -> bool {
+        self.usable
+    }
+
+    /// Frame and byte counters since the connection opened.
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
+    /// The current [`stats`](Self::stats) with per-second rates, average
+    /// frame size and uptime derived from them.
+    pub fn stats_snapshot(&self) -> ConnectionStatsSnapshot {
+        ConnectionStatsSnapshot {
+            pending_write_high_water: self.io.state().unflushed_high_water(),
+            ..self.stats.snapshot()
+        }
+    }
+
+    fn track<T>(&mut self, result: Result<T, WsError>) -> Result<T, WsError> {
+        if let Err(e) = &result {
+            self.usable = false;
+            if let Some(split) = &self.split {
+                split.failed();
+            }
+            self.end_session(Some(e));
+        }
+        result
+    }
+
+    /// The summary of this connection, once it has ended: after a send or
+    /// receive failed or the peer's Close was read. It is also delivered as
+    /// [`WsEvent::SessionEnded`], then or when the client is dropped.
+    pub fn session_summary(&self) -> Option<&SessionSummary> {
+        self.session_end.as_deref()
+    }
+
+    /// Set the session number reported in the summary.
+    pub(crate) fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    /// Build the session summary and emit it, the first time only.
+    fn end_session(&mut self, error: Option<&WsError>) {
+        if self.session_end.is_some() {
+            return;
+        }
+        let summary = Box::new(SessionSummary {
+            endpoint: redact_url(self.info.url()),
+            started_at: self.info.handshake_at,
+            duration: self.stats.connect_time.elapsed(),
+            generation: self.generation,
+            frames_sent: self.stats.frames_sent,
+            frames_received: self.stats.frames_received,
+            bytes_sent: self.stats.bytes_sent,
+            bytes_received: self.stats.bytes_received,
+            close: self.peer_close.clone(),
+            error_code: error.map(WsError::code),
+            rtt: self.rtt.summary(),
+        });
+        self.emit(&WsEvent::SessionEnded {
+            summary: summary.clone(),
+        });
+        self.session_end = Some(summary);
+    }
+
+    /// Receive the next complete message, reassembling fragmented data frames.
+    ///
+    /// Pings are answered automatically. When a
+    /// [`MemoryBudget`] is configured, reassembly buffers are charged against
+    /// it and a message that would exceed the budget fails with
+    /// [`WsError::MemoryBudget`]. With a [`Keepalive`] configured, heartbeats
+    /// are sent while waiting and [`WsError::KeepaliveTimeout`] is returned
+    /// when the peer stops proving liveness.
+    ///
+    /// The peer's Close is returned as [`Message::Close`]; receives after it
+    /// fail with [`WsError::ClosedByPeer`] once any buffered messages are read.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe: it can be raced against a timer or a
+    /// shutdown signal with `monoio::select!`, and the next call resumes
+    /// without losing or corrupting messages.
+    ///
+    /// - Reads pass through a [`GatedStream`], so a frame is only handed to
+    ///   `fastwebsockets` once it is fully buffered and a pending read is
+    ///   always at a frame boundary. Bytes already received stay in the gate.
+    /// - A read submitted to io_uring stays owned by the transport's
+    ///   `StreamWrapper`; its completion lands in that buffer and is returned
+    ///   by the next call rather than being lost.
+    /// - Fragments of a partially received message are kept on the client.
+    /// - A keepalive heartbeat or [custom ping reply] interrupted mid-write is
+    ///   finished by the next call before anything else is read.
+    ///
+    /// Two caveats: if the call is cancelled while the automatic reply to a
+    /// Ping or Close is waiting for the transport, that reply is dropped (never
+    /// half-written) together with the control frame that triggered it. Frames
+    /// declared larger than 64 MiB bypass the gate and are not cancel safe;
+    /// `fastwebsockets` rejects them by default anyway.
+    ///
+    /// `tests/cancel_safe_recv.rs` checks this on the legacy and io_uring
+    /// drivers, dropping thousands of receives mid-frame and mid-read.
+    ///
+    /// During a burst, `recv` periodically yields to the other tasks on the
+    /// thread; see [`WsClientBuilder::read_fairness`].
+    ///
+    /// [custom ping reply]: WsClientBuilder::on_ping_reply
+    pub async fn recv(&mut self) -> Result<Message, WsError> {
+        loop {
+            if self.cancel_requested() {
+                return Err(self.cancelled().await);
+            }
+            self.fairness.before_read().await;
+            let message = match self.inbox.pop_front() {
+                Some(message) => {
+                    self.fairness.record(false, message.payload().len());
+                    message
+                }
+                None => {
+                    self.check_peer_close()?;
+                    let mut awaited_io = false;
+                    let token = self.cancel.as_ref().map(|(token, _)| token.clone());
+                    let result = {
+                        let mut cancelled = token.as_ref().map(CancellationToken::cancelled);
+                        let mut read = pin!(self.recv_message());
+                        poll_fn(|cx| {
+                            if let Some(cancelled) = &mut cancelled
+                                && Pin::new(cancelled).poll(cx).is_ready()
+                            {
+                                return Poll::Ready(None);
+                            }
+                            let polled = read.as_mut().poll(cx);
+                            awaited_io |= polled.is_pending();
+                            polled.map(Some)
+                        })
+                        .await
+                    };
+                    let Some(result) = result else {
+                        return Err(self.cancelled().await);
+                    };
+                    let message = self.track(result)?;
+                    self.fairness.record(awaited_io, message.payload().len());
+                    message
+                }
+            };
+            if let Some(message) = self.intercept(Direction::Inbound, message)? {
+                self.observe(&message);
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Receive a message if one can be completed without waiting.
+    ///
+    /// Returns `Ok(None)` when the transport has no complete message right
+    /// now. Never parks the task: the receive path is polled once and
+    /// abandoned if it would block, which is safe because the stream is
+    /// gated at frame boundaries and partial fragmented messages are kept on
+    /// the client.
+    ///
+    /// A read that would block stays armed inside the transport, but monoio
+    /// only submits it and reaps its completion from the scheduler loop. A
+    /// spin loop must therefore run in a spawned task and yield between
+    /// attempts; while a task stays runnable, monoio polls the driver without
+    /// blocking every few rounds. Spinning on the future passed to
+    /// `block_on` (the `#[monoio::main]` body) never lets the driver run.
+    ///
+    /// ```no_run
+    /// use std::future::poll_fn;
+    /// use std::task::Poll;
+    ///
+    /// # fn run(mut client: websockets_monoio::WsClient) {
+    /// monoio::spawn(async move {
+    ///     loop {
+    ///         while let Some(message) = client.try_recv()? {
+    ///             println!("{message:?}");
+    ///         }
+    ///         // ... poll other sources (e.g. a shared-memory queue) ...
+    ///
+    ///         // Yield once so the runtime can drive I/O.
+    ///         let mut yielded = false;
+    ///         poll_fn(|cx| {
+    ///             if yielded {
+    ///                 return Poll::Ready(());
+    ///             }
+    ///             yielded = true;
+    ///             cx.waker().wake_by_ref();
+    ///             Poll::Pending
+    ///         })
+    ///         .await;
+    ///     }
+    ///     #[allow(unreachable_code)]
+    ///     Ok::<_, websockets_monoio::WsError>(())
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// monoio reschedules a self-woken task ahead of other tasks, so this loop
+    /// is meant to own its (pinned) thread.
+    pub fn try_recv(&mut self) -> Result<Option<Message>, WsError> {
+        loop {
+            let message = match self.inbox.pop_front() {
+                Some(message) => message,
+                None => {
+                    self.check_peer_close()?;
+                    let mut cx = Context::from_waker(Waker::noop());
+                    let polled = pin!(self.recv_message()).poll(&mut cx);
+                    match polled {
+                        Poll::Ready(Ok(message)) => message,
+                        Poll::Ready(Err(e)) => return self.track(Err(e)),
+                        Poll::Pending => return Ok(None),
+                    }
+                }
+            };
+            if let Some(message) = self.intercept(Direction::Inbound, message)? {
+                self.observe(&message);
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    /// Run the interceptors for `direction` on a data message; control
+    /// messages pass untouched.
+    fn intercept(
+        &self,
+        direction: Direction,
+        message: Message,
+    ) -> Result<Option<Message>, WsError> {
+        match message {
+            Message::Text(_) | Message::Binary(_) if !self.interceptors.is_empty() => {
+                self.interceptors.run(direction, message, &self.context)
+            }
+            message => Ok(Some(message)),
+        }
+    }
+
+    /// Counters and time spent for each interceptor registered with
+    /// [`WsClientBuilder::intercept_outbound`] and
+    /// [`intercept_inbound`](WsClientBuilder::intercept_inbound), in
+    /// registration order.
+    pub fn interceptor_stats(&self) -> Vec<InterceptorStats> {
+        self.interceptors.stats()
+    }
+
+    /// Inbound messages skipped because the
+    /// [inbound transform](WsClientBuilder::transform_inbound) failed on
+    /// them.
+    pub fn transform_drops(&self) -> u64 {
+        self.transform_drops
+    }
+
+    /// Frame `message`, through the outbound transform if it is a data
+    /// message and one is set.
+    fn transform_outbound(&self, message: Message) -> Result<Frame<'static>, WsError> {
+        let (Some(transform), Message::Text(_) | Message::Binary(_)) =
+            (&self.transforms.outbound, &message)
+        else {
+            return Ok(message.into_frame());
+        };
+        let opcode = message.opcode();
+        let payload = match message {
+            Message::Text(text) => text.into_bytes(),
+            Message::Binary(data) => data,
+            _ => unreachable!("checked above"),
+        };
+        let (opcode, payload) = transform(opcode, payload).map_err(|error| WsError::Transform {
+            direction: Direction::Outbound,
+            error,
+        })?;
+        Ok(Frame::new(true, data_opcode(opcode), None, payload.into()))
+    }
+
+    /// Run the inbound transform, if any, on a reassembled data payload.
+    /// `None` when it failed and the message is dropped.
+    async fn transform_inbound(
+        &mut self,
+        opcode: OpCode,
+        payload: Vec<u8>,
+    ) -> Result<Option<(OpCode, Vec<u8>)>, WsError> {
+        let Some((transform, on_error)) = &self.transforms.inbound else {
+            return Ok(Some((opcode, payload)));
+        };
+        let error = match transform(opcode, payload) {
+            Ok((opcode, payload)) => return Ok(Some((data_opcode(opcode), payload))),
+            Err(error) => error,
+        };
+        if *on_error == OnTransformError::Drop {
+            self.transform_drops += 1;
+            return Ok(None);
+        }
+        let close = CloseFrame {
+            code: CloseFrame::INVALID_PAYLOAD,
+            reason: "transform failed".into(),
+        };
+        self.outbox = Some(PendingWrite::frame(
+            Message::Close(Some(close)),
+            self.masks(),
+        ));
+        // Best effort: the connection has failed either way.
+        let _ = PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await;
+        Err(WsError::Transform {
+            direction: Direction::Inbound,
+            error,
+        })
+    }
+
+    /// The values set with [`WsClientBuilder::context`]; empty for
+    /// connections not made by a builder.
+    pub fn context(&self) -> &ContextMap {
+        &self.context
+    }
+
+    /// Build the message from a reassembled payload. A Text payload of at least
+    /// the [`ProcessingBudget`] threshold is validated by
+    /// [`validate_pending`](Self::validate_pending).
+    async fn complete(
+        &mut self,
+        opcode: OpCode,
+        payload: Vec<u8>,
+        reservation: Option<Reservation>,
+    ) -> Result<Message, WsError> {
+        let message = if opcode != OpCode::Text {
+            Message::Binary(payload)
+        } else if payload.len() < self.processing.threshold {
+            let started = Instant::now();
+            let checked = check_text(self.ascii_text, &payload, 0, payload.len());
+            self.stats.utf8_validation += started.elapsed();
+            if let Err(e) = checked {
+                return self.reject_text(e).await;
+            }
+            // SAFETY: check_text validated the whole payload as UTF-8.
+            Message::Text(unsafe { String::from_utf8_unchecked(payload) })
+        } else {
+            self.pending_text = Some(PendingText {
+                payload,
+                valid: 0,
+                _reservation: reservation,
+            });
+            return self.validate_pending().await;
+        };
+        self.stats.record_message();
+        Ok(message)
+    }
+
+    /// Validate the pending Text message a chunk at a time, yielding to other
+    /// tasks between chunks.
+    async fn validate_pending(&mut self) -> Result<Message, WsError> {
+        let chunk = self.processing.chunk.max(4);
+        loop {
+            let Some(pending) = &mut self.pending_text else {
+                unreachable!("validate_pending without a pending message");
+            };
+            let started = Instant::now();
+            let len = pending.payload.len();
+            let end = pending.valid.saturating_add(chunk).min(len);
+            let valid = check_text(self.ascii_text, &pending.payload, pending.valid, end);
+            self.stats.utf8_validation += started.elapsed();
+            match valid {
+                Ok(valid) if valid == len => break,
+                Ok(valid) => pending.valid = valid,
+                Err(e) => {
+                    self.pending_text = None;
+                    return self.reject_text(e).await;
+                }
+            }
+            if self.processing.drive_timers {
+                yield_to_timers().await;
+            } else {
+                yield_to_others().await;
+            }
+        }
+        let Some(pending) = self.pending_text.take() else {
+            unreachable!("validated a pending message");
+        };
+        // SAFETY: every byte of the payload was validated as UTF-8 above.
+        let text = unsafe { String::from_utf8_unchecked(pending.payload) };
+        self.stats.record_message();
+        Ok(Message::Text(text))
+    }
+
+    /// Fail `recv` with a Text validation error, first closing with 1008 if
+    /// the message broke [`WsClientBuilder::ascii_text`].
+    async fn reject_text(&mut self, error: WsError) -> Result<Message, WsError> {
+        if let WsError::NonAsciiText { .. } = error {
+            let close = CloseFrame {
+                code: CloseFrame::POLICY_VIOLATION,
+                reason: "text is not ASCII".into(),
+            };
+            self.outbox = Some(PendingWrite::frame(
+                Message::Close(Some(close)),
+                self.masks(),
+            ));
+            // Best effort: the connection has failed either way.
+            let _ = PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await;
+        }
+        Err(error)
+    }
+
+    fn observe(&mut self, message: &Message) {
+        for m in &self.middleware.0 {
+            m.before_deliver(message, &self.context);
+        }
+        if matches!(message, Message::Close(_)) {
+            self.usable = false;
+        }
+        if let Message::Pong(payload) = message {
+            if let Some(rtt) = self.pings.as_ref().and_then(|pings| pings.pong(payload)) {
+                self.rtt.record(rtt);
+            }
+            if let Some(sent) = self.keepalive.as_mut().and_then(|ka| ka.ping_sent.take()) {
+                self.rtt.record(sent.elapsed());
+            }
+            if let Some(hook) = &self.on_pong {
+                (hook.0)(payload, &self.context);
+            }
+        }
+        if let Some(ka) = &mut self.keepalive
+            && ka.config.is_alive(message)
+        {
+            ka.last_alive = Instant::now();
+        }
+    }
+
+    async fn recv_message(&mut self) -> Result<Message, WsError> {
+        if self.pending_text.is_some() {
+            return self.validate_pending().await;
+        }
+        loop {
+            let frame = match self.next_frame().await {
+                Ok(frame) => frame,
+                Err(e) => return Err(self.reject_reserved(e).await),
+            };
+            self.stats.record_received(frame.payload.len());
+            for m in &self.middleware.0 {
+                m.after_read(&frame, &self.context);
+            }
+            match frame.opcode {
+                OpCode::Text | OpCode::Binary => {
+                    if self.partial.is_some() {
+                        return Err(WebSocketError::InvalidFragment.into());
+                    }
+                    if frame.fin {
+                        let Some((opcode, payload)) = self
+                            .transform_inbound(frame.opcode, frame.payload.into())
+                            .await?
+                        else {
+                            continue;
+                        };
+                        return self.complete(opcode, payload, None).await;
+                    }
+                    let reservation = self.reserve(frame.payload.len())?;
+                    self.partial = Some(PartialMessage {
+                        opcode: frame.opcode,
+                        payload: frame.payload.into(),
+                        reservation,
+                    });
+                }
+                OpCode::Continuation => {
+                    let Some(mut partial) = self.partial.take() else {
+                        return Err(WebSocketError::InvalidContinuationFrame.into());
+                    };
+                    if let Some(reservation) = &mut partial.reservation {
+                        reservation
+                            .grow(frame.payload.len())
+                            .inspect_err(|e| emit_budget_event(&self.events, e))?;
+                    }
+                    partial.payload.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        let Some((opcode, payload)) = self
+                            .transform_inbound(partial.opcode, partial.payload)
+                            .await?
+                        else {
+                            continue;
+                        };
+                        return self.complete(opcode, payload, partial.reservation).await;
+                    }
+                    self.partial = Some(partial);
+                }
+                OpCode::Ping => {
+                    let reply = match (&self.ping_reply, &self.split) {
+                        (Some(reply), _) => (reply.0)(&frame.payload, &self.context),
+                        // Split, the automatic Pong is sent here, under the
+                        // write lock.
+                        (None, Some(_)) => Some(frame.payload.to_vec()),
+                        (None, None) => None,
+                    };
+                    if let Some(payload) = reply {
+                        if payload.len() > 125 {
+                            return Err(WsError::PingReplyTooLarge(payload.len()));
+                        }
+                        self.outbox =
+                            Some(PendingWrite::frame(Message::Pong(payload), self.masks()));
+                        PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock)
+                            .await?;
+                    }
+                    if self.ping_reply.is_none() && self.split.is_some() {
+                        continue;
+                    }
+                    return Ok(Message::Ping(frame.payload.into()));
+                }
+                OpCode::Pong => return Ok(Message::Pong(frame.payload.into())),
+                OpCode::Close => {
+                    let close = CloseFrame::parse(&frame.payload);
+                    let peer_close = close.clone().unwrap_or(CloseFrame {
+                        code: CloseFrame::NO_STATUS_RECEIVED,
+                        reason: String::new(),
+                    });
+                    self.peer_close = Some(peer_close.clone());
+                    self.end_session(None);
+                    // Split, the Close is answered here unless the writer
+                    // already sent one.
+                    if let Some(split) = &self.split
+                        && split.peer_closed(peer_close)
+                    {
+                        self.outbox = Some(PendingWrite::frame(
+                            Message::Close(close.clone()),
+                            self.masks(),
+                        ));
+                        PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock)
+                            .await?;
+                    }
+                    return Ok(Message::Close(close));
+                }
+            }
+        }
+    }
+
+    /// Turn `fastwebsockets` rejecting a reserved opcode into
+    /// [`WsError::ReservedOpcode`], closing the connection with 1002.
+    async fn reject_reserved(&mut self, error: WsError) -> WsError {
+        let (WsError::WebSocket(WebSocketError::InvalidValue), Some(opcode)) =
+            (&error, self.reserved.rejected.get())
+        else {
+            return error;
+        };
+        let close = CloseFrame {
+            code: CloseFrame::PROTOCOL_ERROR,
+            reason: "reserved opcode".into(),
+        };
+        self.outbox = Some(PendingWrite::frame(
+            Message::Close(Some(close)),
+            self.masks(),
+        ));
+        // Best effort: the connection has failed either way.
+        let _ = PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await;
+        WsError::ReservedOpcode(opcode)
+    }
+
+    /// Read the next frame, sending heartbeats and enforcing the liveness
+    /// deadline while waiting.
+    async fn next_frame(&mut self) -> Result<Frame<'static>, WsError> {
+        PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await?;
+        let mask = self.masks();
+        let Some(ka) = &mut self.keepalive else {
+            return Ok(self.ws.read_frame().await?);
+        };
+        loop {
+            let now = Instant::now();
+            let deadline = ka.last_alive + ka.config.timeout;
+            if now >= deadline {
+                return Err(WsError::KeepaliveTimeout {
+                    timeout: ka.config.timeout,
+                    local_stall: self
+                        .watchdog
+                        .as_ref()
+                        .and_then(|watchdog| watchdog.stall_since(ka.last_alive)),
+                });
+            }
+            if now >= ka.next_heartbeat {
+                ka.next_heartbeat = now + ka.config.interval;
+                let heartbeat = ka.config.next_heartbeat();
+                if matches!(heartbeat, Message::Ping(_)) {
+                    ka.ping_sent = Some(now);
+                }
+                self.outbox = Some(PendingWrite::frame(heartbeat, mask));
+            }
+            PendingWrite::flush(&mut self.outbox, &mut self.io, &self.write_lock).await?;
+
+            // Safe to abandon on timeout: the gated stream only waits at frame boundaries.
+            let wake = monoio::time::Instant::from_std(ka.next_heartbeat.min(deadline));
+            if let Ok(frame) = monoio::time::timeout_at(wake, self.ws.read_frame()).await {
+                return Ok(frame?);
+            }
+        }
+    }
+
+    /// Read until the peer closes, discarding data messages, and return its
+    /// Close frame.
+    ///
+    /// Control frames are handled as in [`recv`](Self::recv) and the Close
+    /// is answered automatically. A Close without a status is reported with
+    /// code [`CloseFrame::NO_STATUS_RECEIVED`].
+    pub async fn consume_until_close(&mut self) -> Result<CloseFrame, WsError> {
+        loop {
+            if let Message::Close(close) = self.recv().await? {
+                return Ok(close.unwrap_or(CloseFrame {
+                    code: CloseFrame::NO_STATUS_RECEIVED,
+                    reason: String::new(),
+                }));
+            }
+        }
+    }
+
+    /// [`consume_until_close`](Self::consume_until_close), failing with
+    /// [`WsError::Timeout`] if the peer has not closed within `timeout`.
+    pub async fn consume_until_close_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CloseFrame, WsError> {
+        monoio::time::timeout(timeout, self.consume_until_close())
+            .await
+            .map_err(|_| WsError::Timeout(TimedOut::after(timeout)))?
+    }
+
+    /// Write a pre-serialized frame as two slices without going through
+    /// `fastwebsockets`' frame serialization.
+    ///
+    /// **This bypasses every protocol safety check.** The caller is
+    /// responsible for a well-formed header (including the mask bit and key
+    /// for client frames) and for masking the payload. A malformed frame will
+    /// make the peer fail the connection.
+    ///
+    /// Both slices are submitted with a single vectored write when the
+    /// transport supports it, as a [`Transport`] implementing
+    /// `poll_write_vectored` can. `StreamWrapper`-based transports (TCP,
+    /// TLS, Unix) do not, so the slices are copied into their write buffer
+    /// and written from there.
+    #[cfg(feature = "raw-frames")]
+    pub async fn write_frame_vectored(
+        &mut self,
+        header: &[u8],
+        payload: &[u8],
+    ) -> Result<(), WsError> {
+        self.check_peer_close()?;
+        let lock = self.write_lock.clone();
+        let write = lock.lock().await;
+        if let Err(e) = write_all_vectored(&mut self.io, header, payload).await {
+            drop(write);
+            return Err(self.write_failed(e).await);
+        }
+        self.stats.record_sent(payload.len());
+        Ok(())
+    }
+
+    /// Send a single-frame message built around a buffer the caller owns.
+    ///
+    /// The payload is masked in place and written straight from the buffer,
+    /// skipping the copy `fastwebsockets` makes when it has to mask a
+    /// borrowed payload. The buffer is handed back for reuse; its contents
+    /// are the masked bytes, so treat it as scratch space.
+    ///
+    /// Text payloads are not checked for valid UTF-8, and control frames must
+    /// stay within 125 bytes. Outbound interceptors do not run.
+    pub async fn send_owned(
+        &mut self,
+        opcode: OpCode,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>, WsError> {
+        match self.write_owned(opcode, Payload::Owned(payload)).await? {
+            Payload::Owned(buf) => Ok(buf),
+            _ => unreachable!("masking keeps an owned payload owned"),
+        }
+    }
+
+    /// [`send_owned`](Self::send_owned) for a `Bytes` payload. The bytes are
+    /// masked in place when this is the only handle to them and copied
+    /// otherwise.
+    pub async fn send_owned_bytes(
+        &mut self,
+        opcode: OpCode,
+        payload: Bytes,
+    ) -> Result<BytesMut, WsError> {
+        let payload = payload
+            .try_into_mut()
+            .unwrap_or_else(|shared| BytesMut::from(&shared[..]));
+        match self.write_owned(opcode, Payload::Bytes(payload)).await? {
+            Payload::Bytes(buf) => Ok(buf),
+            _ => unreachable!("masking keeps an owned payload owned"),
+        }
+    }
+
+    /// Send a [`PreparedMessage`], masking a copy of its stored frame with a
+    /// fresh key instead of encoding it again.
+    ///
+    /// Outbound interceptors do not run. Send Close messages with
+    /// [`send`](Self::send), which also stops automatic replies afterwards.
+    pub async fn send_prepared(&mut self, message: &PreparedMessage) -> Result<(), WsError> {
+        if self.cancel_requested() {
+            return Err(self.cancelled().await);
+        }
+        self.check_peer_close()?;
+        let lock = self.write_lock.clone();
+        let write = lock.lock().await;
+        let result = if self.masks() {
+            message.masked_into(&mut self.prepared);
+            write_all_vectored(&mut self.io, &self.prepared, &[]).await
+        } else {
+            let (head, len) = message.unmasked_head();
+            write_all_vectored(&mut self.io, &head[..len], message.payload()).await
+        };
+        if let Err(e) = result {
+            drop(write);
+            return Err(self.write_failed(e).await);
+        }
+        self.stats.record_sent(message.payload().len());
+        Ok(())
+    }
+
+    async fn write_owned(
+        &mut self,
+        opcode: OpCode,
+        payload: Payload<'static>,
+    ) -> Result<Payload<'static>, WsError> {
+        if self.cancel_requested() {
+            return Err(self.cancelled().await);
+        }
+        self.check_peer_close()?;
+        let mut frame = Frame::new(true, opcode, None, payload);
+        if self.masks() {
+            frame.mask();
+        }
+        let mut head = [0u8; 14];
+        let len = frame.fmt_head(&mut head);
+        let lock = self.write_lock.clone();
+        let write = lock.lock().await;
+        if let Err(e) = write_all_vectored(&mut self.io, &head[..len], &frame.payload).await {
+            drop(write);
+            return Err(self.write_failed(e).await);
+        }
+        self.stats.record_sent(frame.payload.len());
+        Ok(frame.payload)
+    }
+
+    fn reserve(&self, bytes: usize) -> Result<Option<Reservation>, WsError> {
+        match &self.budget {
+            Some(budget) => budget
+                .try_reserve(bytes)
+                .inspect_err(|e| emit_budget_event(&self.events, e))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Wrap the client so that messages already received are conflated by
+    /// the key `key` extracts, delivering only the newest per key.
+    ///
+    /// ```no_run
+    /// # async fn run(client: websockets_monoio::WsClient) -> Result<(), websockets_monoio::WsError> {
+    /// use websockets_monoio::Message;
+    ///
+    /// // Keep only the latest update per instrument, e.g. `BTC-USD:...`.
+    /// let mut feed = client.conflate(|msg| match msg {
+    ///     Message::Text(text) => text.split_once(':').map(|(symbol, _)| symbol.to_owned()),
+    ///     _ => None,
+    /// });
+    /// let latest = feed.recv().await?;
+    /// # drop(latest);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn conflate<K: Eq + Hash>(
+        self,
+        key: impl Fn(&Message) -> Option<K> + 'static,
+    ) -> ConflatingClient<K> {
+        ConflatingClient::new(self, Conflator::new(key))
+    }
+
+    /// Send and receive values of `codec` as Binary messages. See the
+    /// [`codec`](crate::codec) module.
+    pub fn with_codec<C: Codec>(self, codec: C) -> CodecClient<C> {
+        CodecClient::new(self, codec)
+    }
+
+    /// Check the sequence numbers `seq` extracts, calling `recover` on a gap,
+    /// a duplicate or a reset. See the [`sequence`](crate::sequence) module.
+    ///
+    /// `recover` resolves to the sequence number the application has caught
+    /// up to, e.g. from a snapshot, or `None` to accept the next number as
+    /// is. It runs while [`SequencedClient::recv`] is awaited, alongside
+    /// reading further messages, which `policy` handles.
+    pub fn track_sequence<F>(
+        self,
+        policy: RecoveryPolicy,
+        seq: impl Fn(&Message) -> Option<u64> + 'static,
+        recover: impl Fn(SequenceGap) -> F + 'static,
+    ) -> SequencedClient
+    where
+        F: Future<Output = Result<Option<u64>, WsError>> + 'static,
+    {
+        SequencedClient::new(
+            self,
+            policy,
+            Box::new(seq),
+            Box::new(move |gap| Box::pin(recover(gap))),
+        )
+    }
+
+    /// Split the client into a [`WsReader`] that receives and a [`WsWriter`]
+    /// that sends from another task, sharing the connection's
+    /// [`ConnState`](crate::split::ConnState). See the
+    /// [`split`](crate::split) module.
+    pub fn split(mut self) -> (WsReader, WsWriter) {
+        let shared = Rc::new(SplitShared::default());
+        // Replies are written under the write lock, and the writer may have
+        // sent a Close already, which fastwebsockets would not know about.
+        self.ws.set_auto_close(false);
+        self.ws.set_auto_pong(false);
+        self.split = Some(shared.clone());
+        let writer = WsWriter::new(
+            self.io.clone(),
+            self.write_lock.clone(),
+            self.masks(),
+            shared.clone(),
+        );
+        (WsReader::new(self, shared), writer)
+    }
+
+    /// Deliver `event` to the sink set with [`WsClientBuilder::on_event`].
+    pub(crate) fn emit(&self, event: &WsEvent) {
+        if let Some(events) = &self.events {
+            events.emit(event);
+        }
+    }
+
+    /// Send a WS Ping every `interval` from a spawned task, whether or not
+    /// the application is inside [`recv`](Self::recv), and call `on_timeout`
+    /// when no Pong arrives within `2 * interval`.
+    ///
+    /// Unlike [`Keepalive`], which only runs inside `recv` and fails it, this
+    /// keeps pinging while the application is busy elsewhere and leaves the
+    /// reaction to the callback. Pongs are still read by `recv`, and
+    /// [`BackgroundPing::last_rtt`] reports the latest round trip. Writes from
+    /// the task and from the client are serialized frame by frame. Starting
+    /// another background ping makes the previous handle's RTT stale; stop it
+    /// first.
+    ///
+    /// Must be called inside a monoio runtime with the timer enabled.
+    pub fn background_ping(
+        &mut self,
+        interval: Duration,
+        on_timeout: Box<dyn Fn() + Send>,
+    ) -> BackgroundPing {
+        let ping = BackgroundPing::spawn(
+            &self.io,
+            self.write_lock.clone(),
+            self.masks(),
+            interval,
+            on_timeout,
+            self.cancellation_token().cloned(),
+        );
+        self.pings = Some(ping.shared());
+        ping
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Whether `fastwebsockets` answers Pings itself, rather than
+    /// [`recv`](Self::recv) with a custom reply or, split, under the write
+    /// lock.
+    fn auto_pong(&self) -> bool {
+        self.ping_reply.is_none() && self.split.is_none()
+    }
+
+    /// Whether outgoing frames are masked: as a client, unless
+    /// [`WsClientBuilder::unmasked_client_frames`] is set.
+    fn masks(&self) -> bool {
+        self.role == Role::Client && self.mask
+    }
+
+    /// Switch the endpoint role for the rest of the session, e.g. when a
+    /// bridge starts as a client and then relays as the server side.
+    ///
+    /// `fastwebsockets` fixes the role at construction, so [`ws`](Self::ws)
+    /// is rebuilt over the same transport. No received data is lost, but
+    /// settings changed directly on `ws` are reset to the client's defaults.
+    ///
+    /// Switching from `Client` to `Server` makes every following frame,
+    /// including heartbeats, ping replies and `send_owned*`, go out
+    /// **unmasked**, and incoming frames are expected masked. That is only
+    /// correct if the peer now expects server frames; a standard server
+    /// closes the connection on an unmasked client frame.
+    pub fn set_role(&mut self, role: Role) {
+        if role == self.role {
+            return;
+        }
+        self.rebuild_ws(role, |_| {});
+        self.role = role;
+    }
+
+    /// Frames with a reserved opcode dropped so far, with
+    /// [`WsClientBuilder::skip_reserved_opcodes`] set.
+    pub fn reserved_frames_skipped(&self) -> u64 {
+        self.reserved.skipped.get()
+    }
+
+    fn skip_reserved_opcodes(&mut self) {
+        let reserved = self.reserved.clone();
+        let events = self.events.clone();
+        self.rebuild_ws(self.role, |gate| {
+            gate.on_reserved_opcode(move |opcode| {
+                reserved.skipped.set(reserved.skipped.get() + 1);
+                if let Some(events) = &events {
+                    events.emit(&WsEvent::ReservedOpcodeSkipped { opcode });
+                }
+                true
+            })
+        });
+    }
+
+    /// Rebuild [`ws`](Self::ws) as `role` over the same gate, after `f` has
+    /// adjusted the gate. Only safe at a frame boundary, which the gate
+    /// guarantees between reads.
+    fn rebuild_ws(&mut self, role: Role, f: impl FnOnce(&mut WsStream)) {
+        let placeholder = WebSocket::after_handshake(GatedStream::new(self.io.clone()), role);
+        let mut gate = std::mem::replace(&mut self.ws, placeholder).into_inner();
+        f(&mut gate);
+        self.ws = new_websocket(gate, role, self.writev, self.auto_pong(), self.mask);
+        if self.split.is_some() {
+            self.ws.set_auto_close(false);
+        }
+    }
+
+    /// The underlying `fastwebsockets` socket. The connection carries on
+    /// there, so no [`WsEvent::SessionEnded`] is emitted for it.
+    pub fn into_inner(mut self) -> WebSocket<WsStream> {
+        // Marked unusable so dropping the rest does not end the session.
+        self.usable = false;
+        let placeholder = WebSocket::after_handshake(GatedStream::new(self.io.clone()), self.role);
+        std::mem::replace(&mut self.ws, placeholder)
+    }
+}
+
+/// Check `payload[start..end]` as Text, returning the offset it is valid up
+/// to. A char cut by `end` is left for the next chunk when more follows.
+fn check_text(
+    ascii_only: bool,
+    payload: &[u8],
+    start: usize,
+    end: usize,
+) -> Result<usize, WsError> {
+    let bytes = &payload[start..end];
+    if ascii_only {
+        return match text::ascii_prefix(bytes) {
+            ascii if ascii == bytes.len() => Ok(end),
+            ascii => Err(WsError::NonAsciiText {
+                offset: start + ascii,
+            }),
+        };
+    }
+    match text::validate(bytes) {
+        Ok(()) => Ok(end),
+        Err(e) if e.error_len.is_none() && end < payload.len() => Ok(start + e.valid_up_to),
+        Err(_) => Err(WebSocketError::InvalidUTF8.into()),
+    }
+}
+
+/// Text stays Text; any other opcode a transform returns is a Binary message.
+fn data_opcode(opcode: OpCode) -> OpCode {
+    match opcode {
+        OpCode::Text => OpCode::Text,
+        _ => OpCode::Binary,
+    }
+}
+
+fn new_websocket(
+    gate: WsStream,
+    role: Role,
+    writev: bool,
+    auto_pong: bool,
+    mask: bool,
+) -> WebSocket<WsStream> {
+    let mut ws = WebSocket::after_handshake(gate, role);
+    if role == Role::Client {
+        // As a client this only controls masking of written frames.
+        ws.set_auto_apply_mask(mask);
+    }
+    ws.set_auto_close(true);
+    ws.set_auto_pong(auto_pong);
+    ws.set_writev(writev);
+    ws
+}
+
+/// Whether an I/O error only means "not now": per the `AsyncWrite` contract
+/// nothing was written, so the same call can simply be made again.
+fn is_transient(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock)
+}
+
+/// Poll `op` until it gives something other than a transient error.
+///
+/// `Interrupted` is retried at once. `WouldBlock` means the stream did not
+/// register a wakeup, so the task polls again after the other runnable
+/// tasks instead of spinning.
+fn retry_transient<T>(
+    cx: &mut Context<'_>,
+    mut op: impl FnMut(&mut Context<'_>) -> Poll<std::io::Result<T>>,
+) -> Poll<std::io::Result<T>> {
+    loop {
+        match op(cx) {
+            Poll::Ready(Err(e)) if is_transient(&e) => {
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    wake_after_others(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Whether a write failed because the peer is gone (or, for
+/// `ConnectionClosed`, because its Close was already answered).
+fn peer_gone(e: &WsError) -> bool {
+    use std::io::ErrorKind;
+
+    let io = match e {
+        WsError::Io(e) => e,
+        WsError::WebSocket(WebSocketError::IoError(e)) => e,
+        WsError::WebSocket(WebSocketError::ConnectionClosed) => return true,
+        _ => return false,
+    };
+    matches!(
+        io.kind(),
+        ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+    )
+}
+
+/// Write `header` followed by `payload`, gathering them when the transport
+/// supports vectored writes.
+async fn write_all_vectored(
+    io: &mut SharedStream<AnyStream>,
+    header: &[u8],
+    payload: &[u8],
+) -> Result<(), WsError> {
+    use monoio_compat::AsyncWriteExt;
+    use std::io::IoSlice;
+
+    let total = header.len() + payload.len();
+    let mut written = 0;
+    while written < total {
+        let n = if written < header.len() {
+            let slices = [IoSlice::new(&header[written..]), IoSlice::new(payload)];
+            io.write_vectored(&slices).await?
+        } else {
+            io.write(&payload[written - header.len()..]).await?
+        };
+        if n == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+        }
+        written += n;
+    }
+    Ok(())
+}
+
+impl Drop for WsClient {
+    /// Emits the session summary unless an error or the peer's Close
+    /// already did.
+    fn drop(&mut self) {
+        if self.usable {
+            self.end_session(None);
+        }
+    }
+}
+
+fn record_tcp(info: &mut ConnectionInfo, tcp: &TcpStream, started: Instant) {
+    info.timings.tcp = started.elapsed();
+    info.peer_addr = tcp.peer_addr().ok();
+    info.local_addr = tcp.local_addr().ok();
+}
+
+/// Like [`record_tcp`] for a connection to a proxy, whose tunnel leads to
+/// `target`: the peer is the target if it is an address.
+fn record_tunnel(
+    info: &mut ConnectionInfo,
+    tcp: &TcpStream,
+    started: Instant,
+    target: &str,
+    port: u16,
+) {
+    record_tcp(info, tcp, started);
+    info.proxy_addr = info.peer_addr;
+    info.peer_addr = target
+        .parse::<IpAddr>()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, port));
+}
+
+fn emit_accept_event(events: &Option<EventSink>, err: &WsError) {
+    if let (Some(events), WsError::Upgrade(UpgradeErr::Accept(error))) = (events, err) {
+        events.emit(&WsEvent::AcceptRejected {
+            error: error.clone(),
+        });
+    }
+}
+
+fn emit_budget_event(events: &Option<EventSink>, err: &WsError) {
+    if let (
+        Some(events),
+        WsError::MemoryBudget {
+            requested,
+            used,
+            limit,
+        },
+    ) = (events, err)
+    {
+        events.emit(&WsEvent::MemoryBudgetExceeded {
+            requested: *requested,
+            used: *used,
+            limit: *limit,
+        });
+    }
+}
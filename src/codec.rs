@@ -0,0 +1,243 @@
+//! Typed messages over binary frames.
+//!
+//! A [`Codec`] turns application values into binary payloads and back.
+//! [`WsClient::with_codec`] wraps a client in a [`CodecClient`] that sends
+//! and receives those values. With the `cbor` feature, [`CborCodec`] encodes
+//! any `serde` type as CBOR.
+//!
+//! Platforms that version their schema with a magic prefix can set it with
+//! [`CodecClient::prefix`]: it is put in front of every outbound payload, and
+//! inbound payloads that do not start with it are rejected with
+//! [`CodecError::SchemaRejected`] before decoding, without failing the
+//! connection. [`CodecClient::schema_guard`] replaces that check, e.g. to
+//! also accept the previous version.
+//!
+//! ```no_run
+//! # #[cfg(feature = "cbor")]
+//! # async fn run(client: websockets_monoio::WsClient) -> Result<(), websockets_monoio::codec::CodecError> {
+//! use websockets_monoio::codec::{CborCodec, CodecError};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Quote {
+//!     symbol: String,
+//!     bid: f64,
+//! }
+//!
+//! // Version 2 of the schema; still accept version 1 payloads.
+//! let mut client = client
+//!     .with_codec(CborCodec::<Quote>::new())
+//!     .prefix([0xC2])
+//!     .schema_guard(|payload| matches!(payload.first(), Some(0xC1 | 0xC2)));
+//! client.send(&Quote { symbol: "BTC-USD".into(), bid: 64_000.0 }).await?;
+//! loop {
+//!     match client.recv().await {
+//!         Ok(quote) => println!("{} {}", quote.symbol, quote.bid),
+//!         Err(CodecError::SchemaRejected { found }) => eprintln!("skipped schema {found:x?}"),
+//!         Err(e) => return Err(e),
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! [`WsClient::with_codec`]: crate::WsClient::with_codec
+
+use std::fmt;
+
+use crate::{CloseFrame, Message, WsClient, WsError};
+
+/// Encodes values of type [`Item`](Self::Item) as binary payloads and
+/// decodes them back.
+pub trait Codec {
+    type Item;
+
+    /// Append the encoding of `item` to `out`.
+    fn encode(&self, item: &Self::Item, out: &mut Vec<u8>) -> Result<(), CodecError>;
+
+    fn decode(&self, payload: &[u8]) -> Result<Self::Item, CodecError>;
+}
+
+/// Error returned by a [`CodecClient`] or a [`Codec`].
+#[derive(thiserror::Error, Debug)]
+pub enum CodecError {
+    #[error("encoding failed: {0}")]
+    Encode(String),
+    #[error("decoding failed: {0}")]
+    Decode(String),
+    /// The schema guard rejected an inbound payload; `found` holds its
+    /// first bytes, as many as the prefix has (at least one). The
+    /// connection stays usable.
+    #[error("payload rejected by schema guard, found prefix {found:02x?}")]
+    SchemaRejected { found: Vec<u8> },
+    /// A Text message arrived where binary payloads were expected.
+    #[error("expected a binary message, got text")]
+    UnexpectedText,
+    #[error("{0}")]
+    Ws(#[from] WsError),
+}
+
+/// Decides whether an inbound binary payload, prefix included, is decoded.
+type GuardFn = dyn Fn(&[u8]) -> bool;
+
+/// A [`WsClient`] that sends and receives values of a [`Codec`], created
+/// with [`WsClient::with_codec`].
+///
+/// Values go out as Binary messages. [`recv`](Self::recv) skips Ping and
+/// Pong (which are still answered), and returns the peer's Close as
+/// [`WsError::ClosedByPeer`].
+pub struct CodecClient<C> {
+    client: WsClient,
+    codec: C,
+    prefix: Vec<u8>,
+    guard: Option<Box<GuardFn>>,
+    schema_rejections: u64,
+}
+
+impl<C: Codec> CodecClient<C> {
+    pub(crate) fn new(client: WsClient, codec: C) -> Self {
+        Self {
+            client,
+            codec,
+            prefix: Vec::new(),
+            guard: None,
+            schema_rejections: 0,
+        }
+    }
+
+    /// Put `prefix` in front of every outbound payload and strip as many
+    /// bytes from every inbound one before decoding. Unless a
+    /// [`schema_guard`](Self::schema_guard) is set, inbound payloads must
+    /// start with `prefix`.
+    pub fn prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Check every inbound binary payload, prefix included, before it is
+    /// decoded. Payloads it returns `false` for fail [`recv`](Self::recv)
+    /// with [`CodecError::SchemaRejected`] and are counted in
+    /// [`schema_rejections`](Self::schema_rejections); the connection stays
+    /// open.
+    pub fn schema_guard(mut self, guard: impl Fn(&[u8]) -> bool + 'static) -> Self {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Encode `item` and send it as one Binary message.
+    pub async fn send(&mut self, item: &C::Item) -> Result<(), CodecError> {
+        let mut payload = self.prefix.clone();
+        self.codec.encode(item, &mut payload)?;
+        self.client.send(Message::Binary(payload)).await?;
+        Ok(())
+    }
+
+    /// Receive and decode the next binary message.
+    ///
+    /// A rejected or undecodable payload fails this call only; call it
+    /// again for the next message.
+    pub async fn recv(&mut self) -> Result<C::Item, CodecError> {
+        loop {
+            let payload = match self.client.recv().await? {
+                Message::Binary(payload) => payload,
+                Message::Text(_) => return Err(CodecError::UnexpectedText),
+                Message::Ping(_) | Message::Pong(_) => continue,
+                Message::Close(close) => {
+                    return Err(WsError::ClosedByPeer(close.unwrap_or(CloseFrame {
+                        code: CloseFrame::NO_STATUS_RECEIVED,
+                        reason: String::new(),
+                    }))
+                    .into());
+                }
+            };
+            let accepted = match &self.guard {
+                Some(guard) => guard(&payload),
+                None => payload.starts_with(&self.prefix),
+            };
+            if !accepted || payload.len() < self.prefix.len() {
+                self.schema_rejections += 1;
+                let found = payload[..payload.len().min(self.prefix.len().max(1))].to_vec();
+                return Err(CodecError::SchemaRejected { found });
+            }
+            return self.codec.decode(&payload[self.prefix.len()..]);
+        }
+    }
+
+    /// Inbound payloads rejected by the schema guard.
+    pub fn schema_rejections(&self) -> u64 {
+        self.schema_rejections
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn client(&self) -> &WsClient {
+        &self.client
+    }
+
+    /// The underlying client. Messages sent or read on it directly bypass
+    /// the codec and the schema guard.
+    pub fn client_mut(&mut self) -> &mut WsClient {
+        &mut self.client
+    }
+
+    pub fn into_inner(self) -> WsClient {
+        self.client
+    }
+}
+
+impl<C> fmt::Debug for CodecClient<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CodecClient")
+            .field("prefix", &self.prefix)
+            .field("schema_guard", &self.guard.is_some())
+            .field("schema_rejections", &self.schema_rejections)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A [`Codec`] for any `serde` type, encoded as CBOR with `ciborium`.
+///
+/// ```
+/// use websockets_monoio::codec::{CborCodec, Codec};
+///
+/// let codec = CborCodec::<(String, u32)>::new();
+/// let mut payload = Vec::new();
+/// codec.encode(&("BTC-USD".to_owned(), 7), &mut payload).unwrap();
+/// assert_eq!(codec.decode(&payload).unwrap(), ("BTC-USD".to_owned(), 7));
+/// ```
+#[cfg(feature = "cbor")]
+pub struct CborCodec<T>(std::marker::PhantomData<fn() -> T>);
+
+#[cfg(feature = "cbor")]
+impl<T> CborCodec<T> {
+    pub fn new() -> Self {
+        Self(std::marker::PhantomData)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T> Default for CborCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T> fmt::Debug for CborCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CborCodec")
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec for CborCodec<T> {
+    type Item = T;
+
+    fn encode(&self, item: &T, out: &mut Vec<u8>) -> Result<(), CodecError> {
+        ciborium::into_writer(item, out).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        ciborium::from_reader(payload).map_err(|e| CodecError::Decode(e.to_string()))
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+
+use crate::{Message, WsClient, WsError};
+
+/// Extracts the conflation key of a message; `None` never conflates.
+type KeyFn<K> = dyn Fn(&Message) -> Option<K>;
+
+/// A queue that keeps only the newest undelivered message per key.
+///
+/// Pushing a message whose key matches one still queued replaces that
+/// message in place, so it is delivered at the position of the first update
+/// with the content of the latest. Messages without a key are never
+/// conflated.
+pub struct Conflator<K> {
+    key: Box<KeyFn<K>>,
+    queue: VecDeque<Message>,
+    /// Sequence number of the message at the front of `queue`.
+    head_seq: u64,
+    /// Sequence number of the queued message for each key.
+    slots: HashMap<K, u64>,
+    superseded: u64,
+}
+
+impl<K: Eq + Hash> Conflator<K> {
+    pub fn new(key: impl Fn(&Message) -> Option<K> + 'static) -> Self {
+        Self {
+            key: Box::new(key),
+            queue: VecDeque::new(),
+            head_seq: 0,
+            slots: HashMap::new(),
+            superseded: 0,
+        }
+    }
+
+    pub fn push(&mut self, message: Message) {
+        let Some(key) = (self.key)(&message) else {
+            self.queue.push_back(message);
+            return;
+        };
+        if let Some(&seq) = self.slots.get(&key) {
+            self.queue[(seq - self.head_seq) as usize] = message;
+            self.superseded += 1;
+            return;
+        }
+        self.slots
+            .insert(key, self.head_seq + self.queue.len() as u64);
+        self.queue.push_back(message);
+    }
+
+    pub fn pop(&mut self) -> Option<Message> {
+        let message = self.queue.pop_front()?;
+        let seq = self.head_seq;
+        self.head_seq += 1;
+        if let Some(key) = (self.key)(&message)
+            && self.slots.get(&key) == Some(&seq)
+        {
+            self.slots.remove(&key);
+        }
+        Some(message)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Messages replaced by a newer one with the same key before delivery.
+    pub fn superseded(&self) -> u64 {
+        self.superseded
+    }
+}
+
+impl<K> fmt::Debug for Conflator<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conflator")
+            .field("queued", &self.queue.len())
+            .field("superseded", &self.superseded)
+            .finish()
+    }
+}
+
+/// A [`WsClient`] whose [`recv`](Self::recv) conflates the messages that have
+/// already arrived, created with [`WsClient::conflate`].
+///
+/// Each `recv` first drains every message that can be read without waiting
+/// into a [`Conflator`], then returns the oldest entry. A consumer that falls
+/// behind therefore skips intermediate updates for a key instead of working
+/// through them. Only data already received by the runtime is drained; with
+/// io_uring that is what the last completed read brought in.
+pub struct ConflatingClient<K> {
+    client: WsClient,
+    conflator: Conflator<K>,
+    /// Error hit while draining, returned once the queue is empty.
+    error: Option<WsError>,
+}
+
+impl<K: Eq + Hash> ConflatingClient<K> {
+    pub(crate) fn new(client: WsClient, conflator: Conflator<K>) -> Self {
+        Self {
+            client,
+            conflator,
+            error: None,
+        }
+    }
+
+    /// Receive the next message, after conflating everything already received.
+    ///
+    /// Cancel safe: messages drained before the cancellation stay queued.
+    pub async fn recv(&mut self) -> Result<Message, WsError> {
+        loop {
+            while self.error.is_none() {
+                match self.client.try_recv() {
+                    Ok(Some(message)) => self.conflator.push(message),
+                    Ok(None) => break,
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            if let Some(message) = self.conflator.pop() {
+                return Ok(message);
+            }
+            if let Some(e) = self.error.take() {
+                return Err(e);
+            }
+            let message = self.client.recv().await?;
+            self.conflator.push(message);
+        }
+    }
+
+    /// Messages dropped because a newer one with the same key arrived first.
+    pub fn superseded(&self) -> u64 {
+        self.conflator.superseded()
+    }
+
+    /// Messages received but not yet returned by `recv`.
+    pub fn queued(&self) -> usize {
+        self.conflator.len()
+    }
+
+    pub fn client(&self) -> &WsClient {
+        &self.client
+    }
+
+    /// The underlying client, e.g. for sending. Messages read from it directly
+    /// bypass conflation.
+    pub fn client_mut(&mut self) -> &mut WsClient {
+        &mut self.client
+    }
+
+    /// Return the client, discarding any queued messages.
+    pub fn into_inner(self) -> WsClient {
+        self.client
+    }
+}
+
+impl<K> fmt::Debug for ConflatingClient<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConflatingClient")
+            .field("conflator", &self.conflator)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,90 @@
+//! Values identifying a connection, handed to its callbacks.
+//!
+//! Set them once with [`WsClientBuilder::context`](crate::WsClientBuilder::context)
+//! instead of capturing clones into every closure: event sinks, hooks,
+//! interceptors and middleware all receive the connection's [`ContextMap`].
+//!
+//! ```
+//! use websockets_monoio::{WsClientBuilder, WsEvent};
+//!
+//! let builder = WsClientBuilder::new()
+//!     .context("tenant", "acme".to_string())
+//!     .context("conn_id", 42u64)
+//!     .on_event(|event: &WsEvent, ctx| {
+//!         let tenant = ctx.get::<String>("tenant").map_or("-", |t| t.as_str());
+//!         eprintln!("[{tenant}] {event:?} {ctx:?}");
+//!     });
+//! # drop(builder);
+//! ```
+
+use std::any::Any;
+use std::fmt;
+use std::rc::Rc;
+
+/// A value that can be looked up by type and printed.
+trait Value: Any + fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + fmt::Debug> Value for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A small map from static keys to values of any `Debug` type. Cheap to
+/// clone: clones share the entries until one of them is changed.
+///
+/// Its `Debug` output lists every entry, so it can go straight into a log
+/// line.
+#[derive(Clone, Default)]
+pub struct ContextMap(Rc<Vec<(&'static str, Rc<dyn Value>)>>);
+
+impl ContextMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `key` to `value`, replacing any earlier value.
+    pub fn insert(&mut self, key: &'static str, value: impl Any + fmt::Debug) {
+        let entries = Rc::make_mut(&mut self.0);
+        let value: Rc<dyn Value> = Rc::new(value);
+        match entries.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => entries.push((key, value)),
+        }
+    }
+
+    /// The value under `key`, or `None` if there is none or it is not a `T`.
+    pub fn get<T: Any>(&self, key: &str) -> Option<&T> {
+        self.0
+            .iter()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v.as_ref().as_any().downcast_ref())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| *k == key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The entries in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &dyn fmt::Debug)> {
+        self.0
+            .iter()
+            .map(|(k, v)| (*k, v.as_ref() as &dyn fmt::Debug))
+    }
+}
+
+impl fmt::Debug for ContextMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
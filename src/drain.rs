@@ -0,0 +1,200 @@
+//! Closing many connections at a controlled pace, e.g. on process shutdown.
+//!
+//! Closing thousands of connections in the same instant can trip a
+//! gateway's abuse protection. [`drain`] closes them with 1001 (going away)
+//! at a fixed rate instead, most idle first, and aborts whatever is left at
+//! the deadline.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use websockets_monoio::WsClient;
+//! use websockets_monoio::drain::drain;
+//! use websockets_monoio::time::Deadline;
+//!
+//! # async fn run(clients: Vec<WsClient>) {
+//! // 200 closes a second, and everything gone within 30 seconds.
+//! let summary = drain(clients, 200.0, Deadline::after(Duration::from_secs(30))).await;
+//! println!(
+//!     "{} closed, {} aborted, {} already dead",
+//!     summary.closed, summary.forced, summary.dead
+//! );
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::time::Deadline;
+use crate::{CloseFrame, Message, WsClient, WsError, WsEvent};
+
+/// How [`drain`] ended one connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DrainOutcome {
+    /// The Close handshake completed.
+    Closed,
+    /// The deadline came, or the token of [`drain_cancellable`] was
+    /// cancelled, before the connection's turn or before the peer answered
+    /// its Close; it was dropped without waiting.
+    Forced,
+    /// The connection was already unusable, or failed while closing.
+    Dead,
+}
+
+/// What [`drain`] did, by [`DrainOutcome`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainSummary {
+    pub closed: usize,
+    pub forced: usize,
+    pub dead: usize,
+    /// Time from the start of the drain until the last connection ended.
+    pub elapsed: Duration,
+}
+
+impl DrainSummary {
+    pub fn total(&self) -> usize {
+        self.closed + self.forced + self.dead
+    }
+
+    fn record(&mut self, outcome: DrainOutcome) {
+        match outcome {
+            DrainOutcome::Closed => self.closed += 1,
+            DrainOutcome::Forced => self.forced += 1,
+            DrainOutcome::Dead => self.dead += 1,
+        }
+    }
+}
+
+/// Close `clients` gracefully, starting at most `rate` closes per second,
+/// and abort those still open at `deadline`.
+///
+/// The connections that have been [idle](crate::ConnectionStats::idle_time)
+/// longest go first. Each one is sent a Close with 1001 (going away) and
+/// its remaining messages are discarded until the peer's Close arrives.
+/// Closes run concurrently, so a slow peer does not hold up the pace.
+/// Unusable connections are dropped right away without taking a turn. A
+/// rate of zero closes nothing gracefully: everything is aborted at the
+/// deadline.
+///
+/// Every connection is reported to its builder's
+/// [`on_event`](crate::WsClientBuilder::on_event) sink as
+/// [`WsEvent::ConnectionDrained`] when it ends, so progress can be followed
+/// per connection. Returns once all connections have ended, by the
+/// deadline. Uses `monoio::time`, so the runtime needs the timer enabled.
+pub async fn drain(
+    clients: impl IntoIterator<Item = WsClient>,
+    rate: f64,
+    deadline: Deadline,
+) -> DrainSummary {
+    drain_until(clients, rate, deadline, None).await
+}
+
+/// [`drain`], also aborting whatever is still open as soon as `token` is
+/// cancelled, e.g. when a second shutdown signal asks for a hard stop.
+pub async fn drain_cancellable(
+    clients: impl IntoIterator<Item = WsClient>,
+    rate: f64,
+    deadline: Deadline,
+    token: &CancellationToken,
+) -> DrainSummary {
+    drain_until(clients, rate, deadline, Some(token)).await
+}
+
+async fn drain_until(
+    clients: impl IntoIterator<Item = WsClient>,
+    rate: f64,
+    deadline: Deadline,
+    token: Option<&CancellationToken>,
+) -> DrainSummary {
+    let started = Instant::now();
+    let mut summary = DrainSummary::default();
+    let mut clients: Vec<WsClient> = clients
+        .into_iter()
+        .filter_map(|client| {
+            if client.is_usable() {
+                return Some(client);
+            }
+            finish(&client, DrainOutcome::Dead, &mut summary);
+            None
+        })
+        .collect();
+    clients.sort_by_key(|client| std::cmp::Reverse(client.stats().idle_time()));
+
+    // `None` when the rate is zero, negative or NaN: no turn ever comes.
+    let interval = Duration::try_from_secs_f64(1.0 / rate).ok();
+    let mut closing = Vec::with_capacity(clients.len());
+    let mut clients = clients.into_iter();
+    let mut turn = 0u32;
+    while let Some(client) = clients.next() {
+        let start = interval.and_then(|interval| interval.checked_mul(turn));
+        let start = start.and_then(|offset| started.checked_add(offset));
+        let turn_came = match start {
+            Some(start) if start < deadline.instant() => sleep_until(start, token).await,
+            // No turn before the deadline: what is left is aborted then.
+            _ => !sleep_until(deadline.instant(), token).await,
+        };
+        if !turn_came {
+            for client in std::iter::once(client).chain(clients.by_ref()) {
+                finish(&client, DrainOutcome::Forced, &mut summary);
+            }
+            break;
+        }
+        turn += 1;
+        closing.push(monoio::spawn(close(client, deadline, token.cloned())));
+    }
+    for task in closing {
+        summary.record(task.await);
+    }
+    summary.elapsed = started.elapsed();
+    summary
+}
+
+/// Sleep until `instant`; `false` if `token` was cancelled first.
+async fn sleep_until(instant: Instant, token: Option<&CancellationToken>) -> bool {
+    let sleep = monoio::time::sleep_until(monoio::time::Instant::from_std(instant));
+    match token {
+        Some(token) => token.run_until_cancelled(sleep).await.is_some(),
+        None => {
+            sleep.await;
+            true
+        }
+    }
+}
+
+/// Close one connection, giving up at `deadline` or once `token` is
+/// cancelled.
+async fn close(
+    mut client: WsClient,
+    deadline: Deadline,
+    token: Option<CancellationToken>,
+) -> DrainOutcome {
+    let handshake = async {
+        let close = Message::Close(Some(CloseFrame {
+            code: CloseFrame::GOING_AWAY,
+            reason: String::new(),
+        }));
+        match client.send(close).await {
+            Ok(()) => client.consume_until_close().await.map(drop),
+            Err(WsError::ClosedByPeer(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    };
+    let deadline = monoio::time::Instant::from_std(deadline.instant());
+    let handshake = monoio::time::timeout_at(deadline, handshake);
+    let result = match &token {
+        Some(token) => token.run_until_cancelled(handshake).await,
+        None => Some(handshake.await),
+    };
+    let outcome = match result {
+        Some(Ok(Ok(()))) => DrainOutcome::Closed,
+        Some(Ok(Err(WsError::Cancelled))) | Some(Err(_)) | None => DrainOutcome::Forced,
+        Some(Ok(Err(_))) => DrainOutcome::Dead,
+    };
+    client.emit(&WsEvent::ConnectionDrained { outcome });
+    outcome
+}
+
+/// Report a connection that is dropped without a close handshake.
+fn finish(client: &WsClient, outcome: DrainOutcome, summary: &mut DrainSummary) {
+    client.emit(&WsEvent::ConnectionDrained { outcome });
+    summary.record(outcome);
+}
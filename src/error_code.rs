@@ -0,0 +1,480 @@
+//! Stable, machine-readable codes for errors.
+//!
+//! Every [`WsError`], [`UpgradeErr`], [`TlsErr`], [`ProxyErr`],
+//! [`UrlError`] and [`CodecError`] has a `code()` such as `handshake.status.4xx`,
+//! `tls.cert.expired` or `ws.close.abnormal`, and a `category()`. Unlike
+//! the `Display` messages, codes only change deliberately: the full list is
+//! [`ALL_CODES`], kept in sync with the checked-in `error-codes.txt`
+//! snapshot by the example below, which runs as a doctest. Events that
+//! report an error carry the same code; see [`WsEvent::error_code`].
+//!
+//! Codes are dot-separated, from general to specific, and the first segment
+//! names the [`ErrorCategory`]. An error wrapped in [`TlsErr::Io`] has the
+//! code of what it wraps.
+//!
+//! ```
+//! use websockets_monoio::error_code::{ALL_CODES, ErrorCategory};
+//!
+//! let snapshot = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/error-codes.txt"));
+//! assert_eq!(ALL_CODES, snapshot.lines().collect::<Vec<_>>());
+//! for code in ALL_CODES {
+//!     ErrorCategory::of(code).expect("every code has a category");
+//! }
+//! ```
+//!
+//! [`WsEvent::error_code`]: crate::WsEvent::error_code
+
+use std::io::{self, ErrorKind};
+
+use fastwebsockets::WebSocketError;
+
+use crate::WsError;
+use crate::codec::CodecError;
+use crate::http_upgrade::{AcceptError, UpgradeErr};
+use crate::proxy::ProxyErr;
+use crate::time::ConnectPhase;
+use crate::tls::TlsErr;
+use crate::url::UrlError;
+
+/// The broad class of an error, the first segment of its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The URL, the builder's options or an endpoint policy rejected the
+    /// connection before it was dialed (`config.*`).
+    Config,
+    /// The transport failed (`io.*`).
+    Io,
+    /// TLS failed, e.g. on an untrusted certificate (`tls.*`).
+    Tls,
+    /// A [`Codec`](crate::codec::Codec) failed or a payload was rejected
+    /// (`codec.*`).
+    Codec,
+    /// The proxy refused or mangled the tunnel (`proxy.*`).
+    Proxy,
+    /// The HTTP upgrade failed (`handshake.*`).
+    Handshake,
+    /// The peer broke the WebSocket protocol, or the connection was used
+    /// after it closed (`ws.*`, except `ws.close.*`).
+    Protocol,
+    /// The peer closed the connection (`ws.close.*`).
+    Closed,
+    /// A timeout or deadline ran out (`timeout.*`).
+    Timeout,
+    /// A memory budget or pool limit was reached (`resource.*`).
+    Resource,
+    /// An application hook rejected something (`app.*`).
+    Application,
+    /// Connecting failed after retries or across URLs (`connect.*`).
+    Connect,
+    /// A reconnecting client gave up or a replacement failed
+    /// (`reconnect.*`).
+    Reconnect,
+    /// An operation was called in a state that does not allow it
+    /// (`usage.*`).
+    Usage,
+}
+
+impl ErrorCategory {
+    /// The category of `code`, or `None` if it is not one of this crate's.
+    pub fn of(code: &str) -> Option<Self> {
+        if code.starts_with("ws.close.") {
+            return Some(ErrorCategory::Closed);
+        }
+        Some(match code.split('.').next()? {
+            "config" => ErrorCategory::Config,
+            "io" => ErrorCategory::Io,
+            "tls" => ErrorCategory::Tls,
+            "codec" => ErrorCategory::Codec,
+            "proxy" => ErrorCategory::Proxy,
+            "handshake" => ErrorCategory::Handshake,
+            "ws" => ErrorCategory::Protocol,
+            "timeout" => ErrorCategory::Timeout,
+            "resource" => ErrorCategory::Resource,
+            "app" => ErrorCategory::Application,
+            "connect" => ErrorCategory::Connect,
+            "reconnect" => ErrorCategory::Reconnect,
+            "usage" => ErrorCategory::Usage,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Config => "config",
+            ErrorCategory::Io => "io",
+            ErrorCategory::Tls => "tls",
+            ErrorCategory::Codec => "codec",
+            ErrorCategory::Proxy => "proxy",
+            ErrorCategory::Handshake => "handshake",
+            ErrorCategory::Protocol => "protocol",
+            ErrorCategory::Closed => "closed",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Resource => "resource",
+            ErrorCategory::Application => "app",
+            ErrorCategory::Connect => "connect",
+            ErrorCategory::Reconnect => "reconnect",
+            ErrorCategory::Usage => "usage",
+        }
+    }
+}
+
+/// Every code an error of this crate can have, sorted. Adding, renaming or
+/// removing one also means updating `error-codes.txt`.
+pub const ALL_CODES: &[&str] = &[
+    "app.first_message.rejected",
+    "app.intercept",
+    "app.non_ascii_text",
+    "app.ping_reply_too_large",
+    "app.transform",
+    "codec.decode",
+    "codec.encode",
+    "codec.schema_rejected",
+    "codec.unexpected_text",
+    "config.policy",
+    "config.proxy_url",
+    "config.url.empty_host",
+    "config.url.fragment",
+    "config.url.idna",
+    "config.url.illegal_character",
+    "config.url.ipv6_literal",
+    "config.url.ipv6_unbracketed",
+    "config.url.port",
+    "config.url.scheme",
+    "connect.all_failed",
+    "connect.retries_exhausted",
+    "handshake.accept.malformed",
+    "handshake.accept.mismatch",
+    "handshake.eof",
+    "handshake.exchange",
+    "handshake.headers",
+    "handshake.oversized",
+    "handshake.protocol",
+    "handshake.status.1xx",
+    "handshake.status.2xx",
+    "handshake.status.3xx",
+    "handshake.status.4xx",
+    "handshake.status.5xx",
+    "handshake.status.other",
+    "handshake.utf8",
+    "io.address",
+    "io.broken_pipe",
+    "io.connection_aborted",
+    "io.connection_refused",
+    "io.connection_reset",
+    "io.eof",
+    "io.not_connected",
+    "io.other",
+    "io.permission_denied",
+    "io.timeout",
+    "io.unreachable",
+    "io.write_zero",
+    "proxy.malformed",
+    "proxy.status",
+    "reconnect.replacement_failed",
+    "reconnect.stopped",
+    "resource.memory_budget",
+    "resource.pool_exhausted",
+    "timeout",
+    "timeout.deadline.connect",
+    "timeout.deadline.first_message",
+    "timeout.deadline.resolve",
+    "timeout.deadline.tls",
+    "timeout.deadline.upgrade",
+    "timeout.first_message",
+    "timeout.keepalive",
+    "tls.alert",
+    "tls.cert.expired",
+    "tls.cert.invalid",
+    "tls.cert.name_mismatch",
+    "tls.cert.not_yet_valid",
+    "tls.cert.revoked",
+    "tls.cert.unknown_issuer",
+    "tls.handshake",
+    "tls.server_name",
+    "usage.cancelled",
+    "usage.pending_data",
+    "ws.close.abnormal",
+    "ws.close.application",
+    "ws.close.going_away",
+    "ws.close.invalid_data",
+    "ws.close.no_status",
+    "ws.close.normal",
+    "ws.close.other",
+    "ws.close.policy_violation",
+    "ws.close.protocol_error",
+    "ws.close.server_error",
+    "ws.close.service_restart",
+    "ws.close.too_big",
+    "ws.close.try_again_later",
+    "ws.close.unsupported_data",
+    "ws.closed",
+    "ws.eof",
+    "ws.frame_too_large",
+    "ws.protocol.close_code",
+    "ws.protocol.close_frame",
+    "ws.protocol.continuation",
+    "ws.protocol.control_fragmented",
+    "ws.protocol.control_too_large",
+    "ws.protocol.fragment",
+    "ws.protocol.handshake",
+    "ws.protocol.invalid_value",
+    "ws.protocol.reserved_bits",
+    "ws.protocol.reserved_opcode",
+    "ws.protocol.utf8",
+];
+
+/// The category of one of the codes returned in this module.
+fn category(code: &'static str) -> ErrorCategory {
+    ErrorCategory::of(code).expect("every error code has a category")
+}
+
+impl WsError {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WsError::Url(e) => e.code(),
+            WsError::Upgrade(e) => e.code(),
+            WsError::Tls(e) => e.code(),
+            WsError::Proxy(e) => e.code(),
+            WsError::Io(e) => io_code(e),
+            WsError::WebSocket(e) => websocket_code(e),
+            WsError::Timeout(e) => match e.phase() {
+                None => "timeout",
+                Some(ConnectPhase::Resolve) => "timeout.deadline.resolve",
+                Some(ConnectPhase::Connect) => "timeout.deadline.connect",
+                Some(ConnectPhase::Tls) => "timeout.deadline.tls",
+                Some(ConnectPhase::Upgrade) => "timeout.deadline.upgrade",
+                Some(ConnectPhase::FirstMessage) => "timeout.deadline.first_message",
+            },
+            WsError::KeepaliveTimeout { .. } => "timeout.keepalive",
+            WsError::Policy(_) => "config.policy",
+            WsError::MemoryBudget { .. } => "resource.memory_budget",
+            WsError::PingReplyTooLarge(_) => "app.ping_reply_too_large",
+            WsError::ReservedOpcode(_) => "ws.protocol.reserved_opcode",
+            WsError::MaxRetriesExceeded { .. } => "connect.retries_exhausted",
+            WsError::AllFailed(_) => "connect.all_failed",
+            WsError::PoolExhausted { .. } => "resource.pool_exhausted",
+            WsError::ClosedByPeer(close) => close_code(close.code),
+            WsError::Intercept { .. } => "app.intercept",
+            WsError::Transform { .. } => "app.transform",
+            WsError::FirstMessageRejected { .. } => "app.first_message.rejected",
+            WsError::NonAsciiText { .. } => "app.non_ascii_text",
+            WsError::FirstMessageTimeout(_) => "timeout.first_message",
+            WsError::ReconnectStopped => "reconnect.stopped",
+            WsError::ReplacementFailed(_) => "reconnect.replacement_failed",
+            WsError::PendingData => "usage.pending_data",
+            WsError::Cancelled => "usage.cancelled",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+impl UpgradeErr {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UpgradeErr::Eof => "handshake.eof",
+            UpgradeErr::Oversized => "handshake.oversized",
+            UpgradeErr::Status(status) => match status {
+                100..=199 => "handshake.status.1xx",
+                200..=299 => "handshake.status.2xx",
+                300..=399 => "handshake.status.3xx",
+                400..=499 => "handshake.status.4xx",
+                500..=599 => "handshake.status.5xx",
+                _ => "handshake.status.other",
+            },
+            UpgradeErr::Headers => "handshake.headers",
+            UpgradeErr::Accept(e) => e.code(),
+            UpgradeErr::UnofferedProtocol(_) => "handshake.protocol",
+            UpgradeErr::Exchange(_) => "handshake.exchange",
+            UpgradeErr::Io(e) => io_code(e),
+            UpgradeErr::Utf8(_) => "handshake.utf8",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+impl AcceptError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AcceptError::Malformed { .. } => "handshake.accept.malformed",
+            AcceptError::Mismatch { .. } => "handshake.accept.mismatch",
+        }
+    }
+}
+
+impl TlsErr {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TlsErr::Dns => "tls.server_name",
+            TlsErr::Io(e) => io_code(e),
+            TlsErr::Rustls(monoio_rustls::TlsError::Io(e)) => io_code(e),
+            TlsErr::Rustls(monoio_rustls::TlsError::Rustls(e)) => rustls_code(e),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+impl ProxyErr {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProxyErr::Url => "config.proxy_url",
+            ProxyErr::Status(_) => "proxy.status",
+            ProxyErr::Malformed => "proxy.malformed",
+            ProxyErr::Io(e) => io_code(e),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+impl UrlError {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UrlError::Scheme => "config.url.scheme",
+            UrlError::Port => "config.url.port",
+            UrlError::FragmentNotAllowed(_) => "config.url.fragment",
+            UrlError::UnbracketedIpv6(_) => "config.url.ipv6_unbracketed",
+            UrlError::Ipv6Literal(_) => "config.url.ipv6_literal",
+            UrlError::EmptyHost => "config.url.empty_host",
+            UrlError::IllegalCharacter { .. } => "config.url.illegal_character",
+            UrlError::Idna(_) => "config.url.idna",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+impl CodecError {
+    /// The stable code of this error; see the [`error_code`](crate::error_code)
+    /// module.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodecError::Encode(_) => "codec.encode",
+            CodecError::Decode(_) => "codec.decode",
+            CodecError::SchemaRejected { .. } => "codec.schema_rejected",
+            CodecError::UnexpectedText => "codec.unexpected_text",
+            CodecError::Ws(e) => e.code(),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        category(self.code())
+    }
+}
+
+/// The code of a transport error. TLS streams report handshake failures as
+/// I/O errors wrapping the `rustls` error, which keep their TLS code.
+fn io_code(e: &io::Error) -> &'static str {
+    if let Some(e) = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+        return rustls_code(e);
+    }
+    match e.kind() {
+        ErrorKind::TimedOut => "io.timeout",
+        ErrorKind::ConnectionRefused => "io.connection_refused",
+        ErrorKind::ConnectionReset => "io.connection_reset",
+        ErrorKind::ConnectionAborted => "io.connection_aborted",
+        ErrorKind::BrokenPipe => "io.broken_pipe",
+        ErrorKind::UnexpectedEof => "io.eof",
+        ErrorKind::NotConnected => "io.not_connected",
+        ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable => "io.address",
+        ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable => "io.unreachable",
+        ErrorKind::WriteZero => "io.write_zero",
+        ErrorKind::PermissionDenied => "io.permission_denied",
+        _ => "io.other",
+    }
+}
+
+fn rustls_code(e: &rustls::Error) -> &'static str {
+    use rustls::CertificateError;
+
+    match e {
+        rustls::Error::InvalidCertificate(e) => match e {
+            CertificateError::Expired | CertificateError::ExpiredContext { .. } => {
+                "tls.cert.expired"
+            }
+            CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+                "tls.cert.not_yet_valid"
+            }
+            CertificateError::Revoked => "tls.cert.revoked",
+            CertificateError::UnknownIssuer => "tls.cert.unknown_issuer",
+            CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. } => {
+                "tls.cert.name_mismatch"
+            }
+            _ => "tls.cert.invalid",
+        },
+        rustls::Error::AlertReceived(_) => "tls.alert",
+        _ => "tls.handshake",
+    }
+}
+
+fn websocket_code(e: &WebSocketError) -> &'static str {
+    match e {
+        WebSocketError::InvalidFragment => "ws.protocol.fragment",
+        WebSocketError::InvalidUTF8 => "ws.protocol.utf8",
+        WebSocketError::InvalidContinuationFrame => "ws.protocol.continuation",
+        WebSocketError::InvalidCloseFrame => "ws.protocol.close_frame",
+        WebSocketError::InvalidCloseCode => "ws.protocol.close_code",
+        WebSocketError::ReservedBitsNotZero => "ws.protocol.reserved_bits",
+        WebSocketError::ControlFrameFragmented => "ws.protocol.control_fragmented",
+        WebSocketError::PingFrameTooLarge => "ws.protocol.control_too_large",
+        WebSocketError::InvalidValue => "ws.protocol.invalid_value",
+        // `fastwebsockets`' own handshake, which this crate does not use.
+        WebSocketError::InvalidStatusCode(_)
+        | WebSocketError::InvalidUpgradeHeader
+        | WebSocketError::InvalidConnectionHeader
+        | WebSocketError::InvalidSecWebsocketVersion
+        | WebSocketError::MissingSecWebSocketKey => "ws.protocol.handshake",
+        WebSocketError::FrameTooLarge => "ws.frame_too_large",
+        WebSocketError::ConnectionClosed => "ws.closed",
+        WebSocketError::UnexpectedEOF => "ws.eof",
+        WebSocketError::IoError(e) => io_code(e),
+        // Variants behind `fastwebsockets` features another crate may enable.
+        #[allow(unreachable_patterns)]
+        _ => "ws.protocol.handshake",
+    }
+}
+
+/// The code of a Close received from the peer, by its status code.
+fn close_code(code: u16) -> &'static str {
+    match code {
+        1000 => "ws.close.normal",
+        1001 => "ws.close.going_away",
+        1002 => "ws.close.protocol_error",
+        1003 => "ws.close.unsupported_data",
+        1005 => "ws.close.no_status",
+        1006 => "ws.close.abnormal",
+        1007 => "ws.close.invalid_data",
+        1008 => "ws.close.policy_violation",
+        1009 => "ws.close.too_big",
+        1011 => "ws.close.server_error",
+        1012 => "ws.close.service_restart",
+        1013 => "ws.close.try_again_later",
+        4000..=4999 => "ws.close.application",
+        _ => "ws.close.other",
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::context::ContextMap;
+use crate::drain::DrainOutcome;
+use crate::http_upgrade::AcceptError;
+use crate::pool::EvictionReason;
+use crate::reconnect::Jitter;
+use crate::summary::SessionSummary;
+
+/// Notable things that happen on a connection, delivered to the sink
+/// registered with [`WsClientBuilder::on_event`](crate::WsClientBuilder::on_event).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WsEvent {
+    /// A reservation against the shared [`MemoryBudget`](crate::MemoryBudget) was refused.
+    MemoryBudgetExceeded {
+        requested: usize,
+        used: usize,
+        limit: usize,
+    },
+    /// A [`WsPool`](crate::WsPool) handed out a connection, either an idle one
+    /// (`reused`) or a newly dialed one.
+    PoolCheckout { reused: bool },
+    /// A [`WsPool`](crate::WsPool) closed one of its connections.
+    PoolEviction { reason: EvictionReason },
+    /// A [`WsReconnectClient`](crate::WsReconnectClient) dropped a queued
+    /// message whose time to live ran out before it could be sent.
+    OutboundExpired { queued_for: std::time::Duration },
+    /// A frame with a reserved opcode was dropped, as set up with
+    /// [`WsClientBuilder::skip_reserved_opcodes`](crate::WsClientBuilder::skip_reserved_opcodes).
+    ReservedOpcodeSkipped { opcode: u8 },
+    /// The upgrade response carried a wrong `Sec-WebSocket-Accept`. The
+    /// connect fails with the same error; see also
+    /// [`accept_rejections`](crate::http_upgrade::accept_rejections).
+    AcceptRejected { error: AcceptError },
+    /// A [`SequencedClient`](crate::sequence::SequencedClient) received a
+    /// sequence number other than the expected one and started recovering.
+    SequenceGap { expected: u64, got: u64 },
+    /// [`WsReconnectClient::send_reliable`](crate::WsReconnectClient::send_reliable)
+    /// is sending its message on `session`; an `attempt` above 1 is a replay
+    /// after a reconnect.
+    ReliableSend {
+        dedupe_key: Option<String>,
+        session: u64,
+        attempt: u32,
+    },
+    /// A run of a [`ScheduledJob`](crate::schedule::ScheduledJob) failed,
+    /// the `failures`th time in a row. With `reconnect`, the job's threshold
+    /// was reached and the session is being re-established.
+    ScheduledJobFailed {
+        name: String,
+        failures: u32,
+        error: String,
+        /// The [code](crate::error_code) of the error.
+        code: &'static str,
+        reconnect: bool,
+    },
+    /// [`drain`](crate::drain::drain) ended this connection.
+    ConnectionDrained { outcome: DrainOutcome },
+    /// A [`WsReconnectClient`](crate::WsReconnectClient) waits `wait` before
+    /// reconnect attempt `attempt` (zero-based since the last connection
+    /// that delivered a message): the policy's `delay` after `jitter`.
+    ReconnectScheduled {
+        attempt: u32,
+        delay: std::time::Duration,
+        wait: std::time::Duration,
+        jitter: Jitter,
+    },
+    /// The [reconnect pacing hook](crate::WsClientBuilder::reconnect_pacing)
+    /// did not finish within `timeout`; the attempt went ahead without it.
+    ReconnectPacingTimedOut {
+        attempt: u32,
+        timeout: std::time::Duration,
+    },
+    /// A [`StallWatchdog`](crate::StallWatchdog) woke `duration` later than
+    /// it should have: something blocked the thread.
+    EventLoopStall { duration: std::time::Duration },
+    /// The upgrade response spelled handshake headers other than RFC 6455
+    /// does, e.g. all lowercase, which points at a proxy rewriting them.
+    /// Informational: names are compared case-insensitively. `names` are
+    /// as received; see
+    /// [`UpgradeResponse::unusual_casing`](crate::http_upgrade::UpgradeResponse::unusual_casing).
+    UnusualHeaderCasing { names: Vec<String> },
+    /// A connection ended: the peer closed, a send or receive failed, or
+    /// the client was dropped. Sent once per connection.
+    SessionEnded { summary: Box<SessionSummary> },
+}
+
+impl WsEvent {
+    /// The [code](crate::error_code) of the error this event reports, the
+    /// same as [`WsError::code`](crate::WsError::code) of the error it
+    /// comes with. `None` for events that do not report one.
+    pub fn error_code(&self) -> Option<&'static str> {
+        match self {
+            WsEvent::MemoryBudgetExceeded { .. } => Some("resource.memory_budget"),
+            WsEvent::AcceptRejected { error } => Some(error.code()),
+            WsEvent::ScheduledJobFailed { code, .. } => Some(code),
+            WsEvent::SessionEnded { summary } => summary.error_code,
+            _ => None,
+        }
+    }
+}
+
+/// Shared callback that receives [`WsEvent`]s. Cheap to clone.
+#[derive(Clone)]
+pub struct EventSink {
+    f: Rc<EventFn>,
+    context: ContextMap,
+}
+
+/// Receives an event with the context of the connection it happened on.
+type EventFn = dyn Fn(&WsEvent, &ContextMap);
+
+impl EventSink {
+    pub fn new(f: impl Fn(&WsEvent, &ContextMap) + 'static) -> Self {
+        Self {
+            f: Rc::new(f),
+            context: ContextMap::new(),
+        }
+    }
+
+    /// Pass `context` with every event from now on.
+    pub(crate) fn set_context(&mut self, context: ContextMap) {
+        self.context = context;
+    }
+
+    pub fn emit(&self, event: &WsEvent) {
+        (self.f)(event, &self.context)
+    }
+
+    /// A sink that shows every event to `f` and then passes it on to
+    /// `inner`, if any, with `inner`'s context.
+    pub(crate) fn tap(inner: Option<EventSink>, f: impl Fn(&WsEvent) + 'static) -> Self {
+        let context = inner
+            .as_ref()
+            .map_or_else(ContextMap::new, |inner| inner.context.clone());
+        let mut sink = EventSink::new(move |event, context| {
+            f(event);
+            if let Some(inner) = &inner {
+                (inner.f)(event, context);
+            }
+        });
+        sink.context = context;
+        sink
+    }
+}
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink(..)")
+    }
+}
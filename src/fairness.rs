@@ -0,0 +1,158 @@
+use std::future::poll_fn;
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+/// How much a [`WsClient`] may read back to back before letting other
+/// tasks on the thread run.
+///
+/// During a burst every frame is often already buffered, so
+/// [`recv`](crate::WsClient::recv) completes without awaiting I/O and a
+/// tight receive loop never gives the scheduler a turn. Once `max_messages`
+/// messages or `max_bytes` payload bytes have been received that way, the
+/// next `recv` first yields to every other runnable task. Any `recv` that
+/// had to wait for the transport starts the count again.
+///
+/// The defaults are high enough not to show up in throughput benchmarks;
+/// [`ReadFairness::UNLIMITED`] never yields.
+///
+/// [`WsClient`]: crate::WsClient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadFairness {
+    pub max_messages: usize,
+    pub max_bytes: usize,
+}
+
+impl ReadFairness {
+    /// Never yield.
+    pub const UNLIMITED: Self = Self {
+        max_messages: usize::MAX,
+        max_bytes: usize::MAX,
+    };
+}
+
+impl Default for ReadFairness {
+    fn default() -> Self {
+        Self {
+            max_messages: 1024,
+            max_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// How a [`WsClient`] validates the UTF-8 of large Text messages.
+///
+/// Validation runs inside [`recv`](crate::WsClient::recv), and for a
+/// message of several megabytes it can hold the thread for milliseconds. A
+/// Text message of at least `threshold` bytes is instead validated `chunk`
+/// bytes at a time, yielding to the other tasks on the thread in between.
+/// The progress is kept on the client, so a `recv` cancelled in between
+/// resumes where it stopped, and [`try_recv`](crate::WsClient::try_recv)
+/// validates one chunk per call.
+///
+/// fastwebsockets validates a Text message sent in a single frame as it
+/// reads it, in one go; the budget spreads out the validation of messages
+/// reassembled from fragments.
+///
+/// monoio fires timers only once no task is runnable, which a yield alone
+/// does not bring about. Set `drive_timers` to also give the runtime a turn
+/// between chunks, so a timer due during a long validation fires on time;
+/// the runtime must then be built with the timer enabled.
+///
+/// [`ProcessingBudget::UNLIMITED`] validates every message in one go.
+///
+/// [`WsClient`]: crate::WsClient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessingBudget {
+    /// Smallest Text message validated in chunks.
+    pub threshold: usize,
+    /// Bytes validated between yields; at least 4 are used.
+    pub chunk: usize,
+    /// Let due timers fire between chunks, not only other tasks run.
+    pub drive_timers: bool,
+}
+
+impl ProcessingBudget {
+    /// Never yield.
+    pub const UNLIMITED: Self = Self {
+        threshold: usize::MAX,
+        chunk: usize::MAX,
+        drive_timers: false,
+    };
+}
+
+impl Default for ProcessingBudget {
+    fn default() -> Self {
+        Self {
+            threshold: 1024 * 1024,
+            chunk: 256 * 1024,
+            drive_timers: false,
+        }
+    }
+}
+
+/// Messages and bytes received since the last wait for I/O or yield.
+#[derive(Debug, Default)]
+pub(crate) struct FairnessState {
+    pub(crate) config: ReadFairness,
+    messages: usize,
+    bytes: usize,
+}
+
+impl FairnessState {
+    pub(crate) fn new(config: ReadFairness) -> Self {
+        Self {
+            config,
+            messages: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Yield first if the previous reads used up the budget.
+    pub(crate) async fn before_read(&mut self) {
+        if self.messages >= self.config.max_messages || self.bytes >= self.config.max_bytes {
+            self.messages = 0;
+            self.bytes = 0;
+            yield_to_others().await;
+        }
+    }
+
+    pub(crate) fn record(&mut self, awaited_io: bool, bytes: usize) {
+        if awaited_io {
+            self.messages = 0;
+            self.bytes = 0;
+        }
+        self.messages += 1;
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
+}
+
+/// Let every other runnable task run before resuming.
+pub(crate) async fn yield_to_others() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        wake_after_others(cx.waker().clone());
+        Poll::Pending
+    })
+    .await
+}
+
+/// Let every other runnable task run, then let the runtime fire the timers
+/// that are due. The deadline has already passed, so the sleep ends on the
+/// driver's next turn, or at once if timers were fired this millisecond.
+pub(crate) async fn yield_to_timers() {
+    yield_to_others().await;
+    let past = monoio::time::Instant::now() - Duration::from_millis(1);
+    monoio::time::sleep_until(past).await;
+}
+
+/// Wake `waker` once the tasks runnable now have had a turn.
+///
+/// monoio puts a task that wakes itself back at the *front* of the run
+/// queue, so the wake comes from a helper task spawned behind the others.
+pub(crate) fn wake_after_others(waker: Waker) {
+    monoio::spawn(async move { waker.wake() });
+}
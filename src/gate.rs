@@ -0,0 +1,262 @@
+use core::pin::Pin;
+use core::task::{Context, Poll, ready};
+
+use monoio_compat::{AsyncRead, AsyncWrite};
+use tokio::io::ReadBuf;
+
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Frames declared larger than this are passed through as they arrive instead
+/// of being buffered whole. Matches the `fastwebsockets` default message limit,
+/// which rejects such frames from the header alone.
+const MAX_GATED_FRAME: u64 = 64 << 20;
+
+/// A read buffer that only hands out complete WebSocket frames.
+///
+/// `fastwebsockets` consumes a frame header before it has read the payload, so
+/// dropping `read_frame` half-way loses bytes and desynchronises the stream.
+/// Behind this gate the reader only ever waits for I/O at a frame boundary:
+/// once a frame's first byte is released, the rest is already buffered. A
+/// pending `read_frame` can therefore be dropped (by a timeout or `select!`)
+/// without losing data.
+///
+/// Each read stops at the end of the frame being handed out, so a reader that
+/// has just finished a frame holds no bytes of the next one. The `WebSocket`
+/// on top can then be dropped and rebuilt over the same gate (as
+/// [`WsClient::set_role`](crate::WsClient::set_role) does) without losing
+/// frames.
+///
+/// A frame with a reserved opcode can be dropped here, before
+/// `fastwebsockets` (which fails the read on one) sees it; see
+/// [`on_reserved_opcode`](Self::on_reserved_opcode).
+///
+/// Writes go straight to the inner stream.
+pub struct GatedStream<S> {
+    inner: S,
+    buf: Vec<u8>,
+    /// Start of the bytes not yet handed out.
+    head: usize,
+    /// End of the complete frames that may be handed out.
+    ready: usize,
+    /// Bytes of an oversized frame still to pass through without gating.
+    passthrough: u64,
+    /// Bytes of the frame at `head` not yet handed out; zero at a boundary.
+    frame_left: u64,
+    /// Whether the frame at `head` is being dropped rather than handed out.
+    discarding: bool,
+    on_reserved: Option<Box<ReservedOpcodeFn>>,
+    eof: bool,
+}
+
+/// Decides whether a frame with a reserved opcode is dropped.
+type ReservedOpcodeFn = dyn Fn(u8) -> bool;
+
+impl<S> GatedStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            head: 0,
+            ready: 0,
+            passthrough: 0,
+            frame_left: 0,
+            discarding: false,
+            on_reserved: None,
+            eof: false,
+        }
+    }
+
+    /// Gate `inner`, starting with `bytes` that were already read from it
+    /// (e.g. frames that arrived together with the upgrade response).
+    pub fn with_buffered(inner: S, bytes: Vec<u8>) -> Self {
+        let mut gate = Self::new(inner);
+        gate.buf = bytes;
+        gate.scan();
+        gate
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Call `f` with the opcode of each frame using a reserved opcode (`0x3`
+    /// to `0x7`, `0xB` to `0xF`) when it reaches the front of the buffer.
+    /// If `f` returns `true` the frame is dropped whole and the reader never
+    /// sees it; otherwise it is handed out as usual.
+    ///
+    /// Only the one frame is dropped, so continuation frames following a
+    /// fragmented reserved frame still reach the reader.
+    pub fn on_reserved_opcode(&mut self, f: impl Fn(u8) -> bool + 'static) {
+        self.on_reserved = Some(Box::new(f));
+    }
+
+    /// Bytes received from the transport but not yet handed to the reader.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.head
+    }
+
+    /// Drop the gate. Any buffered bytes are discarded.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn reset_if_drained(&mut self) {
+        if self.head == self.buf.len() {
+            self.buf.clear();
+            self.head = 0;
+            self.ready = 0;
+        }
+    }
+
+    /// Advance `ready` over every complete frame in the buffer.
+    fn scan(&mut self) {
+        loop {
+            if self.passthrough > 0 {
+                let n = (self.buf.len() - self.ready).min(self.passthrough as usize);
+                self.ready += n;
+                self.passthrough -= n as u64;
+                if self.passthrough > 0 {
+                    return;
+                }
+            }
+            let Some(len) = frame_len(&self.buf[self.ready..]) else {
+                return;
+            };
+            if len > MAX_GATED_FRAME {
+                self.passthrough = len;
+                continue;
+            }
+            let len = len as usize;
+            if self.buf.len() - self.ready < len {
+                return;
+            }
+            self.ready += len;
+        }
+    }
+}
+
+/// Total encoded length of the frame starting at `bytes`, once its header is
+/// available.
+fn frame_len(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let masked = bytes[1] & 0x80 != 0;
+    let (extra, payload) = match bytes[1] & 0x7F {
+        126 => {
+            let ext = bytes.get(2..4)?;
+            (2, u16::from_be_bytes([ext[0], ext[1]]) as u64)
+        }
+        127 => {
+            let ext = bytes.get(2..10)?;
+            (8, u64::from_be_bytes(ext.try_into().ok()?))
+        }
+        n => (0, n as u64),
+    };
+    let header = 2 + extra + if masked { 4 } else { 0 };
+    Some(payload.saturating_add(header))
+}
+
+fn is_reserved(opcode: u8) -> bool {
+    matches!(opcode, 0x3..=0x7 | 0xB..=0xF)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for GatedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.head < this.ready {
+                if this.frame_left == 0 {
+                    // Only a truncated frame at EOF has no parsable header.
+                    this.frame_left =
+                        frame_len(&this.buf[this.head..this.ready]).unwrap_or(u64::MAX);
+                    let opcode = this.buf[this.head] & 0x0F;
+                    this.discarding =
+                        is_reserved(opcode) && this.on_reserved.as_ref().is_some_and(|f| f(opcode));
+                }
+                if this.discarding {
+                    let n = (this.ready - this.head)
+                        .min(usize::try_from(this.frame_left).unwrap_or(usize::MAX));
+                    this.frame_left -= n as u64;
+                    this.discarding = this.frame_left > 0;
+                    this.head += n;
+                    this.reset_if_drained();
+                    continue;
+                }
+                let n = (this.ready - this.head)
+                    .min(out.remaining())
+                    .min(usize::try_from(this.frame_left).unwrap_or(usize::MAX));
+                this.frame_left -= n as u64;
+                out.put_slice(&this.buf[this.head..this.head + n]);
+                this.head += n;
+                this.reset_if_drained();
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.head > 0 {
+                this.buf.drain(..this.head);
+                this.ready -= this.head;
+                this.head = 0;
+            }
+            this.buf.reserve(READ_CHUNK);
+            let len = this.buf.len();
+            let mut read_buf = ReadBuf::uninit(this.buf.spare_capacity_mut());
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            let n = read_buf.filled().len();
+            // SAFETY: `poll_read` initialised the first `n` bytes of the spare capacity.
+            unsafe { this.buf.set_len(len + n) };
+
+            if n == 0 {
+                // Release whatever is left so the reader sees the truncated frame, then EOF.
+                this.eof = true;
+                this.ready = this.buf.len();
+            } else {
+                this.scan();
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for GatedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,174 @@
+//! Recreating a session on another thread.
+//!
+//! A [`WsClient`] is tied to the monoio runtime it was created on. To move a
+//! subscription to another thread, take a [`HandoffDescriptor`] from the
+//! old client, send it over, open a new connection from it with
+//! [`WsClient::connect_from_descriptor`], and close the old client once the
+//! new one is ready.
+//!
+//! ```no_run
+//! use websockets_monoio::{Message, WsClient};
+//!
+//! # async fn run(client: WsClient) -> Result<(), websockets_monoio::WsError> {
+//! let desc = client
+//!     .handoff_descriptor()
+//!     .with_resubscribe(vec![Message::Text(r#"{"op":"subscribe","from":1042}"#.into())]);
+//! let (tx, rx) = std::sync::mpsc::channel();
+//! std::thread::spawn(move || {
+//!     let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!         .enable_timer()
+//!         .build()
+//!         .unwrap();
+//!     runtime.block_on(async move {
+//!         let desc = rx.recv().unwrap();
+//!         let mut client = WsClient::connect_from_descriptor(&desc).await?;
+//!         loop {
+//!             println!("{:?}", client.recv().await?);
+//!         }
+//!         #[allow(unreachable_code)]
+//!         Ok::<_, websockets_monoio::WsError>(())
+//!     })
+//! });
+//! tx.send(desc).unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::message::Message;
+use crate::{WsClient, WsClientBuilder, WsError};
+
+/// The builder options a [`HandoffDescriptor`] carries: the ones that are
+/// plain data.
+///
+/// Callbacks, middleware, memory budgets, proxies, TLS connectors and other
+/// shared state belong to the thread that set them up and are not carried;
+/// set them again on [`HandoffDescriptor::builder`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferOptions {
+    pub extra_headers: Vec<(String, String)>,
+    /// Offered with [`WsClientBuilder::subprotocol`].
+    pub subprotocols: Vec<String>,
+    /// Formatted `Sec-WebSocket-Extensions` offers.
+    pub extensions: Vec<String>,
+    pub tcp_nodelay: Option<bool>,
+    pub buffer_size: Option<usize>,
+    pub connect_timeout: Option<Duration>,
+    pub vectored_writes: Option<bool>,
+    pub max_response_header: Option<usize>,
+    /// See [`WsClientBuilder::unmasked_client_frames`].
+    pub unmasked_client_frames: bool,
+    pub skip_reserved_opcodes: bool,
+    /// See [`WsClientBuilder::ascii_text`].
+    pub ascii_text: bool,
+    /// See [`WsClientBuilder::strip_url_fragment`].
+    pub strip_url_fragment: bool,
+    /// See [`WsClientBuilder::withhold_url_credentials`].
+    pub withhold_url_credentials: bool,
+}
+
+/// Everything needed to open the same session on another thread, from
+/// [`WsClient::handoff_descriptor`].
+///
+/// `Send`, and with the `json` feature serializable, so it can also cross
+/// process boundaries. The extra headers are kept verbatim, so treat a
+/// descriptor like the credentials it may contain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct HandoffDescriptor {
+    /// The URL the old connection dialed.
+    pub url: String,
+    /// The subprotocol the server selected, requested again with
+    /// `Sec-WebSocket-Protocol`.
+    pub protocol: Option<String>,
+    pub options: TransferOptions,
+    /// Sent on the new connection, in order, before it is returned. The
+    /// client does not know the application's subscriptions, so this starts
+    /// empty; fill it from the resume state, e.g. with the last sequence
+    /// number seen on the old connection.
+    pub resubscribe: Vec<Message>,
+}
+
+impl HandoffDescriptor {
+    /// Set the messages that re-create the subscriptions.
+    pub fn with_resubscribe(mut self, messages: Vec<Message>) -> Self {
+        self.resubscribe = messages;
+        self
+    }
+
+    /// A builder with the carried options, to add what the descriptor cannot
+    /// carry before connecting to [`url`](Self::url). The `resubscribe`
+    /// messages are not sent by a connect from it.
+    pub fn builder(&self) -> WsClientBuilder {
+        let o = &self.options;
+        let mut builder = WsClientBuilder::new()
+            .extra_headers(&o.extra_headers)
+            .skip_reserved_opcodes(o.skip_reserved_opcodes)
+            .ascii_text(o.ascii_text)
+            .strip_url_fragment(o.strip_url_fragment)
+            .withhold_url_credentials(o.withhold_url_credentials)
+            .unmasked_client_frames(o.unmasked_client_frames);
+        for extension in &o.extensions {
+            builder = builder.with_extension_raw(extension);
+        }
+        for protocol in &o.subprotocols {
+            builder = builder.subprotocol(protocol);
+        }
+        let requested = !o.subprotocols.is_empty()
+            || o.extra_headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Protocol"));
+        if let Some(protocol) = self.protocol.as_ref().filter(|_| !requested) {
+            builder = builder.subprotocol(protocol);
+        }
+        if let Some(nodelay) = o.tcp_nodelay {
+            builder = builder.tcp_nodelay(nodelay);
+        }
+        if let Some(size) = o.buffer_size {
+            builder = builder.buffer_size(size);
+        }
+        if let Some(timeout) = o.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(enabled) = o.vectored_writes {
+            builder = builder.vectored_writes(enabled);
+        }
+        if let Some(bytes) = o.max_response_header {
+            builder = builder.max_response_header(bytes);
+        }
+        builder
+    }
+}
+
+impl WsClient {
+    /// Describe this session for [`connect_from_descriptor`] on another
+    /// thread. See the [`handoff`](crate::handoff) module.
+    ///
+    /// Options are recorded for connections made by a [`WsClientBuilder`];
+    /// the other constructors only record the URL and the extra headers.
+    ///
+    /// [`connect_from_descriptor`]: Self::connect_from_descriptor
+    pub fn handoff_descriptor(&self) -> HandoffDescriptor {
+        HandoffDescriptor {
+            url: self.info().url().to_owned(),
+            protocol: self.info().protocol().map(str::to_owned),
+            options: self.transfer.clone(),
+            resubscribe: Vec::new(),
+        }
+    }
+
+    /// Connect with `desc`'s options and send its
+    /// [`resubscribe`](HandoffDescriptor::resubscribe) messages.
+    ///
+    /// The old connection keeps running until its owner closes it, so close
+    /// it once this returns to avoid a gap.
+    pub async fn connect_from_descriptor(desc: &HandoffDescriptor) -> Result<Self, WsError> {
+        let mut client = desc.builder().connect(&desc.url).await?;
+        for message in &desc.resubscribe {
+            client.send(message.clone()).await?;
+        }
+        Ok(client)
+    }
+}
@@ -3,6 +3,11 @@ use httparse::Status;
 use monoio_compat::{AsyncReadExt, AsyncWriteExt};
 use rand::RngCore;
 use sha1::{Digest, Sha1};
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "no-alloc-handshake")]
+pub mod fixed;
 
 const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
@@ -12,18 +17,93 @@ pub enum UpgradeErr {
     Eof,
     #[error("oversized handshake")]
     Oversized,
-    #[error("non-101 status line")]
-    Status,
+    #[error("server answered with status {0} instead of 101")]
+    Status(u16),
     #[error("missing upgrade headers")]
     Headers,
-    #[error("bad Sec-WebSocket-Accept")]
-    Accept,
-    #[error(transparent)]
+    #[error("bad Sec-WebSocket-Accept: {0}")]
+    Accept(#[source] AcceptError),
+    /// RFC 6455 §4.1: the server must select one of the offered
+    /// subprotocols, or none.
+    #[error("server selected subprotocol {0:?}, which was not offered")]
+    UnofferedProtocol(String),
+    #[error("pre-upgrade exchange failed: {0}")]
+    Exchange(&'static str),
+    #[error("I/O error during the upgrade")]
     Io(#[from] std::io::Error),
-    #[error(transparent)]
+    #[error("handshake response is not UTF-8")]
     Utf8(#[from] std::str::Utf8Error),
 }
 
+/// Why a `Sec-WebSocket-Accept` was rejected, with what is needed to tell
+/// a replayed response from a broken server.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum AcceptError {
+    /// The value is not base64 of a 20-byte SHA-1 digest.
+    #[error("{received:?} is not a base64 SHA-1 digest; {}", self.hint())]
+    Malformed { received: String },
+    /// A well-formed value for another key, e.g. one sent on an earlier
+    /// connection.
+    #[error("expected {expected:?} for key {key:?}, got {received:?}; {}", self.hint())]
+    Mismatch {
+        /// The `Sec-WebSocket-Key` of this request.
+        key: String,
+        expected: String,
+        received: String,
+    },
+}
+
+impl AcceptError {
+    /// The likely cause, for logs.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            AcceptError::Malformed { .. } => {
+                "the server or a middlebox rewrote or truncated the header"
+            }
+            AcceptError::Mismatch { .. } => {
+                "a caching proxy or middlebox may be replaying the 101 response of an earlier connection"
+            }
+        }
+    }
+}
+
+static ACCEPT_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Upgrade responses rejected for their `Sec-WebSocket-Accept` since the
+/// process started, across all threads. A count that keeps rising on one
+/// route points at a middlebox rather than a flaky server.
+pub fn accept_rejections() -> u64 {
+    ACCEPT_REJECTIONS.load(Ordering::Relaxed)
+}
+
+fn check_accept(received: &[u8], key: &ClientKey) -> Result<(), AcceptError> {
+    if received == key.expected_accept.as_bytes() {
+        return Ok(());
+    }
+    Err(reject_accept(
+        received,
+        &key.sec_websocket_key,
+        &key.expected_accept,
+    ))
+}
+
+/// Count a rejected `Sec-WebSocket-Accept` and say what was wrong with it.
+fn reject_accept(received: &[u8], key: &str, expected: &str) -> AcceptError {
+    ACCEPT_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+    let received = String::from_utf8_lossy(received).into_owned();
+    if b64
+        .decode(&received)
+        .map_or(true, |digest| digest.len() != 20)
+    {
+        return AcceptError::Malformed { received };
+    }
+    AcceptError::Mismatch {
+        key: key.to_owned(),
+        expected: expected.to_owned(),
+        received,
+    }
+}
+
 pub struct ClientKey {
     pub sec_websocket_key: String,
     pub expected_accept: String,
@@ -45,75 +125,338 @@ pub fn generate_client_key() -> ClientKey {
     }
 }
 
+/// A header name and value, borrowed or owned.
+///
+/// Implemented for pairs of anything `AsRef<str>` and for references to
+/// them, so header lists can be `&[(&str, &str)]`, `Vec<(String, String)>`
+/// or any iterator of computed pairs.
+pub trait HeaderPair {
+    fn name(&self) -> &str;
+    fn value(&self) -> &str;
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> HeaderPair for (K, V) {
+    fn name(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    fn value(&self) -> &str {
+        self.1.as_ref()
+    }
+}
+
+impl<T: HeaderPair + ?Sized> HeaderPair for &T {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn value(&self) -> &str {
+        (**self).value()
+    }
+}
+
+/// Write the upgrade request.
+///
+/// The request is assembled from slices of its parts: the fixed text, the
+/// path, the host, the key and each extra header. Streams that support
+/// vectored writes get those slices directly; for the others (including the
+/// plain and TLS transports of [`WsClient`](crate::WsClient)) they are
+/// copied into one buffer and written at once, rather than issuing a write
+/// per part.
+///
+/// `subprotocols`, when not empty, are offered in one comma-separated
+/// `Sec-WebSocket-Protocol` header; pass the same slice to
+/// [`read_response`] to check the server's choice. `extra_headers` follow
+/// the handshake headers in the order given. A name that appears more than
+/// once is sent once per occurrence, nothing is merged:
+///
+/// ```
+/// use websockets_monoio::http_upgrade::write_request;
+///
+/// let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+///     .build()
+///     .unwrap();
+/// let mut request = Vec::new();
+/// runtime
+///     .block_on(write_request(
+///         &mut request,
+///         "example.com",
+///         "/feed",
+///         "dGhlIHNhbXBsZSBub25jZQ==",
+///         &["graphql-transport-ws", "graphql-ws"],
+///         &[("Cookie", "a=1"), ("X-Trace", "7"), ("Cookie", "b=2")],
+///     ))
+///     .unwrap();
+/// assert_eq!(
+///     request,
+///     b"GET /feed HTTP/1.1\r\n\
+///       Host: example.com\r\n\
+///       Upgrade: websocket\r\n\
+///       Connection: Upgrade\r\n\
+///       Sec-WebSocket-Version: 13\r\n\
+///       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+///       Sec-WebSocket-Protocol: graphql-transport-ws, graphql-ws\r\n\
+///       Cookie: a=1\r\n\
+///       X-Trace: 7\r\n\
+///       Cookie: b=2\r\n\
+///       \r\n"
+/// );
+/// ```
 pub async fn write_request<S>(
     stream: &mut S,
     host: &str,
     path_and_query: &str,
     sec_websocket_key: &str,
+    subprotocols: &[&str],
     extra_headers: &[(&str, &str)],
 ) -> Result<(), UpgradeErr>
 where
     S: AsyncWriteExt + Unpin,
 {
-    // Write HTTP request line by line to avoid string allocation
-    stream.write_all(b"GET ").await?;
-    stream.write_all(path_and_query.as_bytes()).await?;
-    stream.write_all(b" HTTP/1.1\r\nHost: ").await?;
-    stream.write_all(host.as_bytes()).await?;
-    stream
-        .write_all(
-            b"\r\nUpgrade: websocket\r\n\
+    let offered = subprotocols.join(", ");
+    let mut parts: Vec<&[u8]> = Vec::with_capacity(11 + 4 * extra_headers.len());
+    parts.extend([
+        b"GET ".as_slice(),
+        path_and_query.as_bytes(),
+        b" HTTP/1.1\r\nHost: ",
+        host.as_bytes(),
+        b"\r\nUpgrade: websocket\r\n\
           Connection: Upgrade\r\n\
           Sec-WebSocket-Version: 13\r\n\
           Sec-WebSocket-Key: ",
-        )
-        .await?;
-    stream.write_all(sec_websocket_key.as_bytes()).await?;
-    stream.write_all(b"\r\n").await?;
-
-    // Write extra headers
+        sec_websocket_key.as_bytes(),
+        b"\r\n",
+    ]);
+    if !subprotocols.is_empty() {
+        parts.extend([
+            b"Sec-WebSocket-Protocol: ".as_slice(),
+            offered.as_bytes(),
+            b"\r\n",
+        ]);
+    }
     for (k, v) in extra_headers {
-        stream.write_all(k.as_bytes()).await?;
-        stream.write_all(b": ").await?;
-        stream.write_all(v.as_bytes()).await?;
-        stream.write_all(b"\r\n").await?;
+        parts.extend([k.as_bytes(), b": ", v.as_bytes(), b"\r\n"]);
     }
+    parts.push(b"\r\n");
 
-    // End headers
-    stream.write_all(b"\r\n").await?;
+    if stream.is_write_vectored() {
+        let mut slices: Vec<IoSlice<'_>> = parts.iter().map(|part| IoSlice::new(part)).collect();
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            let n = stream.write_vectored(slices).await?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            IoSlice::advance_slices(&mut slices, n);
+        }
+    } else {
+        stream.write_all(&parts.concat()).await?;
+    }
     stream.flush().await?;
     Ok(())
 }
 
-pub async fn read_response<S>(stream: &mut S, expected_accept: &str) -> Result<(), UpgradeErr>
+/// What the server agreed to in its `101 Switching Protocols` response.
+///
+/// Header names are compared case-insensitively by [`get`](Self::get) and
+/// [`get_all`](Self::get_all), however a proxy on the way rewrote them;
+/// [`headers`](Self::headers) keeps them as received.
+///
+/// ```
+/// use websockets_monoio::http_upgrade::{ClientKey, read_response};
+///
+/// // The key and accept from RFC 6455 §1.3.
+/// let key = ClientKey {
+///     sec_websocket_key: "dGhlIHNhbXBsZSBub25jZQ==".into(),
+///     expected_accept: "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".into(),
+/// };
+/// let lowercase = "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\n\
+///     connection: Upgrade\r\nsec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+///     sec-websocket-protocol: v2.feed\r\nx-trace: a\r\nx-trace: b\r\n\r\n";
+/// let uppercase = "HTTP/1.1 101 Switching Protocols\r\nUPGRADE: websocket\r\n\
+///     CONNECTION: Upgrade\r\nSEC-WEBSOCKET-ACCEPT: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+///     SEC-WEBSOCKET-PROTOCOL: v2.feed\r\nX-TRACE: a\r\nX-TRACE: b\r\n\r\n";
+/// let mixed = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+///     Connection: Upgrade\r\nSec-Websocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+///     Sec-WebSocket-Protocol: v2.feed\r\nX-Trace: a\r\nx-TRACE: b\r\n\r\n";
+///
+/// let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+///     .build()
+///     .unwrap();
+/// runtime.block_on(async {
+///     for (fixture, unusual) in [
+///         (lowercase, &["upgrade", "connection", "sec-websocket-accept", "sec-websocket-protocol"][..]),
+///         (uppercase, &["UPGRADE", "CONNECTION", "SEC-WEBSOCKET-ACCEPT", "SEC-WEBSOCKET-PROTOCOL"]),
+///         (mixed, &["Sec-Websocket-Accept"]),
+///     ] {
+///         let response = read_response(&mut fixture.as_bytes(), &key, &[]).await.unwrap();
+///         assert_eq!(response.protocol.as_deref(), Some("v2.feed"));
+///         assert_eq!(response.get("Sec-WebSocket-Protocol"), Some("v2.feed"));
+///         assert_eq!(response.get("sec-websocket-accept"), Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+///         assert_eq!(response.get_all("X-Trace").collect::<Vec<_>>(), ["a", "b"]);
+///         assert_eq!(response.get("Content-Length"), None);
+///         assert_eq!(response.unusual_casing().collect::<Vec<_>>(), unusual);
+///     }
+///
+///     // The names as they arrived.
+///     let response = read_response(&mut mixed.as_bytes(), &key, &[]).await.unwrap();
+///     assert_eq!(response.headers[2].0, "Sec-Websocket-Accept");
+///     assert_eq!(response.headers[5], ("x-TRACE".to_owned(), "b".to_owned()));
+/// });
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpgradeResponse {
+    /// `Sec-WebSocket-Protocol`, if the server selected a subprotocol.
+    pub protocol: Option<String>,
+    /// `Sec-WebSocket-Extensions`, if the server accepted any extensions.
+    pub extensions: Option<String>,
+    /// Every header in the order received, names in their original casing.
+    /// Values that are not UTF-8 are converted lossily.
+    pub headers: Vec<(String, String)>,
+    /// Bytes read past the end of the response headers. They are the start of
+    /// the WebSocket stream and must be processed before reading further.
+    pub trailing: Vec<u8>,
+}
+
+/// The handshake headers a server sends, spelled as in RFC 6455.
+pub const CANONICAL_RESPONSE_HEADERS: [&str; 5] = [
+    "Upgrade",
+    "Connection",
+    "Sec-WebSocket-Accept",
+    "Sec-WebSocket-Protocol",
+    "Sec-WebSocket-Extensions",
+];
+
+impl UpgradeResponse {
+    /// The first header called `name`, compared case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every header called `name`, compared case-insensitively, in the
+    /// order received.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Names of [`CANONICAL_RESPONSE_HEADERS`] that arrived spelled
+    /// differently, as received, e.g. `sec-websocket-accept` from a proxy
+    /// that lowercases headers. Reported by the client as
+    /// [`WsEvent::UnusualHeaderCasing`](crate::WsEvent::UnusualHeaderCasing).
+    pub fn unusual_casing(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter().filter_map(|(name, _)| {
+            CANONICAL_RESPONSE_HEADERS
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(name) && c != name)
+                .then_some(name.as_str())
+        })
+    }
+}
+
+/// Largest response header block [`read_response`] accepts.
+pub const DEFAULT_MAX_RESPONSE_HEADER: usize = 16 * 1024;
+
+/// Headers parsed without allocating; larger responses get an array sized
+/// to their line count.
+const INLINE_HEADERS: usize = 32;
+
+/// Read and validate the server's answer to the upgrade request sent with
+/// `key`.
+///
+/// When `subprotocols` is not empty, a `Sec-WebSocket-Protocol` in the
+/// response must name one of them, or the upgrade fails with
+/// [`UpgradeErr::UnofferedProtocol`]. With an empty slice the server's
+/// choice is not checked.
+///
+/// ```
+/// use websockets_monoio::http_upgrade::{ClientKey, UpgradeErr, read_response};
+///
+/// // The key and accept from RFC 6455 §1.3.
+/// let key = ClientKey {
+///     sec_websocket_key: "dGhlIHNhbXBsZSBub25jZQ==".into(),
+///     expected_accept: "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".into(),
+/// };
+/// let response = |protocol: &str| {
+///     format!(
+///         "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+///          Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+///          {protocol}\r\n"
+///     )
+/// };
+/// let selected = response("Sec-WebSocket-Protocol: graphql-ws\r\n");
+/// let offered = ["graphql-transport-ws", "graphql-ws"];
+///
+/// let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+///     .build()
+///     .unwrap();
+/// runtime.block_on(async {
+///     let ok = read_response(&mut selected.as_bytes(), &key, &offered).await;
+///     assert_eq!(ok.unwrap().protocol.as_deref(), Some("graphql-ws"));
+///
+///     // Tokens are case-sensitive.
+///     let other = response("Sec-WebSocket-Protocol: GraphQL-WS\r\n");
+///     match read_response(&mut other.as_bytes(), &key, &offered).await {
+///         Err(UpgradeErr::UnofferedProtocol(p)) => assert_eq!(p, "GraphQL-WS"),
+///         other => panic!("{other:?}"),
+///     }
+///
+///     // Selecting none is allowed, and an empty offer checks nothing.
+///     let none = response("");
+///     assert_eq!(read_response(&mut none.as_bytes(), &key, &offered).await.unwrap().protocol, None);
+///     assert!(read_response(&mut selected.as_bytes(), &key, &[]).await.is_ok());
+/// });
+/// ```
+pub async fn read_response<S>(
+    stream: &mut S,
+    key: &ClientKey,
+    subprotocols: &[&str],
+) -> Result<UpgradeResponse, UpgradeErr>
 where
     S: AsyncReadExt + Unpin,
 {
-    let mut hdr = Vec::with_capacity(2048);
-    let mut chunk = [0u8; 1024];
-    let mut headers = [httparse::EMPTY_HEADER; 32];
-
-    while !hdr.windows(4).any(|w| w == b"\r\n\r\n") {
-        let n = stream.read(&mut chunk).await?;
-        if n == 0 {
-            return Err(UpgradeErr::Eof);
-        }
+    read_response_with_limit(stream, key, subprotocols, DEFAULT_MAX_RESPONSE_HEADER).await
+}
 
-        hdr.extend_from_slice(&chunk[..n]);
-        if hdr.len() > 16 * 1024 {
-            return Err(UpgradeErr::Oversized);
-        }
+/// [`read_response`], failing with [`UpgradeErr::Oversized`] once the
+/// header block exceeds `max_bytes` instead of
+/// [`DEFAULT_MAX_RESPONSE_HEADER`].
+///
+/// Each byte is searched for the end of the headers once and the block is
+/// parsed once, so large limits cost time linear in the response size.
+pub async fn read_response_with_limit<S>(
+    stream: &mut S,
+    key: &ClientKey,
+    subprotocols: &[&str],
+    max_bytes: usize,
+) -> Result<UpgradeResponse, UpgradeErr>
+where
+    S: AsyncReadExt + Unpin,
+{
+    let (hdr, header_len) = read_head(stream, max_bytes).await?;
+    let mut inline = [httparse::EMPTY_HEADER; INLINE_HEADERS];
+    let mut sized = Vec::new();
+    let mut response = httparse::Response::new(&mut inline);
+    let mut status = response.parse(&hdr);
+    if let Err(httparse::Error::TooManyHeaders) = status {
+        let lines = hdr[..header_len]
+            .windows(2)
+            .filter(|w| w == b"\r\n")
+            .count();
+        sized.resize(lines, httparse::EMPTY_HEADER);
+        response = httparse::Response::new(&mut sized);
+        status = response.parse(&hdr);
     }
-
-    let mut response = httparse::Response::new(&mut headers);
-    let status = {
-        let data: &[u8] = &hdr;
-        response.parse(data)
-    };
     match status {
-        Ok(Status::Complete(_header_len)) => {
-            if response.code != Some(101) {
-                return Err(UpgradeErr::Status);
+        Ok(Status::Complete(header_len)) => {
+            match response.code {
+                Some(101) => {}
+                code => return Err(UpgradeErr::Status(code.unwrap_or(0))),
             }
 
             let connection =
@@ -129,17 +472,235 @@ where
 
             let accept =
                 find_header(response.headers, "Sec-WebSocket-Accept").ok_or(UpgradeErr::Headers)?;
-            let accept_str = std::str::from_utf8(accept)?;
-            if accept_str != expected_accept {
-                return Err(UpgradeErr::Accept);
-            }
+            check_accept(accept, key).map_err(UpgradeErr::Accept)?;
 
-            Ok(())
+            let text_header = |name| -> Result<Option<String>, UpgradeErr> {
+                match find_header(response.headers, name) {
+                    Some(value) => Ok(Some(std::str::from_utf8(value)?.to_owned())),
+                    None => Ok(None),
+                }
+            };
+            let protocol = text_header("Sec-WebSocket-Protocol")?;
+            if let Some(selected) = &protocol
+                && !subprotocols.is_empty()
+                && !subprotocols.contains(&selected.as_str())
+            {
+                return Err(UpgradeErr::UnofferedProtocol(selected.clone()));
+            }
+            Ok(UpgradeResponse {
+                protocol,
+                extensions: text_header("Sec-WebSocket-Extensions")?,
+                headers: response
+                    .headers
+                    .iter()
+                    .map(|h| {
+                        let value = String::from_utf8_lossy(h.value).into_owned();
+                        (h.name.to_owned(), value)
+                    })
+                    .collect(),
+                trailing: hdr[header_len..].to_vec(),
+            })
         }
         _ => Err(UpgradeErr::Headers),
     }
 }
 
+/// Read up to the blank line ending a response's headers. Returns everything
+/// read and the length of the header block within it.
+async fn read_head<S>(stream: &mut S, max_bytes: usize) -> Result<(Vec<u8>, usize), UpgradeErr>
+where
+    S: AsyncReadExt + Unpin,
+{
+    let mut hdr = Vec::with_capacity(2048);
+    let mut chunk = [0u8; 4096];
+    // Where the terminator search resumes: the last three bytes already
+    // searched may be the start of a terminator split across reads.
+    let mut scanned = 0;
+    let header_len = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(UpgradeErr::Eof);
+        }
+        hdr.extend_from_slice(&chunk[..n]);
+        if let Some(end) = hdr[scanned..].windows(4).position(|w| w == b"\r\n\r\n") {
+            break scanned + end + 4;
+        }
+        if hdr.len() > max_bytes {
+            return Err(UpgradeErr::Oversized);
+        }
+        scanned = hdr.len().saturating_sub(3);
+    };
+    if header_len > max_bytes {
+        return Err(UpgradeErr::Oversized);
+    }
+    Ok((hdr, header_len))
+}
+
+/// Largest response body [`http_exchange`] reads.
+pub const MAX_EXCHANGE_BODY: usize = 64 * 1024;
+
+/// A plain HTTP/1.1 request sent with [`http_exchange`] before the upgrade.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimpleRequest {
+    pub method: String,
+    /// Path and query, e.g. `/auth?v=2`.
+    pub path: String,
+    /// Sent after `Host`. `Content-Length` is added from the body.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl SimpleRequest {
+    pub fn new(method: &str, path: &str) -> Self {
+        Self {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// The answer to a [`SimpleRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl SimpleResponse {
+    /// The first header called `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Send `request` to `host` over `stream` and read the response, leaving the
+/// connection open for the upgrade request that follows.
+///
+/// The response body must be framed by `Content-Length` and is limited to
+/// [`MAX_EXCHANGE_BODY`]; chunked bodies fail with
+/// [`UpgradeErr::Exchange`], as does anything the server sends past the
+/// body, since it could not be told apart from the upgrade response.
+pub async fn http_exchange<S>(
+    stream: &mut S,
+    host: &str,
+    request: &SimpleRequest,
+) -> Result<SimpleResponse, UpgradeErr>
+where
+    S: AsyncReadExt + AsyncWriteExt + Unpin,
+{
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {host}\r\n",
+        request.method, request.path
+    );
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    let bodiless = ["GET", "HEAD"].contains(&request.method.as_str());
+    if !request.body.is_empty() || !bodiless {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&request.body).await?;
+    stream.flush().await?;
+
+    let (hdr, header_len) = read_head(stream, DEFAULT_MAX_RESPONSE_HEADER).await?;
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut response = httparse::Response::new(&mut headers);
+    if !matches!(response.parse(&hdr), Ok(Status::Complete(_))) {
+        return Err(UpgradeErr::Headers);
+    }
+    let status = response.code.unwrap_or(0);
+    let headers = response
+        .headers
+        .iter()
+        .map(|h| Ok((h.name.to_owned(), std::str::from_utf8(h.value)?.to_owned())))
+        .collect::<Result<Vec<_>, UpgradeErr>>()?;
+    let mut response = SimpleResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    if response.header("Transfer-Encoding").is_some() {
+        return Err(UpgradeErr::Exchange(
+            "chunked response bodies are not supported",
+        ));
+    }
+    let len = match response.header("Content-Length") {
+        _ if request.method == "HEAD" => 0,
+        Some(len) => len
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| UpgradeErr::Exchange("invalid Content-Length"))?,
+        None if matches!(status, 100..=199 | 204 | 304) => 0,
+        None => return Err(UpgradeErr::Exchange("response has no Content-Length")),
+    };
+    if len > MAX_EXCHANGE_BODY {
+        return Err(UpgradeErr::Oversized);
+    }
+    let mut body = hdr[header_len..].to_vec();
+    if body.len() > len {
+        return Err(UpgradeErr::Exchange(
+            "server sent data past the response body",
+        ));
+    }
+    let read = body.len();
+    body.resize(len, 0);
+    stream.read_exact(&mut body[read..]).await?;
+    response.body = body;
+    Ok(response)
+}
+
+/// Format one `Sec-WebSocket-Extensions` offer (RFC 6455 §9.1): the
+/// extension name followed by `; param` or `; param=value` for each parameter.
+/// Values that are not valid tokens are sent as quoted strings.
+pub fn format_extension(name: &str, params: &[(&str, Option<&str>)]) -> String {
+    let mut out = String::from(name);
+    for (param, value) in params {
+        out.push_str("; ");
+        out.push_str(param);
+        if let Some(value) = value {
+            out.push('=');
+            if is_token(value) {
+                out.push_str(value);
+            } else {
+                out.push('"');
+                for c in value.chars() {
+                    if c == '"' || c == '\\' {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push('"');
+            }
+        }
+    }
+    out
+}
+
+/// RFC 7230 `token`.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
 fn find_header<'a>(headers: &'a [httparse::Header<'a>], name: &str) -> Option<&'a [u8]> {
     headers
         .iter()
@@ -0,0 +1,279 @@
+//! The upgrade handshake in fixed-size buffers, for builds where heap
+//! allocations are audited (feature `no-alloc-handshake`).
+//!
+//! [`FixedKey`] holds the key and the expected accept value in arrays, and
+//! [`FixedHandshake`] writes the request and reads the response in buffers
+//! of `REQ` and `RESP` bytes. A request or response that does not fit fails
+//! with [`UpgradeErr::Oversized`] instead of growing a buffer. Nothing on
+//! the success path allocates; errors that carry text, such as
+//! [`UpgradeErr::Accept`], do. Whether the stream itself allocates is up to
+//! the stream.
+//!
+//! ```
+//! use websockets_monoio::http_upgrade::fixed::{FixedHandshake, FixedKey};
+//!
+//! # async fn run(stream: &mut monoio_compat::StreamWrapper<monoio::net::TcpStream>) -> Result<(), websockets_monoio::http_upgrade::UpgradeErr> {
+//! let key = FixedKey::generate();
+//! let mut handshake = FixedHandshake::<512, 1024>::new();
+//! let response = handshake
+//!     .run(stream, "edge.example", "/feed", &key, &[("Origin", "https://edge.example")])
+//!     .await?;
+//! // Frames that arrived with the response come first.
+//! let _start = response.trailing;
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::Engine as _;
+use monoio_compat::{AsyncReadExt, AsyncWriteExt};
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+
+use super::{
+    INLINE_HEADERS, UpgradeErr, WS_GUID, b64, header_has_token, reject_accept, value_eq_ascii,
+};
+
+/// Length of a base64 `Sec-WebSocket-Key` (16 random bytes).
+const KEY_LEN: usize = 24;
+/// Length of a base64 `Sec-WebSocket-Accept` (a 20-byte SHA-1 digest).
+const ACCEPT_LEN: usize = 28;
+
+/// A `Sec-WebSocket-Key` and the `Sec-WebSocket-Accept` it calls for, held
+/// in arrays. The allocating counterpart is [`ClientKey`](super::ClientKey).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedKey {
+    key: [u8; KEY_LEN],
+    accept: [u8; ACCEPT_LEN],
+}
+
+impl FixedKey {
+    pub fn generate() -> Self {
+        let mut raw = [0u8; 16];
+        rand::rng().fill_bytes(&mut raw);
+        let mut key = [0u8; KEY_LEN];
+        encode_into(&raw, &mut key);
+        Self {
+            key,
+            accept: accept_for(&key),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        std::str::from_utf8(&self.key).expect("base64 is ASCII")
+    }
+
+    pub fn expected_accept(&self) -> &str {
+        std::str::from_utf8(&self.accept).expect("base64 is ASCII")
+    }
+}
+
+/// The `Sec-WebSocket-Accept` for `key` (RFC 6455 §4.2.2).
+fn accept_for(key: &[u8]) -> [u8; ACCEPT_LEN] {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WS_GUID.as_bytes());
+    let mut accept = [0u8; ACCEPT_LEN];
+    encode_into(&sha1.finalize(), &mut accept);
+    accept
+}
+
+fn encode_into(input: &[u8], output: &mut [u8]) {
+    let n = b64
+        .encode_slice(input, output)
+        .expect("output sized for the input");
+    debug_assert_eq!(n, output.len());
+}
+
+/// Buffers for one upgrade: `REQ` bytes for the request head and `RESP`
+/// for the response head plus whatever arrives with it.
+///
+/// Keep it on the stack, or in a struct that lives as long as the
+/// connection, and call [`run`](Self::run) once per connection.
+pub struct FixedHandshake<const REQ: usize = 1024, const RESP: usize = 2048> {
+    request: [u8; REQ],
+    response: [u8; RESP],
+}
+
+/// What the server agreed to, borrowed from the [`FixedHandshake`]'s
+/// buffer. The allocating counterpart is
+/// [`UpgradeResponse`](super::UpgradeResponse).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedResponse<'a> {
+    pub protocol: Option<&'a str>,
+    pub extensions: Option<&'a str>,
+    /// Bytes read past the end of the headers; the start of the WebSocket
+    /// stream.
+    pub trailing: &'a [u8],
+}
+
+impl<const REQ: usize, const RESP: usize> FixedHandshake<REQ, RESP> {
+    pub const fn new() -> Self {
+        Self {
+            request: [0; REQ],
+            response: [0; RESP],
+        }
+    }
+
+    /// Send the upgrade request, as [`write_request`](super::write_request)
+    /// would, and read and validate the response, as
+    /// [`read_response`](super::read_response) would.
+    ///
+    /// Fails with [`UpgradeErr::Oversized`] if the request does not fit in
+    /// `REQ` bytes, the response headers do not fit in `RESP` bytes, or the
+    /// response has more than 32 headers.
+    pub async fn run<S>(
+        &mut self,
+        stream: &mut S,
+        host: &str,
+        path_and_query: &str,
+        key: &FixedKey,
+        extra_headers: &[(&str, &str)],
+    ) -> Result<FixedResponse<'_>, UpgradeErr>
+    where
+        S: AsyncReadExt + AsyncWriteExt + Unpin,
+    {
+        let len = self.encode_request(host, path_and_query, key, extra_headers)?;
+        stream.write_all(&self.request[..len]).await?;
+        stream.flush().await?;
+        let (read, header_len) = self.read_head(stream).await?;
+        parse_response(&self.response[..read], header_len, key)
+    }
+
+    fn encode_request(
+        &mut self,
+        host: &str,
+        path_and_query: &str,
+        key: &FixedKey,
+        extra_headers: &[(&str, &str)],
+    ) -> Result<usize, UpgradeErr> {
+        let mut out = Cursor {
+            buf: &mut self.request,
+            len: 0,
+        };
+        out.put(b"GET ")?;
+        out.put(path_and_query.as_bytes())?;
+        out.put(b" HTTP/1.1\r\nHost: ")?;
+        out.put(host.as_bytes())?;
+        out.put(
+            b"\r\nUpgrade: websocket\r\n\
+              Connection: Upgrade\r\n\
+              Sec-WebSocket-Version: 13\r\n\
+              Sec-WebSocket-Key: ",
+        )?;
+        out.put(&key.key)?;
+        out.put(b"\r\n")?;
+        for (name, value) in extra_headers {
+            out.put(name.as_bytes())?;
+            out.put(b": ")?;
+            out.put(value.as_bytes())?;
+            out.put(b"\r\n")?;
+        }
+        out.put(b"\r\n")?;
+        Ok(out.len)
+    }
+
+    /// Read until the blank line ending the headers. Returns the bytes read
+    /// and the length of the header block within them.
+    async fn read_head<S>(&mut self, stream: &mut S) -> Result<(usize, usize), UpgradeErr>
+    where
+        S: AsyncReadExt + Unpin,
+    {
+        let mut read = 0;
+        let mut scanned = 0;
+        loop {
+            if read == RESP {
+                return Err(UpgradeErr::Oversized);
+            }
+            let n = stream.read(&mut self.response[read..]).await?;
+            if n == 0 {
+                return Err(UpgradeErr::Eof);
+            }
+            read += n;
+            if let Some(end) = self.response[scanned..read]
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+            {
+                return Ok((read, scanned + end + 4));
+            }
+            scanned = read.saturating_sub(3);
+        }
+    }
+}
+
+impl<const REQ: usize, const RESP: usize> Default for FixedHandshake<REQ, RESP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends to a fixed buffer, failing once it is full.
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Cursor<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), UpgradeErr> {
+        let end = self.len + bytes.len();
+        let dst = self
+            .buf
+            .get_mut(self.len..end)
+            .ok_or(UpgradeErr::Oversized)?;
+        dst.copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// The first header called `name`, borrowed from the response buffer.
+fn header<'a>(headers: &[httparse::Header<'a>], name: &str) -> Option<&'a [u8]> {
+    headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value)
+}
+
+fn parse_response<'a>(
+    buf: &'a [u8],
+    header_len: usize,
+    key: &FixedKey,
+) -> Result<FixedResponse<'a>, UpgradeErr> {
+    let mut headers = [httparse::EMPTY_HEADER; INLINE_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    match response.parse(&buf[..header_len]) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Err(httparse::Error::TooManyHeaders) => return Err(UpgradeErr::Oversized),
+        _ => return Err(UpgradeErr::Headers),
+    }
+    match response.code {
+        Some(101) => {}
+        code => return Err(UpgradeErr::Status(code.unwrap_or(0))),
+    }
+    let connection = header(response.headers, "Connection").ok_or(UpgradeErr::Headers)?;
+    if !header_has_token(connection, "upgrade")? {
+        return Err(UpgradeErr::Headers);
+    }
+    let upgrade = header(response.headers, "Upgrade").ok_or(UpgradeErr::Headers)?;
+    if !value_eq_ascii(upgrade, "websocket")? {
+        return Err(UpgradeErr::Headers);
+    }
+    let accept = header(response.headers, "Sec-WebSocket-Accept").ok_or(UpgradeErr::Headers)?;
+    if accept != key.accept {
+        return Err(UpgradeErr::Accept(reject_accept(
+            accept,
+            key.key(),
+            key.expected_accept(),
+        )));
+    }
+    let text_header = |name| -> Result<Option<&'a str>, UpgradeErr> {
+        match header(response.headers, name) {
+            Some(value) => Ok(Some(std::str::from_utf8(value)?)),
+            None => Ok(None),
+        }
+    };
+    Ok(FixedResponse {
+        protocol: text_header("Sec-WebSocket-Protocol")?,
+        extensions: text_header("Sec-WebSocket-Extensions")?,
+        trailing: &buf[header_len..],
+    })
+}
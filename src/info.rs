@@ -0,0 +1,182 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use crate::preset::Preset;
+
+/// What was negotiated when a [`WsClient`](crate::WsClient) connected.
+///
+/// Populated once the upgrade completes and never changed afterwards. Fields
+/// that do not apply to a connection are `None`: `tls` on `ws://`, `protocol`
+/// when the server selected no subprotocol, and so on.
+///
+/// `Debug` (and `Serialize` with the `json` feature) redact the query string
+/// and any userinfo of the URL, which commonly carry tokens, so the value is
+/// safe to log on every connect.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct ConnectionInfo {
+    #[cfg_attr(feature = "json", serde(serialize_with = "serialize_redacted"))]
+    pub(crate) url: String,
+    pub(crate) protocol: Option<String>,
+    pub(crate) extensions: Option<String>,
+    pub(crate) tls: Option<TlsInfo>,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) proxy_addr: Option<SocketAddr>,
+    pub(crate) local_addr: Option<SocketAddr>,
+    pub(crate) peer_label: Option<String>,
+    pub(crate) timings: ConnectTimings,
+    pub(crate) preset: Option<Preset>,
+    pub(crate) handshake_at: SystemTime,
+}
+
+/// Properties of the TLS session under a `wss://` connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct TlsInfo {
+    /// e.g. `TLSv1_3`.
+    pub version: Option<String>,
+    /// e.g. `TLS13_AES_256_GCM_SHA384`.
+    pub cipher_suite: Option<String>,
+    /// ALPN protocol agreed with the server, if any.
+    pub alpn: Option<String>,
+    /// Whether the session was resumed rather than fully negotiated.
+    pub resumed: bool,
+}
+
+/// How long each connect phase took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct ConnectTimings {
+    /// DNS resolution and TCP connect, plus opening the tunnel when a proxy
+    /// is used.
+    pub tcp: Duration,
+    /// TLS handshake; `None` on `ws://`.
+    pub tls: Option<Duration>,
+    /// HTTP upgrade request and response.
+    pub upgrade: Duration,
+}
+
+impl ConnectTimings {
+    pub fn total(&self) -> Duration {
+        self.tcp + self.tls.unwrap_or_default() + self.upgrade
+    }
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            protocol: None,
+            extensions: None,
+            tls: None,
+            peer_addr: None,
+            proxy_addr: None,
+            local_addr: None,
+            peer_label: None,
+            timings: ConnectTimings::default(),
+            preset: None,
+            handshake_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    /// The URL that was dialed, unredacted.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Subprotocol selected by the server (`Sec-WebSocket-Protocol`).
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Extensions accepted by the server (`Sec-WebSocket-Extensions`).
+    pub fn extensions(&self) -> Option<&str> {
+        self.extensions.as_deref()
+    }
+
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.tls.as_ref()
+    }
+
+    /// The server's address. Through a proxy this is the tunnel target,
+    /// known only when the `CONNECT` named an address rather than a host
+    /// name (as it does with an endpoint policy, which resolves the name
+    /// first); see [`proxy_addr`](Self::proxy_addr) for the proxy's.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The address of the proxy the connection was tunneled through.
+    pub fn proxy_addr(&self) -> Option<SocketAddr> {
+        self.proxy_addr
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// What a [`Transport`](crate::transport::Transport) calls its other
+    /// end; `None` for connections the crate dialed.
+    pub fn peer_label(&self) -> Option<&str> {
+        self.peer_label.as_deref()
+    }
+
+    pub fn timings(&self) -> &ConnectTimings {
+        &self.timings
+    }
+
+    /// The [`WsClientBuilder`](crate::WsClientBuilder) preset the connection
+    /// was made with.
+    pub fn preset(&self) -> Option<Preset> {
+        self.preset
+    }
+
+    /// Wall-clock time the upgrade request was made, read from the
+    /// builder's [`Clock`](crate::Clock).
+    pub fn handshake_at(&self) -> SystemTime {
+        self.handshake_at
+    }
+}
+
+impl fmt::Debug for ConnectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionInfo")
+            .field("url", &redact_url(&self.url))
+            .field("protocol", &self.protocol)
+            .field("extensions", &self.extensions)
+            .field("tls", &self.tls)
+            .field("peer_addr", &self.peer_addr)
+            .field("proxy_addr", &self.proxy_addr)
+            .field("local_addr", &self.local_addr)
+            .field("peer_label", &self.peer_label)
+            .field("timings", &self.timings)
+            .field("preset", &self.preset)
+            .field("handshake_at", &self.handshake_at)
+            .finish()
+    }
+}
+
+/// Replace the userinfo and query of `url` with `<redacted>`.
+pub(crate) fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, _)) => (rest, "?<redacted>"),
+        None => (rest, ""),
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    let rest = match rest[..authority_end].rsplit_once('@') {
+        Some((_, host)) => format!("<redacted>@{host}{}", &rest[authority_end..]),
+        None => rest.to_owned(),
+    };
+    if scheme.is_empty() {
+        format!("{rest}{query}")
+    } else {
+        format!("{scheme}://{rest}{query}")
+    }
+}
+
+#[cfg(feature = "json")]
+fn serialize_redacted<S: serde::Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redact_url(url))
+}
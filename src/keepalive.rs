@@ -0,0 +1,124 @@
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::Message;
+
+/// Periodic heartbeat and liveness check driven by [`WsClient::recv`].
+///
+/// While `recv` is waiting, a heartbeat is sent every `interval`. If no
+/// message satisfying the liveness check arrives within `timeout`, `recv`
+/// fails with [`WsError::KeepaliveTimeout`]. Nothing is sent while the
+/// application is not inside `recv`.
+///
+/// The default heartbeat is a WS Ping answered by a Pong. Exchanges that
+/// ignore WS pings can send an application message instead:
+///
+/// ```
+/// use std::time::Duration;
+/// use websockets_monoio::Message;
+/// use websockets_monoio::keepalive::Keepalive;
+///
+/// let keepalive = Keepalive::ping(Duration::from_secs(15), Duration::from_secs(45))
+///     .heartbeat(|| Message::Text(r#"{"op":"ping"}"#.into()))
+///     .liveness(|msg| matches!(msg, Message::Text(t) if t.contains(r#""op":"pong""#)));
+/// ```
+///
+/// Timers require a runtime built with the timer enabled.
+///
+/// [`WsClient::recv`]: crate::WsClient::recv
+/// [`WsError::KeepaliveTimeout`]: crate::WsError::KeepaliveTimeout
+#[derive(Clone)]
+pub struct Keepalive {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+    pub(crate) heartbeat: Heartbeat,
+    pub(crate) liveness: Liveness,
+}
+
+#[derive(Clone)]
+pub(crate) enum Heartbeat {
+    Ping,
+    Message(Rc<dyn Fn() -> Message>),
+}
+
+#[derive(Clone)]
+pub(crate) enum Liveness {
+    Pong,
+    AnyMessage,
+    Matching(Rc<dyn Fn(&Message) -> bool>),
+}
+
+impl Keepalive {
+    /// Send a WS Ping every `interval`; a Pong within `timeout` proves liveness.
+    pub fn ping(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            heartbeat: Heartbeat::Ping,
+            liveness: Liveness::Pong,
+        }
+    }
+
+    /// Send the message produced by `f` instead of a WS Ping. The closure runs
+    /// for every heartbeat, so it can embed timestamps or counters.
+    pub fn heartbeat(mut self, f: impl Fn() -> Message + 'static) -> Self {
+        self.heartbeat = Heartbeat::Message(Rc::new(f));
+        self
+    }
+
+    /// Treat any incoming message as proof of liveness.
+    pub fn any_message(mut self) -> Self {
+        self.liveness = Liveness::AnyMessage;
+        self
+    }
+
+    /// Treat incoming messages matching `f` as proof of liveness.
+    pub fn liveness(mut self, f: impl Fn(&Message) -> bool + 'static) -> Self {
+        self.liveness = Liveness::Matching(Rc::new(f));
+        self
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub(crate) fn next_heartbeat(&self) -> Message {
+        match &self.heartbeat {
+            Heartbeat::Ping => Message::Ping(Vec::new()),
+            Heartbeat::Message(f) => f(),
+        }
+    }
+
+    pub(crate) fn is_alive(&self, message: &Message) -> bool {
+        match &self.liveness {
+            Liveness::Pong => matches!(message, Message::Pong(_)),
+            Liveness::AnyMessage => true,
+            Liveness::Matching(f) => f(message),
+        }
+    }
+}
+
+impl fmt::Debug for Keepalive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let heartbeat = match self.heartbeat {
+            Heartbeat::Ping => "ping",
+            Heartbeat::Message(_) => "message",
+        };
+        let liveness = match self.liveness {
+            Liveness::Pong => "pong",
+            Liveness::AnyMessage => "any",
+            Liveness::Matching(_) => "matching",
+        };
+        f.debug_struct("Keepalive")
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .field("heartbeat", &heartbeat)
+            .field("liveness", &liveness)
+            .finish()
+    }
+}
@@ -112,15 +112,199 @@
 //! ## Platform Support
 //!
 //! - **Linux**: Full support with `io_uring` (recommended)
-//! - **macOS/Windows**: Limited support (falls back to standard async I/O)
+//! - **macOS/Windows**: Supported via monoio's legacy driver (no `io_uring`)
 //!
 //! For maximum performance, deploy on Linux with kernel version 5.1+ for full `io_uring` support.
 //!
 //! [`monoio`]: https://docs.rs/monoio
 
+pub mod blocking;
+pub mod budget;
+pub mod cancel;
 pub mod client;
+pub mod codec;
+pub mod conflate;
+pub mod context;
+pub mod drain;
+pub mod error_code;
+pub mod event;
+pub mod fairness;
+pub mod gate;
+pub mod handoff;
 pub mod http_upgrade;
+pub mod info;
+pub mod keepalive;
+pub mod message;
+pub mod middleware;
+pub mod ping;
+pub mod policy;
+pub mod pool;
+pub mod preset;
+pub mod probe;
+pub mod proxy;
+#[cfg(feature = "raw-frames")]
+pub mod raw;
+pub mod reconnect;
+pub mod schedule;
+pub mod sequence;
+pub mod shard;
+pub mod split;
+pub mod stats;
+pub mod summary;
+pub mod text;
+pub mod time;
 pub mod tls;
+pub mod transform;
+pub mod transport;
 pub mod url;
+pub mod watchdog;
 
-pub use client::{WsClient, WsStream};
+pub use budget::MemoryBudget;
+pub use cancel::CancellationToken;
+pub use client::{PreUpgrade, SharedStream, TokioIo, WsClient, WsClientBuilder, WsStream};
+pub use context::ContextMap;
+pub use error_code::ErrorCategory;
+pub use event::WsEvent;
+pub use info::ConnectionInfo;
+pub use keepalive::Keepalive;
+pub use message::{CloseFrame, Message, PreparedMessage};
+pub use ping::BackgroundPing;
+pub use policy::{EndpointPolicy, PolicyError};
+pub use pool::{PoolConfig, WsPool};
+pub use preset::Preset;
+pub use probe::{ProbeError, ProbeFailure, ProbeReport};
+pub use reconnect::{
+    Backoff, DefaultReconnectPolicy, DisconnectInfo, Jitter, ReconnectDecision, ReconnectPolicy,
+    ReliableSend, ReplaceOptions, RetryConfig, WsReconnectClient,
+};
+pub use stats::{ConnectionStats, ConnectionStatsSnapshot, Histogram};
+pub use summary::SessionSummary;
+pub use time::{Clock, ConnectPhase, Deadline, TimedOut};
+pub use transport::Transport;
+pub use watchdog::StallWatchdog;
+
+/// Error returned by [`WsClient`] operations.
+///
+/// The variants that only wrap another error (`Url`, `Upgrade`, `Tls`, ...)
+/// are transparent: they display as that error and return its
+/// [`source`](std::error::Error::source), so a reporter that prints the
+/// chain shows each message once. The chain ends at the OS error, e.g.
+/// `UpgradeErr::Io` → `io::Error` for a connection reset mid-upgrade.
+#[derive(thiserror::Error, Debug)]
+pub enum WsError {
+    #[error(transparent)]
+    Url(#[from] url::UrlError),
+    #[error(transparent)]
+    Upgrade(#[from] http_upgrade::UpgradeErr),
+    #[error(transparent)]
+    Tls(#[from] tls::TlsErr),
+    #[error(transparent)]
+    Proxy(#[from] proxy::ProxyErr),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    WebSocket(#[from] fastwebsockets::WebSocketError),
+    /// An operation ran out of its time limit: a connect timeout or
+    /// deadline, which also names the connect phase, or the timeout of a
+    /// receive, send or close.
+    #[error(transparent)]
+    Timeout(#[from] time::TimedOut),
+    /// No liveness proof arrived within `timeout`. `local_stall` is the
+    /// longest [stall of this thread](crate::watchdog) that overlapped the
+    /// wait, when one did: the server may not be to blame.
+    #[error("no keepalive response within {timeout:?}{}", stall_note(*.local_stall))]
+    KeepaliveTimeout {
+        timeout: std::time::Duration,
+        local_stall: Option<std::time::Duration>,
+    },
+    #[error(transparent)]
+    Policy(#[from] policy::PolicyError),
+    #[error("memory budget exceeded: requested {requested} bytes with {used} of {limit} in use")]
+    MemoryBudget {
+        requested: usize,
+        used: usize,
+        limit: usize,
+    },
+    #[error("ping reply of {0} bytes exceeds the 125-byte control frame limit")]
+    PingReplyTooLarge(usize),
+    /// The peer sent a frame with a reserved opcode; the connection was
+    /// closed with 1002. See [`WsClientBuilder::skip_reserved_opcodes`].
+    #[error("peer sent a frame with reserved opcode {0:#x}")]
+    ReservedOpcode(u8),
+    /// Every attempt of [`WsClient::connect_with_retries`] failed:
+    /// `earlier_errors` holds the errors before the last one, in order.
+    #[error("connect failed after {attempts} attempts: {last_error}")]
+    MaxRetriesExceeded {
+        attempts: u32,
+        earlier_errors: Vec<WsError>,
+        #[source]
+        last_error: Box<WsError>,
+    },
+    #[error("all {} URLs failed", .0.len())]
+    AllFailed(Vec<(url::OwnedWsUrl, WsError)>),
+    #[error("connection pool exhausted: all {max} connections are checked out")]
+    PoolExhausted { max: usize },
+    #[error("connection closed by peer with code {}: {}", .0.code, .0.reason)]
+    ClosedByPeer(CloseFrame),
+    #[error("{direction} interceptor {name:?} failed: {reason}")]
+    Intercept {
+        name: String,
+        direction: middleware::Direction,
+        reason: String,
+    },
+    /// A [payload transform](crate::transform) failed. Inbound, with
+    /// [`OnTransformError::Fail`](transform::OnTransformError::Fail), the
+    /// connection was closed with 1007.
+    #[error("{direction} transform failed: {error}")]
+    Transform {
+        direction: middleware::Direction,
+        #[source]
+        error: transform::TransformError,
+    },
+    /// A Text message was not ASCII on a connection built with
+    /// [`WsClientBuilder::ascii_text`]; the connection was closed with 1008.
+    #[error("text message is not ASCII at byte {offset}")]
+    NonAsciiText { offset: usize },
+    #[error("first message rejected: {reason}")]
+    FirstMessageRejected { reason: String, message: Message },
+    #[error("no first message within {0:?}")]
+    FirstMessageTimeout(std::time::Duration),
+    #[error("reconnect policy gave up")]
+    ReconnectStopped,
+    /// A [`WsReconnectClient::replace`] failed; the current session is kept.
+    #[error("replacement session failed: {0}")]
+    ReplacementFailed(#[source] Box<WsError>),
+    #[error("the transport holds data that was not yet flushed or read")]
+    PendingData,
+    /// The operation was stopped by a [`CancellationToken`].
+    #[error("cancelled")]
+    Cancelled,
+}
+
+/// `Result` with [`WsError`], for call sites that match on what failed.
+///
+/// ```
+/// use websockets_monoio::url::UrlError;
+/// use websockets_monoio::{WsClient, WsError, WsResult};
+///
+/// async fn open(url: &str) -> WsResult<WsClient> {
+///     WsClient::connect(url, &[]).await
+/// }
+///
+/// let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+///     .build()
+///     .unwrap();
+/// runtime.block_on(async {
+///     let result = open("https://example.com/").await;
+///     assert!(matches!(result, Err(WsError::Url(UrlError::Scheme))));
+/// });
+/// ```
+pub type WsResult<T> = Result<T, WsError>;
+
+/// The note on a [`WsError::KeepaliveTimeout`] during a local stall.
+fn stall_note(local_stall: Option<std::time::Duration>) -> String {
+    match local_stall {
+        Some(stall) => format!(" (local stall suspected: event loop stalled for {stall:?})"),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,154 @@
+use bytes::Bytes;
+use fastwebsockets::{Frame, OpCode, Payload};
+
+/// A complete WebSocket message, reassembled from one or more frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+/// Status code and reason carried by a Close frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Code reported for a Close frame that carried no status (RFC 6455 §7.1.5).
+    pub const NO_STATUS_RECEIVED: u16 = 1005;
+
+    /// Code for closing a connection because this end is going away, e.g.
+    /// shutting down.
+    pub const GOING_AWAY: u16 = 1001;
+
+    /// Code for closing a connection whose peer broke the protocol.
+    pub const PROTOCOL_ERROR: u16 = 1002;
+
+    /// Code for closing a connection that received a message whose payload
+    /// does not match its type, e.g. one that could not be decrypted.
+    pub const INVALID_PAYLOAD: u16 = 1007;
+
+    /// Code for closing a connection whose peer sent something it should not
+    /// have, when no more specific code applies.
+    pub const POLICY_VIOLATION: u16 = 1008;
+
+    /// Parse a Close payload. Returns `None` for an empty (code-less) payload.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            code: u16::from_be_bytes([payload[0], payload[1]]),
+            reason: String::from_utf8_lossy(&payload[2..]).into_owned(),
+        })
+    }
+}
+
+impl Message {
+    pub fn opcode(&self) -> OpCode {
+        match self {
+            Message::Text(_) => OpCode::Text,
+            Message::Binary(_) => OpCode::Binary,
+            Message::Ping(_) => OpCode::Ping,
+            Message::Pong(_) => OpCode::Pong,
+            Message::Close(_) => OpCode::Close,
+        }
+    }
+
+    /// Raw payload bytes. Close messages return an empty slice.
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(data) | Message::Ping(data) | Message::Pong(data) => data,
+            Message::Close(_) => &[],
+        }
+    }
+
+    pub(crate) fn into_frame(self) -> Frame<'static> {
+        match self {
+            Message::Text(text) => Frame::text(text.into_bytes().into()),
+            Message::Binary(data) => Frame::binary(data.into()),
+            Message::Ping(data) => Frame::new(true, OpCode::Ping, None, data.into()),
+            Message::Pong(data) => Frame::pong(data.into()),
+            Message::Close(Some(close)) => Frame::close(close.code, close.reason.as_bytes()),
+            Message::Close(None) => Frame::close_raw(Vec::new().into()),
+        }
+    }
+}
+
+/// A message serialized once, for sending the same bytes many times with
+/// [`WsClient::send_prepared`](crate::WsClient::send_prepared), e.g. a
+/// heartbeat or a standing subscription refresh.
+///
+/// The frame is stored as a masked client frame with a placeholder key.
+/// Each send only copies it, fills in a fresh key and masks the copy; the
+/// payload is never encoded again. Without masking
+/// ([`WsClientBuilder::unmasked_client_frames`](crate::WsClientBuilder::unmasked_client_frames))
+/// it is written straight from the stored bytes. Clones share the bytes, so
+/// one prepared message can serve any number of connections.
+///
+/// Text payloads given to [`from_payload`](Self::from_payload) are not
+/// checked for valid UTF-8, and control frames must stay within 125 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedMessage {
+    opcode: OpCode,
+    /// Header with a zeroed masking key, followed by the unmasked payload.
+    frame: Bytes,
+    head_len: usize,
+}
+
+impl PreparedMessage {
+    pub fn new(message: Message) -> Self {
+        let frame = message.into_frame();
+        Self::from_payload(frame.opcode, frame.payload)
+    }
+
+    pub fn from_payload(opcode: OpCode, payload: impl Into<Vec<u8>>) -> Self {
+        let payload = Payload::Owned(payload.into());
+        let mut frame = Frame::new(true, opcode, Some([0; 4]), payload);
+        let mut head = [0u8; 14];
+        let head_len = frame.fmt_head(&mut head);
+        let mut bytes = Vec::with_capacity(head_len + frame.payload.len());
+        bytes.extend_from_slice(&head[..head_len]);
+        bytes.extend_from_slice(&frame.payload);
+        Self {
+            opcode,
+            frame: bytes.into(),
+            head_len,
+        }
+    }
+
+    pub fn opcode(&self) -> OpCode {
+        self.opcode
+    }
+
+    /// The unmasked payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.frame[self.head_len..]
+    }
+
+    /// Replace `buf` with the frame masked under a fresh random key.
+    pub(crate) fn masked_into(&self, buf: &mut Vec<u8>) {
+        let key: [u8; 4] = rand::random();
+        buf.clear();
+        buf.extend_from_slice(&self.frame);
+        buf[self.head_len - 4..self.head_len].copy_from_slice(&key);
+        fastwebsockets::unmask(&mut buf[self.head_len..], key);
+    }
+
+    /// The header of the frame without a masking key, and its length.
+    pub(crate) fn unmasked_head(&self) -> ([u8; 14], usize) {
+        let len = self.head_len - 4;
+        let mut head = [0u8; 14];
+        head[..len].copy_from_slice(&self.frame[..len]);
+        head[1] &= 0x7f;
+        (head, len)
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::context::ContextMap;
+use crate::{Message, WsError};
+
+/// What an interceptor decided about a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterceptAction {
+    /// Pass the message, with any changes made in place, to the next
+    /// interceptor.
+    Continue,
+    /// Discard the message. The reason is counted per interceptor in
+    /// [`InterceptorStats::dropped`].
+    Drop(&'static str),
+    /// Pass this message on instead.
+    Replace(Message),
+}
+
+/// Which way the messages an interceptor sees are going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Outbound,
+    Inbound,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Outbound => "outbound",
+            Direction::Inbound => "inbound",
+        })
+    }
+}
+
+/// Inspects or rewrites a message; `Err(reason)` fails it.
+type InterceptFn = dyn Fn(&mut Message, &ContextMap) -> Result<InterceptAction, String>;
+
+/// An interceptor as registered on the builder.
+#[derive(Clone)]
+pub(crate) struct Interceptor {
+    name: Rc<str>,
+    direction: Direction,
+    f: Rc<InterceptFn>,
+}
+
+impl Interceptor {
+    pub(crate) fn new(
+        name: &str,
+        direction: Direction,
+        f: impl Fn(&mut Message, &ContextMap) -> Result<InterceptAction, String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            direction,
+            f: Rc::new(f),
+        }
+    }
+}
+
+impl fmt::Debug for Interceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interceptor({} {:?})", self.direction, self.name)
+    }
+}
+
+/// Counters of one interceptor on one connection, from
+/// [`WsClient::interceptor_stats`](crate::WsClient::interceptor_stats).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterceptorStats {
+    pub name: String,
+    pub direction: Direction,
+    /// Messages the interceptor was called with.
+    pub calls: u64,
+    pub replaced: u64,
+    /// Messages dropped, per reason.
+    pub dropped: BTreeMap<&'static str, u64>,
+    /// Calls that returned an error.
+    pub errors: u64,
+    /// Time spent in the interceptor over all calls.
+    pub time: Duration,
+}
+
+/// The interceptors of one connection, each with its counters.
+#[derive(Default)]
+pub(crate) struct InterceptorChain {
+    entries: Vec<(Interceptor, RefCell<InterceptorStats>)>,
+}
+
+impl InterceptorChain {
+    pub(crate) fn new(interceptors: &[Interceptor]) -> Self {
+        let entries = interceptors
+            .iter()
+            .map(|i| {
+                let stats = InterceptorStats {
+                    name: i.name.to_string(),
+                    direction: i.direction,
+                    calls: 0,
+                    replaced: 0,
+                    dropped: BTreeMap::new(),
+                    errors: 0,
+                    time: Duration::ZERO,
+                };
+                (i.clone(), RefCell::new(stats))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Run the interceptors for `direction` in registration order. `None`
+    /// means one of them dropped the message.
+    pub(crate) fn run(
+        &self,
+        direction: Direction,
+        mut message: Message,
+        context: &ContextMap,
+    ) -> Result<Option<Message>, WsError> {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(i, _)| i.direction == direction);
+        for (interceptor, stats) in entries {
+            let started = Instant::now();
+            let result = (interceptor.f)(&mut message, context);
+            let mut stats = stats.borrow_mut();
+            stats.calls += 1;
+            stats.time += started.elapsed();
+            match result {
+                Ok(InterceptAction::Continue) => {}
+                Ok(InterceptAction::Replace(replacement)) => {
+                    stats.replaced += 1;
+                    message = replacement;
+                }
+                Ok(InterceptAction::Drop(reason)) => {
+                    *stats.dropped.entry(reason).or_default() += 1;
+                    return Ok(None);
+                }
+                Err(reason) => {
+                    stats.errors += 1;
+                    return Err(WsError::Intercept {
+                        name: interceptor.name.to_string(),
+                        direction,
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(Some(message))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn stats(&self) -> Vec<InterceptorStats> {
+        self.entries
+            .iter()
+            .map(|(_, stats)| stats.borrow().clone())
+            .collect()
+    }
+}
+
+impl fmt::Debug for InterceptorChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InterceptorChain({})", self.entries.len())
+    }
+}
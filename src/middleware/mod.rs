@@ -0,0 +1,31 @@
+//! Hooks that observe traffic on a [`WsClient`](crate::WsClient).
+//!
+//! Register a [`Middleware`] with
+//! [`WsClientBuilder::middleware`](crate::WsClientBuilder::middleware).
+//! Hooks run inline on the receive path, so they should be cheap.
+//!
+//! Interceptors go further and may rewrite, drop or fail messages in either
+//! direction; see
+//! [`WsClientBuilder::intercept_outbound`](crate::WsClientBuilder::intercept_outbound).
+
+use fastwebsockets::Frame;
+
+use crate::Message;
+use crate::context::ContextMap;
+
+pub mod intercept;
+pub mod timestamp;
+
+pub use intercept::{Direction, InterceptAction, InterceptorStats};
+pub use timestamp::{TimestampMiddleware, TimestampedFrame};
+
+/// Observes frames and messages as [`WsClient::recv`](crate::WsClient::recv)
+/// and [`try_recv`](crate::WsClient::try_recv) process them, with the
+/// connection's [`ContextMap`]. Every hook has a no-op default.
+pub trait Middleware {
+    /// A frame was read from the connection, before fragments are reassembled.
+    fn after_read(&self, _frame: &Frame<'_>, _context: &ContextMap) {}
+
+    /// A message is about to be returned to the caller.
+    fn before_deliver(&self, _message: &Message, _context: &ContextMap) {}
+}
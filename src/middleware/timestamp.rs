@@ -0,0 +1,134 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Instant;
+
+use fastwebsockets::Frame;
+
+use super::Middleware;
+use crate::Message;
+use crate::context::ContextMap;
+
+/// A frame paired with the wall-clock time it was read.
+///
+/// `received_at` is nanoseconds since the Unix epoch and is laid out as the
+/// first 8 bytes of the struct.
+#[repr(C)]
+pub struct TimestampedFrame<'f> {
+    pub received_at: u64,
+    pub frame: Frame<'f>,
+}
+
+/// Records when frames are read, with nanosecond wall-clock timestamps from
+/// `clock_gettime(CLOCK_REALTIME)`.
+///
+/// Timestamps are taken in [`after_read`](Middleware::after_read), as soon as
+/// `fastwebsockets` has parsed the frame, and are strictly increasing even if
+/// the wall clock steps back. [`avg_latency_ns`](Self::avg_latency_ns)
+/// reports how long messages spent between their first frame being read and
+/// being returned by `recv`, which covers reassembly and other middleware.
+///
+/// Clones share the same state, so keep one to read the figures after
+/// passing another to the builder.
+#[derive(Clone, Default)]
+pub struct TimestampMiddleware {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    last: Cell<u64>,
+    /// Timestamp and read instant of the first frame of the pending message.
+    pending: Cell<Option<(u64, Instant)>>,
+    /// First-frame timestamp of the last delivered message.
+    delivered: Cell<Option<u64>>,
+    latency_ns: Cell<u128>,
+    messages: Cell<u64>,
+}
+
+impl TimestampMiddleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timestamp of the most recently read frame, if any.
+    pub fn last_received_at(&self) -> Option<u64> {
+        Some(self.inner.last.get()).filter(|&t| t != 0)
+    }
+
+    /// When the first frame of the last message returned by `recv` was read.
+    pub fn message_received_at(&self) -> Option<u64> {
+        self.inner.delivered.get()
+    }
+
+    /// Mean nanoseconds from reading a message's first frame to delivering
+    /// it; `0` before the first message.
+    pub fn avg_latency_ns(&self) -> u64 {
+        match self.inner.messages.get() {
+            0 => 0,
+            n => (self.inner.latency_ns.get() / n as u128) as u64,
+        }
+    }
+
+    /// Stamp a frame read outside `recv` (e.g. from `client.ws`) with the
+    /// current time.
+    pub fn stamp<'f>(&self, frame: Frame<'f>) -> TimestampedFrame<'f> {
+        TimestampedFrame {
+            received_at: self.next_timestamp(),
+            frame,
+        }
+    }
+
+    fn next_timestamp(&self) -> u64 {
+        let now = realtime_ns().max(self.inner.last.get() + 1);
+        self.inner.last.set(now);
+        now
+    }
+}
+
+impl Middleware for TimestampMiddleware {
+    fn after_read(&self, _frame: &Frame<'_>, _context: &ContextMap) {
+        let now = self.next_timestamp();
+        if self.inner.pending.get().is_none() {
+            self.inner.pending.set(Some((now, Instant::now())));
+        }
+    }
+
+    fn before_deliver(&self, _message: &Message, _context: &ContextMap) {
+        if let Some((received_at, read)) = self.inner.pending.take() {
+            let inner = &self.inner;
+            inner
+                .latency_ns
+                .set(inner.latency_ns.get() + read.elapsed().as_nanos());
+            inner.messages.set(inner.messages.get() + 1);
+            inner.delivered.set(Some(received_at));
+        }
+    }
+}
+
+impl fmt::Debug for TimestampMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimestampMiddleware")
+            .field("last_received_at", &self.last_received_at())
+            .field("avg_latency_ns", &self.avg_latency_ns())
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+fn realtime_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid, writable timespec and CLOCK_REALTIME is always supported.
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(unix))]
+fn realtime_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
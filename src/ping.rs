@@ -0,0 +1,193 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use monoio::task::JoinHandle;
+
+use crate::cancel::CancellationToken;
+use crate::client::{AnyStream, PendingWrite, SharedStream, WeakStream, WriteLock};
+use crate::{Message, WsError};
+
+/// Handle to a ping task started with [`WsClient::background_ping`].
+///
+/// The task sends a WS Ping every `interval`, independently of whether the
+/// application is inside `recv`, and calls `on_timeout` when no Pong has
+/// arrived for `2 * interval` (again after every further `2 * interval`
+/// without one). Pongs are seen by `recv`/`try_recv`, so the application must
+/// keep reading for them to count.
+///
+/// Dropping the handle cancels the task, as does [`stop`](Self::stop) or
+/// cancelling the connection's
+/// [`cancellation_token`](crate::WsClient::cancellation_token). A ping
+/// already being written is finished first, so no partial frame is left on
+/// the wire. The task also ends once the connection has been dropped.
+///
+/// [`WsClient::background_ping`]: crate::WsClient::background_ping
+pub struct BackgroundPing {
+    shared: Rc<PingShared>,
+    _task: JoinHandle<()>,
+}
+
+/// State shared by the ping task, its handle and the client's receive path.
+pub(crate) struct PingShared {
+    last_pong: Cell<Instant>,
+    /// Sequence number and send time of the latest ping.
+    in_flight: Cell<Option<(u64, Instant)>>,
+    rtt: Cell<Option<Duration>>,
+    stopped: Cell<bool>,
+    waker: Cell<Option<Waker>>,
+    cancel: Option<CancellationToken>,
+    /// Write error that ended the task.
+    error: RefCell<Option<WsError>>,
+}
+
+impl PingShared {
+    /// Record a Pong seen by the client. Returns the round trip when it
+    /// answers the latest ping.
+    pub(crate) fn pong(&self, payload: &[u8]) -> Option<Duration> {
+        let now = Instant::now();
+        self.last_pong.set(now);
+        let (seq, sent) = self.in_flight.get()?;
+        if payload != seq.to_be_bytes() {
+            return None;
+        }
+        self.rtt.set(Some(now - sent));
+        self.in_flight.set(None);
+        Some(now - sent)
+    }
+
+    /// Sleep for `duration`; `false` if the task was stopped meanwhile.
+    async fn sleep(&self, duration: Duration) -> bool {
+        let stopped = poll_fn(|cx| {
+            if self.stopped.get() {
+                return Poll::Ready(());
+            }
+            self.waker.set(Some(cx.waker().clone()));
+            Poll::Pending
+        });
+        let cancelled = async {
+            match &self.cancel {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        monoio::select! {
+            _ = monoio::time::sleep(duration) => !self.stopped.get(),
+            _ = stopped => false,
+            _ = cancelled => false,
+        }
+    }
+}
+
+impl BackgroundPing {
+    pub(crate) fn spawn(
+        io: &SharedStream<AnyStream>,
+        lock: Rc<WriteLock>,
+        mask: bool,
+        interval: Duration,
+        on_timeout: Box<dyn Fn() + Send>,
+        cancel: Option<CancellationToken>,
+    ) -> Self {
+        let shared = Rc::new(PingShared {
+            last_pong: Cell::new(Instant::now()),
+            in_flight: Cell::new(None),
+            rtt: Cell::new(None),
+            stopped: Cell::new(false),
+            waker: Cell::new(None),
+            cancel,
+            error: RefCell::new(None),
+        });
+        let task = monoio::spawn(run(
+            shared.clone(),
+            io.downgrade(),
+            lock,
+            mask,
+            interval,
+            on_timeout,
+        ));
+        Self {
+            shared,
+            _task: task,
+        }
+    }
+
+    pub(crate) fn shared(&self) -> Rc<PingShared> {
+        self.shared.clone()
+    }
+
+    /// Round-trip time of the most recently answered ping.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.shared.rtt.get()
+    }
+
+    /// Cancel the task. Returns the write error that ended it early, if any.
+    pub fn stop(self) -> Result<(), WsError> {
+        self.cancel();
+        match self.shared.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn cancel(&self) {
+        self.shared.stopped.set(true);
+        if let Some(waker) = self.shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for BackgroundPing {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+impl fmt::Debug for BackgroundPing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackgroundPing")
+            .field("last_rtt", &self.last_rtt())
+            .field("stopped", &self.shared.stopped.get())
+            .finish()
+    }
+}
+
+async fn run(
+    shared: Rc<PingShared>,
+    io: WeakStream<AnyStream>,
+    lock: Rc<WriteLock>,
+    mask: bool,
+    interval: Duration,
+    on_timeout: Box<dyn Fn() + Send>,
+) {
+    let mut seq = 0u64;
+    loop {
+        let Some(mut io) = SharedStream::upgrade(&io) else {
+            return;
+        };
+        seq += 1;
+        let mut ping = PendingWrite::frame(Message::Ping(seq.to_be_bytes().to_vec()), mask);
+        let result = {
+            let _write = lock.lock().await;
+            ping.write_to(&mut io).await
+        };
+        drop(io);
+        if let Err(e) = result {
+            *shared.error.borrow_mut() = Some(e);
+            return;
+        }
+        shared.in_flight.set(Some((seq, Instant::now())));
+
+        if !shared.sleep(interval).await {
+            return;
+        }
+        let now = Instant::now();
+        if now - shared.last_pong.get() >= 2 * interval {
+            on_timeout();
+            shared.last_pong.set(now);
+        }
+    }
+}
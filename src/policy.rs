@@ -0,0 +1,189 @@
+//! Vetting which endpoints a [`WsClientBuilder`] may connect to.
+//!
+//! An [`EndpointPolicy`] set with
+//! [`WsClientBuilder::endpoint_policy`] runs on every connect the builder
+//! makes, including each reconnect of a
+//! [`WsReconnectClient`](crate::reconnect::WsReconnectClient) and each
+//! attempt of `connect_list_with_retry`. The client never follows HTTP
+//! redirects (a `3xx` answer to the upgrade fails with
+//! [`UpgradeErr::Status`](crate::http_upgrade::UpgradeErr::Status)), so a
+//! server cannot send it on to an address the policy has not seen.
+//!
+//! [`WsClientBuilder`]: crate::WsClientBuilder
+//! [`WsClientBuilder::endpoint_policy`]: crate::WsClientBuilder::endpoint_policy
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::url::WsUrl;
+
+/// A connection vetoed by an [`EndpointPolicy`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("connection to {host} denied by endpoint policy: {reason}")]
+pub struct PolicyError {
+    pub host: String,
+    pub reason: String,
+}
+
+impl PolicyError {
+    pub fn new(url: &WsUrl<'_>, reason: impl Into<String>) -> Self {
+        Self {
+            host: url.host.clone().into_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Decides whether a connection may be made.
+///
+/// `check` is called twice per connect: right after the URL is parsed, with
+/// no addresses, and again with every address the host resolved to, before
+/// any of them is dialed. Only the addresses that were checked are dialed,
+/// so a name cannot resolve differently in between. Through a
+/// [`proxy`](crate::WsClientBuilder::proxy) the name is resolved and
+/// checked before the proxy is dialed, and the proxy is asked to tunnel to
+/// the first checked address instead of the name; TLS still verifies the
+/// name.
+///
+/// Implemented for closures with the same signature.
+pub trait EndpointPolicy {
+    fn check(&self, url: &WsUrl<'_>, addrs: &[SocketAddr]) -> Result<(), PolicyError>;
+}
+
+impl<F> EndpointPolicy for F
+where
+    F: Fn(&WsUrl<'_>, &[SocketAddr]) -> Result<(), PolicyError>,
+{
+    fn check(&self, url: &WsUrl<'_>, addrs: &[SocketAddr]) -> Result<(), PolicyError> {
+        self(url, addrs)
+    }
+}
+
+/// A class of addresses that is not publicly routable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressRange {
+    /// `127.0.0.0/8` and `::1`, plus the unspecified `0.0.0.0/8` and `::`,
+    /// which reach the local host too.
+    Loopback,
+    /// `169.254.0.0/16` and `fe80::/10`, home of cloud metadata services
+    /// such as `169.254.169.254`.
+    LinkLocal,
+    /// The RFC 1918 ranges `10.0.0.0/8`, `172.16.0.0/12` and
+    /// `192.168.0.0/16`.
+    Private,
+    /// `fc00::/7`.
+    UniqueLocal,
+}
+
+impl AddressRange {
+    /// The range `ip` falls in, if any. IPv4-mapped IPv6 addresses are
+    /// classified by their IPv4 address.
+    pub fn of(ip: IpAddr) -> Option<Self> {
+        match ip {
+            IpAddr::V4(ip) => Self::of_v4(ip),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => Self::of_v4(ip),
+                None => Self::of_v6(ip),
+            },
+        }
+    }
+
+    fn of_v4(ip: Ipv4Addr) -> Option<Self> {
+        if ip.is_loopback() || ip.octets()[0] == 0 {
+            Some(AddressRange::Loopback)
+        } else if ip.is_link_local() {
+            Some(AddressRange::LinkLocal)
+        } else if ip.is_private() {
+            Some(AddressRange::Private)
+        } else {
+            None
+        }
+    }
+
+    fn of_v6(ip: Ipv6Addr) -> Option<Self> {
+        let first = ip.segments()[0];
+        if ip.is_loopback() || ip.is_unspecified() {
+            Some(AddressRange::Loopback)
+        } else if first & 0xffc0 == 0xfe80 {
+            Some(AddressRange::LinkLocal)
+        } else if first & 0xfe00 == 0xfc00 {
+            Some(AddressRange::UniqueLocal)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AddressRange::Loopback => "loopback",
+            AddressRange::LinkLocal => "link-local",
+            AddressRange::Private => "private",
+            AddressRange::UniqueLocal => "unique-local",
+        })
+    }
+}
+
+/// Built-in policy rejecting every [`AddressRange`] unless allowed.
+///
+/// A host given as an IP address is checked as soon as the URL is parsed.
+/// A name is checked by what it resolves to, with or without a proxy; one
+/// private address among public ones rejects the connect.
+///
+/// ```
+/// use websockets_monoio::WsClientBuilder;
+/// use websockets_monoio::policy::{AddressRange, DenyPrivateNetworks};
+///
+/// let builder = WsClientBuilder::new().endpoint_policy(
+///     DenyPrivateNetworks::new()
+///         .allow(AddressRange::Loopback)
+///         .allow_addr("10.1.2.3".parse().unwrap()),
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DenyPrivateNetworks {
+    allowed_ranges: Vec<AddressRange>,
+    allowed_addrs: Vec<IpAddr>,
+}
+
+impl DenyPrivateNetworks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow every address in `range`.
+    pub fn allow(mut self, range: AddressRange) -> Self {
+        self.allowed_ranges.push(range);
+        self
+    }
+
+    /// Allow `addr` alone.
+    pub fn allow_addr(mut self, addr: IpAddr) -> Self {
+        self.allowed_addrs.push(addr);
+        self
+    }
+
+    fn check_ip(&self, url: &WsUrl<'_>, ip: IpAddr) -> Result<(), PolicyError> {
+        match AddressRange::of(ip) {
+            Some(range)
+                if !self.allowed_ranges.contains(&range) && !self.allowed_addrs.contains(&ip) =>
+            {
+                Err(PolicyError::new(url, format!("{ip} is {range}")))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl EndpointPolicy for DenyPrivateNetworks {
+    fn check(&self, url: &WsUrl<'_>, addrs: &[SocketAddr]) -> Result<(), PolicyError> {
+        let literal = url.host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse::<IpAddr>() {
+            self.check_ip(url, ip)?;
+        }
+        addrs
+            .iter()
+            .try_for_each(|addr| self.check_ip(url, addr.ip()))
+    }
+}
@@ -0,0 +1,330 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::event::EventSink;
+use crate::{Message, WsClient, WsClientBuilder, WsError, WsEvent};
+
+/// Sizing and health checking for a [`WsPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Connections opened up front and never evicted for being idle.
+    pub min_connections: usize,
+    /// Upper bound on open connections, idle and checked out together.
+    pub max_connections: usize,
+    /// Idle connections older than this are closed on the next checkout.
+    pub idle_timeout: Duration,
+    /// Connections idle for longer than this are pinged before being handed out.
+    pub health_check_after: Duration,
+    /// How long the health check waits for the Pong.
+    pub health_check_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_connections: 0,
+            max_connections: 8,
+            idle_timeout: Duration::from_secs(60),
+            health_check_after: Duration::from_secs(10),
+            health_check_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Why a pooled connection was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// It sat idle for longer than [`PoolConfig::idle_timeout`].
+    IdleTimeout,
+    /// It did not answer the checkout ping in time.
+    HealthCheckFailed,
+    /// It was returned after an error or after the peer's Close.
+    Broken,
+}
+
+/// Counters kept by a [`WsPool`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Checkouts served by an idle connection.
+    pub hits: u64,
+    /// Checkouts that had to open a new connection.
+    pub misses: u64,
+    pub evictions: u64,
+    /// Connections currently open, idle or checked out.
+    pub open: usize,
+    pub idle: usize,
+}
+
+/// A pool of warm connections to one endpoint.
+///
+/// [`checkout`](Self::checkout) hands out an idle connection when there is
+/// one and dials a new one otherwise. Dropping the returned [`PooledClient`]
+/// puts the connection back unless it is no longer
+/// [usable](WsClient::is_usable). Checkouts and evictions are reported to the
+/// builder's [`on_event`](WsClientBuilder::on_event) sink.
+///
+/// The pool is single-threaded like the rest of monoio; clones share it.
+/// Health checks use `monoio::time`, so the runtime must be built with the
+/// timer enabled.
+#[derive(Clone)]
+pub struct WsPool {
+    inner: Rc<PoolInner>,
+}
+
+struct PoolInner {
+    url: String,
+    builder: WsClientBuilder,
+    config: PoolConfig,
+    events: Option<EventSink>,
+    idle: RefCell<VecDeque<Idle>>,
+    open: Cell<usize>,
+    stats: Cell<PoolStats>,
+}
+
+struct Idle {
+    client: WsClient,
+    since: Instant,
+}
+
+impl WsPool {
+    /// Create a pool and open `config.min_connections` connections.
+    pub async fn connect(
+        builder: WsClientBuilder,
+        url: &str,
+        config: PoolConfig,
+    ) -> Result<Self, WsError> {
+        let pool = Self {
+            inner: Rc::new(PoolInner {
+                url: url.to_owned(),
+                events: builder.events.clone(),
+                builder,
+                config,
+                idle: RefCell::new(VecDeque::new()),
+                open: Cell::new(0),
+                stats: Cell::new(PoolStats::default()),
+            }),
+        };
+        for _ in 0..config.min_connections.min(config.max_connections) {
+            let client = pool.inner.builder.connect(url).await?;
+            pool.inner.open.set(pool.inner.open.get() + 1);
+            pool.inner.idle.borrow_mut().push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+        Ok(pool)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.inner.url
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.inner.config
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            open: self.inner.open.get(),
+            idle: self.inner.idle.borrow().len(),
+            ..self.inner.stats.get()
+        }
+    }
+
+    /// Take a connection from the pool, dialing a new one if none is idle.
+    ///
+    /// Fails with [`WsError::PoolExhausted`] when `max_connections` are
+    /// already checked out. Messages that arrive on an idle connection while
+    /// it is being health checked are discarded.
+    pub async fn checkout(&self) -> Result<PooledClient, WsError> {
+        self.evict_expired();
+        while let Some(idle) = self.pop_idle() {
+            let mut client = idle.client;
+            // Dropping this future mid-check drops the connection with it.
+            let slot = OpenSlot { pool: self };
+            if idle.since.elapsed() > self.inner.config.health_check_after
+                && !self.health_check(&mut client).await
+            {
+                slot.disarm();
+                self.evict(EvictionReason::HealthCheckFailed);
+                continue;
+            }
+            slot.disarm();
+            self.record_checkout(true);
+            return Ok(self.guard(client));
+        }
+
+        let max = self.inner.config.max_connections;
+        if self.inner.open.get() >= max {
+            return Err(WsError::PoolExhausted { max });
+        }
+        // Count the connection as open while dialing so concurrent checkouts
+        // cannot exceed the limit.
+        self.inner.open.set(self.inner.open.get() + 1);
+        let slot = OpenSlot { pool: self };
+        let client = self.inner.builder.connect(&self.inner.url).await?;
+        slot.disarm();
+        self.record_checkout(false);
+        Ok(self.guard(client))
+    }
+
+    /// Most recently returned first, so the warmest connection is reused and
+    /// surplus ones age out.
+    fn pop_idle(&self) -> Option<Idle> {
+        self.inner.idle.borrow_mut().pop_back()
+    }
+
+    fn evict_expired(&self) {
+        let timeout = self.inner.config.idle_timeout;
+        loop {
+            if self.inner.open.get() <= self.inner.config.min_connections {
+                return;
+            }
+            let mut idle = self.inner.idle.borrow_mut();
+            match idle.front() {
+                Some(oldest) if oldest.since.elapsed() > timeout => {
+                    idle.pop_front();
+                }
+                _ => return,
+            }
+            drop(idle);
+            self.evict(EvictionReason::IdleTimeout);
+        }
+    }
+
+    async fn health_check(&self, client: &mut WsClient) -> bool {
+        let check = async {
+            if client.send(Message::Ping(Vec::new())).await.is_err() {
+                return false;
+            }
+            loop {
+                match client.recv().await {
+                    Ok(Message::Pong(_)) => return true,
+                    Ok(Message::Close(_)) | Err(_) => return false,
+                    Ok(_) => {}
+                }
+            }
+        };
+        monoio::time::timeout(self.inner.config.health_check_timeout, check)
+            .await
+            .unwrap_or(false)
+    }
+
+    fn guard(&self, client: WsClient) -> PooledClient {
+        PooledClient {
+            client: Some(client),
+            pool: self.clone(),
+        }
+    }
+
+    fn record_checkout(&self, reused: bool) {
+        let mut stats = self.inner.stats.get();
+        if reused {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        self.inner.stats.set(stats);
+        self.emit(WsEvent::PoolCheckout { reused });
+    }
+
+    /// Account for a connection that has already been dropped.
+    fn evict(&self, reason: EvictionReason) {
+        self.inner.open.set(self.inner.open.get() - 1);
+        let mut stats = self.inner.stats.get();
+        stats.evictions += 1;
+        self.inner.stats.set(stats);
+        self.emit(WsEvent::PoolEviction { reason });
+    }
+
+    fn emit(&self, event: WsEvent) {
+        if let Some(events) = &self.inner.events {
+            events.emit(&event);
+        }
+    }
+
+    fn release(&self, client: WsClient) {
+        if client.is_usable() {
+            self.inner.idle.borrow_mut().push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        } else {
+            drop(client);
+            self.evict(EvictionReason::Broken);
+        }
+    }
+}
+
+impl fmt::Debug for WsPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsPool")
+            .field("url", &self.inner.url)
+            .field("config", &self.inner.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// A connection counted in `open` that no [`PooledClient`] or idle entry
+/// holds yet. Unless disarmed, dropping it gives the count back, so a
+/// checkout cancelled while dialing or health checking does not use up
+/// capacity for good.
+struct OpenSlot<'a> {
+    pool: &'a WsPool,
+}
+
+impl OpenSlot<'_> {
+    fn disarm(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for OpenSlot<'_> {
+    fn drop(&mut self) {
+        let open = &self.pool.inner.open;
+        open.set(open.get() - 1);
+    }
+}
+
+/// A connection checked out of a [`WsPool`]; returned to the pool on drop.
+pub struct PooledClient {
+    client: Option<WsClient>,
+    pool: WsPool,
+}
+
+impl PooledClient {
+    /// Take the connection out of the pool for good. It no longer counts
+    /// towards `max_connections`.
+    pub fn detach(mut self) -> WsClient {
+        let client = self.client.take().expect("client present until drop");
+        self.pool.inner.open.set(self.pool.inner.open.get() - 1);
+        client
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = WsClient;
+
+    fn deref(&self) -> &WsClient {
+        self.client.as_ref().expect("client present until drop")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut WsClient {
+        self.client.as_mut().expect("client present until drop")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.release(client);
+        }
+    }
+}
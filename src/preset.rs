@@ -0,0 +1,72 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::WsClientBuilder;
+use crate::keepalive::Keepalive;
+use crate::reconnect::Backoff;
+
+/// A named bundle of [`WsClientBuilder`] settings for a kind of workload.
+///
+/// Applying a preset only fills in builder fields, so any option set
+/// afterwards overrides the preset's value. The preset last applied is
+/// recorded in [`ConnectionInfo::preset`](crate::ConnectionInfo::preset).
+///
+/// Every send is written and flushed before it completes whatever the
+/// preset; they differ in buffer sizes, socket options and timeouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum Preset {
+    /// Small messages where every millisecond counts: 4 KiB buffers,
+    /// `TCP_NODELAY`, and a 2 second connect timeout.
+    LatencySensitive,
+    /// Bulk transfer: 64 KiB buffers, header and payload gathered into one
+    /// vectored write on plain TCP, and a 10 second connect timeout.
+    Throughput,
+    /// Subscriptions held open for hours: a WS Ping every 15 seconds that has
+    /// to be answered within 45, `TCP_NODELAY`, a 10 second connect timeout,
+    /// and a reconnect backoff capped at 30 seconds for
+    /// [`WsReconnectClient`](crate::reconnect::WsReconnectClient).
+    LongLivedFeed,
+}
+
+impl Preset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Preset::LatencySensitive => "latency_sensitive",
+            Preset::Throughput => "throughput",
+            Preset::LongLivedFeed => "long_lived_feed",
+        }
+    }
+
+    pub(crate) fn apply(self, builder: WsClientBuilder) -> WsClientBuilder {
+        let builder = match self {
+            Preset::LatencySensitive => builder
+                .buffer_size(4 * 1024)
+                .tcp_nodelay(true)
+                .connect_timeout(Duration::from_secs(2)),
+            Preset::Throughput => builder
+                .buffer_size(64 * 1024)
+                .vectored_writes(true)
+                .connect_timeout(Duration::from_secs(10)),
+            Preset::LongLivedFeed => builder
+                .keepalive(Keepalive::ping(
+                    Duration::from_secs(15),
+                    Duration::from_secs(45),
+                ))
+                .tcp_nodelay(true)
+                .connect_timeout(Duration::from_secs(10))
+                .reconnect_backoff(Backoff {
+                    max_interval: Duration::from_secs(30),
+                    ..Backoff::default()
+                }),
+        };
+        builder.with_preset(self)
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::http_upgrade::UpgradeErr;
+use crate::info::ConnectionInfo;
+use crate::message::{CloseFrame, Message};
+use crate::time::{ConnectPhase, PhaseClock};
+use crate::{WsClientBuilder, WsError};
+
+/// How long a probe waits for the server to answer its Close.
+pub const PROBE_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of a successful [`WsClient::probe`](crate::WsClient::probe).
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    /// What the handshake negotiated, with per-phase timings.
+    pub info: ConnectionInfo,
+    /// The server's answer to the probe's Close. `None` if it hung up
+    /// without one or did not answer within [`PROBE_CLOSE_TIMEOUT`]; the
+    /// probe still succeeded.
+    pub close: Option<CloseFrame>,
+    /// From starting to connect until the close finished.
+    pub elapsed: Duration,
+}
+
+/// Where a probe failed.
+///
+/// The classes and their [`as_str`](Self::as_str) names are stable, so CI
+/// can branch on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProbeFailure {
+    /// The URL or the builder's options were rejected before dialing,
+    /// including by an endpoint policy.
+    Config,
+    /// The host name did not resolve.
+    Dns,
+    /// TCP connect failed, or the connection broke outside TLS.
+    Tcp,
+    /// The proxy refused or mangled the tunnel.
+    Proxy,
+    /// TLS handshake failed, e.g. an untrusted certificate.
+    Tls,
+    /// The upgrade was answered with 401 or 403.
+    Auth(u16),
+    /// The upgrade was answered with another status than 101.
+    Status(u16),
+    /// The server's handshake response was malformed or incomplete.
+    Protocol,
+    /// A deadline or timeout ran out.
+    Timeout,
+}
+
+impl ProbeFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeFailure::Config => "config",
+            ProbeFailure::Dns => "dns",
+            ProbeFailure::Tcp => "tcp",
+            ProbeFailure::Proxy => "proxy",
+            ProbeFailure::Tls => "tls",
+            ProbeFailure::Auth(_) => "auth",
+            ProbeFailure::Status(_) => "status",
+            ProbeFailure::Protocol => "protocol",
+            ProbeFailure::Timeout => "timeout",
+        }
+    }
+
+    /// Classify `error`, raised while `phase` was running.
+    fn classify(error: &WsError, phase: Option<ConnectPhase>) -> Self {
+        match error {
+            WsError::Url(_) | WsError::Policy(_) | WsError::MemoryBudget { .. } => {
+                ProbeFailure::Config
+            }
+            WsError::Proxy(_) => ProbeFailure::Proxy,
+            WsError::Tls(_) => ProbeFailure::Tls,
+            WsError::Timeout(_) | WsError::FirstMessageTimeout(_) => ProbeFailure::Timeout,
+            WsError::Upgrade(UpgradeErr::Status(code @ (401 | 403))) => ProbeFailure::Auth(*code),
+            WsError::Upgrade(UpgradeErr::Status(code)) => ProbeFailure::Status(*code),
+            WsError::Upgrade(UpgradeErr::Io(_)) | WsError::Io(_) => match phase {
+                Some(ConnectPhase::Resolve) => ProbeFailure::Dns,
+                Some(ConnectPhase::Tls) => ProbeFailure::Tls,
+                _ => ProbeFailure::Tcp,
+            },
+            _ => ProbeFailure::Protocol,
+        }
+    }
+}
+
+impl fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeFailure::Auth(code) | ProbeFailure::Status(code) => {
+                write!(f, "{} ({code})", self.as_str())
+            }
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+/// A failed [`WsClient::probe`](crate::WsClient::probe).
+#[derive(thiserror::Error, Debug)]
+#[error("probe failed at {kind}: {error}")]
+pub struct ProbeError {
+    pub kind: ProbeFailure,
+    #[source]
+    pub error: WsError,
+}
+
+pub(crate) async fn probe(opts: &WsClientBuilder, url: &str) -> Result<ProbeReport, ProbeError> {
+    let started = Instant::now();
+    let mut clock = PhaseClock::tracking();
+    let mut client = match opts.connect_timed(url, &mut clock).await {
+        Ok(client) => client,
+        Err(error) => {
+            return Err(ProbeError {
+                kind: ProbeFailure::classify(&error, clock.current()),
+                error,
+            });
+        }
+    };
+
+    let close = Message::Close(Some(CloseFrame {
+        code: 1000,
+        reason: String::new(),
+    }));
+    let close = match client.send(close).await {
+        Ok(()) => client
+            .consume_until_close_with_timeout(PROBE_CLOSE_TIMEOUT)
+            .await
+            .ok(),
+        Err(WsError::ClosedByPeer(close)) => Some(close),
+        Err(_) => None,
+    };
+    Ok(ProbeReport {
+        info: client.info().clone(),
+        close,
+        elapsed: started.elapsed(),
+    })
+}
@@ -0,0 +1,147 @@
+use base64::Engine;
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+
+/// How the client talks to the proxy itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyScheme {
+    /// Plaintext to the proxy (`http://`).
+    Http,
+    /// TLS to the proxy (`https://`), with the tunnel inside that session.
+    Https,
+}
+
+/// An HTTP proxy that opens tunnels with `CONNECT`.
+///
+/// Every combination is supported: `ws://` and `wss://` targets through an
+/// `http://` or `https://` proxy. With an `https://` proxy and a `wss://`
+/// target the origin's TLS session runs inside the proxy's.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub scheme: ProxyScheme,
+    pub host: String,
+    pub port: u16,
+    authorization: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProxyErr {
+    #[error("proxy URL must be http://host[:port] or https://host[:port]")]
+    Url,
+    #[error("proxy refused CONNECT with status {0}")]
+    Status(u16),
+    #[error("malformed CONNECT response")]
+    Malformed,
+    #[error("I/O error talking to the proxy")]
+    Io(#[from] std::io::Error),
+}
+
+impl Proxy {
+    /// Parse `http://host[:port]` or `https://host[:port]`. The port defaults
+    /// to 80 and 443 respectively; a trailing `/` is accepted.
+    pub fn parse(url: &str) -> Result<Self, ProxyErr> {
+        let (scheme, rest, default_port) = if let Some(rest) = url.strip_prefix("http://") {
+            (ProxyScheme::Http, rest, 80)
+        } else if let Some(rest) = url.strip_prefix("https://") {
+            (ProxyScheme::Https, rest, 443)
+        } else {
+            return Err(ProxyErr::Url);
+        };
+        let authority = rest.strip_suffix('/').unwrap_or(rest);
+        if authority.is_empty() || authority.contains(['/', '@']) {
+            return Err(ProxyErr::Url);
+        }
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| ProxyErr::Url)?),
+            None => (authority, default_port),
+        };
+        Ok(Self {
+            scheme,
+            host: host.to_owned(),
+            port,
+            authorization: None,
+        })
+    }
+
+    /// Authenticate to the proxy with `Proxy-Authorization: Basic`.
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+        self.authorization = Some(format!("Basic {credentials}"));
+        self
+    }
+
+    pub(crate) fn authorization(&self) -> Option<&str> {
+        self.authorization.as_deref()
+    }
+}
+
+impl std::fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Proxy")
+            .field("scheme", &self.scheme)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field(
+                "authorization",
+                &self.authorization.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Ask the proxy on `stream` to open a tunnel to `host:port`.
+///
+/// Works over any monoio stream, including a TLS session to the proxy. On
+/// success `stream` carries the tunnel. The target must not speak first,
+/// which holds for TLS and the WebSocket upgrade: any bytes past the `200`
+/// response are rejected as [`ProxyErr::Malformed`].
+pub async fn http_connect<S: AsyncReadRent + AsyncWriteRent>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    authorization: Option<&str>,
+) -> Result<(), ProxyErr> {
+    let authority = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some(auth) = authorization {
+        request.push_str("Proxy-Authorization: ");
+        request.push_str(auth);
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    let (res, _) = stream.write_all(request.into_bytes()).await;
+    res?;
+    stream.flush().await?;
+
+    let mut response = Vec::with_capacity(512);
+    loop {
+        let (res, chunk) = stream.read(Vec::with_capacity(512)).await;
+        if res? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        response.extend_from_slice(&chunk);
+
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Response::new(&mut headers);
+        match parsed.parse(&response).map_err(|_| ProxyErr::Malformed)? {
+            httparse::Status::Complete(len) => {
+                let code = parsed.code.ok_or(ProxyErr::Malformed)?;
+                if !(200..300).contains(&code) {
+                    return Err(ProxyErr::Status(code));
+                }
+                if len != response.len() {
+                    return Err(ProxyErr::Malformed);
+                }
+                return Ok(());
+            }
+            httparse::Status::Partial if response.len() > 16 * 1024 => {
+                return Err(ProxyErr::Malformed);
+            }
+            httparse::Status::Partial => {}
+        }
+    }
+}
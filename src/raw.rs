@@ -0,0 +1,273 @@
+//! Frame-level I/O without protocol checks, for test tools and diagnostics.
+//!
+//! [`RawFrameWriter`] puts exactly the header fields and payload bytes of a
+//! [`RawFrame`] on the wire, so it can produce frames the rest of the crate
+//! refuses to: reserved opcodes and RSV bits, a mask bit that contradicts
+//! the role, control frames over 125 bytes, fragmented control frames, and
+//! non-minimal length encodings. [`RawFrameReader`] parses any frame into the
+//! same struct without judging it. Neither tracks connection state, so they
+//! are only meant for talking to a peer under test, never for production
+//! traffic.
+//!
+//! ```
+//! use websockets_monoio::raw::{LengthEncoding, RawFrame, RawFrameReader, RawFrameWriter};
+//!
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     // A Ping with a 126-byte payload, its length needlessly in 64 bits.
+//!     let mut frame = RawFrame::new(0x9, vec![b'x'; 126]);
+//!     frame.length = Some(LengthEncoding::U64);
+//!     let mut writer = RawFrameWriter::new(Vec::new());
+//!     writer.write(&frame).await.unwrap();
+//!
+//!     let wire = writer.into_inner();
+//!     assert_eq!(&wire[..2], [0x89, 127]);
+//!     let mut reader = RawFrameReader::new(wire.as_slice());
+//!     assert_eq!(reader.read().await.unwrap(), Some(frame));
+//!     assert_eq!(reader.read().await.unwrap(), None);
+//! });
+//! ```
+
+use std::io;
+
+use monoio_compat::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest payload [`RawFrameReader`] accepts unless told otherwise.
+pub const DEFAULT_MAX_PAYLOAD: u64 = 16 * 1024 * 1024;
+
+/// How a frame's payload length is written in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LengthEncoding {
+    /// In the 7-bit field; 125 bytes at most.
+    Short,
+    /// Marker 126 followed by a 16-bit length.
+    U16,
+    /// Marker 127 followed by a 64-bit length.
+    U64,
+}
+
+impl LengthEncoding {
+    /// The shortest encoding for a payload of `len` bytes.
+    pub fn minimal(len: u64) -> Self {
+        match len {
+            0..=125 => LengthEncoding::Short,
+            126..=0xFFFF => LengthEncoding::U16,
+            _ => LengthEncoding::U64,
+        }
+    }
+}
+
+/// One frame, field by field, with nothing validated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    pub fin: bool,
+    /// RSV1 to RSV3 in the low three bits, RSV1 highest.
+    pub rsv: u8,
+    /// Any value; only the low four bits are written.
+    pub opcode: u8,
+    /// The MASK bit.
+    pub masked: bool,
+    /// Masking key written after the length. Independent of `masked`, so a
+    /// header can claim a key it does not carry or the other way round.
+    pub mask_key: Option<[u8; 4]>,
+    /// `None` writes the shortest encoding. [`RawFrameReader`] reports the
+    /// encoding it found.
+    pub length: Option<LengthEncoding>,
+    /// Written as given: it is not masked with `mask_key`, see
+    /// [`apply_mask`]. [`RawFrameReader`] returns it as it was on the wire.
+    pub payload: Vec<u8>,
+}
+
+impl RawFrame {
+    /// A final, unmasked frame with no RSV bits set.
+    pub fn new(opcode: u8, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            rsv: 0,
+            opcode,
+            masked: false,
+            mask_key: None,
+            length: None,
+            payload,
+        }
+    }
+
+    /// Set the MASK bit and `key`, and mask the payload with it, as a
+    /// well-behaved client would.
+    pub fn masked_with(mut self, key: [u8; 4]) -> Self {
+        apply_mask(key, &mut self.payload);
+        self.masked = true;
+        self.mask_key = Some(key);
+        self
+    }
+
+    /// The payload with the masking key applied, if there is one.
+    pub fn unmasked_payload(&self) -> Vec<u8> {
+        let mut payload = self.payload.clone();
+        if let Some(key) = self.mask_key {
+            apply_mask(key, &mut payload);
+        }
+        payload
+    }
+
+    /// The header bytes, from the first byte up to the masking key.
+    ///
+    /// Fails with `InvalidInput` if the payload does not fit the chosen
+    /// [`LengthEncoding`].
+    pub fn header(&self) -> io::Result<Vec<u8>> {
+        let len = self.payload.len() as u64;
+        let encoding = self.length.unwrap_or(LengthEncoding::minimal(len));
+        let mut header = Vec::with_capacity(14);
+        header.push((self.fin as u8) << 7 | (self.rsv & 0x7) << 4 | self.opcode & 0xF);
+        let mask_bit = (self.masked as u8) << 7;
+        match encoding {
+            LengthEncoding::Short if len <= 125 => header.push(mask_bit | len as u8),
+            LengthEncoding::U16 if len <= 0xFFFF => {
+                header.push(mask_bit | 126);
+                header.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            LengthEncoding::U64 => {
+                header.push(mask_bit | 127);
+                header.extend_from_slice(&len.to_be_bytes());
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{len}-byte payload does not fit {encoding:?} length encoding"),
+                ));
+            }
+        }
+        if let Some(key) = self.mask_key {
+            header.extend_from_slice(&key);
+        }
+        Ok(header)
+    }
+}
+
+/// XOR `payload` with `key` as RFC 6455 §5.3 describes. Masking twice
+/// restores the original.
+pub fn apply_mask(key: [u8; 4], payload: &mut [u8]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+/// Writes [`RawFrame`]s to a stream exactly as given.
+#[derive(Debug)]
+pub struct RawFrameWriter<S> {
+    stream: S,
+}
+
+impl<S: AsyncWrite + Unpin> RawFrameWriter<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Write `frame` and flush.
+    pub async fn write(&mut self, frame: &RawFrame) -> io::Result<()> {
+        self.stream.write_all(&frame.header()?).await?;
+        self.stream.write_all(&frame.payload).await?;
+        self.stream.flush().await
+    }
+
+    /// Write `bytes` as they are, e.g. half a frame.
+    pub async fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+/// Parses [`RawFrame`]s from a stream without enforcing anything but a
+/// payload size limit.
+#[derive(Debug)]
+pub struct RawFrameReader<S> {
+    stream: S,
+    max_payload: u64,
+}
+
+impl<S: AsyncRead + Unpin> RawFrameReader<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            max_payload: DEFAULT_MAX_PAYLOAD,
+        }
+    }
+
+    /// Fail with `InvalidData` instead of reading a payload larger than
+    /// `bytes`, so a hostile length cannot exhaust memory.
+    pub fn max_payload(mut self, bytes: u64) -> Self {
+        self.max_payload = bytes;
+        self
+    }
+
+    /// Read the next frame. Returns `None` if the stream ends before its
+    /// first byte, and fails with `UnexpectedEof` if it ends inside one.
+    ///
+    /// Not cancel safe: a read dropped partway loses the bytes consumed.
+    pub async fn read(&mut self) -> io::Result<Option<RawFrame>> {
+        let mut head = [0u8; 2];
+        if self.stream.read(&mut head[..1]).await? == 0 {
+            return Ok(None);
+        }
+        self.stream.read_exact(&mut head[1..]).await?;
+        let (len, length) = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                self.stream.read_exact(&mut len).await?;
+                (u16::from_be_bytes(len) as u64, LengthEncoding::U16)
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                self.stream.read_exact(&mut len).await?;
+                (u64::from_be_bytes(len), LengthEncoding::U64)
+            }
+            len => (len as u64, LengthEncoding::Short),
+        };
+        let masked = head[1] & 0x80 != 0;
+        let mask_key = if masked {
+            let mut key = [0u8; 4];
+            self.stream.read_exact(&mut key).await?;
+            Some(key)
+        } else {
+            None
+        };
+        if len > self.max_payload {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{len}-byte payload exceeds the {} byte limit",
+                    self.max_payload
+                ),
+            ));
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.stream.read_exact(&mut payload).await?;
+        Ok(Some(RawFrame {
+            fin: head[0] & 0x80 != 0,
+            rsv: (head[0] >> 4) & 0x7,
+            opcode: head[0] & 0xF,
+            masked,
+            mask_key,
+            length: Some(length),
+            payload,
+        }))
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
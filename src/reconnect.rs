@@ -0,0 +1,1390 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::pending;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use fastwebsockets::WebSocketError;
+use monoio::task::JoinHandle;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::budget::Reservation;
+use crate::cancel::CancellationToken;
+use crate::event::EventSink;
+use crate::schedule::{ScheduledJob, Scheduler};
+use crate::summary::SessionSummary;
+use crate::time::TimedOut;
+use crate::{CloseFrame, Message, WsClient, WsClientBuilder, WsError, WsEvent};
+
+/// Default overall deadline of a [`ReliableSend`].
+pub const DEFAULT_RELIABLE_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Default number of times a [`ReliableSend`] is sent before giving up.
+pub const DEFAULT_RELIABLE_ATTEMPTS: u32 = 3;
+
+/// Default cap on the delay between reconnect attempts.
+pub const DEFAULT_MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default time a [`WsReconnectClient::replace`] has to connect and become
+/// ready.
+pub const DEFAULT_REPLACE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a replaced session is drained for the server's answer to its
+/// Close before it is dropped.
+pub const RETIRE_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ended sessions kept by [`WsReconnectClient::session_history`].
+pub const SESSION_HISTORY: usize = 16;
+
+/// Exponential backoff between reconnect attempts.
+///
+/// The delay before attempt `n` (zero-based) is `initial * multiplier^n`,
+/// capped at `max_interval`. [`WsReconnectClient`] then randomizes it as
+/// set with [`WsClientBuilder::reconnect_jitter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub multiplier: f64,
+    pub max_interval: Duration,
+    /// Give up after this many consecutive failed attempts; `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_interval: DEFAULT_MAX_RECONNECT_INTERVAL,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay to wait before the given zero-based attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let secs = self.initial.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        if !secs.is_finite() || secs >= self.max_interval.as_secs_f64() {
+            return self.max_interval;
+        }
+        Duration::from_secs_f64(secs).min(self.max_interval)
+    }
+}
+
+/// How a [`WsReconnectClient`] randomizes the delays its
+/// [`ReconnectPolicy`] decides on, so that clients disconnected at the same
+/// moment, e.g. by a gateway restart, do not retry in lockstep.
+///
+/// A delay of zero, such as the default policy's immediate retry after a
+/// 1006, stays zero; a [pacing hook](crate::WsClientBuilder::reconnect_pacing)
+/// can spread those.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Jitter {
+    /// Wait exactly the policy's delay.
+    None,
+    /// Wait anywhere between zero and the policy's delay.
+    Full,
+    /// Wait half the policy's delay plus up to as much again.
+    Equal,
+    /// AWS's decorrelated jitter: wait between the policy's delay and three
+    /// times the previous wait (or the policy's delay, before the first),
+    /// capped at the backoff's `max_interval` unless the policy asked for
+    /// more. Consecutive waits of one client wander apart from those of
+    /// others instead of following the same exponential steps.
+    #[default]
+    Decorrelated,
+}
+
+/// The jitter state of one client: the [`Jitter`] kind, its random number
+/// generator and, for [`Jitter::Decorrelated`], the previous wait.
+///
+/// [`WsReconnectClient`] keeps one, seeded from [`process_jitter_seed`]
+/// unless the builder sets [`jitter_seed`](crate::WsClientBuilder::jitter_seed).
+/// It is public so that a schedule can be simulated, e.g. to check its
+/// spread:
+///
+/// ```
+/// use std::time::Duration;
+/// use websockets_monoio::reconnect::{Jitter, ReconnectJitter};
+///
+/// let cap = Duration::from_secs(60);
+/// // 1000 clients retrying after a 1 s backoff step.
+/// let waits: Vec<f64> = (0..1000)
+///     .map(|client| {
+///         let mut jitter = ReconnectJitter::new(Jitter::Decorrelated, cap, 7 + client);
+///         jitter.delay(Duration::from_secs(1)).as_secs_f64()
+///     })
+///     .collect();
+/// // Between the delay and three times it, and spread evenly over that range.
+/// assert!(waits.iter().all(|&w| (1.0..=3.0).contains(&w)));
+/// let mean = waits.iter().sum::<f64>() / waits.len() as f64;
+/// assert!((mean - 2.0).abs() < 0.1, "mean {mean}");
+/// let mut buckets = [0; 4];
+/// for w in &waits {
+///     buckets[(((w - 1.0) / 0.5) as usize).min(3)] += 1;
+/// }
+/// assert!(buckets.iter().all(|&n| (200..=300).contains(&n)), "{buckets:?}");
+///
+/// // The same seed gives the same schedule; later waits stay below the cap.
+/// let schedule = |seed| {
+///     let mut jitter = ReconnectJitter::new(Jitter::Decorrelated, cap, seed);
+///     (0..50).map(|_| jitter.delay(Duration::from_secs(1))).collect::<Vec<_>>()
+/// };
+/// assert_eq!(schedule(42), schedule(42));
+/// assert_ne!(schedule(42), schedule(43));
+/// assert!(schedule(42).iter().all(|&w| w <= cap));
+///
+/// // Full jitter averages half the delay.
+/// let mut full = ReconnectJitter::new(Jitter::Full, cap, 1);
+/// let mean = (0..10_000)
+///     .map(|_| full.delay(Duration::from_secs(2)).as_secs_f64())
+///     .sum::<f64>()
+///     / 10_000.0;
+/// assert!((mean - 1.0).abs() < 0.05, "mean {mean}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReconnectJitter {
+    jitter: Jitter,
+    cap: Duration,
+    rng: StdRng,
+    previous: Option<Duration>,
+}
+
+impl ReconnectJitter {
+    /// `cap` bounds [`Jitter::Decorrelated`] waits; it is the backoff's
+    /// `max_interval` in a [`WsReconnectClient`].
+    pub fn new(jitter: Jitter, cap: Duration, seed: u64) -> Self {
+        Self {
+            jitter,
+            cap,
+            rng: StdRng::seed_from_u64(seed),
+            previous: None,
+        }
+    }
+
+    pub fn jitter(&self) -> Jitter {
+        self.jitter
+    }
+
+    /// The wait for a policy `delay`.
+    pub fn delay(&mut self, delay: Duration) -> Duration {
+        if delay.is_zero() {
+            return delay;
+        }
+        let wait = match self.jitter {
+            Jitter::None => delay,
+            Jitter::Full => self.between(Duration::ZERO, delay),
+            Jitter::Equal => delay / 2 + self.between(Duration::ZERO, delay / 2),
+            Jitter::Decorrelated => {
+                let previous = self.previous.unwrap_or(delay).max(delay);
+                let upper = previous.saturating_mul(3).min(self.cap.max(delay));
+                self.between(delay, upper)
+            }
+        };
+        self.previous = Some(wait);
+        wait
+    }
+
+    /// Forget the previous wait, e.g. once a connection succeeded.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    fn between(&mut self, low: Duration, high: Duration) -> Duration {
+        let low = low.as_nanos().min(u64::MAX as u128) as u64;
+        let high = high.as_nanos().min(u64::MAX as u128) as u64;
+        Duration::from_nanos(self.rng.random_range(low..=high.max(low)))
+    }
+}
+
+/// A random value drawn once per process, from which each
+/// [`WsReconnectClient`]'s jitter is seeded, so that processes started
+/// together still draw different schedules.
+pub fn process_jitter_seed() -> u64 {
+    static SEED: OnceLock<u64> = OnceLock::new();
+    *SEED.get_or_init(rand::random)
+}
+
+/// Seed for the next client's jitter: the process seed plus a counter, so
+/// the clients of one process differ too.
+fn next_jitter_seed() -> u64 {
+    static CLIENTS: AtomicU64 = AtomicU64::new(0);
+    process_jitter_seed().wrapping_add(CLIENTS.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Awaited before each reconnect attempt; see
+/// [`WsClientBuilder::reconnect_pacing`].
+type PacingFn = dyn Fn(u32) -> Pin<Box<dyn Future<Output = ()>>>;
+
+/// A pacing hook set on a builder, with how long it may take.
+#[derive(Clone)]
+pub(crate) struct Pacing {
+    pub(crate) f: Rc<PacingFn>,
+    pub(crate) timeout: Duration,
+}
+
+impl fmt::Debug for Pacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pacing")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Why a [`WsReconnectClient`] lost its connection, or why the last
+/// reconnect attempt failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisconnectInfo {
+    /// The peer's Close, if it sent one.
+    pub close: Option<CloseFrame>,
+    /// Kind of the error that ended the session or failed the attempt.
+    /// Timeouts map to `TimedOut`, protocol and other errors to `Other`.
+    pub error: Option<ErrorKind>,
+    /// Stable [code](crate::error_code) of that error, e.g. to label
+    /// metrics. `None` for a plain Close.
+    pub error_code: Option<&'static str>,
+    /// How long the connection was up; zero after a failed attempt.
+    pub session: Duration,
+    /// The `local_stall` of a [`WsError::KeepaliveTimeout`]: this thread
+    /// stalled while the liveness proof was awaited.
+    pub local_stall: Option<Duration>,
+}
+
+impl DisconnectInfo {
+    fn from_error(e: &WsError, session: Duration) -> Self {
+        let error = match e {
+            WsError::ClosedByPeer(close) => {
+                return Self {
+                    close: Some(close.clone()),
+                    error: None,
+                    error_code: None,
+                    session,
+                    local_stall: None,
+                };
+            }
+            WsError::Io(e) | WsError::WebSocket(WebSocketError::IoError(e)) => e.kind(),
+            WsError::WebSocket(WebSocketError::UnexpectedEOF) => ErrorKind::UnexpectedEof,
+            WsError::Timeout(_) | WsError::KeepaliveTimeout { .. } => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        };
+        let local_stall = match e {
+            WsError::KeepaliveTimeout { local_stall, .. } => *local_stall,
+            _ => None,
+        };
+        Self {
+            close: None,
+            error: Some(error),
+            error_code: Some(e.code()),
+            session,
+            local_stall,
+        }
+    }
+
+    /// The close code, with 1006 (abnormal closure) when the connection
+    /// ended without a Close and 1005 for a Close without a status.
+    pub fn code(&self) -> u16 {
+        match &self.close {
+            Some(close) => close.code,
+            None if self.error.is_some() => 1006,
+            None => CloseFrame::NO_STATUS_RECEIVED,
+        }
+    }
+}
+
+/// What a [`WsReconnectClient`] does after a disconnect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectDecision {
+    /// Wait, then try to connect again.
+    RetryAfter(Duration),
+    /// Give up and surface the disconnect to the caller.
+    Stop,
+}
+
+/// Decides whether and when a [`WsReconnectClient`] reconnects.
+///
+/// `attempt` counts the reconnect attempts made since a connection last
+/// delivered a message: `0` right after a productive session ended, then
+/// one more per attempt, so a server that accepts and immediately drops
+/// connections escalates the backoff. `disconnect` describes the latest
+/// disconnect or failed attempt.
+/// Implement it to handle exchange-specific 4xxx codes, delegating to
+/// [`DefaultReconnectPolicy`] for the rest.
+pub trait ReconnectPolicy {
+    fn decide(&self, disconnect: &DisconnectInfo, attempt: u32) -> ReconnectDecision;
+}
+
+/// The standard reconnect policy, built on a [`Backoff`].
+///
+/// - 1008 (policy violation, usually failed authentication) stops.
+/// - 1012 (service restart) and 1013 (try again later) wait at least
+///   [`try_again_delay`](Self::try_again_delay).
+/// - 1006 (connection lost without a Close) retries immediately once, then
+///   backs off.
+/// - A keepalive timeout during a [local stall](crate::watchdog) retries
+///   immediately: the connection was probably fine.
+/// - Everything else, including 4xxx application codes, backs off.
+///
+/// `backoff.max_attempts` stops after that many failed attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DefaultReconnectPolicy {
+    pub backoff: Backoff,
+    pub try_again_delay: Duration,
+}
+
+impl DefaultReconnectPolicy {
+    pub fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            try_again_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Default for DefaultReconnectPolicy {
+    fn default() -> Self {
+        Self::new(Backoff::default())
+    }
+}
+
+impl ReconnectPolicy for DefaultReconnectPolicy {
+    fn decide(&self, disconnect: &DisconnectInfo, attempt: u32) -> ReconnectDecision {
+        if self.backoff.max_attempts.is_some_and(|max| attempt >= max) {
+            return ReconnectDecision::Stop;
+        }
+        if disconnect.local_stall.is_some() {
+            return ReconnectDecision::RetryAfter(Duration::ZERO);
+        }
+        let delay = match disconnect.code() {
+            1008 => return ReconnectDecision::Stop,
+            1012 | 1013 => self.backoff.delay(attempt).max(self.try_again_delay),
+            1006 if attempt == 0 => Duration::ZERO,
+            1006 => self.backoff.delay(attempt - 1),
+            _ => self.backoff.delay(attempt),
+        };
+        ReconnectDecision::RetryAfter(delay)
+    }
+}
+
+/// A [`ReconnectPolicy`] set on a builder.
+#[derive(Clone)]
+pub(crate) struct Policy(pub(crate) Rc<dyn ReconnectPolicy>);
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReconnectPolicy(..)")
+    }
+}
+
+/// How [`WsClientBuilder::connect_list_with_retry`] works through a list of
+/// servers.
+///
+/// Each URL is tried up to `attempts_per_url` times, sleeping
+/// `backoff.delay(n)` before retry `n`, before moving on to the next URL.
+/// `backoff.max_attempts` is not used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryConfig {
+    pub attempts_per_url: u32,
+    pub backoff: Backoff,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts_per_url: 3,
+            backoff: Backoff {
+                initial: Duration::from_millis(100),
+                multiplier: 2.0,
+                max_interval: Duration::from_secs(5),
+                max_attempts: None,
+            },
+        }
+    }
+}
+
+/// A client that transparently re-establishes its connection.
+///
+/// `recv` reconnects when the connection fails or the peer closes it, as
+/// decided by the builder's [`ReconnectPolicy`]
+/// ([`DefaultReconnectPolicy`] over the builder's [`Backoff`] unless set
+/// with [`WsClientBuilder::reconnect_policy`]). When the policy stops, the
+/// error that ended the session is returned, or [`WsError::ClosedByPeer`]
+/// after a Close. Backoff sleeps use `monoio::time`, so the runtime must be
+/// built with the timer enabled.
+///
+/// Outgoing messages go through a queue that survives reconnects. A message
+/// may carry a time to live, by default the builder's
+/// [`outbound_ttl`](WsClientBuilder::outbound_ttl); if it is still queued
+/// when that runs out, for example because reconnecting took a while, it is
+/// dropped instead of being sent late. Ping, Pong and Close never expire.
+///
+/// With [`resume_with`](Self::resume_with), state taken from received
+/// messages (such as a sequence number) is carried over to each new
+/// connection's resubscribe messages.
+pub struct WsReconnectClient {
+    url: String,
+    builder: WsClientBuilder,
+    client: Option<WsClient>,
+    policy: Rc<dyn ReconnectPolicy>,
+    /// Why the last connection was dropped, for the next reconnect.
+    disconnect: Option<DisconnectInfo>,
+    /// Reconnect attempts since a connection last delivered a message.
+    attempts: u32,
+    outbound: VecDeque<Queued>,
+    expired: u64,
+    resume: Option<Box<dyn ResumeSlot>>,
+    /// Id of the current session; every new connection gets the next one.
+    session: u64,
+    sessions: u64,
+    replacement: Option<Replacement>,
+    retiring: Option<Retiring>,
+    scheduler: Scheduler,
+    jitter: ReconnectJitter,
+    /// Summaries of ended sessions, oldest first.
+    history: Rc<RefCell<VecDeque<SessionSummary>>>,
+}
+
+/// Type-erased resume state and its callbacks, see
+/// [`WsReconnectClient::resume_with`].
+trait ResumeSlot {
+    fn observe(&mut self, message: &Message);
+    fn resubscribe(&self) -> Vec<Message>;
+}
+
+struct Resume<S, E, R> {
+    state: Option<S>,
+    extract: E,
+    resubscribe: R,
+}
+
+impl<S, E, R> ResumeSlot for Resume<S, E, R>
+where
+    E: Fn(&Message) -> Option<S>,
+    R: Fn(Option<&S>) -> Vec<Message>,
+{
+    fn observe(&mut self, message: &Message) {
+        if let Some(state) = (self.extract)(message) {
+            self.state = Some(state);
+        }
+    }
+
+    fn resubscribe(&self) -> Vec<Message> {
+        (self.resubscribe)(self.state.as_ref())
+    }
+}
+
+/// Decides whether a replacement session is ready to take over.
+type ReadyFn = dyn Fn(&Message) -> bool;
+
+/// How [`WsReconnectClient::replace`] brings up the new session.
+pub struct ReplaceOptions {
+    url: Option<String>,
+    ready: Option<Box<ReadyFn>>,
+    timeout: Duration,
+}
+
+impl ReplaceOptions {
+    /// Connect to the client's URL and swap as soon as resubscribed.
+    pub fn new() -> Self {
+        Self {
+            url: None,
+            ready: None,
+            timeout: DEFAULT_REPLACE_TIMEOUT,
+        }
+    }
+
+    /// Connect to `url` instead; it is also used for later reconnects once
+    /// the replacement is swapped in.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Only swap once a message from the new session satisfies `ready`,
+    /// e.g. the first snapshot after resubscribing.
+    pub fn ready_when(mut self, ready: impl Fn(&Message) -> bool + 'static) -> Self {
+        self.ready = Some(Box::new(ready));
+        self
+    }
+
+    /// Give up if the new session is not ready within `timeout`, counted
+    /// from [`replace`](WsReconnectClient::replace).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for ReplaceOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ReplaceOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplaceOptions")
+            .field("url", &self.url)
+            .field("ready", &self.ready.as_ref().map(|_| ".."))
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// An idempotent message for [`WsReconnectClient::send_reliable`], with
+/// its deadline and attempt cap.
+#[derive(Debug, Clone)]
+pub struct ReliableSend {
+    message: Message,
+    dedupe_key: Option<String>,
+    deadline: Duration,
+    max_attempts: u32,
+}
+
+impl ReliableSend {
+    /// Send `message` within [`DEFAULT_RELIABLE_DEADLINE`] and at most
+    /// [`DEFAULT_RELIABLE_ATTEMPTS`] times.
+    pub fn new(message: Message) -> Self {
+        Self {
+            message,
+            dedupe_key: None,
+            deadline: DEFAULT_RELIABLE_DEADLINE,
+            max_attempts: DEFAULT_RELIABLE_ATTEMPTS,
+        }
+    }
+
+    /// Report each attempt with `key` in [`WsEvent::ReliableSend`], so
+    /// replays of the same message can be traced.
+    pub fn dedupe_key(mut self, key: impl Into<String>) -> Self {
+        self.dedupe_key = Some(key.into());
+        self
+    }
+
+    /// Give up after `deadline`, reconnects included.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Send the message at most `attempts` times (at least once).
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+}
+
+/// A session being brought up by [`WsReconnectClient::replace`].
+struct Replacement {
+    session: u64,
+    url: Option<String>,
+    state: ReplacementState,
+    ready: Option<Box<ReadyFn>>,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+enum ReplacementState {
+    /// Connecting and resubscribing in a spawned task.
+    Connecting(JoinHandle<Result<Box<WsClient>, WsError>>),
+    /// Connected, waiting for a message that satisfies `ready`.
+    Warming(Box<WsClient>),
+}
+
+/// A replaced session that was sent a Close and is drained until the server
+/// answers it.
+struct Retiring {
+    session: u64,
+    client: WsClient,
+    deadline: Instant,
+}
+
+/// What [`WsReconnectClient::recv_tagged`] waited for.
+enum Step {
+    Current(Result<Message, WsError>),
+    Connected(Result<Box<WsClient>, WsError>),
+    Replacement(Result<Message, WsError>),
+    Retiring(Result<Message, WsError>),
+    /// A [`ScheduledJob`] run finished with the messages to send.
+    Job(usize, Result<Vec<Message>, WsError>),
+    Deadline,
+    /// The builder's [`CancellationToken`] was cancelled.
+    Cancelled,
+}
+
+/// A message waiting to be sent.
+struct Queued {
+    message: Message,
+    enqueued: Instant,
+    /// Drop the message instead of sending it after this instant.
+    deadline: Option<Instant>,
+    /// The payload's charge to the builder's memory budget.
+    _reservation: Option<Reservation>,
+}
+
+impl Queued {
+    fn is_expired(&self) -> bool {
+        let exempt = matches!(
+            self.message,
+            Message::Ping(_) | Message::Pong(_) | Message::Close(_)
+        );
+        !exempt
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl WsReconnectClient {
+    /// Connect once (without retries) and keep the settings for reconnects.
+    pub async fn connect(mut builder: WsClientBuilder, url: &str) -> Result<Self, WsError> {
+        let history = Rc::new(RefCell::new(VecDeque::new()));
+        let recorded = history.clone();
+        builder.events = Some(EventSink::tap(builder.events, move |event| {
+            if let WsEvent::SessionEnded { summary } = event {
+                let mut history = recorded.borrow_mut();
+                if history.len() == SESSION_HISTORY {
+                    history.pop_front();
+                }
+                history.push_back((**summary).clone());
+            }
+        }));
+        let client = builder.connect(url).await?;
+        let policy = match &builder.reconnect_policy {
+            Some(policy) => policy.0.clone(),
+            None => Rc::new(DefaultReconnectPolicy::new(builder.backoff)),
+        };
+        let jitter = ReconnectJitter::new(
+            builder.reconnect_jitter,
+            builder.backoff.max_interval,
+            builder.jitter_seed.unwrap_or_else(next_jitter_seed),
+        );
+        Ok(Self {
+            url: url.to_owned(),
+            builder,
+            client: Some(client),
+            policy,
+            disconnect: None,
+            attempts: 0,
+            outbound: VecDeque::new(),
+            expired: 0,
+            resume: None,
+            session: 0,
+            sessions: 0,
+            replacement: None,
+            retiring: None,
+            scheduler: Scheduler::default(),
+            jitter,
+            history,
+        })
+    }
+
+    /// Keep resume state across reconnects.
+    ///
+    /// `extract` sees every message `recv` returns, before the caller does,
+    /// and a `Some` replaces the stored state. After each reconnect,
+    /// `resubscribe` gets the latest state (`None` if none was extracted yet)
+    /// and its messages are sent on the new connection before anything queued
+    /// and before the next message is received, so a subscription can resume
+    /// from the last sequence number seen on the old connection. If sending
+    /// them fails, the attempt counts as a failed reconnect.
+    ///
+    /// The state lives in memory only; it does not survive the process.
+    /// Replaces any earlier `resume_with` and its state.
+    pub fn resume_with<S: 'static>(
+        &mut self,
+        extract: impl Fn(&Message) -> Option<S> + 'static,
+        resubscribe: impl Fn(Option<&S>) -> Vec<Message> + 'static,
+    ) {
+        self.resume = Some(Box::new(Resume {
+            state: None,
+            extract,
+            resubscribe,
+        }));
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Id of the current session, as reported by
+    /// [`recv_tagged`](Self::recv_tagged). The first connection is `0`, and
+    /// each reconnect or replacement gets the next number.
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Summaries of the latest [`SESSION_HISTORY`] sessions that ended,
+    /// oldest first. Each one's [`generation`](SessionSummary::generation)
+    /// is its [`session`](Self::session) id.
+    pub fn session_history(&self) -> Vec<SessionSummary> {
+        self.history.borrow().iter().cloned().collect()
+    }
+
+    /// The current connection, if one is established.
+    pub fn client(&mut self) -> Option<&mut WsClient> {
+        self.client.as_mut()
+    }
+
+    /// Drop the current connection and reconnect as the policy decides,
+    /// even after it decided to stop.
+    pub async fn reconnect(&mut self) -> Result<&mut WsClient, WsError> {
+        let session = self.client.take().map_or(Duration::ZERO, |client| {
+            client.stats().connect_time.elapsed()
+        });
+        self.disconnect = Some(DisconnectInfo {
+            close: None,
+            error: None,
+            error_code: None,
+            session,
+            local_stall: None,
+        });
+        self.reconnect_after(None).await
+    }
+
+    /// Drop the connection, remembering why for the next reconnect.
+    fn disconnected(&mut self, close: Option<CloseFrame>, error: Option<&WsError>) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let session = client.stats().connect_time.elapsed();
+        self.disconnect = Some(match error {
+            Some(e) => DisconnectInfo::from_error(e, session),
+            None => DisconnectInfo {
+                close,
+                error: None,
+                error_code: None,
+                session,
+                local_stall: None,
+            },
+        });
+    }
+
+    /// Reconnect after the disconnect recorded in `self.disconnect`. `error`
+    /// is what ended the session, returned if the policy stops right away.
+    async fn reconnect_after(
+        &mut self,
+        mut error: Option<WsError>,
+    ) -> Result<&mut WsClient, WsError> {
+        let mut info = self.disconnect.take().unwrap_or(DisconnectInfo {
+            close: None,
+            error: None,
+            error_code: None,
+            session: Duration::ZERO,
+            local_stall: None,
+        });
+        loop {
+            match self.policy.decide(&info, self.attempts) {
+                ReconnectDecision::Stop => {
+                    let e = match (error, &info.close) {
+                        (Some(e), _) => e,
+                        (None, Some(close)) => WsError::ClosedByPeer(close.clone()),
+                        (None, None) => WsError::ReconnectStopped,
+                    };
+                    // Later calls ask the policy about the same disconnect.
+                    self.disconnect = Some(info);
+                    return Err(e);
+                }
+                ReconnectDecision::RetryAfter(delay) => self.wait_to_reconnect(delay).await?,
+            }
+            self.attempts += 1;
+            match self.connect_and_resubscribe(self.sessions + 1).await {
+                Ok(client) => {
+                    self.session = self.next_session();
+                    return Ok(self.client.insert(client));
+                }
+                Err(WsError::Cancelled) => return Err(WsError::Cancelled),
+                Err(e) => {
+                    info = DisconnectInfo::from_error(&e, Duration::ZERO);
+                    error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Wait out the jittered `delay` and then the pacing hook, if any,
+    /// before the next attempt. Fails if the builder's cancellation token
+    /// is cancelled meanwhile.
+    async fn wait_to_reconnect(&mut self, delay: Duration) -> Result<(), WsError> {
+        let wait = self.jitter.delay(delay);
+        if let Some(events) = &self.builder.events {
+            events.emit(&WsEvent::ReconnectScheduled {
+                attempt: self.attempts,
+                delay,
+                wait,
+                jitter: self.jitter.jitter(),
+            });
+        }
+        self.builder.sleep(wait).await?;
+        let Some(pacing) = &self.builder.reconnect_pacing else {
+            return Ok(());
+        };
+        let paced = monoio::time::timeout(pacing.timeout, (pacing.f)(self.attempts));
+        let paced = match self.builder.cancel_token() {
+            Some(token) => token
+                .run_until_cancelled(paced)
+                .await
+                .ok_or(WsError::Cancelled)?,
+            None => paced.await,
+        };
+        if paced.is_err()
+            && let Some(events) = &self.builder.events
+        {
+            events.emit(&WsEvent::ReconnectPacingTimedOut {
+                attempt: self.attempts,
+                timeout: pacing.timeout,
+            });
+        }
+        Ok(())
+    }
+
+    async fn connect_and_resubscribe(&self, session: u64) -> Result<WsClient, WsError> {
+        open_session(
+            &self.builder,
+            &self.url,
+            session,
+            self.resubscribe_messages(),
+        )
+        .await
+    }
+
+    fn resubscribe_messages(&self) -> Vec<Message> {
+        self.resume
+            .as_ref()
+            .map_or_else(Vec::new, |resume| resume.resubscribe())
+    }
+
+    fn next_session(&mut self) -> u64 {
+        self.sessions += 1;
+        self.sessions
+    }
+
+    /// Bring up a new session next to the current one and swap it in
+    /// without a gap (make-before-break), e.g. ahead of a gateway's
+    /// scheduled maintenance.
+    ///
+    /// The new session is connected in a spawned task, to `options`' URL or
+    /// the client's own, and the [`resume_with`](Self::resume_with)
+    /// resubscribe messages (from the state at this call) are sent on it.
+    /// Without a [`ready_when`](ReplaceOptions::ready_when) condition it is
+    /// swapped in as soon as that is done; otherwise once one of its
+    /// messages satisfies the condition. From the swap on, sends go to the
+    /// new session, and the old one is sent a Close (1000) and drained for
+    /// up to [`RETIRE_CLOSE_TIMEOUT`] before it is dropped.
+    ///
+    /// All of this is driven by [`recv`](Self::recv), so keep receiving.
+    /// During the overlap messages of both sessions are delivered, and
+    /// [`recv_tagged`](Self::recv_tagged) says which one each came from, so
+    /// the application can drop duplicates. If the current session fails in
+    /// the meantime, a connected replacement is swapped in right away
+    /// instead of reconnecting.
+    ///
+    /// If the new session fails, or is not ready within the options'
+    /// timeout, it is dropped and the next `recv` returns
+    /// [`WsError::ReplacementFailed`]; the current session is unaffected and
+    /// `recv` can be called again. Calling `replace` again abandons a
+    /// replacement still in progress.
+    pub fn replace(&mut self, options: ReplaceOptions) {
+        let builder = self.builder.clone();
+        let url = options.url.clone().unwrap_or_else(|| self.url.clone());
+        let messages = self.resubscribe_messages();
+        let session = self.next_session();
+        let task = monoio::spawn(async move {
+            open_session(&builder, &url, session, messages)
+                .await
+                .map(Box::new)
+        });
+        self.replacement = Some(Replacement {
+            session,
+            url: options.url,
+            state: ReplacementState::Connecting(task),
+            ready: options.ready,
+            timeout: options.timeout,
+            deadline: Instant::now() + options.timeout,
+        });
+    }
+
+    /// Run `job` on its schedule for as long as the client lives. See the
+    /// [`schedule`](crate::schedule) module.
+    ///
+    /// Like reconnects, jobs are driven by [`recv`](Self::recv), so keep
+    /// receiving; a job that falls due while the application is elsewhere
+    /// runs at the next `recv`.
+    pub fn schedule(&mut self, job: ScheduledJob) {
+        self.scheduler.add(job);
+    }
+
+    /// Send the messages of a scheduled run on the current session.
+    async fn send_scheduled(&mut self, messages: Vec<Message>) -> Result<(), WsError> {
+        let Some(client) = self.client.as_mut() else {
+            return Err(WebSocketError::ConnectionClosed.into());
+        };
+        for message in messages {
+            client.send(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether a [`replace`](Self::replace) is still in progress.
+    pub fn is_replacing(&self) -> bool {
+        self.replacement.is_some()
+    }
+
+    /// Make `client` the current session and retire the old one.
+    async fn promote(&mut self, client: WsClient, session: u64, url: Option<String>) {
+        if let Some(url) = url {
+            self.url = url;
+        }
+        let old_session = std::mem::replace(&mut self.session, session);
+        self.attempts = 0;
+        self.jitter.reset();
+        self.disconnect = None;
+        let Some(mut old) = self.client.replace(client) else {
+            return;
+        };
+        let close = Message::Close(Some(CloseFrame {
+            code: 1000,
+            reason: "replaced".into(),
+        }));
+        if old.send(close).await.is_ok() {
+            self.retiring = Some(Retiring {
+                session: old_session,
+                client: old,
+                deadline: Instant::now() + RETIRE_CLOSE_TIMEOUT,
+            });
+        }
+    }
+
+    /// Queue `message` with the default time to live and send everything
+    /// queued, reconnecting first if there is no connection.
+    pub async fn send(&mut self, message: Message) -> Result<(), WsError> {
+        self.enqueue(message)?;
+        self.flush().await
+    }
+
+    /// Send an **idempotent** message, such as a subscription or a query,
+    /// reconnecting and sending it again if it may not have arrived.
+    ///
+    /// If the connection is already unusable, or the send fails because the
+    /// connection broke or the peer closed it, the connection is dropped and
+    /// re-established as the [`ReconnectPolicy`] decides, the
+    /// [`resume_with`](Self::resume_with) resubscribe messages are sent, and
+    /// the message is sent again on the new session. Other errors are
+    /// returned right away. After the [`ReliableSend`]'s attempts, the last
+    /// error is returned; after its deadline, [`WsError::Timeout`], and the
+    /// session is dropped, since the attempt may have been cut off partway
+    /// through a frame. Each attempt flushes, so a write to a connection the
+    /// peer has reset fails here rather than on a later send.
+    ///
+    /// A failed send may still have reached the peer, so the message can be
+    /// delivered more than once. Never use this for messages that must not
+    /// be repeated, such as orders; use [`send`](Self::send) for those. Each
+    /// attempt is reported as [`WsEvent::ReliableSend`] with the message's
+    /// dedupe key and session.
+    ///
+    /// The message does not go through the outbound queue: messages already
+    /// queued stay there for the next [`flush`](Self::flush).
+    pub async fn send_reliable(&mut self, send: ReliableSend) -> Result<(), WsError> {
+        let deadline = send.deadline;
+        match monoio::time::timeout(deadline, self.send_with_retries(send)).await {
+            Ok(result) => result,
+            Err(_) => {
+                // The attempt may have stopped partway through a frame, so the
+                // session cannot be written to again; the next call reconnects.
+                let e = WsError::Timeout(TimedOut::after(deadline));
+                self.disconnected(None, Some(&e));
+                Err(e)
+            }
+        }
+    }
+
+    async fn send_with_retries(&mut self, send: ReliableSend) -> Result<(), WsError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if let Some(client) = self.client.as_ref().filter(|c| !c.is_usable()) {
+                let close = client.peer_close().cloned();
+                self.disconnected(close, None);
+            }
+            if self.client.is_none() {
+                self.reconnect_after(None).await?;
+            }
+            if let Some(events) = &self.builder.events {
+                events.emit(&WsEvent::ReliableSend {
+                    dedupe_key: send.dedupe_key.clone(),
+                    session: self.session,
+                    attempt,
+                });
+            }
+            let Some(client) = self.client.as_mut() else {
+                unreachable!("reconnected above");
+            };
+            let sent = match client.send(send.message.clone()).await {
+                Ok(()) => client.flush().await,
+                Err(e) => Err(e),
+            };
+            let Err(e) = sent else {
+                return Ok(());
+            };
+            self.disconnected(None, Some(&e));
+            if attempt >= send.max_attempts || !is_connection_lost(&e) {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Queue `message` with the builder's default time to live.
+    pub fn enqueue(&mut self, message: Message) -> Result<(), WsError> {
+        self.enqueue_with_ttl(message, self.builder.outbound_ttl)
+    }
+
+    /// Queue `message`, dropping it if it has not been sent within `ttl`
+    /// (`None` keeps it until sent).
+    ///
+    /// With a [`memory_budget`](WsClientBuilder::memory_budget) the payload
+    /// is charged to it until the message is sent or expires; a message the
+    /// budget cannot cover is not queued and [`WsError::MemoryBudget`] is
+    /// returned.
+    pub fn enqueue_with_ttl(
+        &mut self,
+        message: Message,
+        ttl: Option<Duration>,
+    ) -> Result<(), WsError> {
+        let reservation = self.builder.reserve(message.payload().len())?;
+        let enqueued = Instant::now();
+        self.outbound.push_back(Queued {
+            message,
+            enqueued,
+            deadline: ttl.map(|ttl| enqueued + ttl),
+            _reservation: reservation,
+        });
+        Ok(())
+    }
+
+    /// Send queued messages in order, reconnecting as needed and dropping
+    /// those that expired while waiting.
+    ///
+    /// If a send fails the connection is dropped and the error returned; that
+    /// message may or may not have reached the peer and is not retried. The
+    /// rest of the queue is kept for the next call.
+    pub async fn flush(&mut self) -> Result<(), WsError> {
+        while let Some(queued) = self.outbound.pop_front() {
+            if queued.is_expired() {
+                self.expired += 1;
+                if let Some(events) = &self.builder.events {
+                    events.emit(&WsEvent::OutboundExpired {
+                        queued_for: queued.enqueued.elapsed(),
+                    });
+                }
+                continue;
+            }
+            let client = match self.client.as_mut() {
+                Some(client) => client,
+                None => match self.reconnect_after(None).await {
+                    Ok(client) => client,
+                    Err(e) => {
+                        self.outbound.push_front(queued);
+                        return Err(e);
+                    }
+                },
+            };
+            // Re-check: the reconnect may have outlived the deadline.
+            if queued.is_expired() {
+                self.outbound.push_front(queued);
+                continue;
+            }
+            if let Err(e) = client.send(queued.message).await {
+                self.disconnected(None, Some(&e));
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.outbound.len()
+    }
+
+    /// Messages dropped because their time to live ran out before sending.
+    pub fn expired(&self) -> u64 {
+        self.expired
+    }
+
+    /// Receive the next message, reconnecting on failure or peer close.
+    ///
+    /// The peer's Close message is still returned so the caller can observe
+    /// it; the reconnect happens on the following call. Errors are only
+    /// returned when the policy decides to stop, or by a failed
+    /// [`replace`](Self::replace).
+    pub async fn recv(&mut self) -> Result<Message, WsError> {
+        self.recv_tagged().await.map(|(_, message)| message)
+    }
+
+    /// [`recv`](Self::recv), also returning the id of the
+    /// [`session`](Self::session) the message arrived on. Only differs from
+    /// the current one while a [`replace`](Self::replace) overlaps two
+    /// sessions.
+    pub async fn recv_tagged(&mut self) -> Result<(u64, Message), WsError> {
+        let mut error = None;
+        loop {
+            if self
+                .builder
+                .cancel_token()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                return Err(self.cancelled().await);
+            }
+            if self.client.is_none() {
+                match self.replacement.take() {
+                    Some(Replacement {
+                        session,
+                        url,
+                        state: ReplacementState::Warming(client),
+                        ..
+                    }) => self.promote(*client, session, url).await,
+                    // Whether it connects or fails, the next step tells.
+                    Some(connecting) => self.replacement = Some(connecting),
+                    None => {
+                        self.reconnect_after(error.take()).await?;
+                    }
+                }
+            }
+            match self.next_step().await {
+                Step::Current(Ok(Message::Close(close))) => {
+                    let code = close.clone().unwrap_or(CloseFrame {
+                        code: CloseFrame::NO_STATUS_RECEIVED,
+                        reason: String::new(),
+                    });
+                    let session = self.session;
+                    self.disconnected(Some(code), None);
+                    return Ok((session, Message::Close(close)));
+                }
+                Step::Current(Ok(message)) => {
+                    self.attempts = 0;
+                    self.jitter.reset();
+                    self.observe(&message);
+                    return Ok((self.session, message));
+                }
+                Step::Current(Err(e)) => {
+                    self.disconnected(None, Some(&e));
+                    error = Some(e);
+                }
+                Step::Connected(Ok(client)) => {
+                    let Some(replacement) = self.replacement.take() else {
+                        continue;
+                    };
+                    if replacement.ready.is_some() {
+                        self.replacement = Some(Replacement {
+                            state: ReplacementState::Warming(client),
+                            ..replacement
+                        });
+                    } else {
+                        self.promote(*client, replacement.session, replacement.url)
+                            .await;
+                    }
+                }
+                Step::Replacement(Ok(Message::Close(close))) => {
+                    self.replacement = None;
+                    let close = close.unwrap_or(CloseFrame {
+                        code: CloseFrame::NO_STATUS_RECEIVED,
+                        reason: String::new(),
+                    });
+                    return Err(WsError::ReplacementFailed(Box::new(WsError::ClosedByPeer(
+                        close,
+                    ))));
+                }
+                Step::Replacement(Ok(message)) => {
+                    let Some(replacement) = self.replacement.take() else {
+                        continue;
+                    };
+                    self.observe(&message);
+                    let session = replacement.session;
+                    match replacement {
+                        Replacement {
+                            url,
+                            state: ReplacementState::Warming(client),
+                            ready: Some(ready),
+                            ..
+                        } if ready(&message) => self.promote(*client, session, url).await,
+                        replacement => self.replacement = Some(replacement),
+                    }
+                    return Ok((session, message));
+                }
+                Step::Connected(Err(e)) | Step::Replacement(Err(e)) => {
+                    self.replacement = None;
+                    return Err(WsError::ReplacementFailed(Box::new(e)));
+                }
+                Step::Retiring(Ok(message)) if !matches!(message, Message::Close(_)) => {
+                    let Some(retiring) = &self.retiring else {
+                        continue;
+                    };
+                    let session = retiring.session;
+                    self.observe(&message);
+                    return Ok((session, message));
+                }
+                Step::Retiring(_) => self.retiring = None,
+                Step::Job(index, result) => {
+                    let result = match result {
+                        Ok(messages) => self.send_scheduled(messages).await,
+                        Err(e) => Err(e),
+                    };
+                    let failure = self.scheduler.finished(index, result.is_ok());
+                    if let (Some(failure), Err(e)) = (failure, result) {
+                        if let Some(events) = &self.builder.events {
+                            events.emit(&WsEvent::ScheduledJobFailed {
+                                name: failure.name,
+                                failures: failure.failures,
+                                error: e.to_string(),
+                                code: e.code(),
+                                reconnect: failure.reconnect,
+                            });
+                        }
+                        // A failed send leaves the session unusable anyway.
+                        let broken = self.client.as_ref().is_some_and(|c| !c.is_usable());
+                        if failure.reconnect || broken {
+                            self.disconnected(None, Some(&e));
+                            error = Some(e);
+                        }
+                    }
+                }
+                Step::Cancelled => {}
+                Step::Deadline => {
+                    let now = Instant::now();
+                    if self.retiring.as_ref().is_some_and(|r| now >= r.deadline) {
+                        self.retiring = None;
+                    }
+                    if let Some(replacement) = self.replacement.take_if(|r| now >= r.deadline) {
+                        return Err(WsError::ReplacementFailed(Box::new(WsError::Timeout(
+                            TimedOut::after(replacement.timeout),
+                        ))));
+                    }
+                }
+            }
+        }
+    }
+
+    /// End every session as the builder's [`OnCancel`](crate::cancel::OnCancel)
+    /// says, dropping a replacement still connecting.
+    async fn cancelled(&mut self) -> WsError {
+        self.replacement = None;
+        if let Some(retiring) = &mut self.retiring {
+            retiring.client.cancelled().await;
+        }
+        self.retiring = None;
+        if let Some(client) = &mut self.client {
+            client.cancelled().await;
+        }
+        self.disconnected(None, Some(&WsError::Cancelled));
+        WsError::Cancelled
+    }
+
+    fn observe(&mut self, message: &Message) {
+        if let Some(resume) = &mut self.resume {
+            resume.observe(message);
+        }
+    }
+
+    /// Wait for whichever session has something to report first.
+    async fn next_step(&mut self) -> Step {
+        let deadline = [
+            self.replacement.as_ref().map(|r| r.deadline),
+            self.retiring.as_ref().map(|r| r.deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let current = async {
+            match &mut self.client {
+                Some(client) => Step::Current(client.recv().await),
+                None => pending().await,
+            }
+        };
+        let replacement = async {
+            match self.replacement.as_mut().map(|r| &mut r.state) {
+                Some(ReplacementState::Connecting(task)) => Step::Connected(task.await),
+                Some(ReplacementState::Warming(client)) => Step::Replacement(client.recv().await),
+                None => pending().await,
+            }
+        };
+        let retiring = async {
+            match &mut self.retiring {
+                Some(retiring) => Step::Retiring(retiring.client.recv().await),
+                None => pending().await,
+            }
+        };
+        let jobs = async {
+            if self.scheduler.is_empty() {
+                return pending().await;
+            }
+            let (index, result) = self.scheduler.next_done().await;
+            Step::Job(index, result)
+        };
+        let cancelled = async {
+            match self.builder.cancel_token() {
+                Some(token) => {
+                    token.cancelled().await;
+                    Step::Cancelled
+                }
+                None => pending().await,
+            }
+        };
+        let timer = async {
+            match deadline {
+                Some(deadline) => {
+                    monoio::time::sleep_until(monoio::time::Instant::from_std(deadline)).await;
+                    Step::Deadline
+                }
+                None => pending().await,
+            }
+        };
+        monoio::select! {
+            step = current => step,
+            step = replacement => step,
+            step = retiring => step,
+            step = jobs => step,
+            step = timer => step,
+            step = cancelled => step,
+        }
+    }
+}
+
+/// Connect to `url` as session `session` and send `resubscribe` on the new
+/// connection.
+async fn open_session(
+    builder: &WsClientBuilder,
+    url: &str,
+    session: u64,
+    resubscribe: Vec<Message>,
+) -> Result<WsClient, WsError> {
+    // Boxed to keep the large connect future out of every `recv` future:
+    // inline, the reconnect overflowed a 2 MiB thread stack in debug builds.
+    let mut client = Box::pin(builder.connect(url)).await?;
+    client.set_generation(session);
+    for message in resubscribe {
+        client.send(message).await?;
+    }
+    Ok(client)
+}
+
+/// Whether `e` means the connection broke or was closed, so a message sent
+/// again on a new connection may succeed.
+fn is_connection_lost(e: &WsError) -> bool {
+    matches!(
+        e,
+        WsError::Io(_)
+            | WsError::ClosedByPeer(_)
+            | WsError::Timeout(_)
+            | WsError::KeepaliveTimeout { .. }
+            | WsError::WebSocket(
+                WebSocketError::IoError(_)
+                    | WebSocketError::UnexpectedEOF
+                    | WebSocketError::ConnectionClosed
+            )
+    )
+}
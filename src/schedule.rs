@@ -0,0 +1,276 @@
+//! Recurring jobs run by a [`WsReconnectClient`], such as re-authenticating
+//! before a token expires.
+//!
+//! A [`ScheduledJob`] produces the messages to send each time it runs. Jobs
+//! are driven by [`WsReconnectClient::recv`], like the rest of the client's
+//! background work, and their messages go out on whichever session is
+//! current when the job finishes.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use websockets_monoio::schedule::ScheduledJob;
+//! use websockets_monoio::{Message, WsClientBuilder, WsReconnectClient};
+//!
+//! # async fn fetch_token() -> Result<String, websockets_monoio::WsError> { Ok(String::new()) }
+//! # async fn run() -> Result<(), websockets_monoio::WsError> {
+//! let mut client = WsReconnectClient::connect(WsClientBuilder::new(), "wss://venue.example/ws").await?;
+//! // Tokens last an hour: re-authenticate every 50 minutes, give or take one.
+//! client.schedule(
+//!     ScheduledJob::every("reauth", Duration::from_secs(50 * 60), || async {
+//!         let token = fetch_token().await?;
+//!         Ok(vec![Message::Text(format!(r#"{{"op":"auth","token":"{token}"}}"#))])
+//!     })
+//!     .jitter(Duration::from_secs(60))
+//!     .reconnect_after(3),
+//! );
+//! loop {
+//!     let message = client.recv().await?;
+//!     println!("{message:?}");
+//! }
+//! # }
+//! ```
+//!
+//! [`WsReconnectClient`]: crate::WsReconnectClient
+//! [`WsReconnectClient::recv`]: crate::WsReconnectClient::recv
+
+use std::fmt;
+use std::future::{pending, poll_fn};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Message, WsError};
+
+type JobFuture = Pin<Box<dyn Future<Output = Result<Vec<Message>, WsError>>>>;
+/// Starts one run of a job.
+type JobFn = dyn Fn() -> JobFuture;
+/// Gives the wall-clock time of the next run after the given time.
+type NextFn = dyn Fn(SystemTime) -> SystemTime;
+
+enum Schedule {
+    Every(Duration),
+    Next(Box<NextFn>),
+}
+
+/// A recurring job for [`WsReconnectClient::schedule`].
+///
+/// Each run calls the job's closure and sends the messages its future
+/// resolves to, in order, on the current session. A run fails if the future
+/// fails or a send does; the failure is reported as
+/// [`WsEvent::ScheduledJobFailed`], and after
+/// [`reconnect_after`](Self::reconnect_after) consecutive failures the
+/// session is dropped and re-established. A run that succeeds resets the
+/// count.
+///
+/// The next run is scheduled when a run starts, so a slow run does not
+/// delay the schedule, and a job never runs twice at once. The schedule is
+/// kept across reconnects.
+///
+/// [`WsReconnectClient::schedule`]: crate::WsReconnectClient::schedule
+/// [`WsEvent::ScheduledJobFailed`]: crate::WsEvent::ScheduledJobFailed
+pub struct ScheduledJob {
+    name: String,
+    schedule: Schedule,
+    jitter: Duration,
+    reconnect_after: Option<u32>,
+    run: Box<JobFn>,
+}
+
+impl ScheduledJob {
+    /// Run `job` every `interval`, first after one `interval`.
+    pub fn every<F>(
+        name: impl Into<String>,
+        interval: Duration,
+        job: impl Fn() -> F + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<Vec<Message>, WsError>> + 'static,
+    {
+        Self::new(name.into(), Schedule::Every(interval), job)
+    }
+
+    /// Run `job` at the wall-clock times `next` gives: it is called with the
+    /// current time and returns the time of the next run, e.g. the next full
+    /// hour. A time in the past runs the job right away.
+    pub fn at<F>(
+        name: impl Into<String>,
+        next: impl Fn(SystemTime) -> SystemTime + 'static,
+        job: impl Fn() -> F + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<Vec<Message>, WsError>> + 'static,
+    {
+        Self::new(name.into(), Schedule::Next(Box::new(next)), job)
+    }
+
+    fn new<F>(name: String, schedule: Schedule, job: impl Fn() -> F + 'static) -> Self
+    where
+        F: Future<Output = Result<Vec<Message>, WsError>> + 'static,
+    {
+        Self {
+            name,
+            schedule,
+            jitter: Duration::ZERO,
+            reconnect_after: None,
+            run: Box::new(move || Box::pin(job())),
+        }
+    }
+
+    /// Delay each run by a random time of up to `jitter`, so that many
+    /// connections do not run the same job at the same instant.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Drop and re-establish the session after `failures` consecutive
+    /// failed runs (at least one). By default failures are only reported.
+    pub fn reconnect_after(mut self, failures: u32) -> Self {
+        self.reconnect_after = Some(failures.max(1));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// When the run after one starting at `now` is due.
+    fn next_run(&self, now: Instant) -> Instant {
+        let delay = match &self.schedule {
+            Schedule::Every(interval) => *interval,
+            Schedule::Next(next) => {
+                let wall = SystemTime::now();
+                next(wall).duration_since(wall).unwrap_or(Duration::ZERO)
+            }
+        };
+        let jitter = match self.jitter.as_nanos() {
+            0 => Duration::ZERO,
+            max => Duration::from_nanos(rand::random_range(0..=max.min(u64::MAX as u128) as u64)),
+        };
+        now + delay + jitter
+    }
+}
+
+impl fmt::Debug for ScheduledJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let schedule = match &self.schedule {
+            Schedule::Every(interval) => format!("every {interval:?}"),
+            Schedule::Next(_) => "at ..".to_owned(),
+        };
+        f.debug_struct("ScheduledJob")
+            .field("name", &self.name)
+            .field("schedule", &schedule)
+            .field("jitter", &self.jitter)
+            .field("reconnect_after", &self.reconnect_after)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A job with its next due time and the run in progress, if any.
+struct JobState {
+    job: ScheduledJob,
+    due: Instant,
+    running: Option<JobFuture>,
+    failures: u32,
+}
+
+/// What a failed run of a job leads to.
+pub(crate) struct JobFailure {
+    pub(crate) name: String,
+    pub(crate) failures: u32,
+    /// Whether the job's threshold was reached and the session should be
+    /// dropped.
+    pub(crate) reconnect: bool,
+}
+
+/// The jobs of a [`WsReconnectClient`](crate::WsReconnectClient).
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    jobs: Vec<JobState>,
+}
+
+impl Scheduler {
+    pub(crate) fn add(&mut self, job: ScheduledJob) {
+        let due = job.next_run(Instant::now());
+        self.jobs.push(JobState {
+            job,
+            due,
+            running: None,
+            failures: 0,
+        });
+    }
+
+    /// Start the runs that are due, and wait for one to finish. Returns its
+    /// index and result.
+    ///
+    /// Cancel safe: runs in progress stay on the scheduler and are resumed
+    /// by the next call.
+    pub(crate) async fn next_done(&mut self) -> (usize, Result<Vec<Message>, WsError>) {
+        loop {
+            let now = Instant::now();
+            for state in &mut self.jobs {
+                if state.running.is_none() && state.due <= now {
+                    state.running = Some((state.job.run)());
+                    state.due = state.job.next_run(now);
+                }
+            }
+            let wake = self
+                .jobs
+                .iter()
+                .filter(|state| state.running.is_none())
+                .map(|state| state.due)
+                .min();
+            let done = poll_fn(|cx| {
+                for (index, state) in self.jobs.iter_mut().enumerate() {
+                    if let Some(run) = &mut state.running
+                        && let Poll::Ready(result) = run.as_mut().poll(cx)
+                    {
+                        state.running = None;
+                        return Poll::Ready((index, result));
+                    }
+                }
+                Poll::Pending
+            });
+            let timer = async {
+                match wake {
+                    Some(wake) => {
+                        monoio::time::sleep_until(monoio::time::Instant::from_std(wake)).await
+                    }
+                    None => pending().await,
+                }
+            };
+            monoio::select! {
+                done = done => return done,
+                _ = timer => {}
+            }
+        }
+    }
+
+    /// Record how the run of job `index` ended. Returns what a failure leads
+    /// to.
+    pub(crate) fn finished(&mut self, index: usize, ok: bool) -> Option<JobFailure> {
+        let state = &mut self.jobs[index];
+        if ok {
+            state.failures = 0;
+            return None;
+        }
+        state.failures += 1;
+        let failures = state.failures;
+        let reconnect = state
+            .job
+            .reconnect_after
+            .is_some_and(|threshold| failures >= threshold);
+        if reconnect {
+            state.failures = 0;
+        }
+        Some(JobFailure {
+            name: state.job.name.clone(),
+            failures,
+            reconnect,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
@@ -0,0 +1,324 @@
+//! Gap detection for feeds that number their messages.
+//!
+//! [`WsClient::track_sequence`] wraps a client so every message with a
+//! sequence number is checked against the previous one. A gap, a duplicate
+//! or a restart from a lower number is reported as
+//! [`WsEvent::SequenceGap`] and starts the recovery callback, typically a
+//! snapshot fetched out of band; [`RecoveryPolicy`] decides what happens to
+//! the messages that arrive meanwhile.
+//!
+//! ```no_run
+//! use websockets_monoio::sequence::RecoveryPolicy;
+//! use websockets_monoio::{Message, WsClient};
+//!
+//! # async fn fetch_snapshot() -> Result<u64, websockets_monoio::WsError> { Ok(0) }
+//! # async fn run(client: WsClient) -> Result<(), websockets_monoio::WsError> {
+//! let mut feed = client.track_sequence(
+//!     RecoveryPolicy::Buffer,
+//!     |message| match message {
+//!         Message::Text(text) => text.strip_prefix("seq ")?.parse().ok(),
+//!         _ => None,
+//!     },
+//!     |gap| async move {
+//!         eprintln!("expected {}, got {}; resyncing", gap.expected, gap.got);
+//!         // Messages up to the snapshot's sequence number are dropped.
+//!         Ok(Some(fetch_snapshot().await?))
+//!     },
+//! );
+//! loop {
+//!     let next = feed.recv().await?;
+//!     println!("{:?} {:?}", next.seq, next.message);
+//! }
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+
+use crate::{Message, WsClient, WsError, WsEvent};
+
+/// Extracts a message's sequence number; `None` means it is not part of the
+/// sequenced stream.
+type SeqFn = dyn Fn(&Message) -> Option<u64>;
+type RecoveryFuture = Pin<Box<dyn Future<Output = Result<Option<u64>, WsError>>>>;
+/// Starts recovering from a gap.
+type RecoverFn = dyn Fn(SequenceGap) -> RecoveryFuture;
+
+/// A sequence number other than the one expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceGap {
+    pub expected: u64,
+    /// Above `expected` for a gap, below it for a duplicate or a reset.
+    pub got: u64,
+}
+
+/// What [`SequencedClient::recv`] does with sequenced messages that arrive
+/// while a recovery is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Hold them, and check them against the recovered sequence number once
+    /// recovery completes: those at or below it are dropped, the rest are
+    /// delivered in order.
+    Buffer,
+    /// Drop them.
+    Drop,
+    /// Deliver them with [`SequencedMessage::recovering`] set, without
+    /// checking their numbers.
+    PassThrough,
+}
+
+/// Tracks the last sequence number seen and spots the next one that does not
+/// follow it.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceTracker {
+    last: Option<u64>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `seq` if it follows the last number, or if there is none yet.
+    /// Otherwise the last number is left as it was.
+    pub fn check(&mut self, seq: u64) -> Result<(), SequenceGap> {
+        match self.last {
+            Some(last) if seq != last.wrapping_add(1) => Err(SequenceGap {
+                expected: last.wrapping_add(1),
+                got: seq,
+            }),
+            _ => {
+                self.last = Some(seq);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Continue after `last`, or from whatever number comes next if `None`.
+    pub fn reset(&mut self, last: Option<u64>) {
+        self.last = last;
+    }
+}
+
+/// A message returned by [`SequencedClient::recv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequencedMessage {
+    pub message: Message,
+    /// The extracted sequence number, if any.
+    pub seq: Option<u64>,
+    /// Delivered unchecked during a recovery, with
+    /// [`RecoveryPolicy::PassThrough`].
+    pub recovering: bool,
+}
+
+/// A [`WsClient`] that checks sequence numbers, created with
+/// [`WsClient::track_sequence`].
+pub struct SequencedClient {
+    client: WsClient,
+    tracker: SequenceTracker,
+    policy: RecoveryPolicy,
+    seq: Box<SeqFn>,
+    recover: Box<RecoverFn>,
+    recovery: Option<RecoveryFuture>,
+    /// Messages held during a recovery, and then replayed.
+    held: VecDeque<Message>,
+    /// Set after a recovery until a message follows the recovered number,
+    /// so older ones are dropped as stale rather than reported.
+    resyncing: bool,
+    gaps: u64,
+    dropped: u64,
+}
+
+impl SequencedClient {
+    pub(crate) fn new(
+        client: WsClient,
+        policy: RecoveryPolicy,
+        seq: Box<SeqFn>,
+        recover: Box<RecoverFn>,
+    ) -> Self {
+        Self {
+            client,
+            tracker: SequenceTracker::new(),
+            policy,
+            seq,
+            recover,
+            recovery: None,
+            held: VecDeque::new(),
+            resyncing: false,
+            gaps: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Receive the next message to deliver, running any recovery alongside.
+    ///
+    /// Messages without a sequence number are always delivered at once. If
+    /// the recovery fails, its error is returned, held messages are
+    /// discarded, and the next sequence number is accepted as is.
+    ///
+    /// Cancel safe: a recovery in progress is kept and resumed by the next
+    /// call.
+    pub async fn recv(&mut self) -> Result<SequencedMessage, WsError> {
+        loop {
+            if self.recovery.is_none()
+                && let Some(message) = self.held.pop_front()
+            {
+                if let Some(next) = self.accept(message) {
+                    return Ok(next);
+                }
+                continue;
+            }
+            let message = match &mut self.recovery {
+                None => self.client.recv().await?,
+                Some(recovery) => {
+                    let recovered = monoio::select! {
+                        recovered = recovery.as_mut() => Some(recovered),
+                        message = self.client.recv() => {
+                            let message = message?;
+                            if let Some(next) = self.accept(message) {
+                                return Ok(next);
+                            }
+                            None
+                        }
+                    };
+                    if let Some(recovered) = recovered {
+                        self.recovered(recovered)?;
+                    }
+                    continue;
+                }
+            };
+            if let Some(next) = self.accept(message) {
+                return Ok(next);
+            }
+        }
+    }
+
+    /// Check `message`, returning it if it is to be delivered now.
+    fn accept(&mut self, message: Message) -> Option<SequencedMessage> {
+        let Some(seq) = (self.seq)(&message) else {
+            return Some(SequencedMessage {
+                message,
+                seq: None,
+                recovering: false,
+            });
+        };
+        if self.recovery.is_some() {
+            return self.during_recovery(message, seq);
+        }
+        if self.resyncing
+            && let Some(last) = self.tracker.last()
+            && seq <= last
+        {
+            self.dropped += 1;
+            return None;
+        }
+        match self.tracker.check(seq) {
+            Ok(()) => {
+                self.resyncing = false;
+                Some(SequencedMessage {
+                    message,
+                    seq: Some(seq),
+                    recovering: false,
+                })
+            }
+            Err(gap) => {
+                self.gaps += 1;
+                self.client.emit(&WsEvent::SequenceGap {
+                    expected: gap.expected,
+                    got: gap.got,
+                });
+                self.recovery = Some((self.recover)(gap));
+                self.during_recovery(message, seq)
+            }
+        }
+    }
+
+    fn during_recovery(&mut self, message: Message, seq: u64) -> Option<SequencedMessage> {
+        match self.policy {
+            RecoveryPolicy::Buffer => {
+                self.held.push_back(message);
+                None
+            }
+            RecoveryPolicy::Drop => {
+                self.dropped += 1;
+                None
+            }
+            RecoveryPolicy::PassThrough => Some(SequencedMessage {
+                message,
+                seq: Some(seq),
+                recovering: true,
+            }),
+        }
+    }
+
+    fn recovered(&mut self, result: Result<Option<u64>, WsError>) -> Result<(), WsError> {
+        self.recovery = None;
+        match result {
+            Ok(last) => {
+                self.tracker.reset(last);
+                self.resyncing = last.is_some();
+                Ok(())
+            }
+            Err(e) => {
+                self.dropped += self.held.len() as u64;
+                self.held.clear();
+                self.tracker.reset(None);
+                self.resyncing = false;
+                Err(e)
+            }
+        }
+    }
+
+    /// The last sequence number accepted, or recovered to; e.g. to resume a
+    /// subscription from it after a reconnect.
+    pub fn last_sequence(&self) -> Option<u64> {
+        self.tracker.last()
+    }
+
+    pub fn is_recovering(&self) -> bool {
+        self.recovery.is_some()
+    }
+
+    /// Gaps, duplicates and resets seen so far.
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+
+    /// Sequenced messages not delivered: dropped during a recovery, stale
+    /// after one, or held when it failed.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn client(&self) -> &WsClient {
+        &self.client
+    }
+
+    /// The underlying client, e.g. for sending. Messages read from it
+    /// directly are not checked.
+    pub fn client_mut(&mut self) -> &mut WsClient {
+        &mut self.client
+    }
+
+    /// Return the client, discarding held messages and any recovery in
+    /// progress.
+    pub fn into_inner(self) -> WsClient {
+        self.client
+    }
+}
+
+impl fmt::Debug for SequencedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SequencedClient")
+            .field("last", &self.tracker.last())
+            .field("policy", &self.policy)
+            .field("recovering", &self.recovery.is_some())
+            .field("held", &self.held.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,671 @@
+//! Consuming many streams across cores, one monoio runtime per core.
+//!
+//! Clients are not `Send`, so each stream is owned by the core it runs on
+//! for its whole life. [`ShardedRunner`] takes the [`ConnectSpec`]s of all
+//! streams and assigns each to a shard by its [`shard_key`], or by a hash
+//! of its id. Every shard runs a [`WsReconnectClient`] per stream on its
+//! own thread and runtime, and hands data messages to a handler made for
+//! that shard. Shards report to a [`Supervisor`] over a channel, so a
+//! single thread can follow connects, [`WsEvent`]s and stats of all of
+//! them, and shut them down together.
+//!
+//! ```no_run
+//! use websockets_monoio::shard::{ConnectSpec, ShardReport, ShardedRunner};
+//! use websockets_monoio::{Keepalive, Message, WsClientBuilder};
+//! use std::time::Duration;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let specs = (0..200).map(|i| {
+//!     ConnectSpec::new(format!("book-{i}"), "wss://venue.example/ws")
+//!         .subscribe(vec![Message::Text(format!(r#"{{"op":"subscribe","book":{i}}}"#))])
+//! });
+//! let supervisor = ShardedRunner::new(specs)
+//!     .shards(4)
+//!     .builder(|_spec| {
+//!         WsClientBuilder::new()
+//!             .keepalive(Keepalive::ping(Duration::from_secs(15), Duration::from_secs(45)))
+//!     })
+//!     .handler(|_shard| {
+//!         // One handler per core, so per-core state needs no locking.
+//!         let mut updates = 0u64;
+//!         move |_spec: &ConnectSpec, _message: Message| updates += 1
+//!     })
+//!     .start()?;
+//! for report in supervisor.reports().iter().take(1000) {
+//!     if let ShardReport::Stats(stats) = report {
+//!         println!("shard {}: {} messages", stats.shard, stats.messages);
+//!     }
+//! }
+//! supervisor.shutdown().expect("a shard panicked");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`shard_key`]: ConnectSpec::shard_key
+//! [`WsReconnectClient`]: crate::WsReconnectClient
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::event::EventSink;
+use crate::{CloseFrame, Message, WsClientBuilder, WsError, WsEvent, WsReconnectClient};
+
+/// Default [`ShardedRunner::stats_interval`].
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a stream waits for the server's Close on shutdown.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One stream for a [`ShardedRunner`] to keep connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectSpec {
+    pub id: String,
+    pub url: String,
+    /// Picks the shard explicitly: the stream goes to shard
+    /// `key % shards`. Without one the shard comes from a hash of the id.
+    pub shard_key: Option<u64>,
+    /// Sent on every connection of the stream, the first and each
+    /// reconnect, before anything is received.
+    pub subscribe: Vec<Message>,
+}
+
+impl ConnectSpec {
+    pub fn new(id: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            url: url.into(),
+            shard_key: None,
+            subscribe: Vec::new(),
+        }
+    }
+
+    pub fn shard_key(mut self, key: u64) -> Self {
+        self.shard_key = Some(key);
+        self
+    }
+
+    pub fn subscribe(mut self, messages: Vec<Message>) -> Self {
+        self.subscribe = messages;
+        self
+    }
+
+    /// The shard of `shards` this stream runs on.
+    pub fn shard(&self, shards: usize) -> usize {
+        let key = self.shard_key.unwrap_or_else(|| fnv1a(self.id.as_bytes()));
+        (key % shards.max(1) as u64) as usize
+    }
+}
+
+/// FNV-1a, so the assignment of streams to shards is stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Counters of one shard since it started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShardStats {
+    pub shard: usize,
+    /// Streams assigned to the shard.
+    pub streams: usize,
+    /// Streams that connected and have not stopped, including those
+    /// reconnecting.
+    pub connected: usize,
+    /// Data messages passed to the handler.
+    pub messages: u64,
+    /// Payload bytes of those messages.
+    pub bytes: u64,
+}
+
+/// What shards tell the [`Supervisor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShardReport {
+    /// The shard's runtime is up and its streams are connecting.
+    Started { shard: usize, streams: usize },
+    /// A stream connected, the first time or after a reconnect; `session`
+    /// is the [`WsReconnectClient::session`] id.
+    Connected {
+        shard: usize,
+        spec: String,
+        session: u64,
+    },
+    /// The first connect of a stream failed; it is retried with the
+    /// builder's [`reconnect_backoff`](WsClientBuilder::reconnect_backoff).
+    ConnectFailed {
+        shard: usize,
+        spec: String,
+        code: &'static str,
+        error: String,
+    },
+    /// An event of one of the stream's connections, as the builder's
+    /// [`on_event`](WsClientBuilder::on_event) sink would see it.
+    Event {
+        shard: usize,
+        spec: String,
+        event: WsEvent,
+    },
+    /// The stream gave up: its reconnect policy stopped, or its first
+    /// connect ran out of attempts.
+    Stopped {
+        shard: usize,
+        spec: String,
+        code: &'static str,
+        error: String,
+    },
+    /// Sent every [`stats_interval`](ShardedRunner::stats_interval).
+    Stats(ShardStats),
+    /// The shard closed its streams and ended.
+    Finished(ShardStats),
+    /// The shard's runtime could not be built.
+    Failed { shard: usize, error: String },
+}
+
+type BuilderFn = dyn Fn(&ConnectSpec) -> WsClientBuilder + Send + Sync;
+type Handler = Box<dyn FnMut(&ConnectSpec, Message)>;
+type HandlerFactory = dyn Fn(usize) -> Handler + Send + Sync;
+
+/// Spreads [`ConnectSpec`]s over shards, one thread and monoio runtime
+/// each; see the [module docs](self).
+pub struct ShardedRunner {
+    specs: Vec<ConnectSpec>,
+    shards: usize,
+    builder: Arc<BuilderFn>,
+    handler: Arc<HandlerFactory>,
+    stats_interval: Duration,
+    pin_threads: bool,
+}
+
+impl ShardedRunner {
+    /// One shard per available core, plain builders and a handler that
+    /// drops every message until [`shards`](Self::shards),
+    /// [`builder`](Self::builder) and [`handler`](Self::handler) say
+    /// otherwise.
+    pub fn new(specs: impl IntoIterator<Item = ConnectSpec>) -> Self {
+        Self {
+            specs: specs.into_iter().collect(),
+            shards: thread::available_parallelism().map_or(1, |n| n.get()),
+            builder: Arc::new(|_| WsClientBuilder::new()),
+            handler: Arc::new(|_| Box::new(|_, _| {})),
+            stats_interval: DEFAULT_STATS_INTERVAL,
+            pin_threads: false,
+        }
+    }
+
+    /// Number of shards; at least one.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// Configure each stream's client. Called on the stream's shard, so the
+    /// builder may hold thread-local state such as an event sink; its
+    /// events are also forwarded as [`ShardReport::Event`].
+    pub fn builder(
+        mut self,
+        f: impl Fn(&ConnectSpec) -> WsClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.builder = Arc::new(f);
+        self
+    }
+
+    /// Make the message handler of each shard, called once on the shard
+    /// with its index. The handler gets the Text and Binary messages of
+    /// all the shard's streams; control frames and Closes are handled by
+    /// the clients.
+    pub fn handler<H>(mut self, factory: impl Fn(usize) -> H + Send + Sync + 'static) -> Self
+    where
+        H: FnMut(&ConnectSpec, Message) + 'static,
+    {
+        self.handler = Arc::new(move |shard| Box::new(factory(shard)));
+        self
+    }
+
+    /// How often each shard sends [`ShardReport::Stats`].
+    pub fn stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Bind the thread of shard `i` to CPU `i` (modulo the CPUs available)
+    /// in [`start`](Self::start). Best effort: where binding fails or is
+    /// not supported the thread runs unbound.
+    pub fn pin_threads(mut self, pin: bool) -> Self {
+        self.pin_threads = pin;
+        self
+    }
+
+    /// The shard each spec was assigned to, in the order given.
+    pub fn assignment(&self) -> Vec<usize> {
+        self.specs
+            .iter()
+            .map(|spec| spec.shard(self.shards))
+            .collect()
+    }
+
+    /// Start every shard on its own thread, with a monoio runtime that uses
+    /// io_uring where available and timers enabled.
+    pub fn start(self) -> std::io::Result<Supervisor> {
+        let pin = self.pin_threads;
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        let (shards, mut supervisor) = self.into_shards();
+        for shard in shards {
+            let index = shard.index;
+            let reports = shard.reports.clone();
+            let thread = thread::Builder::new()
+                .name(format!("ws-shard-{index}"))
+                .spawn(move || {
+                    if pin {
+                        let _ = monoio::utils::bind_to_cpu_set([index % cpus]);
+                    }
+                    let runtime = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+                        .enable_timer()
+                        .build();
+                    match runtime {
+                        Ok(mut runtime) => runtime.block_on(shard.run()),
+                        Err(e) => {
+                            let _ = reports.send(ShardReport::Failed {
+                                shard: index,
+                                error: e.to_string(),
+                            });
+                        }
+                    }
+                });
+            match thread {
+                Ok(thread) => supervisor.threads.push(thread),
+                Err(e) => {
+                    supervisor.stop();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(supervisor)
+    }
+
+    /// The shards without threads, to run each with [`Shard::run`] on a
+    /// runtime of the caller's, with timers enabled. The [`Supervisor`]'s
+    /// [`shutdown`](Supervisor::shutdown) then only signals them.
+    pub fn into_shards(self) -> (Vec<Shard>, Supervisor) {
+        let (reports, receiver) = mpsc::channel();
+        let stop = Arc::new(StopSignal::new(self.shards));
+        let mut specs: Vec<Vec<ConnectSpec>> = vec![Vec::new(); self.shards];
+        for spec in self.specs {
+            specs[spec.shard(self.shards)].push(spec);
+        }
+        let shards = specs
+            .into_iter()
+            .enumerate()
+            .map(|(index, specs)| Shard {
+                index,
+                specs,
+                builder: self.builder.clone(),
+                handler: self.handler.clone(),
+                stats_interval: self.stats_interval,
+                reports: reports.clone(),
+                stop: stop.clone(),
+            })
+            .collect();
+        let supervisor = Supervisor {
+            reports: receiver,
+            stop,
+            threads: Vec::new(),
+        };
+        (shards, supervisor)
+    }
+}
+
+impl fmt::Debug for ShardedRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedRunner")
+            .field("specs", &self.specs.len())
+            .field("shards", &self.shards)
+            .field("stats_interval", &self.stats_interval)
+            .field("pin_threads", &self.pin_threads)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A shutdown request, set by the [`Supervisor`] and awaited by one task of
+/// each shard, which holds the shard's slot. Needs monoio's `sync` feature
+/// to wake shard runtimes from the supervisor's thread.
+struct StopSignal {
+    stopped: AtomicBool,
+    waiters: Mutex<Vec<Option<Waker>>>,
+}
+
+impl StopSignal {
+    fn new(shards: usize) -> Self {
+        Self {
+            stopped: AtomicBool::new(false),
+            waiters: Mutex::new(vec![None; shards]),
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    fn stop(&self) {
+        let mut waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+        self.stopped.store(true, Ordering::Release);
+        for waker in waiters.iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+    }
+
+    /// Resolves once [`stop`](Self::stop) was called.
+    async fn wait(&self, shard: usize) {
+        poll_fn(|cx| {
+            let mut waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            if self.is_stopped() {
+                return Poll::Ready(());
+            }
+            let slot = &mut waiters[shard];
+            if !slot.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+                *slot = Some(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Receives the [`ShardReport`]s of all shards and shuts them down.
+pub struct Supervisor {
+    reports: Receiver<ShardReport>,
+    stop: Arc<StopSignal>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn reports(&self) -> &Receiver<ShardReport> {
+        &self.reports
+    }
+
+    /// Ask every shard to close its streams and end, without waiting.
+    /// Shards waiting on the network see it at once.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// [`stop`](Self::stop) the shards and wait for the threads started by
+    /// [`ShardedRunner::start`] to end. Each shard closes its streams with
+    /// 1000, waiting up to [`CLOSE_TIMEOUT`] for the server's answer, and
+    /// sends [`ShardReport::Finished`]. Returns the panic of a shard whose
+    /// handler or builder panicked.
+    pub fn shutdown(self) -> thread::Result<()> {
+        self.stop();
+        let mut result = Ok(());
+        for thread in self.threads {
+            if let Err(panic) = thread.join() {
+                result = result.and(Err(panic));
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("stopping", &self.stop.is_stopped())
+            .field("threads", &self.threads.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The streams of one shard, from [`ShardedRunner::into_shards`].
+pub struct Shard {
+    index: usize,
+    specs: Vec<ConnectSpec>,
+    builder: Arc<BuilderFn>,
+    handler: Arc<HandlerFactory>,
+    stats_interval: Duration,
+    reports: Sender<ShardReport>,
+    stop: Arc<StopSignal>,
+}
+
+/// State shared by the tasks of a running shard.
+struct ShardState {
+    index: usize,
+    builder: Arc<BuilderFn>,
+    handler: RefCell<Handler>,
+    reports: Sender<ShardReport>,
+    /// Cancelled when the [`Supervisor`] stops the shard.
+    shutdown: CancellationToken,
+    streams: usize,
+    connected: Cell<usize>,
+    messages: Cell<u64>,
+    bytes: Cell<u64>,
+}
+
+impl ShardState {
+    fn report(&self, report: ShardReport) {
+        // A supervisor that went away just stops listening.
+        let _ = self.reports.send(report);
+    }
+
+    fn stats(&self) -> ShardStats {
+        ShardStats {
+            shard: self.index,
+            streams: self.streams,
+            connected: self.connected.get(),
+            messages: self.messages.get(),
+            bytes: self.bytes.get(),
+        }
+    }
+
+    fn stopping(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Resolves once shutdown was requested.
+    async fn stopped(&self) {
+        self.shutdown.cancelled().await
+    }
+}
+
+impl Shard {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn specs(&self) -> &[ConnectSpec] {
+        &self.specs
+    }
+
+    /// Run the shard's streams until the [`Supervisor`] stops it. Must run
+    /// inside a monoio runtime with the timer enabled.
+    pub async fn run(self) {
+        let shutdown = CancellationToken::new();
+        let watch = monoio::spawn({
+            let (stop, shutdown) = (self.stop, shutdown.clone());
+            let index = self.index;
+            async move {
+                stop.wait(index).await;
+                shutdown.cancel();
+            }
+        });
+        let state = Rc::new(ShardState {
+            index: self.index,
+            builder: self.builder,
+            handler: RefCell::new((self.handler)(self.index)),
+            reports: self.reports,
+            shutdown,
+            streams: self.specs.len(),
+            connected: Cell::new(0),
+            messages: Cell::new(0),
+            bytes: Cell::new(0),
+        });
+        state.report(ShardReport::Started {
+            shard: state.index,
+            streams: state.streams,
+        });
+        let streams: Vec<_> = self
+            .specs
+            .into_iter()
+            .map(|spec| monoio::spawn(run_stream(state.clone(), spec)))
+            .collect();
+        let interval = self.stats_interval;
+        let stats = monoio::spawn({
+            let state = state.clone();
+            async move {
+                while !state.stopping() {
+                    monoio::select! {
+                        _ = monoio::time::sleep(interval) => {
+                            state.report(ShardReport::Stats(state.stats()));
+                        }
+                        _ = state.stopped() => {}
+                    }
+                }
+            }
+        });
+        for stream in streams {
+            stream.await;
+        }
+        stats.await;
+        watch.await;
+        state.report(ShardReport::Finished(state.stats()));
+    }
+}
+
+impl fmt::Debug for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shard")
+            .field("index", &self.index)
+            .field("specs", &self.specs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keep one stream connected and feed its messages to the shard's handler
+/// until shutdown.
+async fn run_stream(state: Rc<ShardState>, spec: ConnectSpec) {
+    let Some(mut client) = connect(&state, &spec).await else {
+        return;
+    };
+    state.connected.set(state.connected.get() + 1);
+    let mut session = client.session();
+    loop {
+        let received = monoio::select! {
+            received = client.recv() => received,
+            _ = state.stopped() => break,
+        };
+        if client.session() != session {
+            session = client.session();
+            state.report(ShardReport::Connected {
+                shard: state.index,
+                spec: spec.id.clone(),
+                session,
+            });
+        }
+        match received {
+            Ok(message @ (Message::Text(_) | Message::Binary(_))) => {
+                state.messages.set(state.messages.get() + 1);
+                state
+                    .bytes
+                    .set(state.bytes.get() + message.payload().len() as u64);
+                (state.handler.borrow_mut())(&spec, message);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                state.connected.set(state.connected.get() - 1);
+                stopped(&state, &spec, &e);
+                return;
+            }
+        }
+    }
+    state.connected.set(state.connected.get() - 1);
+    if let Some(client) = client.client()
+        && client.is_usable()
+    {
+        let close = Message::Close(Some(CloseFrame {
+            code: 1000,
+            reason: "shutdown".into(),
+        }));
+        if client.send(close).await.is_ok() {
+            let _ = monoio::time::timeout(CLOSE_TIMEOUT, client.consume_until_close()).await;
+        }
+    }
+}
+
+/// The first connect of a stream, retried with the builder's backoff;
+/// `None` once it gave up or shutdown was requested.
+async fn connect(state: &ShardState, spec: &ConnectSpec) -> Option<WsReconnectClient> {
+    let mut attempt = 0;
+    loop {
+        let mut builder = (state.builder)(spec);
+        let reports = state.reports.clone();
+        let (shard, id) = (state.index, spec.id.clone());
+        builder.events = Some(EventSink::tap(builder.events, move |event| {
+            let _ = reports.send(ShardReport::Event {
+                shard,
+                spec: id.clone(),
+                event: event.clone(),
+            });
+        }));
+        let backoff = builder.backoff;
+        let connected = monoio::select! {
+            connected = open(builder, spec) => connected,
+            _ = state.stopped() => return None,
+        };
+        let e = match connected {
+            Ok(client) => {
+                state.report(ShardReport::Connected {
+                    shard: state.index,
+                    spec: spec.id.clone(),
+                    session: client.session(),
+                });
+                return Some(client);
+            }
+            Err(e) => e,
+        };
+        attempt += 1;
+        if backoff.max_attempts.is_some_and(|max| attempt >= max) {
+            stopped(state, spec, &e);
+            return None;
+        }
+        state.report(ShardReport::ConnectFailed {
+            shard: state.index,
+            spec: spec.id.clone(),
+            code: e.code(),
+            error: e.to_string(),
+        });
+        monoio::select! {
+            _ = monoio::time::sleep(backoff.delay(attempt - 1)) => {}
+            _ = state.stopped() => return None,
+        }
+    }
+}
+
+/// Connect and subscribe, and have reconnects subscribe again.
+async fn open(builder: WsClientBuilder, spec: &ConnectSpec) -> Result<WsReconnectClient, WsError> {
+    let mut client = WsReconnectClient::connect(builder, &spec.url).await?;
+    let subscribe = spec.subscribe.clone();
+    client.resume_with(|_| None::<()>, move |_| subscribe.clone());
+    if let Some(ws) = client.client() {
+        for message in spec.subscribe.iter().cloned() {
+            ws.send(message).await?;
+        }
+    }
+    Ok(client)
+}
+
+fn stopped(state: &ShardState, spec: &ConnectSpec, e: &WsError) {
+    state.report(ShardReport::Stopped {
+        shard: state.index,
+        spec: spec.id.clone(),
+        code: e.code(),
+        error: e.to_string(),
+    });
+}
@@ -0,0 +1,339 @@
+//! Separate read and write halves of a connection, for applications that
+//! receive in one task and send from another.
+//!
+//! [`WsClient::split`] hands the receive path to a [`WsReader`] and gives a
+//! [`WsWriter`] its own handle on the transport. The halves share a
+//! [`ConnState`], so each learns promptly how the other one ended the
+//! session:
+//!
+//! - When the reader receives the peer's Close, the writer's sends fail
+//!   right away with [`WsError::ClosedByPeer`], and
+//!   [`WsWriter::closed`] resolves.
+//! - When the writer sends a Close, the reader discards data messages until
+//!   the peer's Close, returns it, and then stops.
+//!
+//! ```no_run
+//! use websockets_monoio::split::ConnState;
+//! use websockets_monoio::{Message, WsClient};
+//!
+//! # async fn run(client: WsClient) -> Result<(), websockets_monoio::WsError> {
+//! let (mut reader, mut writer) = client.split();
+//! monoio::spawn(async move {
+//!     loop {
+//!         monoio::select! {
+//!             state = writer.closed() => {
+//!                 eprintln!("connection ended: {state:?}");
+//!                 return;
+//!             }
+//!             _ = monoio::time::sleep(std::time::Duration::from_secs(1)) => {}
+//!         }
+//!         if writer.send(Message::Text("tick".into())).await.is_err() {
+//!             return;
+//!         }
+//!     }
+//! });
+//! while !matches!(reader.state(), ConnState::ClosedByPeer(_) | ConnState::Failed) {
+//!     println!("{:?}", reader.recv().await?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use fastwebsockets::WebSocketError;
+
+use crate::client::{AnyStream, PendingWrite, SharedStream, WriteLock};
+use crate::{CloseFrame, Message, WsClient, WsError};
+
+/// How far the connection shared by a [`WsReader`] and a [`WsWriter`] has
+/// gone towards closing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnState {
+    Open,
+    /// The writer sent a Close and the peer's has not arrived yet.
+    Closing,
+    /// The reader received the peer's Close. A Close without a status is
+    /// reported with code [`CloseFrame::NO_STATUS_RECEIVED`].
+    ClosedByPeer(CloseFrame),
+    /// A read or write failed; the connection cannot be used any more.
+    Failed,
+}
+
+/// The [`ConnState`] of a split connection, and the tasks waiting for it
+/// to end.
+#[derive(Default)]
+pub(crate) struct SplitShared {
+    state: RefCell<Option<ConnState>>,
+    /// One slot per pending [`Closed`] future, freed when it is dropped.
+    waiters: RefCell<Vec<Option<Waker>>>,
+}
+
+impl SplitShared {
+    pub(crate) fn state(&self) -> ConnState {
+        self.state.borrow().clone().unwrap_or(ConnState::Open)
+    }
+
+    fn is_ended(&self) -> bool {
+        matches!(self.state(), ConnState::ClosedByPeer(_) | ConnState::Failed)
+    }
+
+    fn set(&self, state: ConnState) {
+        *self.state.borrow_mut() = Some(state);
+        // The slots stay with their futures until those are dropped.
+        for waker in self.waiters.borrow().iter().flatten() {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Record the peer's Close. Returns whether it still has to be answered,
+    /// i.e. the writer has not sent a Close of its own.
+    pub(crate) fn peer_closed(&self, close: CloseFrame) -> bool {
+        let answer = match self.state() {
+            ConnState::Open => true,
+            ConnState::Closing => false,
+            ConnState::ClosedByPeer(_) | ConnState::Failed => return false,
+        };
+        self.set(ConnState::ClosedByPeer(close));
+        answer
+    }
+
+    /// Record a failed read or write, unless the session already ended.
+    pub(crate) fn failed(&self) {
+        if !self.is_ended() {
+            self.set(ConnState::Failed);
+        }
+    }
+
+    /// The error a write gets in the current state, if any.
+    fn check_writable(&self) -> Result<(), WsError> {
+        match self.state() {
+            ConnState::Open => Ok(()),
+            ConnState::ClosedByPeer(close) => Err(WsError::ClosedByPeer(close)),
+            ConnState::Closing | ConnState::Failed => Err(WebSocketError::ConnectionClosed.into()),
+        }
+    }
+}
+
+/// The receiving half of a split [`WsClient`], created with
+/// [`WsClient::split`].
+///
+/// Pings are answered and the peer's Close is replied to as with
+/// [`WsClient::recv`], through the writer's lock so replies never land
+/// inside a frame the [`WsWriter`] is writing.
+pub struct WsReader {
+    client: WsClient,
+    shared: Rc<SplitShared>,
+    /// The peer's Close was returned; nothing more is read.
+    done: bool,
+}
+
+impl WsReader {
+    pub(crate) fn new(client: WsClient, shared: Rc<SplitShared>) -> Self {
+        Self {
+            client,
+            shared,
+            done: false,
+        }
+    }
+
+    /// Receive the next message, as [`WsClient::recv`].
+    ///
+    /// Once the [`WsWriter`] has sent a Close, Text and Binary messages are
+    /// discarded until the peer's Close, which is returned. After the peer's
+    /// Close has been returned, calls fail with [`WsError::ClosedByPeer`]
+    /// without reading.
+    pub async fn recv(&mut self) -> Result<Message, WsError> {
+        if self.done
+            && let ConnState::ClosedByPeer(close) = self.shared.state()
+        {
+            return Err(WsError::ClosedByPeer(close));
+        }
+        loop {
+            let message = self.client.recv().await?;
+            match message {
+                Message::Close(_) => {
+                    self.done = true;
+                    return Ok(message);
+                }
+                Message::Text(_) | Message::Binary(_)
+                    if self.shared.state() == ConnState::Closing => {}
+                message => return Ok(message),
+            }
+        }
+    }
+
+    pub fn state(&self) -> ConnState {
+        self.shared.state()
+    }
+
+    /// The underlying client, e.g. for its [`stats`](WsClient::stats) or
+    /// [`info`](WsClient::info). Frames written by the [`WsWriter`] are not
+    /// counted in its stats.
+    pub fn client(&self) -> &WsClient {
+        &self.client
+    }
+}
+
+impl fmt::Debug for WsReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsReader")
+            .field("state", &self.shared.state())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The sending half of a split [`WsClient`], created with
+/// [`WsClient::split`].
+///
+/// Writes are serialized frame by frame with the [`WsReader`]'s replies and
+/// any [`BackgroundPing`](crate::BackgroundPing). A send cancelled mid-frame
+/// is finished by the next call before anything else is written. Outbound
+/// interceptors do not run on this path.
+pub struct WsWriter {
+    io: SharedStream<AnyStream>,
+    lock: Rc<WriteLock>,
+    mask: bool,
+    outbox: Option<PendingWrite>,
+    shared: Rc<SplitShared>,
+}
+
+impl WsWriter {
+    pub(crate) fn new(
+        io: SharedStream<AnyStream>,
+        lock: Rc<WriteLock>,
+        mask: bool,
+        shared: Rc<SplitShared>,
+    ) -> Self {
+        Self {
+            io,
+            lock,
+            mask,
+            outbox: None,
+            shared,
+        }
+    }
+
+    /// Send a complete message as a single frame.
+    ///
+    /// Fails without writing with [`WsError::ClosedByPeer`] once the reader
+    /// has received the peer's Close, and with
+    /// [`WebSocketError::ConnectionClosed`] after [`close`](Self::close) or
+    /// a failed read or write.
+    pub async fn send(&mut self, message: Message) -> Result<(), WsError> {
+        self.flush_outbox().await?;
+        self.shared.check_writable()?;
+        let closing = matches!(message, Message::Close(_));
+        self.outbox = Some(PendingWrite::frame(message, self.mask));
+        self.flush_outbox().await?;
+        if closing && self.shared.state() == ConnState::Open {
+            self.shared.set(ConnState::Closing);
+        }
+        Ok(())
+    }
+
+    /// Send a Close with `code` and `reason`. The [`WsReader`] then drains
+    /// the connection until the peer answers.
+    pub async fn close(&mut self, code: u16, reason: &str) -> Result<(), WsError> {
+        self.send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await
+    }
+
+    async fn flush_outbox(&mut self) -> Result<(), WsError> {
+        let result = PendingWrite::flush(&mut self.outbox, &mut self.io, &self.lock).await;
+        if let Err(e) = result {
+            self.outbox = None;
+            self.shared.failed();
+            return Err(match self.shared.state() {
+                ConnState::ClosedByPeer(close) => WsError::ClosedByPeer(close),
+                _ => e,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> ConnState {
+        self.shared.state()
+    }
+
+    /// Wait until the peer's Close has been received or the connection
+    /// failed, and return that state. Resolves right away if it already has.
+    ///
+    /// Only the [`WsReader`] sees the peer's Close, so this relies on the
+    /// reader being polled.
+    ///
+    /// Each future holds one waiter slot until it resolves or is dropped,
+    /// so calling this again on every turn of a `select!` loop does not
+    /// pile up wakers.
+    pub fn closed(&self) -> Closed<'_> {
+        Closed {
+            shared: &self.shared,
+            slot: None,
+        }
+    }
+}
+
+/// Future returned by [`WsWriter::closed`].
+pub struct Closed<'a> {
+    shared: &'a SplitShared,
+    slot: Option<usize>,
+}
+
+impl Future for Closed<'_> {
+    type Output = ConnState;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ConnState> {
+        if self.shared.is_ended() {
+            return Poll::Ready(self.shared.state());
+        }
+        let mut waiters = self.shared.waiters.borrow_mut();
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => match waiters.iter().position(Option::is_none) {
+                Some(free) => free,
+                None => {
+                    waiters.push(None);
+                    waiters.len() - 1
+                }
+            },
+        };
+        waiters[slot] = Some(cx.waker().clone());
+        drop(waiters);
+        self.slot = Some(slot);
+        Poll::Pending
+    }
+}
+
+impl Drop for Closed<'_> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot
+            && let Some(waker) = self.shared.waiters.borrow_mut().get_mut(slot)
+        {
+            *waker = None;
+        }
+    }
+}
+
+impl fmt::Debug for Closed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closed")
+            .field("state", &self.shared.state())
+            .finish()
+    }
+}
+
+impl fmt::Debug for WsWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsWriter")
+            .field("state", &self.shared.state())
+            .finish_non_exhaustive()
+    }
+}
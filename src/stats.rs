@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+/// Number of bucket bounds in a [`Histogram`].
+pub const HISTOGRAM_BUCKETS: usize = 8;
+
+/// Default [`ConnectionStats::inter_arrival`] bounds.
+pub const DEFAULT_INTER_ARRIVAL_BOUNDS: [Duration; HISTOGRAM_BUCKETS] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+/// Counts of durations in fixed buckets.
+///
+/// `counts[i]` counts durations up to and including `bounds[i]` and above
+/// the bound before it; the last count is everything above the last bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct Histogram {
+    pub bounds: [Duration; HISTOGRAM_BUCKETS],
+    pub counts: [u64; HISTOGRAM_BUCKETS + 1],
+}
+
+impl Histogram {
+    /// An empty histogram; `bounds` are sorted first.
+    pub fn new(mut bounds: [Duration; HISTOGRAM_BUCKETS]) -> Self {
+        bounds.sort();
+        Self {
+            bounds,
+            counts: [0; HISTOGRAM_BUCKETS + 1],
+        }
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[bucket] += 1;
+    }
+
+    /// Durations recorded.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTER_ARRIVAL_BOUNDS)
+    }
+}
+
+/// Frame and byte counters for one connection.
+///
+/// Counts frames sent with [`WsClient::send`], `send_owned*` and
+/// `write_frame_vectored`, and every frame read by [`WsClient::recv`] and
+/// [`WsClient::try_recv`], control frames included. Byte counts are payload
+/// bytes. Frames written or read directly on [`WsClient::ws`] and keepalive
+/// heartbeats are not counted.
+///
+/// [`WsClient::send`]: crate::WsClient::send
+/// [`WsClient::recv`]: crate::WsClient::recv
+/// [`WsClient::try_recv`]: crate::WsClient::try_recv
+/// [`WsClient::ws`]: crate::WsClient::ws
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// When the upgrade completed.
+    pub connect_time: Instant,
+    /// When the last frame was read, control frames (including unsolicited
+    /// Pongs) too.
+    pub last_received: Option<Instant>,
+    /// When the last frame was sent.
+    pub last_sent: Option<Instant>,
+    /// When the last complete Text or Binary message was read.
+    pub last_message_received: Option<Instant>,
+    /// Time from the upgrade completing to the first complete Text or Binary
+    /// message, e.g. how long the server took to set up a subscription.
+    pub first_message_latency: Option<Duration>,
+    /// Time between consecutive Text and Binary messages, counted when the
+    /// last fragment of each is read. Bounds are set with
+    /// [`WsClientBuilder::inter_arrival_buckets`](crate::WsClientBuilder::inter_arrival_buckets).
+    pub inter_arrival: Histogram,
+    /// Time spent validating the UTF-8 of Text messages; see
+    /// [`ProcessingBudget`](crate::fairness::ProcessingBudget).
+    pub utf8_validation: Duration,
+}
+
+/// [`ConnectionStats`] at one point in time, with rates derived from them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct ConnectionStatsSnapshot {
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Frames in both directions per second of uptime.
+    pub frames_per_second: f64,
+    /// Payload bytes in both directions per second of uptime.
+    pub bytes_per_second: f64,
+    /// Mean payload size over all counted frames; `0.0` before the first one.
+    pub average_frame_size: f64,
+    pub first_message_latency: Option<Duration>,
+    pub inter_arrival: Histogram,
+    pub utf8_validation: Duration,
+    pub uptime_secs: f64,
+    /// Most bytes that were written but not yet confirmed by a flush at one
+    /// time; see [`WsClient::pending_write_bytes`]. Only filled in by
+    /// [`WsClient::stats_snapshot`]; zero from [`ConnectionStats::snapshot`].
+    ///
+    /// [`WsClient::pending_write_bytes`]: crate::WsClient::pending_write_bytes
+    /// [`WsClient::stats_snapshot`]: crate::WsClient::stats_snapshot
+    pub pending_write_high_water: usize,
+}
+
+impl ConnectionStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            frames_sent: 0,
+            frames_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            connect_time: Instant::now(),
+            last_received: None,
+            last_sent: None,
+            last_message_received: None,
+            first_message_latency: None,
+            inter_arrival: Histogram::default(),
+            utf8_validation: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn record_sent(&mut self, payload_len: usize) {
+        self.frames_sent += 1;
+        self.bytes_sent += payload_len as u64;
+        self.last_sent = Some(Instant::now());
+    }
+
+    pub(crate) fn record_received(&mut self, payload_len: usize) {
+        self.frames_received += 1;
+        self.bytes_received += payload_len as u64;
+        self.last_received = Some(Instant::now());
+    }
+
+    /// A Text or Binary message was completed by the frame just recorded.
+    pub(crate) fn record_message(&mut self) {
+        let now = self.last_received.unwrap_or_else(Instant::now);
+        match self.last_message_received {
+            Some(previous) => self.inter_arrival.record(now - previous),
+            None => self.first_message_latency = Some(now - self.connect_time),
+        }
+        self.last_message_received = Some(now);
+    }
+
+    /// Time since the last frame was sent or received, or since the upgrade
+    /// if there was none.
+    pub fn idle_time(&self) -> Duration {
+        let last = [self.last_received, self.last_sent]
+            .into_iter()
+            .flatten()
+            .fold(self.connect_time, Instant::max);
+        last.elapsed()
+    }
+
+    pub fn snapshot(&self) -> ConnectionStatsSnapshot {
+        let uptime_secs = self.connect_time.elapsed().as_secs_f64();
+        let frames = (self.frames_sent + self.frames_received) as f64;
+        let bytes = (self.bytes_sent + self.bytes_received) as f64;
+        let per_second = |n: f64| {
+            if uptime_secs > 0.0 {
+                n / uptime_secs
+            } else {
+                0.0
+            }
+        };
+        ConnectionStatsSnapshot {
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received,
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            frames_per_second: per_second(frames),
+            bytes_per_second: per_second(bytes),
+            average_frame_size: if frames > 0.0 { bytes / frames } else { 0.0 },
+            first_message_latency: self.first_message_latency,
+            inter_arrival: self.inter_arrival,
+            utf8_validation: self.utf8_validation,
+            uptime_secs,
+            pending_write_high_water: 0,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! One record per finished connection, for logs.
+//!
+//! When a [`WsClient`] ends, because the peer closed, a send or receive
+//! failed, or the client was dropped, it builds a [`SessionSummary`] and
+//! delivers it once as [`WsEvent::SessionEnded`] to the builder's
+//! [`on_event`](crate::WsClientBuilder::on_event) sink. Its `Display` is a
+//! single `key=value` line; with the `json` feature it is also
+//! `Serialize`. [`WsReconnectClient::session_history`] keeps the summaries
+//! of its recent sessions.
+//!
+//! ```no_run
+//! use websockets_monoio::{WsClientBuilder, WsEvent};
+//!
+//! let builder = WsClientBuilder::new().on_event(|event, _| {
+//!     if let WsEvent::SessionEnded { summary } = event {
+//!         // endpoint=wss://venue.example/ws generation=0 duration=3612.4s ...
+//!         println!("{summary}");
+//!     }
+//! });
+//! ```
+//!
+//! [`WsClient`]: crate::WsClient
+//! [`WsEvent::SessionEnded`]: crate::WsEvent::SessionEnded
+//! [`WsReconnectClient::session_history`]: crate::WsReconnectClient::session_history
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::CloseFrame;
+
+/// How a connection went, from its stats, its [`ConnectionInfo`] and how
+/// it ended.
+///
+/// Counts cover frames that went through [`send`](crate::WsClient::send)
+/// and [`recv`](crate::WsClient::recv), as in
+/// [`ConnectionStats`](crate::ConnectionStats). The crate does not
+/// negotiate compression, so there is no compression ratio to report.
+///
+/// [`ConnectionInfo`]: crate::ConnectionInfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct SessionSummary {
+    /// The URL with its userinfo and query redacted.
+    pub endpoint: String,
+    /// When the upgrade request was sent, by the builder's clock.
+    pub started_at: SystemTime,
+    /// From the upgrade completing to the end of the session.
+    pub duration: Duration,
+    /// The session number of a
+    /// [`WsReconnectClient`](crate::WsReconnectClient), counting reconnects
+    /// and replacements; 0 for the first connection and for a plain client.
+    pub generation: u64,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The peer's Close, if one arrived.
+    pub close: Option<CloseFrame>,
+    /// The [code](crate::error_code) of the error that ended the session;
+    /// `None` after a Close or when the client was dropped.
+    pub error_code: Option<&'static str>,
+    /// Round trips measured by a [`Keepalive`](crate::Keepalive) with WS
+    /// Pings or a [`BackgroundPing`](crate::BackgroundPing); `None` without
+    /// either.
+    pub rtt: Option<RttSummary>,
+}
+
+/// Round trip times over the latest samples of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct RttSummary {
+    /// Samples the figures are taken from, at most the latest
+    /// [`RTT_SAMPLES`].
+    pub samples: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+}
+
+/// Round trips kept per connection for its [`RttSummary`].
+pub const RTT_SAMPLES: usize = 256;
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "endpoint={} generation={} duration={:.1}s sent={}f/{}B received={}f/{}B",
+            self.endpoint,
+            self.generation,
+            self.duration.as_secs_f64(),
+            self.frames_sent,
+            self.bytes_sent,
+            self.frames_received,
+            self.bytes_received,
+        )?;
+        if let Some(close) = &self.close {
+            write!(f, " close={} reason={:?}", close.code, close.reason)?;
+        }
+        if let Some(code) = self.error_code {
+            write!(f, " error={code}")?;
+        }
+        if let Some(rtt) = &self.rtt {
+            write!(
+                f,
+                " rtt_min={:?} rtt_median={:?} rtt_max={:?}",
+                rtt.min, rtt.median, rtt.max
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The latest round trip times of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct RttSamples {
+    samples: VecDeque<Duration>,
+}
+
+impl RttSamples {
+    pub(crate) fn record(&mut self, rtt: Duration) {
+        if self.samples.len() == RTT_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+    }
+
+    pub(crate) fn summary(&self) -> Option<RttSummary> {
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        Some(RttSummary {
+            samples: sorted.len(),
+            min: *sorted.first()?,
+            median: sorted[sorted.len() / 2],
+            max: *sorted.last()?,
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! UTF-8 validation of Text payloads, with a fast path for ASCII.
+//!
+//! Exchange JSON is usually pure ASCII. [`validate`] first finds the
+//! longest ASCII prefix 64 bytes at a time, checking the high bit of eight
+//! 64-bit words at once, and hands only what follows it to the standard
+//! validator. The client validates every Text message this way once it is
+//! reassembled, and large messages a
+//! [`ProcessingBudget`](crate::fairness::ProcessingBudget) chunk at a time,
+//! so characters cut by a fragment or chunk boundary are checked whole.
+//!
+//! [`WsClientBuilder::ascii_text`](crate::WsClientBuilder::ascii_text)
+//! goes further and rejects Text messages that are not ASCII.
+//!
+//! ```
+//! use websockets_monoio::text::{InvalidUtf8, ascii_prefix, validate};
+//!
+//! assert_eq!(ascii_prefix(br#"{"px":"101.5"}"#), 14);
+//! assert_eq!(validate("prix: 101,5 €".as_bytes()), Ok(()));
+//! assert_eq!(
+//!     validate(b"caf\xC3"),
+//!     Err(InvalidUtf8 { valid_up_to: 3, error_len: None })
+//! );
+//!
+//! // Agrees with the standard validator on multi-byte and broken sequences
+//! // placed across the 64-byte blocks and their 8-byte words.
+//! let pieces: [&[u8]; 8] = [
+//!     b"a", "é".as_bytes(), "€".as_bytes(), "𝄞".as_bytes(),
+//!     b"\x80", b"\xC3", b"\xE2\x82", b"\xF0\x9D\x84",
+//! ];
+//! let mut state = 0x2545_F491_4F6C_DD1Du64;
+//! let mut next = |n: usize| {
+//!     state ^= state << 13;
+//!     state ^= state >> 7;
+//!     state ^= state << 17;
+//!     state as usize % n
+//! };
+//! for _ in 0..20_000 {
+//!     let mut payload = vec![b'x'; next(200)];
+//!     for _ in 0..next(3) {
+//!         let at = next(payload.len() + 1);
+//!         let piece = pieces[next(pieces.len())];
+//!         payload.splice(at..at, piece.iter().copied());
+//!     }
+//!     let expected = std::str::from_utf8(&payload).map(drop).map_err(|e| InvalidUtf8 {
+//!         valid_up_to: e.valid_up_to(),
+//!         error_len: e.error_len(),
+//!     });
+//!     assert_eq!(validate(&payload), expected, "{payload:?}");
+//!     assert_eq!(ascii_prefix(&payload), payload.iter().take_while(|b| b.is_ascii()).count());
+//! }
+//! ```
+
+/// Where the UTF-8 of a payload went wrong, as in [`std::str::Utf8Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// Length of the valid prefix.
+    pub valid_up_to: usize,
+    /// Length of the invalid sequence after it; `None` when the payload
+    /// ends in the middle of a character.
+    pub error_len: Option<usize>,
+}
+
+/// Bytes checked at once by [`ascii_prefix`].
+const BLOCK: usize = 64;
+
+/// Length of the ASCII prefix of `bytes`.
+pub fn ascii_prefix(bytes: &[u8]) -> usize {
+    const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+    let mut ascii = 0;
+    for block in bytes.chunks_exact(BLOCK) {
+        let any = block
+            .chunks_exact(8)
+            .map(|word| u64::from_ne_bytes(word.try_into().expect("8-byte word")))
+            .fold(0, |any, word| any | word);
+        if any & HIGH_BITS != 0 {
+            break;
+        }
+        ascii += BLOCK;
+    }
+    ascii
+        + bytes[ascii..]
+            .iter()
+            .position(|b| !b.is_ascii())
+            .unwrap_or(bytes.len() - ascii)
+}
+
+/// Check that `bytes` is UTF-8, skipping the standard validator over its
+/// ASCII prefix.
+pub fn validate(bytes: &[u8]) -> Result<(), InvalidUtf8> {
+    let ascii = ascii_prefix(bytes);
+    match std::str::from_utf8(&bytes[ascii..]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(InvalidUtf8 {
+            valid_up_to: ascii + e.valid_up_to(),
+            error_len: e.error_len(),
+        }),
+    }
+}
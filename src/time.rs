@@ -0,0 +1,292 @@
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::WsError;
+
+/// Source of wall-clock time, set with
+/// [`WsClientBuilder::clock`](crate::WsClientBuilder::clock).
+///
+/// The crate reads it when it records a point in time that a peer or a log
+/// can see, such as [`ConnectionInfo::handshake_at`], which is also the time
+/// handed to a [custom upgrade request] so signed timestamps match it.
+/// Timeouts, deadlines and durations run on the monotonic clock and are not
+/// affected.
+///
+/// Implemented for closures returning `SystemTime`.
+///
+/// [`ConnectionInfo::handshake_at`]: crate::ConnectionInfo::handshake_at
+/// [custom upgrade request]: crate::WsClientBuilder::with_custom_http_request
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+impl<F: Fn() -> SystemTime> Clock for F {
+    fn now(&self) -> SystemTime {
+        self()
+    }
+}
+
+/// The system's wall clock; the default [`Clock`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A [`Clock`] that only moves when told to, for deterministic tests.
+///
+/// Clones share the same time, so keep one to move it after passing another
+/// to the builder.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use websockets_monoio::WsClientBuilder;
+/// use websockets_monoio::time::FrozenClock;
+///
+/// let clock = FrozenClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+/// let builder = WsClientBuilder::new().clock(clock.clone());
+/// clock.advance(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrozenClock(Rc<Cell<SystemTime>>);
+
+impl FrozenClock {
+    pub fn new(at: SystemTime) -> Self {
+        Self(Rc::new(Cell::new(at)))
+    }
+
+    pub fn set(&self, at: SystemTime) {
+        self.0.set(at);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+impl Clock for FrozenClock {
+    fn now(&self) -> SystemTime {
+        self.0.get()
+    }
+}
+
+/// A fixed point in time by which an operation has to be done.
+///
+/// Create one for a whole task (connect, subscribe, wait for the ack) and
+/// hand it to each step, so every step draws on the same budget instead of
+/// having its own timeout. See
+/// [`WsClientBuilder::connect_with_deadline`](crate::WsClientBuilder::connect_with_deadline).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// A deadline `budget` from now.
+    pub fn after(budget: Duration) -> Self {
+        Self(Instant::now() + budget)
+    }
+
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Time left before the deadline; zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// A step of opening a connection, as reported by [`TimedOut`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectPhase {
+    /// DNS lookup of the server, or of the proxy when one is set.
+    Resolve,
+    /// TCP connect, plus opening the tunnel when a proxy is used.
+    Connect,
+    /// TLS handshake with the server.
+    Tls,
+    /// HTTP upgrade request and response.
+    Upgrade,
+    /// Waiting for the message set up with
+    /// [`WsClientBuilder::await_first_message`](crate::WsClientBuilder::await_first_message).
+    FirstMessage,
+}
+
+impl fmt::Display for ConnectPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectPhase::Resolve => "resolve",
+            ConnectPhase::Connect => "connect",
+            ConnectPhase::Tls => "tls",
+            ConnectPhase::Upgrade => "upgrade",
+            ConnectPhase::FirstMessage => "first message",
+        })
+    }
+}
+
+/// An operation ran out of its time limit.
+///
+/// For a connect, records the budget left when each phase started, so the
+/// message tells a slow DNS lookup from a slow TLS handshake, e.g. `connect
+/// deadline exceeded during tls (budget left at start: resolve 3s, connect
+/// 2.98s, tls 40ms)`. Anything else reports its limit, e.g. `timed out after
+/// 5s`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    limit: Duration,
+    phases: Vec<(ConnectPhase, Duration)>,
+}
+
+impl TimedOut {
+    /// An operation that took longer than `limit`.
+    pub(crate) fn after(limit: Duration) -> Self {
+        Self {
+            limit,
+            phases: Vec::new(),
+        }
+    }
+
+    /// The time the operation was given; for a connect, the budget left
+    /// when it started.
+    pub fn limit(&self) -> Duration {
+        self.limit
+    }
+
+    /// The connect phase that was running when the limit passed, or `None`
+    /// if the operation was not a connect.
+    pub fn phase(&self) -> Option<ConnectPhase> {
+        self.phases.last().map(|&(phase, _)| phase)
+    }
+
+    /// Each connect phase that started, in order, with the budget left at
+    /// its start. Empty if the operation was not a connect.
+    pub fn phases(&self) -> &[(ConnectPhase, Duration)] {
+        &self.phases
+    }
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.phase() {
+            Some(phase) => write!(
+                f,
+                "connect deadline exceeded during {phase} (budget left at start: {})",
+                BudgetList(&self.phases)
+            ),
+            None => write!(f, "timed out after {:?}", self.limit),
+        }
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+struct BudgetList<'a>(&'a [(ConnectPhase, Duration)]);
+
+impl fmt::Display for BudgetList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (phase, left)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{phase} {left:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs connect phases against an optional [`Deadline`].
+///
+/// Without a deadline phases simply run to completion, so connects that do
+/// not use one need no timer.
+#[derive(Debug, Default)]
+pub(crate) struct PhaseClock {
+    deadline: Option<Deadline>,
+    phases: Vec<(ConnectPhase, Duration)>,
+    /// Time resolution separately even without a deadline.
+    split_resolve: bool,
+    current: Option<ConnectPhase>,
+}
+
+impl PhaseClock {
+    pub(crate) fn new(deadline: Option<Deadline>) -> Self {
+        Self {
+            deadline,
+            ..Self::default()
+        }
+    }
+
+    /// A clock that only keeps track of the running phase, with DNS
+    /// resolution as a phase of its own.
+    pub(crate) fn tracking() -> Self {
+        Self {
+            split_resolve: true,
+            ..Self::default()
+        }
+    }
+
+    /// Also stop at `deadline`, if it comes before the current one.
+    pub(crate) fn limit(&mut self, deadline: Deadline) {
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+    }
+
+    /// Whether resolution has to run as its own phase.
+    pub(crate) fn splits_resolve(&self) -> bool {
+        self.split_resolve || self.deadline.is_some()
+    }
+
+    /// The phase that last started.
+    pub(crate) fn current(&self) -> Option<ConnectPhase> {
+        self.current
+    }
+
+    /// Run `phase`, failing with [`TimedOut`] if the deadline passes
+    /// first. Consecutive steps of the same phase are recorded once.
+    pub(crate) async fn run<T, E>(
+        &mut self,
+        phase: ConnectPhase,
+        step: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, WsError>
+    where
+        WsError: From<E>,
+    {
+        self.current = Some(phase);
+        let Some(deadline) = self.deadline else {
+            return Ok(step.await?);
+        };
+        let left = deadline.remaining();
+        if self.phases.last().is_none_or(|&(last, _)| last != phase) {
+            self.phases.push((phase, left));
+        }
+        if left.is_zero() {
+            return Err(self.exceeded());
+        }
+        let at = monoio::time::Instant::from_std(deadline.instant());
+        match monoio::time::timeout_at(at, step).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(self.exceeded()),
+        }
+    }
+
+    fn exceeded(&self) -> WsError {
+        WsError::Timeout(TimedOut {
+            limit: self
+                .phases
+                .first()
+                .map_or(Duration::ZERO, |&(_, left)| left),
+            phases: self.phases.clone(),
+        })
+    }
+}
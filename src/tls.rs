@@ -1,21 +1,31 @@
+use monoio::io::{AsyncReadRent, AsyncWriteRent};
 use monoio::net::TcpStream;
 use monoio_rustls::{ClientTlsStream, TlsConnector};
 use rustls::pki_types::ServerName;
-use rustls::{ClientConfig, RootCertStore};
+use rustls::{ClientConfig, ClientConnection, HandshakeKind, RootCertStore};
+use std::net::SocketAddr;
 use std::sync::{Arc, OnceLock};
 
+use crate::info::TlsInfo;
+
 #[derive(thiserror::Error, Debug)]
 pub enum TlsErr {
     #[error("dns name")]
     Dns,
-    #[error(transparent)]
+    #[error("I/O error setting up TLS")]
     Io(#[from] std::io::Error),
-    #[error(transparent)]
+    #[error("TLS handshake failed")]
     Rustls(#[from] monoio_rustls::TlsError),
 }
 
 static GLOBAL_CONNECTOR: OnceLock<TlsConnector> = OnceLock::new();
 
+/// The connector used for `wss://` unless a builder is given another one.
+///
+/// Session tickets are kept in memory by its `ClientConfig`, so reconnects
+/// within the process resume; a restarted process starts with none. `rustls`
+/// cannot serialize client sessions, and a session is only resumed with the
+/// verifier instance that checked it, so they are not persisted.
 pub fn default_connector() -> &'static TlsConnector {
     GLOBAL_CONNECTOR.get_or_init(|| {
         // Install default crypto provider
@@ -37,7 +47,68 @@ pub async fn connect_wss(
     connector: &TlsConnector,
 ) -> Result<ClientTlsStream<TcpStream>, TlsErr> {
     let tcp = TcpStream::connect((host, port)).await?;
-    let dns = ServerName::try_from(host.to_owned()).map_err(|_| TlsErr::Dns)?;
-    let tls = connector.connect(dns, tcp).await?;
+    let (tls, _) = tls_handshake(tcp, host, connector).await?;
+    Ok(tls)
+}
+
+/// Connect TLS to a fixed address while presenting `server_name` for SNI and
+/// certificate verification, e.g. when dialing a TLS terminator directly.
+pub async fn connect_wss_addr(
+    addr: SocketAddr,
+    server_name: &str,
+    connector: &TlsConnector,
+) -> Result<ClientTlsStream<TcpStream>, TlsErr> {
+    let tcp = TcpStream::connect(addr).await?;
+    let (tls, _) = tls_handshake(tcp, server_name, connector).await?;
+    Ok(tls)
+}
+
+/// Run TLS over an already established stream, e.g. a proxy tunnel or
+/// another TLS session, presenting `server_name` for SNI and certificate
+/// verification.
+pub async fn connect_wss_over<IO: AsyncReadRent + AsyncWriteRent>(
+    stream: IO,
+    server_name: &str,
+    connector: &TlsConnector,
+) -> Result<ClientTlsStream<IO>, TlsErr> {
+    let (tls, _) = tls_handshake(stream, server_name, connector).await?;
     Ok(tls)
 }
+
+/// Run the TLS handshake over an established stream and report what was
+/// negotiated.
+///
+/// An IPv6 literal, bare or bracketed as in a URI (`[::1]`), is verified as
+/// an IP address, for which rustls sends no SNI.
+pub(crate) async fn tls_handshake<IO: AsyncReadRent + AsyncWriteRent>(
+    io: IO,
+    server_name: &str,
+    connector: &TlsConnector,
+) -> Result<(ClientTlsStream<IO>, TlsInfo), TlsErr> {
+    let server_name = server_name
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .unwrap_or(server_name);
+    let dns = ServerName::try_from(server_name.to_owned()).map_err(|_| TlsErr::Dns)?;
+    let tls = connector.connect(dns, io).await?;
+
+    // `monoio-rustls` only exposes the session through `into_parts`. Right
+    // after the handshake its write buffer is flushed and the server has sent
+    // nothing past `Finished`, so rebuilding the stream loses no bytes.
+    let (io, session) = tls.into_parts();
+    let info = tls_info(&session);
+    Ok((ClientTlsStream::new(io, session), info))
+}
+
+fn tls_info(session: &ClientConnection) -> TlsInfo {
+    TlsInfo {
+        version: session.protocol_version().map(|v| format!("{v:?}")),
+        cipher_suite: session
+            .negotiated_cipher_suite()
+            .map(|s| format!("{:?}", s.suite())),
+        alpn: session
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+        resumed: session.handshake_kind() == Some(HandshakeKind::Resumed),
+    }
+}
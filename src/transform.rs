@@ -0,0 +1,91 @@
+//! Transforms of whole message payloads, e.g. application-layer encryption.
+//!
+//! Some venues encrypt payloads inside binary frames with a key negotiated
+//! out of band. [`WsClientBuilder::transform_inbound`] and
+//! [`transform_outbound`] register a function that rewrites the opcode and
+//! payload of every data message, so the decryption and its nonce handling
+//! live in one place instead of in every consumer.
+//!
+//! Inbound, the transform sees each Text or Binary message once it is
+//! reassembled from its fragments, before UTF-8 validation, the inbound
+//! interceptors, middleware and any [`Codec`](crate::codec::Codec).
+//! Outbound, it runs on what [`WsClient::send`] is left with after the
+//! outbound interceptors, just before the message is framed. Interceptors
+//! therefore always see plaintext.
+//!
+//! ```
+//! use websockets_monoio::WsClientBuilder;
+//! use websockets_monoio::transform::{OnTransformError, TransformError};
+//! use fastwebsockets::OpCode;
+//!
+//! // A stand-in for a real cipher.
+//! fn xor(mut payload: Vec<u8>) -> Vec<u8> {
+//!     payload.iter_mut().for_each(|b| *b ^= 0x5A);
+//!     payload
+//! }
+//!
+//! let builder = WsClientBuilder::new()
+//!     .transform_outbound(|_, payload| Ok((OpCode::Binary, xor(payload))))
+//!     .transform_inbound(
+//!         |opcode, payload| match opcode {
+//!             OpCode::Binary => Ok((OpCode::Text, xor(payload))),
+//!             _ => Err(TransformError::new("expected an encrypted binary frame")),
+//!         },
+//!         OnTransformError::Drop,
+//!     );
+//! # drop(builder);
+//! ```
+//!
+//! [`WsClientBuilder::transform_inbound`]: crate::WsClientBuilder::transform_inbound
+//! [`transform_outbound`]: crate::WsClientBuilder::transform_outbound
+//! [`WsClient::send`]: crate::WsClient::send
+
+use std::fmt;
+use std::rc::Rc;
+
+use fastwebsockets::OpCode;
+
+/// A payload transform failed, e.g. a message did not authenticate.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{0}")]
+pub struct TransformError(pub String);
+
+impl TransformError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self(reason.into())
+    }
+}
+
+/// What happens to an inbound message its transform fails on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OnTransformError {
+    /// Skip the message, counted in
+    /// [`WsClient::transform_drops`](crate::WsClient::transform_drops).
+    #[default]
+    Drop,
+    /// Close the connection with 1007 (invalid payload data) and fail
+    /// `recv` with [`WsError::Transform`](crate::WsError::Transform).
+    Fail,
+}
+
+/// Rewrites the opcode and payload of a data message.
+pub(crate) type TransformFn = dyn Fn(OpCode, Vec<u8>) -> Result<(OpCode, Vec<u8>), TransformError>;
+
+/// The transforms registered on a builder and copied to its connections.
+#[derive(Clone, Default)]
+pub(crate) struct Transforms {
+    pub(crate) inbound: Option<(Rc<TransformFn>, OnTransformError)>,
+    pub(crate) outbound: Option<Rc<TransformFn>>,
+}
+
+impl fmt::Debug for Transforms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transforms")
+            .field(
+                "inbound",
+                &self.inbound.as_ref().map(|(_, on_error)| on_error),
+            )
+            .field("outbound", &self.outbound.is_some())
+            .finish()
+    }
+}
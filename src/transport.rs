@@ -0,0 +1,218 @@
+//! Running WebSocket over transports the crate does not dial itself.
+//!
+//! Anything readable and writable with the `monoio_compat` (tokio) traits
+//! can carry a connection: a shared-memory ring, a QUIC stream, an
+//! in-process pipe. Implement [`Transport`] for it and pass it to
+//! [`WsClientBuilder::connect_over`]. The upgrade, framing and everything
+//! built on them, such as keepalives, stats, events and the close
+//! handshake, then run over it as over TCP. Internally the transport is
+//! boxed into [`AnyStream::Custom`], so the client type stays the same.
+//!
+//! ```
+//! # use std::io;
+//! # use std::pin::Pin;
+//! # use std::task::{Context, Poll};
+//! # use base64::Engine;
+//! # use sha1::Digest;
+//! use fastwebsockets::{OpCode, Role, WebSocket};
+//! use monoio_compat::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+//! use tokio::io::DuplexStream;
+//! use websockets_monoio::transport::Transport;
+//! use websockets_monoio::{CloseFrame, Message, WsClientBuilder};
+//!
+//! /// One end of an in-memory pipe.
+//! struct Pipe(DuplexStream);
+//!
+//! impl Transport for Pipe {
+//!     fn peer_label(&self) -> Option<String> {
+//!         Some("pipe:echo".into())
+//!     }
+//! }
+//! # impl AsyncRead for Pipe {
+//! #     fn poll_read(
+//! #         mut self: Pin<&mut Self>,
+//! #         cx: &mut Context<'_>,
+//! #         buf: &mut tokio::io::ReadBuf<'_>,
+//! #     ) -> Poll<io::Result<()>> {
+//! #         Pin::new(&mut self.0).poll_read(cx, buf)
+//! #     }
+//! # }
+//! # impl AsyncWrite for Pipe {
+//! #     fn poll_write(
+//! #         mut self: Pin<&mut Self>,
+//! #         cx: &mut Context<'_>,
+//! #         buf: &[u8],
+//! #     ) -> Poll<io::Result<usize>> {
+//! #         Pin::new(&mut self.0).poll_write(cx, buf)
+//! #     }
+//! #     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+//! #         Pin::new(&mut self.0).poll_flush(cx)
+//! #     }
+//! #     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+//! #         Pin::new(&mut self.0).poll_shutdown(cx)
+//! #     }
+//! # }
+//!
+//! /// Answer the upgrade on the other end and echo data messages.
+//! async fn echo_server(mut io: DuplexStream) {
+//! #   let mut request = Vec::new();
+//! #   while !request.ends_with(b"\r\n\r\n") {
+//! #       request.push(io.read_u8().await.unwrap());
+//! #   }
+//! #   let request = String::from_utf8(request).unwrap();
+//! #   let key = request
+//! #       .lines()
+//! #       .filter_map(|line| line.split_once(':'))
+//! #       .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+//! #       .unwrap()
+//! #       .1;
+//! #   let mut sha1 = sha1::Sha1::new();
+//! #   sha1.update(key.trim().as_bytes());
+//! #   sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+//! #   let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+//! #   let response = format!(
+//! #       "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+//! #        Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+//! #   );
+//! #   io.write_all(response.as_bytes()).await.unwrap();
+//!     // ...
+//!     let mut ws = WebSocket::after_handshake(io, Role::Server);
+//!     loop {
+//!         let frame = ws.read_frame().await.unwrap();
+//!         match frame.opcode {
+//!             OpCode::Text | OpCode::Binary => ws.write_frame(frame).await.unwrap(),
+//!             OpCode::Close => return,
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//!
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .enable_timer()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+//!     let server = monoio::spawn(echo_server(server_end));
+//!
+//!     let mut client = WsClientBuilder::new()
+//!         .keepalive(websockets_monoio::Keepalive::ping(
+//!             std::time::Duration::from_secs(1),
+//!             std::time::Duration::from_secs(3),
+//!         ))
+//!         .connect_over("ws://echo.internal/feed", Pipe(client_end))
+//!         .await
+//!         .unwrap();
+//!     assert_eq!(client.info().peer_label(), Some("pipe:echo"));
+//!
+//!     client.send(Message::Text("hello".into())).await.unwrap();
+//!     assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+//!     assert_eq!(client.stats().frames_received, 1);
+//!
+//!     // The close handshake: the server answers our Close with its own.
+//!     let close = CloseFrame { code: 1000, reason: String::new() };
+//!     client.send(Message::Close(Some(close.clone()))).await.unwrap();
+//!     assert_eq!(client.recv().await.unwrap(), Message::Close(Some(close)));
+//!     server.await;
+//! });
+//! ```
+//!
+//! [`WsClientBuilder::connect_over`]: crate::WsClientBuilder::connect_over
+//! [`AnyStream::Custom`]: crate::client::AnyStream::Custom
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use monoio_compat::{AsyncRead, AsyncWrite};
+
+use crate::client::{AnyStream, TokioIo};
+
+/// A byte stream a WebSocket connection can run over.
+///
+/// Reading and writing are the `monoio_compat` (tokio) traits, see
+/// [`TokioIo`]. The metadata methods have defaults, so a bare stream needs
+/// only `impl Transport for MyStream {}`.
+pub trait Transport: TokioIo {
+    /// A name for the other end, e.g. a shared-memory segment or a QUIC
+    /// connection id, reported as
+    /// [`ConnectionInfo::peer_label`](crate::ConnectionInfo::peer_label).
+    fn peer_label(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the transport encrypts the bytes it carries. A `wss://` URL
+    /// does not add TLS over a transport, so one that is not secure sends
+    /// the connection in the clear.
+    fn is_secure(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn peer_label(&self) -> Option<String> {
+        (**self).peer_label()
+    }
+
+    fn is_secure(&self) -> bool {
+        (**self).is_secure()
+    }
+}
+
+impl Transport for AnyStream {
+    fn peer_label(&self) -> Option<String> {
+        match self {
+            AnyStream::Custom(s) => s.peer_label(),
+            _ => None,
+        }
+    }
+
+    fn is_secure(&self) -> bool {
+        self.is_tls()
+    }
+}
+
+/// A [`TokioIo`] stream with no metadata.
+pub(crate) struct Opaque<S>(pub(crate) S);
+
+impl<S: TokioIo> Transport for Opaque<S> {}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Opaque<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Opaque<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
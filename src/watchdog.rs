@@ -0,0 +1,288 @@
+//! Detecting stalls of the monoio thread the connections run on.
+//!
+//! A blocking call anywhere on the thread freezes every connection on it:
+//! Pongs sit unread and keepalives time out, and it looks as if the
+//! network or the server failed. A [`StallWatchdog`] is a task that wakes
+//! every `tick` and measures how late it woke. When it is late by
+//! `threshold` or more, it records a stall and emits
+//! [`WsEvent::EventLoopStall`].
+//!
+//! Connections from a builder given the watchdog with
+//! [`WsClientBuilder::stall_watchdog`] check it when a keepalive times out.
+//! If a stall overlaps the time the Pong was awaited, including one still
+//! going on when the timeout is noticed, the error is
+//! [`WsError::KeepaliveTimeout`] with `local_stall` set. A
+//! [`WsReconnectClient`] passes it on as [`DisconnectInfo::local_stall`],
+//! and [`DefaultReconnectPolicy`] reconnects without backing off.
+//!
+//! ```
+//! use std::time::Duration;
+//! use websockets_monoio::watchdog::StallWatchdog;
+//!
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .enable_timer()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let watchdog =
+//!         StallWatchdog::spawn(Duration::from_millis(10), Duration::from_millis(100), None);
+//!     monoio::time::sleep(Duration::from_millis(30)).await;
+//!
+//!     // Something blocks the thread.
+//!     std::thread::sleep(Duration::from_millis(300));
+//!     monoio::time::sleep(Duration::from_millis(30)).await;
+//!
+//!     let stats = watchdog.stats();
+//!     assert_eq!(stats.stalls, 1);
+//!     assert!(stats.max_stall >= Duration::from_millis(250));
+//! });
+//! ```
+//!
+//! A keepalive that times out during a stall:
+//!
+//! ```
+//! # use std::io::{Read, Write};
+//! # use base64::Engine;
+//! # use sha1::Digest;
+//! use std::time::Duration;
+//! use websockets_monoio::watchdog::StallWatchdog;
+//! use websockets_monoio::{Keepalive, WsClientBuilder, WsError};
+//!
+//! # // A server that completes the upgrade and then never answers.
+//! # let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+//! # let addr = listener.local_addr().unwrap();
+//! # std::thread::spawn(move || {
+//! #     let (mut socket, _) = listener.accept().unwrap();
+//! #     let mut request = Vec::new();
+//! #     let mut byte = [0u8];
+//! #     while !request.ends_with(b"\r\n\r\n") {
+//! #         socket.read_exact(&mut byte).unwrap();
+//! #         request.push(byte[0]);
+//! #     }
+//! #     let request = String::from_utf8(request).unwrap();
+//! #     let key = request
+//! #         .lines()
+//! #         .filter_map(|line| line.split_once(':'))
+//! #         .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+//! #         .unwrap()
+//! #         .1;
+//! #     let mut sha1 = sha1::Sha1::new();
+//! #     sha1.update(key.trim().as_bytes());
+//! #     sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+//! #     let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+//! #     write!(
+//! #         socket,
+//! #         "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+//! #          Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+//! #     )
+//! #     .unwrap();
+//! #     let mut sink = [0u8; 1024];
+//! #     while socket.read(&mut sink).is_ok_and(|n| n > 0) {}
+//! # });
+//! let mut runtime = monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+//!     .enable_timer()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let watchdog =
+//!         StallWatchdog::spawn(Duration::from_millis(10), Duration::from_millis(100), None);
+//!     let mut client = WsClientBuilder::new()
+//!         .keepalive(Keepalive::ping(
+//!             Duration::from_millis(100),
+//!             Duration::from_millis(300),
+//!         ))
+//!         .stall_watchdog(&watchdog)
+//!         .connect(&format!("ws://{addr}/"))
+//!         .await
+//!         .unwrap();
+//!
+//!     std::thread::sleep(Duration::from_millis(500));
+//!
+//!     match client.recv().await {
+//!         Err(WsError::KeepaliveTimeout { local_stall, .. }) => {
+//!             assert!(local_stall.is_some_and(|stall| stall >= Duration::from_millis(400)));
+//!         }
+//!         other => panic!("expected a keepalive timeout, got {other:?}"),
+//!     }
+//! });
+//! ```
+//!
+//! [`WsEvent::EventLoopStall`]: crate::WsEvent::EventLoopStall
+//! [`WsClientBuilder::stall_watchdog`]: crate::WsClientBuilder::stall_watchdog
+//! [`WsError::KeepaliveTimeout`]: crate::WsError::KeepaliveTimeout
+//! [`WsReconnectClient`]: crate::WsReconnectClient
+//! [`DisconnectInfo::local_stall`]: crate::DisconnectInfo::local_stall
+//! [`DefaultReconnectPolicy`]: crate::DefaultReconnectPolicy
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use monoio::task::JoinHandle;
+
+use crate::event::{EventSink, WsEvent};
+
+/// Stalls kept for matching against keepalive timeouts.
+const RECENT_STALLS: usize = 16;
+
+/// Stalls a [`StallWatchdog`] has seen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct StallStats {
+    pub stalls: u64,
+    /// The longest stall.
+    pub max_stall: Duration,
+    /// All stalls added up.
+    pub total_stalled: Duration,
+}
+
+/// Handle to a watchdog task started with [`StallWatchdog::spawn`].
+///
+/// Dropping the handle cancels the task. Stalls recorded before then are
+/// still matched against keepalive timeouts.
+pub struct StallWatchdog {
+    shared: Rc<WatchdogShared>,
+    _task: JoinHandle<()>,
+}
+
+/// State shared by the watchdog task, its handle and the connections
+/// checking it.
+pub(crate) struct WatchdogShared {
+    tick: Duration,
+    threshold: Duration,
+    /// When the task last woke.
+    last_tick: Cell<Instant>,
+    /// Start and length of the latest stalls.
+    recent: RefCell<VecDeque<(Instant, Duration)>>,
+    stats: Cell<StallStats>,
+    events: Option<EventSink>,
+    stopped: Cell<bool>,
+    waker: Cell<Option<Waker>>,
+}
+
+impl WatchdogShared {
+    /// The longest stall that ended after `since` or is still going on.
+    pub(crate) fn stall_since(&self, since: Instant) -> Option<Duration> {
+        let now = Instant::now();
+        let ongoing = (!self.stopped.get())
+            .then(|| now.saturating_duration_since(self.last_tick.get() + self.tick))
+            .filter(|&lag| lag >= self.threshold);
+        self.recent
+            .borrow()
+            .iter()
+            .filter(|&&(start, duration)| start + duration > since)
+            .map(|&(_, duration)| duration)
+            .chain(ongoing)
+            .max()
+    }
+
+    fn record(&self, start: Instant, duration: Duration) {
+        let mut recent = self.recent.borrow_mut();
+        if recent.len() == RECENT_STALLS {
+            recent.pop_front();
+        }
+        recent.push_back((start, duration));
+        drop(recent);
+        let mut stats = self.stats.get();
+        stats.stalls += 1;
+        stats.max_stall = stats.max_stall.max(duration);
+        stats.total_stalled += duration;
+        self.stats.set(stats);
+        if let Some(events) = &self.events {
+            events.emit(&WsEvent::EventLoopStall { duration });
+        }
+    }
+
+    /// Sleep for `duration`; `false` if the task was stopped meanwhile.
+    async fn sleep(&self, duration: Duration) -> bool {
+        let stopped = poll_fn(|cx| {
+            if self.stopped.get() {
+                return Poll::Ready(());
+            }
+            self.waker.set(Some(cx.waker().clone()));
+            Poll::Pending
+        });
+        monoio::select! {
+            _ = monoio::time::sleep(duration) => !self.stopped.get(),
+            _ = stopped => false,
+        }
+    }
+}
+
+impl fmt::Debug for WatchdogShared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StallWatchdog")
+            .field("tick", &self.tick)
+            .field("threshold", &self.threshold)
+            .field("stats", &self.stats.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl StallWatchdog {
+    /// Start a watchdog on the current thread that wakes every `tick` and
+    /// counts waking `threshold` or more late as a stall, reported to
+    /// `events` if given. A `tick` well below `threshold` keeps the
+    /// measurement fine-grained; each tick is one timer wake-up.
+    ///
+    /// Must be called within a monoio runtime with the timer enabled.
+    pub fn spawn(tick: Duration, threshold: Duration, events: Option<EventSink>) -> Self {
+        let shared = Rc::new(WatchdogShared {
+            tick,
+            threshold,
+            last_tick: Cell::new(Instant::now()),
+            recent: RefCell::new(VecDeque::with_capacity(RECENT_STALLS)),
+            stats: Cell::new(StallStats::default()),
+            events,
+            stopped: Cell::new(false),
+            waker: Cell::new(None),
+        });
+        let task = monoio::spawn(run(shared.clone()));
+        Self {
+            shared,
+            _task: task,
+        }
+    }
+
+    pub(crate) fn shared(&self) -> Rc<WatchdogShared> {
+        self.shared.clone()
+    }
+
+    pub fn stats(&self) -> StallStats {
+        self.shared.stats.get()
+    }
+}
+
+impl Drop for StallWatchdog {
+    fn drop(&mut self) {
+        self.shared.stopped.set(true);
+        if let Some(waker) = self.shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for StallWatchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.shared.fmt(f)
+    }
+}
+
+async fn run(shared: Rc<WatchdogShared>) {
+    loop {
+        let expected = shared.last_tick.get() + shared.tick;
+        if !shared.sleep(shared.tick).await {
+            return;
+        }
+        let now = Instant::now();
+        let lag = now.saturating_duration_since(expected);
+        if lag >= shared.threshold {
+            shared.record(expected, lag);
+        }
+        shared.last_tick.set(now);
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener};
+
+use common::{BINARY, CLOSE, TEXT, block_on};
+use websockets_monoio::{Message, WsClient};
+
+#[test]
+fn frames_round_trip_over_an_abstract_socket() {
+    let name = format!("websockets-monoio-test-{}", std::process::id());
+    let addr = SocketAddr::from_abstract_name(&name).unwrap();
+    let listener = UnixListener::bind_addr(&addr).unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let request = common::read_request(&mut socket).unwrap();
+        common::answer(&mut socket, &request).unwrap();
+        while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+            common::write_frame(&mut socket, opcode, &payload).unwrap();
+            if opcode == CLOSE {
+                break;
+            }
+            assert!(opcode == TEXT || opcode == BINARY);
+        }
+        request
+    });
+    block_on(async {
+        let mut client = WsClient::connect_abstract_unix(&name, &[("X-Trace", "7")])
+            .await
+            .unwrap();
+        assert_eq!(client.info().url(), format!("unix-abstract:@{name}"));
+        client.send(Message::Text("hello".into())).await.unwrap();
+        client.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+        assert_eq!(client.recv().await.unwrap(), Message::Binary(vec![1, 2, 3]));
+        client.send(Message::Close(None)).await.unwrap();
+    });
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET / HTTP/1.1\r\n"), "{request}");
+    assert_eq!(common::header(&request, "host"), Some("localhost"));
+    assert_eq!(common::header(&request, "x-trace"), Some("7"));
+}
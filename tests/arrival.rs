@@ -0,0 +1,102 @@
+mod common;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use common::{CLOSE, TEXT, block_on};
+use websockets_monoio::{
+    Backoff, DefaultReconnectPolicy, Message, WsClientBuilder, WsReconnectClient,
+};
+
+const fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+/// How far a measurement may be off: the server's clock starts before the
+/// client sees the upgrade complete, and a loaded machine delays reads.
+const TOLERANCE: Duration = ms(40);
+
+/// A server that waits `delays[i]` before sending message `i`.
+fn delayed(delays: &'static [Duration]) -> impl Fn(&mut std::net::TcpStream) {
+    move |socket| {
+        for (i, delay) in delays.iter().enumerate() {
+            sleep(*delay);
+            common::write_frame(socket, TEXT, format!("m{i}").as_bytes()).unwrap();
+        }
+    }
+}
+
+fn assert_near(measured: Duration, injected: Duration) {
+    assert!(
+        measured.abs_diff(injected) <= TOLERANCE,
+        "measured {measured:?} for an injected {injected:?}"
+    );
+}
+
+#[test]
+fn injected_latency_shows_in_the_first_message_latency_and_histogram() {
+    const DELAYS: &[Duration] = &[ms(150), ms(25), ms(75), ms(300)];
+    let url = common::scripted(|_, mut socket| {
+        delayed(DELAYS)(&mut socket);
+        let _ = common::read_frame(&mut socket);
+    });
+    let bounds = [10, 50, 100, 200, 400, 800, 1600, 3200].map(ms);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .inter_arrival_buckets(bounds)
+            .connect(&url)
+            .await
+            .unwrap();
+        assert_eq!(client.stats().first_message_latency, None);
+        for i in 0..DELAYS.len() {
+            assert_eq!(client.recv().await.unwrap(), Message::Text(format!("m{i}")));
+        }
+        let stats = client.stats();
+        assert_near(stats.first_message_latency.unwrap(), DELAYS[0]);
+        let histogram = stats.inter_arrival;
+        assert_eq!(histogram.bounds, bounds);
+        // 25 ms, 75 ms and 300 ms land in (10, 50], (50, 100] and (200, 400].
+        assert_eq!(histogram.counts, [0, 1, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(client.stats_snapshot().inter_arrival, histogram);
+    });
+}
+
+#[test]
+fn each_reconnect_measures_its_own_first_message_latency() {
+    const FIRST: &[Duration] = &[ms(150), ms(25)];
+    const SECOND: &[Duration] = &[ms(60)];
+    let url = common::scripted(|conn, mut socket| {
+        if conn == 0 {
+            delayed(FIRST)(&mut socket);
+            let _ = common::write_frame(&mut socket, CLOSE, &1012u16.to_be_bytes());
+        } else {
+            delayed(SECOND)(&mut socket);
+        }
+        let _ = common::read_frame(&mut socket);
+    });
+    let policy = DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: ms(10),
+            multiplier: 2.0,
+            max_interval: ms(100),
+            max_attempts: None,
+        },
+        try_again_delay: ms(10),
+    };
+    let builder = WsClientBuilder::new().reconnect_policy(policy);
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &url).await.unwrap();
+        client.recv().await.unwrap();
+        client.recv().await.unwrap();
+        let stats = client.client().unwrap().stats();
+        assert_near(stats.first_message_latency.unwrap(), FIRST[0]);
+        assert_eq!(stats.inter_arrival.total(), 1);
+        assert!(matches!(client.recv().await, Ok(Message::Close(_))));
+
+        assert_eq!(client.recv().await.unwrap(), Message::Text("m0".into()));
+        assert_eq!(client.session(), 1);
+        let stats = client.client().unwrap().stats();
+        assert_near(stats.first_message_latency.unwrap(), SECOND[0]);
+        assert_eq!(stats.inter_arrival.total(), 0);
+    });
+}
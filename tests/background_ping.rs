@@ -0,0 +1,62 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use common::{MockServer, Mode, PING, block_on};
+use websockets_monoio::{Message, WsClientBuilder};
+
+const INTERVAL: Duration = Duration::from_millis(50);
+
+/// Count `on_timeout` calls while `recv` waits for `duration` on `url`.
+/// Returns the count and the last round trip measured.
+fn ping_for(url: &str, duration: Duration) -> (usize, Option<Duration>) {
+    let timeouts = Arc::new(AtomicUsize::new(0));
+    let count = timeouts.clone();
+    let rtt = block_on(async {
+        let mut client = WsClientBuilder::new().connect(url).await.unwrap();
+        let ping = client.background_ping(
+            INTERVAL,
+            Box::new(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+        // Nothing but Pongs arrives; `recv` reads them for the ping task.
+        let deadline = monoio::time::Instant::now() + duration;
+        while let Ok(message) = monoio::time::timeout_at(deadline, client.recv()).await {
+            assert!(matches!(message, Ok(Message::Pong(_))), "{message:?}");
+        }
+        let rtt = ping.last_rtt();
+        ping.stop().unwrap();
+        rtt
+    });
+    (timeouts.load(Ordering::SeqCst), rtt)
+}
+
+#[test]
+fn on_timeout_fires_when_pongs_are_dropped() {
+    // Reads every frame and answers none.
+    let pings = Arc::new(AtomicUsize::new(0));
+    let seen = pings.clone();
+    let url = common::scripted(move |_, mut socket| {
+        while let Ok((opcode, _)) = common::read_frame(&mut socket) {
+            if opcode == PING {
+                seen.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+    // Timeouts are due after 100, 200 and 300 ms.
+    let (timeouts, rtt) = ping_for(&url, Duration::from_millis(350));
+    assert!(timeouts >= 2, "on_timeout fired {timeouts} times");
+    assert_eq!(rtt, None);
+    assert!(pings.load(Ordering::SeqCst) >= 5);
+}
+
+#[test]
+fn answered_pings_never_time_out() {
+    let server = MockServer::start(Mode::Record);
+    let (timeouts, rtt) = ping_for(&server.url(), Duration::from_millis(350));
+    assert_eq!(timeouts, 0);
+    assert!(rtt.is_some());
+}
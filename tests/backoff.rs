@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use websockets_monoio::reconnect::{Jitter, ReconnectJitter};
+use websockets_monoio::{
+    Backoff, CloseFrame, DefaultReconnectPolicy, DisconnectInfo, ReconnectDecision, ReconnectPolicy,
+};
+
+const CAP: Duration = Duration::from_secs(60);
+
+fn doubling() -> Backoff {
+    Backoff {
+        initial: Duration::from_secs(1),
+        multiplier: 2.0,
+        max_interval: CAP,
+        max_attempts: None,
+    }
+}
+
+#[test]
+fn thirty_doublings_stay_within_the_cap() {
+    let backoff = doubling();
+    let delays: Vec<_> = (0..30).map(|attempt| backoff.delay(attempt)).collect();
+    assert!(delays.iter().all(|&d| d <= CAP), "{delays:?}");
+    // 2^6 s is the first step past the cap; from there on it is the cap.
+    assert_eq!(delays[5], Duration::from_secs(32));
+    assert!(delays[6..].iter().all(|&d| d == CAP));
+    // Far past the point where 2^n overflows an f64 it is still the cap.
+    assert_eq!(backoff.delay(5000), CAP);
+}
+
+#[test]
+fn jittered_delays_stay_within_the_cap() {
+    let policy = DefaultReconnectPolicy::new(doubling());
+    // A server error Close: plain backoff on every attempt.
+    let lost = DisconnectInfo {
+        close: Some(CloseFrame {
+            code: 1011,
+            reason: String::new(),
+        }),
+        error: None,
+        error_code: None,
+        session: Duration::from_secs(5),
+        local_stall: None,
+    };
+    for jitter in [
+        Jitter::None,
+        Jitter::Full,
+        Jitter::Equal,
+        Jitter::Decorrelated,
+    ] {
+        for seed in 0..20 {
+            let mut rng = ReconnectJitter::new(jitter, CAP, seed);
+            for attempt in 0..30 {
+                let ReconnectDecision::RetryAfter(delay) = policy.decide(&lost, attempt) else {
+                    panic!("the policy stopped at attempt {attempt}");
+                };
+                let wait = rng.delay(delay);
+                assert!(
+                    wait <= CAP,
+                    "{jitter:?} waited {wait:?} at attempt {attempt}"
+                );
+            }
+        }
+    }
+}
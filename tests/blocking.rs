@@ -0,0 +1,31 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockServer, Mode};
+use websockets_monoio::{Message, WsClientBuilder, blocking};
+
+#[test]
+fn send_returns_once_the_message_has_left() {
+    let server = MockServer::start(Mode::Echo);
+    let mut client = blocking::connect(&server.url(), &WsClientBuilder::new()).unwrap();
+    client.send(Message::Text("status".into())).unwrap();
+    // Nothing drives the runtime between the calls, so the message must
+    // already be on the socket.
+    assert!(server.wait_for(Duration::from_secs(2), |frames| {
+        frames.iter().any(|f| f.text() == "status")
+    }));
+    assert_eq!(
+        client.recv(Duration::from_secs(2)).unwrap(),
+        Message::Text("status".into())
+    );
+    client.close().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "cannot be used from within an async runtime")]
+fn panics_with_a_clear_message_inside_a_monoio_runtime() {
+    common::block_on(async {
+        let _ = blocking::connect("ws://127.0.0.1:9/", &WsClientBuilder::new());
+    });
+}
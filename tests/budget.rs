@@ -0,0 +1,56 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::budget::CONNECTION_BUFFER_BYTES;
+use websockets_monoio::{
+    MemoryBudget, Message, WsClientBuilder, WsError, WsEvent, WsReconnectClient,
+};
+
+#[test]
+fn queued_messages_are_charged_until_sent() {
+    let server = MockServer::start(Mode::Record);
+    let budget = MemoryBudget::new(CONNECTION_BUFFER_BYTES + 10_000);
+    let refused = Rc::new(Cell::new(0));
+    let seen = refused.clone();
+    let builder = WsClientBuilder::new()
+        .memory_budget(budget.clone())
+        .on_event(move |event, _| {
+            if matches!(event, WsEvent::MemoryBudgetExceeded { .. }) {
+                seen.set(seen.get() + 1);
+            }
+        });
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &server.url())
+            .await
+            .unwrap();
+        let connected = budget.used();
+
+        client.enqueue(Message::Binary(vec![1; 6000])).unwrap();
+        assert_eq!(budget.used(), connected + 6000);
+        let result = client.enqueue(Message::Binary(vec![2; 6000]));
+        assert!(
+            matches!(
+                result,
+                Err(WsError::MemoryBudget {
+                    requested: 6000,
+                    ..
+                })
+            ),
+            "{result:?}"
+        );
+        assert_eq!((client.queued(), refused.get()), (1, 1));
+
+        // Sending returns the charge, which makes room again.
+        client.flush().await.unwrap();
+        assert_eq!(budget.used(), connected);
+        client.send(Message::Binary(vec![3; 6000])).await.unwrap();
+        assert_eq!(budget.used(), connected);
+    });
+    assert!(server.wait_for(Duration::from_secs(2), |frames| frames.len() == 2));
+    let sent: Vec<_> = server.received().iter().map(|f| f.payload[0]).collect();
+    assert_eq!(sent, [1, 3]);
+}
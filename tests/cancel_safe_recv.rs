@@ -0,0 +1,168 @@
+mod common;
+
+use std::future::{Future, poll_fn};
+use std::net::TcpListener;
+use std::pin::pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use monoio::time::TimeDriver;
+use monoio::{Buildable, Driver, RuntimeBuilder};
+use websockets_monoio::{CloseFrame, Message, WsClientBuilder, WsError};
+
+const MESSAGES: u32 = 1000;
+
+/// Message `i`: its index, then bytes derived from it, 4 to ~8 KiB long.
+fn message(i: u32) -> Vec<u8> {
+    let len = 4 + (i as usize * 7919) % (8 * 1024);
+    let mut payload = i.to_be_bytes().to_vec();
+    payload.extend((4..len).map(|j| (i as usize + j) as u8));
+    payload
+}
+
+/// Serve one connection: send every message as a Binary frame, cut into
+/// 2 KiB writes with pauses between them, some longer than the client's
+/// timer, so receives are cancelled before, inside and between frames.
+fn serve_slowly() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        socket.set_nodelay(true).unwrap();
+        common::accept(&mut socket).unwrap();
+        let mut pieces = 0;
+        for i in 0..MESSAGES {
+            let mut frame = Vec::new();
+            common::write_frame(&mut frame, common::BINARY, &message(i)).unwrap();
+            for piece in frame.chunks(2048) {
+                std::io::Write::write_all(&mut socket, piece).unwrap();
+                pieces += 1;
+                let pause = if pieces % 4 == 0 { 1500 } else { 50 };
+                std::thread::sleep(Duration::from_micros(pause));
+            }
+        }
+        // Keep the connection open until the client is done.
+        let _ = common::read_frame(&mut socket);
+    });
+    url
+}
+
+/// Race `recv` until all messages arrived, and check each arrived whole
+/// and in order. Three receives in four are polled once and dropped if not
+/// ready, with their read in flight; the fourth gets a 1 ms timer, which
+/// lets the runtime make progress. Returns the cancelled receives.
+async fn race_reads(url: &str) -> usize {
+    let mut client = WsClientBuilder::new().connect(url).await.unwrap();
+    let mut cancelled = 0;
+    let mut next = 0;
+    let mut attempt = 0;
+    while next < MESSAGES {
+        attempt += 1;
+        let received = if attempt % 4 == 0 {
+            monoio::time::timeout(Duration::from_millis(1), client.recv())
+                .await
+                .ok()
+        } else {
+            let mut recv = pin!(client.recv());
+            match poll_fn(|cx| Poll::Ready(recv.as_mut().poll(cx))).await {
+                Poll::Ready(received) => Some(received),
+                Poll::Pending => None,
+            }
+        };
+        match received {
+            None => cancelled += 1,
+            Some(received) => {
+                assert_eq!(received.unwrap(), Message::Binary(message(next)));
+                next += 1;
+            }
+        }
+    }
+    client.send(Message::Close(None)).await.unwrap();
+    client.flush().await.unwrap();
+    cancelled
+}
+
+fn run_on<D>()
+where
+    D: Buildable + Driver + 'static,
+{
+    let url = serve_slowly();
+    let mut runtime = Buildable::build(RuntimeBuilder::<TimeDriver<D>>::new()).unwrap();
+    let cancelled = runtime.block_on(race_reads(&url));
+    assert!(
+        cancelled >= 2000,
+        "only {cancelled} receives were cancelled"
+    );
+}
+
+#[test]
+fn cancelled_receives_lose_nothing_on_the_legacy_driver() {
+    run_on::<monoio::LegacyDriver>();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cancelled_receives_lose_nothing_on_io_uring() {
+    if !monoio::utils::detect_uring() {
+        eprintln!("io_uring is not available; skipping");
+        return;
+    }
+    run_on::<monoio::IoUringDriver>();
+}
+
+/// A server that sends five Binary frames, then closes with `1000 done`.
+fn five_then_close() -> String {
+    common::scripted(|_, mut socket| {
+        for i in 0..5u8 {
+            common::write_frame(&mut socket, common::BINARY, &[i; 3]).unwrap();
+        }
+        let mut close = 1000u16.to_be_bytes().to_vec();
+        close.extend_from_slice(b"done");
+        common::write_frame(&mut socket, common::CLOSE, &close).unwrap();
+        // The client's reply.
+        let _ = common::read_frame(&mut socket);
+    })
+}
+
+fn done() -> CloseFrame {
+    CloseFrame {
+        code: 1000,
+        reason: "done".into(),
+    }
+}
+
+#[test]
+fn every_frame_before_the_close_is_received() {
+    let url = five_then_close();
+    common::block_on(async {
+        let mut client = WsClientBuilder::new().connect(&url).await.unwrap();
+        for i in 0..5u8 {
+            assert_eq!(client.recv().await.unwrap(), Message::Binary(vec![i; 3]));
+        }
+        assert_eq!(client.recv().await.unwrap(), Message::Close(Some(done())));
+        match client.recv().await {
+            Err(WsError::ClosedByPeer(close)) => assert_eq!(close, done()),
+            other => panic!("expected ClosedByPeer, got {other:?}"),
+        }
+    });
+}
+
+#[test]
+fn consume_until_close_discards_the_frames_before_the_close() {
+    let url = five_then_close();
+    common::block_on(async {
+        let mut client = WsClientBuilder::new().connect(&url).await.unwrap();
+        let close = client
+            .consume_until_close_with_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(close, done());
+        let stats = client.stats_snapshot();
+        assert_eq!(stats.frames_received, 6);
+        assert_eq!(stats.bytes_received, 5 * 3 + 6);
+        match client.send(Message::Text("late".into())).await {
+            Err(WsError::ClosedByPeer(close)) => assert_eq!(close, done()),
+            other => panic!("expected ClosedByPeer, got {other:?}"),
+        }
+    });
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::cell::Cell;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use monoio_compat::{AsyncRead, AsyncWrite};
+use tokio::io::DuplexStream;
+use websockets_monoio::transport::Transport;
+use websockets_monoio::{Message, WsClientBuilder};
+
+/// Faults a [`ChaosStream`] injected.
+#[derive(Default)]
+struct Injected {
+    interrupted: Cell<usize>,
+    would_block: Cell<usize>,
+}
+
+/// One end of an in-memory pipe whose writes fail with `Interrupted` or
+/// `WouldBlock` (without registering a wakeup) on a fixed schedule, and
+/// otherwise accept at most 1000 bytes, so frames are cut between faults.
+struct ChaosStream {
+    io: DuplexStream,
+    polls: usize,
+    injected: Rc<Injected>,
+}
+
+impl ChaosStream {
+    /// The fault for this poll, if any.
+    fn fault(&mut self) -> Option<io::Error> {
+        self.polls += 1;
+        let kind = match self.polls % 5 {
+            1 => io::ErrorKind::Interrupted,
+            3 => io::ErrorKind::WouldBlock,
+            _ => return None,
+        };
+        let count = match kind {
+            io::ErrorKind::Interrupted => &self.injected.interrupted,
+            _ => &self.injected.would_block,
+        };
+        count.set(count.get() + 1);
+        Some(kind.into())
+    }
+}
+
+impl Transport for ChaosStream {}
+
+impl AsyncRead for ChaosStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ChaosStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Some(e) = self.fault() {
+            return Poll::Ready(Err(e));
+        }
+        let n = buf.len().min(1000);
+        Pin::new(&mut self.io).poll_write(cx, &buf[..n])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(e) = self.fault() {
+            return Poll::Ready(Err(e));
+        }
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+#[test]
+fn frames_survive_transient_write_errors_intact() {
+    let injected = Rc::new(Injected::default());
+    common::block_on(async {
+        let (client_end, server_end) = tokio::io::duplex(256 * 1024);
+        monoio::spawn(common::echo_over_pipe(server_end));
+        let chaos = ChaosStream {
+            io: client_end,
+            polls: 0,
+            injected: injected.clone(),
+        };
+        let mut client = WsClientBuilder::new()
+            .connect_over("ws://chaos.internal/", chaos)
+            .await
+            .unwrap();
+        let large: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let messages = [
+            Message::Text("hello".into()),
+            Message::Binary(large),
+            Message::Text("after".into()),
+        ];
+        for message in &messages {
+            client.send(message.clone()).await.unwrap();
+            client.flush().await.unwrap();
+        }
+        for message in messages {
+            assert_eq!(client.recv().await.unwrap(), message);
+        }
+    });
+    assert!(injected.interrupted.get() > 10);
+    assert!(injected.would_block.get() > 10);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::time::Duration;
+
+use common::policy::Recording;
+use common::{CLOSE, block_on};
+use websockets_monoio::{
+    Backoff, DefaultReconnectPolicy, Jitter, Message, ReconnectDecision, WsClientBuilder, WsError,
+    WsReconnectClient,
+};
+
+/// What each connection does after the upgrade: `None` drops it without a
+/// Close, which the client sees as 1006.
+const SCRIPT: [Option<u16>; 5] = [Some(1000), None, Some(1012), Some(1013), Some(1008)];
+
+#[test]
+fn scripted_close_codes_drive_the_default_policy() {
+    let url = common::scripted(|conn, mut socket| {
+        let Some(&Some(code)) = SCRIPT.get(conn) else {
+            return;
+        };
+        common::write_frame(&mut socket, CLOSE, &code.to_be_bytes()).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    let (policy, decisions) = Recording::new(DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: Duration::from_millis(10),
+            multiplier: 2.0,
+            max_interval: Duration::from_secs(1),
+            max_attempts: None,
+        },
+        try_again_delay: Duration::from_millis(100),
+    });
+    let builder = WsClientBuilder::new()
+        .reconnect_policy(policy)
+        .reconnect_jitter(Jitter::None);
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &url).await.unwrap();
+        // Each session ends without delivering a message; `recv` returns
+        // each Close and reconnects on the next call until the policy stops,
+        // which fails it with the last Close.
+        let mut closes = Vec::new();
+        let error = loop {
+            match client.recv().await {
+                Ok(Message::Close(close)) => closes.push(close.unwrap().code),
+                Ok(message) => panic!("unexpected {message:?}"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(closes, [1000, 1012, 1013, 1008]);
+        match error {
+            WsError::ClosedByPeer(close) => assert_eq!(close.code, 1008),
+            other => panic!("expected ClosedByPeer, got {other:?}"),
+        }
+    });
+    let decided: Vec<_> = decisions
+        .borrow()
+        .iter()
+        .map(|d| (d.disconnect.code(), d.attempt, d.decision))
+        .collect();
+    let after = |ms| ReconnectDecision::RetryAfter(Duration::from_millis(ms));
+    assert_eq!(
+        decided,
+        [
+            // Normal closure backs off from the initial delay.
+            (1000, 0, after(10)),
+            // A lost connection retries at once only on the first attempt;
+            // after that it backs off one step behind.
+            (1006, 1, after(10)),
+            // Service restart and try again later wait at least 100 ms.
+            (1012, 2, after(100)),
+            (1013, 3, after(100)),
+            // A policy violation stops.
+            (1008, 4, ReconnectDecision::Stop),
+        ]
+    );
+}
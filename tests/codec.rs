@@ -0,0 +1,81 @@
+#![cfg(feature = "cbor")]
+
+mod common;
+
+use std::time::Duration;
+
+use common::{BINARY, MockServer, Mode, block_on};
+use websockets_monoio::WsClientBuilder;
+use websockets_monoio::codec::{CborCodec, Codec, CodecError};
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Quote {
+    symbol: String,
+    bid: f64,
+}
+
+fn quote(symbol: &str, bid: f64) -> Quote {
+    Quote {
+        symbol: symbol.into(),
+        bid,
+    }
+}
+
+/// `quote` as a payload of schema `version`.
+fn payload(version: u8, quote: &Quote) -> Vec<u8> {
+    let mut payload = vec![version];
+    CborCodec::new().encode(quote, &mut payload).unwrap();
+    payload
+}
+
+#[test]
+fn values_round_trip_with_the_prefix_on_the_wire() {
+    let server = MockServer::start(Mode::Echo);
+    let sent = quote("BTC-USD", 64_000.5);
+    block_on(async {
+        let client = WsClientBuilder::new().connect(&server.url()).await.unwrap();
+        let mut client = client.with_codec(CborCodec::<Quote>::new()).prefix([0xC2]);
+        client.send(&sent).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), sent);
+        assert_eq!(client.schema_rejections(), 0);
+    });
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].payload, payload(0xC2, &sent));
+}
+
+#[test]
+fn a_payload_of_another_schema_is_rejected_without_closing() {
+    let old = quote("ETH-USD", 3_000.0);
+    let current = quote("ETH-USD", 3_001.0);
+    let frames = [payload(0xC1, &old), payload(0xC2, &current)];
+    let url = common::scripted(move |_, mut socket| {
+        for frame in &frames {
+            common::write_frame(&mut socket, BINARY, frame).unwrap();
+        }
+        // Echo what comes back, so the test can show the connection is
+        // still usable after the rejection.
+        while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+            if opcode == BINARY {
+                let _ = common::write_frame(&mut socket, BINARY, &payload);
+            }
+        }
+    });
+    block_on(async {
+        let client = WsClientBuilder::new().connect(&url).await.unwrap();
+        let mut client = client.with_codec(CborCodec::<Quote>::new()).prefix([0xC2]);
+        let rejected = client.recv().await;
+        assert!(
+            matches!(&rejected, Err(CodecError::SchemaRejected { found }) if found == &[0xC1]),
+            "{rejected:?}"
+        );
+        assert_eq!(client.schema_rejections(), 1);
+        assert_eq!(client.recv().await.unwrap(), current);
+
+        let reply = quote("ETH-USD", 3_002.0);
+        client.send(&reply).await.unwrap();
+        let echoed = monoio::time::timeout(Duration::from_secs(5), client.recv()).await;
+        assert_eq!(echoed.unwrap().unwrap(), reply);
+        assert_eq!(client.schema_rejections(), 1);
+    });
+}
@@ -0,0 +1,439 @@
+//! A blocking WebSocket server on a std thread, for integration tests that
+//! need a peer they can inspect, stall or kill.
+
+#![allow(dead_code)]
+
+pub mod policy;
+pub mod proxy;
+pub mod tls;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use fastwebsockets::{OpCode, Role, WebSocket};
+use monoio_compat::{AsyncReadExt, AsyncWriteExt};
+use sha1::Digest;
+use tokio::io::DuplexStream;
+
+pub const TEXT: u8 = 0x1;
+pub const BINARY: u8 = 0x2;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xA;
+
+/// A frame the server received, with the index of the connection it came
+/// on (the first accepted connection is `0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    pub conn: usize,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Received {
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.payload).unwrap()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    received: Vec<Received>,
+    sockets: Vec<TcpStream>,
+    accepted: usize,
+    requests: Vec<String>,
+    sni: Vec<Option<String>>,
+}
+
+/// What the server does on each connection after the handshake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Record every frame; answer Pings and Close.
+    Record,
+    /// Like `Record`, and send each Text and Binary frame back.
+    Echo,
+    /// Complete the handshake, then never read or write again.
+    Silent,
+}
+
+pub struct MockServer {
+    pub addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    tls: bool,
+}
+
+impl MockServer {
+    pub fn start(mode: Mode) -> Self {
+        Self::listen(mode, None)
+    }
+
+    /// A server that speaks TLS as `identity`, for `wss://` URLs.
+    pub fn start_tls(mode: Mode, identity: &tls::Identity) -> Self {
+        Self::listen(mode, Some(identity.server_config()))
+    }
+
+    fn listen(mode: Mode, tls: Option<Arc<rustls::ServerConfig>>) -> Self {
+        let secure = tls.is_some();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let Ok(socket) = socket else { return };
+                let conn = {
+                    let mut state = shared.lock().unwrap();
+                    state.sockets.push(socket.try_clone().unwrap());
+                    state.accepted += 1;
+                    state.accepted - 1
+                };
+                let state = shared.clone();
+                let tls = tls.clone();
+                std::thread::spawn(move || match tls {
+                    None => serve(socket, conn, mode, &state),
+                    Some(config) => {
+                        let Ok((socket, sni)) = tls::accept(socket, config) else {
+                            return;
+                        };
+                        state.lock().unwrap().sni.push(sni);
+                        serve(socket, conn, mode, &state);
+                    }
+                });
+            }
+        });
+        Self {
+            addr,
+            state,
+            tls: secure,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self.tls {
+            false => format!("ws://{}/", self.addr),
+            true => format!("wss://localhost:{}/", self.addr.port()),
+        }
+    }
+
+    /// The upgrade requests received so far, as sent.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The SNI names TLS clients sent, in order.
+    pub fn sni(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().sni.clone()
+    }
+
+    /// Connections accepted so far.
+    pub fn accepted(&self) -> usize {
+        self.state.lock().unwrap().accepted
+    }
+
+    /// Data frames received so far, in order.
+    pub fn received(&self) -> Vec<Received> {
+        let state = self.state.lock().unwrap();
+        let data = state
+            .received
+            .iter()
+            .filter(|f| matches!(f.opcode, TEXT | BINARY));
+        data.cloned().collect()
+    }
+
+    /// Reset every open connection, as a crashed server would, so the
+    /// client's next write fails instead of landing in a dead socket.
+    pub fn kill_connections(&self) {
+        let mut state = self.state.lock().unwrap();
+        for socket in state.sockets.drain(..) {
+            reset(&socket);
+            socket.shutdown(Shutdown::Both).ok();
+        }
+    }
+
+    /// Wait up to `timeout` for `f` to hold on the received data frames.
+    pub fn wait_for(&self, timeout: Duration, f: impl Fn(&[Received]) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if f(&self.received()) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+/// A server that upgrades each connection and hands it, with its index
+/// (the first accepted connection is `0`), to `script` on a thread of its
+/// own. Returns the `ws://` URL.
+pub fn scripted(script: impl Fn(usize, TcpStream) + Send + Sync + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let script = Arc::new(script);
+    std::thread::spawn(move || {
+        for (conn, socket) in listener.incoming().enumerate() {
+            let Ok(mut socket) = socket else { return };
+            let script = script.clone();
+            std::thread::spawn(move || {
+                if accept(&mut socket).is_ok() {
+                    script(conn, socket);
+                }
+            });
+        }
+    });
+    url
+}
+
+/// Turn the socket's close into a reset (`SO_LINGER` with a zero timeout).
+#[cfg(unix)]
+pub fn reset(socket: &TcpStream) {
+    use std::os::fd::AsRawFd;
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    // SAFETY: a valid socket and a correctly sized option value.
+    unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            (&linger as *const libc::linger).cast(),
+            std::mem::size_of::<libc::linger>() as libc::socklen_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn reset(_socket: &TcpStream) {}
+
+fn serve(mut socket: impl Read + Write, conn: usize, mode: Mode, state: &Mutex<State>) {
+    let accepted = read_request(&mut socket).and_then(|request| {
+        state.lock().unwrap().requests.push(request.clone());
+        answer(&mut socket, &request)
+    });
+    if accepted.is_err() || mode == Mode::Silent {
+        std::thread::sleep(Duration::from_secs(3600));
+        return;
+    }
+    while let Ok((opcode, payload)) = read_frame(&mut socket) {
+        state.lock().unwrap().received.push(Received {
+            conn,
+            opcode,
+            payload: payload.clone(),
+        });
+        let reply = match opcode {
+            PING => Some(PONG),
+            CLOSE => Some(CLOSE),
+            TEXT | BINARY if mode == Mode::Echo => Some(opcode),
+            _ => None,
+        };
+        if let Some(reply) = reply
+            && (write_frame(&mut socket, reply, &payload).is_err() || reply == CLOSE)
+        {
+            return;
+        }
+    }
+}
+
+/// Read the upgrade request and answer it with a `101`.
+pub fn accept(socket: &mut (impl Read + Write)) -> std::io::Result<()> {
+    let request = read_request(socket)?;
+    answer(socket, &request)
+}
+
+/// Read an HTTP request head, up to and including the blank line.
+pub fn read_request(socket: &mut impl Read) -> std::io::Result<String> {
+    let mut request = Vec::new();
+    let mut byte = [0u8];
+    while !request.ends_with(b"\r\n\r\n") {
+        socket.read_exact(&mut byte)?;
+        request.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// The value of header `name` in a request head.
+pub fn header<'r>(request: &'r str, name: &str) -> Option<&'r str> {
+    request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// An extension offer: its name and its parameters, with quoted values
+/// unescaped.
+pub type Extension = (String, Vec<(String, Option<String>)>);
+
+/// Parse a `Sec-WebSocket-Extensions` value as a server would (RFC 6455
+/// §9.1): comma-separated offers of `;`-separated parts, where a value may
+/// be a quoted string containing either separator.
+pub fn parse_extensions(value: &str) -> Vec<Extension> {
+    // Split into parts outside quotes, keeping which separator ended each.
+    let mut offers = Vec::new();
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                part.push('"');
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => part.extend(chars.next()),
+                        '"' => break,
+                        c => part.push(c),
+                    }
+                }
+            }
+            ';' | ',' => {
+                parts.push(std::mem::take(&mut part));
+                if c == ',' {
+                    offers.push(std::mem::take(&mut parts));
+                }
+            }
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    offers.push(parts);
+    offers
+        .into_iter()
+        .map(|parts| {
+            let mut parts = parts.into_iter().map(|part| part.trim().to_owned());
+            let name = parts.next().unwrap_or_default();
+            let params = parts
+                .map(|param| match param.split_once('=') {
+                    Some((name, value)) => {
+                        let value = value.trim().strip_prefix('"').unwrap_or(value.trim());
+                        (name.trim().to_owned(), Some(value.to_owned()))
+                    }
+                    None => (param, None),
+                })
+                .collect();
+            (name, params)
+        })
+        .collect()
+}
+
+/// Answer the upgrade `request` with a `101`.
+pub fn answer(socket: &mut impl Write, request: &str) -> std::io::Result<()> {
+    answer_with(socket, request, &[])
+}
+
+/// Answer the upgrade `request` with a `101` carrying `headers` as well.
+pub fn answer_with(
+    socket: &mut impl Write,
+    request: &str,
+    headers: &[(&str, &str)],
+) -> std::io::Result<()> {
+    let key = header(request, "sec-websocket-key").unwrap_or_default();
+    let mut sha1 = sha1::Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+    let extra: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}\r\n"))
+        .collect();
+    write!(
+        socket,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n{extra}\r\n"
+    )
+}
+
+/// Read one (masked) client frame.
+pub fn read_frame(socket: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    socket.read_exact(&mut head)?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            socket.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            socket.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        socket.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len];
+    socket.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((head[0] & 0x0F, payload))
+}
+
+/// Write one unmasked, final server frame.
+pub fn write_frame(socket: &mut impl Write, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    socket.write_all(&frame)
+}
+
+/// Answer the upgrade on one end of an in-memory pipe, then echo data
+/// frames until the pipe closes. Runs on the client's runtime.
+pub async fn echo_over_pipe(mut io: DuplexStream) {
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        request.push(io.read_u8().await.unwrap());
+    }
+    let request = String::from_utf8(request).unwrap();
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+        .unwrap()
+        .1;
+    let mut sha1 = sha1::Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    let accept = base64::engine::general_purpose::STANDARD.encode(sha1.finalize());
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    );
+    io.write_all(response.as_bytes()).await.unwrap();
+    let mut ws = WebSocket::after_handshake(io, Role::Server);
+    while let Ok(frame) = ws.read_frame().await {
+        match frame.opcode {
+            OpCode::Text | OpCode::Binary => ws.write_frame(frame).await.unwrap(),
+            OpCode::Close => return,
+            _ => {}
+        }
+    }
+}
+
+/// Run `f` on a single-threaded monoio runtime with timers.
+pub fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    monoio::RuntimeBuilder::<monoio::LegacyDriver>::new()
+        .enable_timer()
+        .build()
+        .unwrap()
+        .block_on(f)
+}
@@ -0,0 +1,43 @@
+//! A reconnect policy that records what it decides.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use websockets_monoio::{DisconnectInfo, ReconnectDecision, ReconnectPolicy};
+
+/// One call of [`ReconnectPolicy::decide`].
+#[derive(Debug, Clone)]
+pub struct Decided {
+    pub disconnect: DisconnectInfo,
+    pub attempt: u32,
+    pub decision: ReconnectDecision,
+}
+
+/// `policy`, recording each disconnect, attempt and decision.
+pub struct Recording<P> {
+    policy: P,
+    decisions: Rc<RefCell<Vec<Decided>>>,
+}
+
+impl<P: ReconnectPolicy> Recording<P> {
+    pub fn new(policy: P) -> (Self, Rc<RefCell<Vec<Decided>>>) {
+        let decisions = Rc::new(RefCell::new(Vec::new()));
+        let recording = Self {
+            policy,
+            decisions: decisions.clone(),
+        };
+        (recording, decisions)
+    }
+}
+
+impl<P: ReconnectPolicy> ReconnectPolicy for Recording<P> {
+    fn decide(&self, disconnect: &DisconnectInfo, attempt: u32) -> ReconnectDecision {
+        let decision = self.policy.decide(disconnect, attempt);
+        self.decisions.borrow_mut().push(Decided {
+            disconnect: disconnect.clone(),
+            attempt,
+            decision,
+        });
+        decision
+    }
+}
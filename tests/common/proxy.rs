@@ -0,0 +1,122 @@
+//! An HTTP `CONNECT` proxy on std threads.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::tls::{self, Identity};
+
+/// A proxy that records the `CONNECT` targets it is asked for and tunnels
+/// to them.
+pub struct MockProxy {
+    pub url: String,
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockProxy {
+    pub fn start() -> Self {
+        Self::listen(None)
+    }
+
+    /// A proxy reached over TLS as `identity`, for `https://` proxy URLs.
+    pub fn start_tls(identity: &Identity) -> Self {
+        Self::listen(Some(identity.server_config()))
+    }
+
+    fn listen(tls: Option<Arc<rustls::ServerConfig>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let url = format!("{scheme}://{addr}");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        std::thread::spawn(move || {
+            for client in listener.incoming() {
+                let client = client.unwrap();
+                let seen = seen.clone();
+                let tls = tls.clone();
+                std::thread::spawn(move || match tls {
+                    None => tunnel_plain(client, &seen),
+                    Some(config) => {
+                        if let Ok((client, _)) = tls::accept(client, config) {
+                            tunnel_tls(client, &seen);
+                        }
+                    }
+                });
+            }
+        });
+        Self {
+            url,
+            addr,
+            requests,
+        }
+    }
+
+    /// The `CONNECT` targets asked for so far, e.g. `127.0.0.1:8080`.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Read a `CONNECT` request from `client` and dial its target, answering
+/// with a `200` or a `502`.
+fn open(client: &mut (impl Read + Write), seen: &Mutex<Vec<String>>) -> Option<TcpStream> {
+    let head = super::read_request(client).ok()?;
+    let target = head.split(' ').nth(1).unwrap_or_default().to_owned();
+    seen.lock().unwrap().push(target.clone());
+    let Ok(origin) = TcpStream::connect(&target) else {
+        let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n");
+        return None;
+    };
+    client
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        .ok()?;
+    client.flush().ok()?;
+    Some(origin)
+}
+
+fn tunnel_plain(mut client: TcpStream, seen: &Mutex<Vec<String>>) {
+    let Some(origin) = open(&mut client, seen) else {
+        return;
+    };
+    pipe(client.try_clone().unwrap(), origin.try_clone().unwrap());
+    pipe(origin, client);
+}
+
+fn pipe(mut from: TcpStream, mut to: TcpStream) {
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut from, &mut to);
+        let _ = to.shutdown(Shutdown::Write);
+    });
+}
+
+/// Relay between a TLS session, which cannot be split across threads, and
+/// the origin by polling each side in turn with a short read timeout.
+fn tunnel_tls(mut client: tls::ServerStream, seen: &Mutex<Vec<String>>) {
+    let Some(mut origin) = open(&mut client, seen) else {
+        return;
+    };
+    let poll = Some(Duration::from_millis(1));
+    client.sock.set_read_timeout(poll).unwrap();
+    origin.set_read_timeout(poll).unwrap();
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let up = relay(&mut client, &mut origin, &mut buf);
+        let down = relay(&mut origin, &mut client, &mut buf);
+        if !(up && down) {
+            let _ = origin.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+/// Forward what `from` has to `to`; `false` once either side is done.
+fn relay(from: &mut impl Read, to: &mut impl Write, buf: &mut [u8]) -> bool {
+    match from.read(buf) {
+        Ok(0) => false,
+        Ok(n) => to.write_all(&buf[..n]).and_then(|()| to.flush()).is_ok(),
+        Err(e) => matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut),
+    }
+}
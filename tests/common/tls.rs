@@ -0,0 +1,78 @@
+//! Self-signed certificates and the server side of TLS, for tests that
+//! connect with `wss://` or through an `https://` proxy.
+
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use monoio_rustls::TlsConnector;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ClientConfig, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+/// A self-signed certificate for some host names, and its key.
+pub struct Identity {
+    pub cert: CertificateDer<'static>,
+    key: PrivatePkcs8KeyDer<'static>,
+}
+
+impl Identity {
+    pub fn new(names: &[&str]) -> Self {
+        let names = names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let certified = rcgen::generate_simple_self_signed(names).unwrap();
+        Self {
+            cert: certified.cert.der().clone(),
+            key: PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()),
+        }
+    }
+
+    pub fn server_config(&self) -> Arc<ServerConfig> {
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![self.cert.clone()],
+                PrivateKeyDer::Pkcs8(self.key.clone_key()),
+            )
+            .unwrap();
+        Arc::new(config)
+    }
+}
+
+/// A connector for `WsClientBuilder::tls_connector` that trusts exactly
+/// `trusted`.
+pub fn connector(trusted: &[&Identity]) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    for identity in trusted {
+        roots.add(identity.cert.clone()).unwrap();
+    }
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+/// A TLS session accepted on the server side.
+pub type ServerStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Complete the server side of a TLS handshake on `tcp`, returning the
+/// session and the name the client sent with SNI.
+pub fn accept(
+    mut tcp: TcpStream,
+    config: Arc<ServerConfig>,
+) -> std::io::Result<(ServerStream, Option<String>)> {
+    let mut session = ServerConnection::new(config).map_err(std::io::Error::other)?;
+    while session.is_handshaking() {
+        session.complete_io(&mut tcp)?;
+    }
+    let sni = session.server_name().map(str::to_owned);
+    Ok((StreamOwned::new(session, tcp), sni))
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
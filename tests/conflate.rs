@@ -0,0 +1,46 @@
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use common::{TEXT, block_on};
+use websockets_monoio::{Message, WsClientBuilder};
+
+fn text(text: &str) -> Message {
+    Message::Text(text.into())
+}
+
+#[test]
+fn a_paused_consumer_gets_only_the_latest_update_per_key() {
+    // The whole burst goes out in one write.
+    let url = common::scripted(|_, mut socket| {
+        let mut burst = Vec::new();
+        for update in [
+            "BTC:1", "ETH:1", "BTC:2", "news-a", "ETH:2", "BTC:3", "news-b",
+        ] {
+            common::write_frame(&mut burst, TEXT, update.as_bytes()).unwrap();
+        }
+        socket.write_all(&burst).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    block_on(async {
+        let client = WsClientBuilder::new().connect(&url).await.unwrap();
+        let mut feed = client.conflate(|message| match message {
+            Message::Text(text) => text.split_once(':').map(|(symbol, _)| symbol.to_owned()),
+            _ => None,
+        });
+        // The consumer is busy while the burst arrives.
+        monoio::time::sleep(Duration::from_millis(100)).await;
+        let mut delivered = Vec::new();
+        for _ in 0..4 {
+            delivered.push(feed.recv().await.unwrap());
+        }
+        // Each key keeps the position of its first update; keyless messages
+        // are all delivered.
+        assert_eq!(
+            delivered,
+            [text("BTC:3"), text("ETH:2"), text("news-a"), text("news-b")]
+        );
+        assert_eq!((feed.superseded(), feed.queued()), (3, 0));
+    });
+}
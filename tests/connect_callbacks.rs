@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::WsClientBuilder;
+
+#[test]
+fn the_tcp_callback_sees_the_servers_bound_address() {
+    let server = MockServer::start(Mode::Echo);
+    let dialed = Arc::new(Mutex::new(Vec::new()));
+    let seen = dialed.clone();
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .with_tcp_connect_cb(Box::new(move |addr, _| seen.lock().unwrap().push(addr)))
+            .connect(&server.url())
+            .await
+            .unwrap();
+        assert_eq!(client.info().peer_addr(), Some(server.addr));
+    });
+    assert_eq!(dialed.lock().unwrap()[..], [server.addr]);
+}
+
+#[test]
+fn the_tcp_callback_sees_each_address_a_name_resolves_to() {
+    // `localhost` may resolve to `::1` first, which refuses; the address
+    // that connects is the last one reported.
+    let server = MockServer::start(Mode::Echo);
+    let dialed = Arc::new(Mutex::new(Vec::new()));
+    let seen = dialed.clone();
+    let url = format!("ws://localhost:{}/", server.addr.port());
+    block_on(async {
+        WsClientBuilder::new()
+            .with_tcp_connect_cb(Box::new(move |addr, _| seen.lock().unwrap().push(addr)))
+            .connect(&url)
+            .await
+            .unwrap();
+    });
+    let dialed = dialed.lock().unwrap();
+    assert_eq!(dialed.last(), Some(&server.addr));
+    assert!(dialed.iter().all(|addr| addr.ip().is_loopback()));
+}
+
+#[test]
+fn the_tls_callback_sees_the_negotiated_session() {
+    let identity = Identity::new(&["localhost"]);
+    let server = MockServer::start_tls(Mode::Echo, &identity);
+    let sessions = Arc::new(Mutex::new(Vec::new()));
+    let seen = sessions.clone();
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .tls_connector(connector(&[&identity]))
+            .with_tls_connect_cb(Box::new(move |tls, _| {
+                seen.lock().unwrap().push(tls.clone())
+            }))
+            .connect(&server.url())
+            .await
+            .unwrap();
+        assert_eq!(
+            sessions.lock().unwrap()[..],
+            [client.info().tls().unwrap().clone()]
+        );
+    });
+}
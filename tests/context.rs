@@ -0,0 +1,129 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{CLOSE, PING, PONG, TEXT, block_on};
+use websockets_monoio::middleware::InterceptAction;
+use websockets_monoio::{
+    Backoff, ContextMap, DefaultReconnectPolicy, Message, WsClientBuilder, WsReconnectClient,
+};
+
+/// `hook` and the identity it found in the context, e.g. `pong acme/42`.
+type Log = Arc<Mutex<Vec<String>>>;
+
+fn record(log: &Log, hook: &str, ctx: &ContextMap) {
+    let tenant = ctx.get::<String>("tenant").map_or("-", |t| t.as_str());
+    let conn_id = ctx.get::<u64>("conn_id").map_or(0, |id| *id);
+    log.lock()
+        .unwrap()
+        .push(format!("{hook} {tenant}/{conn_id}"));
+}
+
+/// The entries of `log` for `hook`.
+fn seen(log: &Log, hook: &str) -> Vec<String> {
+    let prefix = format!("{hook} ");
+    let log = log.lock().unwrap();
+    log.iter()
+        .filter(|e| e.starts_with(&prefix))
+        .cloned()
+        .collect()
+}
+
+fn builder(log: &Log) -> WsClientBuilder {
+    let policy = DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: Duration::from_millis(10),
+            multiplier: 2.0,
+            max_interval: Duration::from_secs(1),
+            max_attempts: None,
+        },
+        try_again_delay: Duration::from_millis(10),
+    };
+    let (tcp, welcome, ping, pong) = (log.clone(), log.clone(), log.clone(), log.clone());
+    let (outbound, inbound, events) = (log.clone(), log.clone(), log.clone());
+    WsClientBuilder::new()
+        .context("tenant", "acme".to_string())
+        .context("conn_id", 42u64)
+        .reconnect_policy(policy)
+        .with_tcp_connect_cb(Box::new(move |_, ctx| record(&tcp, "tcp", ctx)))
+        .await_first_message(Duration::from_secs(5), move |_, ctx| {
+            record(&welcome, "welcome", ctx);
+            Ok(())
+        })
+        .on_ping_reply(move |payload, ctx| {
+            record(&ping, "ping", ctx);
+            Some(payload.to_vec())
+        })
+        .on_pong(move |_, ctx| record(&pong, "pong", ctx))
+        .intercept_outbound("out", move |_, ctx| {
+            record(&outbound, "outbound", ctx);
+            Ok(InterceptAction::Continue)
+        })
+        .intercept_inbound("in", move |_, ctx| {
+            record(&inbound, "inbound", ctx);
+            Ok(InterceptAction::Continue)
+        })
+        .on_event(move |_, ctx| record(&events, "event", ctx))
+}
+
+#[test]
+fn every_hook_sees_the_context_across_a_reconnect() {
+    // Each connection greets, pings, sends an unsolicited Pong and a tick;
+    // the first one then closes with 1012, service restart.
+    let url = common::scripted(|conn, mut socket| {
+        let welcome = format!("welcome {conn}");
+        common::write_frame(&mut socket, TEXT, welcome.as_bytes()).unwrap();
+        common::write_frame(&mut socket, PING, b"p").unwrap();
+        common::write_frame(&mut socket, PONG, b"u").unwrap();
+        common::write_frame(&mut socket, TEXT, format!("tick {conn}").as_bytes()).unwrap();
+        if conn == 0 {
+            let _ = common::write_frame(&mut socket, CLOSE, &1012u16.to_be_bytes());
+        }
+        while let Ok((opcode, _)) = common::read_frame(&mut socket) {
+            if opcode == CLOSE {
+                return;
+            }
+        }
+    });
+    let log = Log::default();
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder(&log), &url)
+            .await
+            .unwrap();
+        for session in 0..2 {
+            assert_eq!(client.recv().await.unwrap(), Message::Ping(b"p".to_vec()));
+            assert_eq!(client.recv().await.unwrap(), Message::Pong(b"u".to_vec()));
+            let tick = Message::Text(format!("tick {session}"));
+            assert_eq!(client.recv().await.unwrap(), tick);
+            client.send(Message::Text("ack".into())).await.unwrap();
+            if session == 0 {
+                let close = client.recv().await.unwrap();
+                assert!(matches!(close, Message::Close(Some(c)) if c.code == 1012));
+            }
+        }
+        assert_eq!(client.session(), 1);
+        let ctx = client.client().unwrap().context();
+        assert_eq!(
+            ctx.get::<String>("tenant").map(String::as_str),
+            Some("acme")
+        );
+        assert_eq!(ctx.get::<u64>("conn_id"), Some(&42));
+    });
+    // Once per session, except that the inbound interceptor also sees
+    // each welcome.
+    let calls = [
+        ("tcp", 2),
+        ("welcome", 2),
+        ("ping", 2),
+        ("pong", 2),
+        ("outbound", 2),
+        ("inbound", 4),
+    ];
+    for (hook, times) in calls {
+        assert_eq!(seen(&log, hook), vec![format!("{hook} acme/42"); times]);
+    }
+    let events = seen(&log, "event");
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|e| e == "event acme/42"), "{events:?}");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use common::{CLOSE, block_on};
+use websockets_monoio::drain::{DrainOutcome, drain};
+use websockets_monoio::time::Deadline;
+use websockets_monoio::{WsClient, WsClientBuilder, WsEvent};
+
+const fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+/// How late a close may go out on a loaded machine.
+const TOLERANCE: Duration = ms(40);
+
+const CONNECTIONS: usize = 30;
+
+/// A server that reports when each connection's Close arrived, and answers
+/// it unless the connection is one of `silent`.
+fn server(silent: &'static [usize]) -> (String, mpsc::Receiver<Instant>) {
+    let (tx, rx) = mpsc::channel();
+    let url = common::scripted(move |conn, mut socket| {
+        while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+            if opcode == CLOSE {
+                tx.send(Instant::now()).unwrap();
+                if !silent.contains(&conn) {
+                    let _ = common::write_frame(&mut socket, CLOSE, &payload);
+                }
+            }
+        }
+    });
+    (url, rx)
+}
+
+/// Connect [`CONNECTIONS`] clients that report their drain outcomes to
+/// `outcomes`.
+async fn connect(url: &str, outcomes: &Rc<RefCell<Vec<DrainOutcome>>>) -> Vec<WsClient> {
+    let mut clients = Vec::new();
+    for _ in 0..CONNECTIONS {
+        let seen = outcomes.clone();
+        let builder = WsClientBuilder::new().on_event(move |event, _| {
+            if let WsEvent::ConnectionDrained { outcome } = event {
+                seen.borrow_mut().push(*outcome);
+            }
+        });
+        clients.push(builder.connect(url).await.unwrap());
+    }
+    clients
+}
+
+fn count(outcomes: &[DrainOutcome], outcome: DrainOutcome) -> usize {
+    outcomes.iter().filter(|o| **o == outcome).count()
+}
+
+#[test]
+fn closes_go_out_at_the_configured_rate() {
+    let (url, closes) = server(&[]);
+    let outcomes = Rc::new(RefCell::new(Vec::new()));
+    let started = Instant::now();
+    let summary = block_on(async {
+        let clients = connect(&url, &outcomes).await;
+        // One close every 10 ms.
+        drain(clients, 100.0, Deadline::after(Duration::from_secs(5))).await
+    });
+    assert_eq!(
+        (summary.closed, summary.forced, summary.dead),
+        (CONNECTIONS, 0, 0)
+    );
+    assert!(summary.elapsed >= ms(290), "{summary:?}");
+    assert_eq!(count(&outcomes.borrow(), DrainOutcome::Closed), CONNECTIONS);
+
+    let mut arrived: Vec<_> = closes.try_iter().collect();
+    assert_eq!(arrived.len(), CONNECTIONS);
+    arrived.sort();
+    for (turn, at) in arrived.iter().enumerate() {
+        // `started` is before the connects, so a close is never early by it.
+        let offset = *at - started;
+        let due = ms(10) * turn as u32;
+        assert!(offset >= due, "close {turn} at {offset:?}, before its turn");
+        let first = arrived[0] - started;
+        assert!(
+            offset <= first + due + TOLERANCE,
+            "close {turn} at {offset:?}, the first at {first:?}"
+        );
+    }
+}
+
+#[test]
+fn the_deadline_aborts_the_rest() {
+    // One peer never answers its Close.
+    let (url, closes) = server(&[0]);
+    let outcomes = Rc::new(RefCell::new(Vec::new()));
+    let summary = block_on(async {
+        let clients = connect(&url, &outcomes).await;
+        // A close every 20 ms: turns at 0, 20, ..., 180 ms come before the
+        // deadline, and the rest are aborted at it.
+        let deadline = Deadline::after(ms(190));
+        drain(clients, 50.0, deadline).await
+    });
+    assert_eq!(closes.try_iter().count(), 10);
+    assert_eq!(summary.closed, 9, "{summary:?}");
+    assert_eq!(summary.forced, CONNECTIONS - 9);
+    assert_eq!(summary.total(), CONNECTIONS);
+    assert!(
+        summary.elapsed >= ms(185) && summary.elapsed <= ms(190) + TOLERANCE,
+        "{summary:?}"
+    );
+    let outcomes = outcomes.borrow();
+    assert_eq!(count(&outcomes, DrainOutcome::Closed), 9);
+    assert_eq!(count(&outcomes, DrainOutcome::Forced), CONNECTIONS - 9);
+}
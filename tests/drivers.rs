@@ -0,0 +1,69 @@
+mod common;
+
+use std::time::Duration;
+
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode};
+use monoio::time::TimeDriver;
+use monoio::{Buildable, Driver, RuntimeBuilder};
+use websockets_monoio::{Message, WsClientBuilder};
+
+/// Connect, round-trip a small message and one several times the transport
+/// buffers, then close.
+async fn round_trip(url: &str, builder: WsClientBuilder) {
+    let mut client = builder.buffer_size(4 * 1024).connect(url).await.unwrap();
+    let large: Vec<u8> = (0..256 * 1024).map(|i| i as u8).collect();
+    for message in [Message::Text("hello".into()), Message::Binary(large)] {
+        client.send(message.clone()).await.unwrap();
+        client.flush().await.unwrap();
+        let echoed = monoio::time::timeout(Duration::from_secs(5), client.recv()).await;
+        assert_eq!(echoed.unwrap().unwrap(), message);
+    }
+    client.send(Message::Close(None)).await.unwrap();
+    client.flush().await.unwrap();
+    assert!(matches!(client.recv().await, Ok(Message::Close(_))));
+}
+
+/// Round-trip over `ws://` and over `wss://`.
+fn run_on<D>()
+where
+    D: Buildable + Driver + 'static,
+{
+    let plain = MockServer::start(Mode::Echo);
+    let identity = Identity::new(&["localhost"]);
+    let tls = MockServer::start_tls(Mode::Echo, &identity);
+    let mut runtime = Buildable::build(RuntimeBuilder::<TimeDriver<D>>::new()).unwrap();
+    runtime.block_on(async {
+        round_trip(&plain.url(), WsClientBuilder::new()).await;
+        let builder = WsClientBuilder::new().tls_connector(connector(&[&identity]));
+        round_trip(&tls.url(), builder).await;
+    });
+    assert_eq!(plain.received().len(), 2);
+    assert_eq!(tls.received().len(), 2);
+}
+
+#[test]
+fn legacy_driver() {
+    run_on::<monoio::LegacyDriver>();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn io_uring_driver() {
+    if !monoio::utils::detect_uring() {
+        eprintln!("io_uring is not available; skipping");
+        return;
+    }
+    run_on::<monoio::IoUringDriver>();
+}
+
+#[test]
+fn fusion_driver() {
+    let server = MockServer::start(Mode::Echo);
+    let mut runtime = RuntimeBuilder::<monoio::FusionDriver>::new()
+        .enable_timer()
+        .build()
+        .unwrap();
+    runtime.block_on(round_trip(&server.url(), WsClientBuilder::new()));
+    assert_eq!(server.received().len(), 2);
+}
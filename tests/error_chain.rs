@@ -0,0 +1,80 @@
+mod common;
+
+use std::error::Error;
+use std::io::{self, ErrorKind};
+use std::net::TcpListener;
+
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::http_upgrade::UpgradeErr;
+use websockets_monoio::tls::TlsErr;
+use websockets_monoio::{WsClientBuilder, WsError};
+
+/// `error` and its sources, outermost first.
+fn chain<'e>(error: &'e (dyn Error + 'static)) -> Vec<&'e (dyn Error + 'static)> {
+    std::iter::successors(Some(error), |&e| e.source()).collect()
+}
+
+/// The messages along the chain, checking that none repeats the one before.
+fn messages(chain: &[&(dyn Error + 'static)]) -> Vec<String> {
+    let messages: Vec<_> = chain.iter().map(|e| e.to_string()).collect();
+    for pair in messages.windows(2) {
+        assert_ne!(pair[0], pair[1], "{messages:?}");
+    }
+    messages
+}
+
+#[test]
+fn a_reset_during_the_upgrade_chains_to_the_io_error() {
+    // Reads the upgrade request, then resets the connection.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        common::read_request(&mut socket).unwrap();
+        common::reset(&socket);
+    });
+    let error = block_on(WsClientBuilder::new().connect(&url))
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, WsError::Upgrade(UpgradeErr::Io(_))),
+        "{error:?}"
+    );
+    let chain = chain(&error);
+    assert_eq!(chain.len(), 2);
+    let root = chain[1].downcast_ref::<io::Error>().unwrap();
+    assert_eq!(root.kind(), ErrorKind::ConnectionReset);
+    let messages = messages(&chain);
+    assert_eq!(messages[0], "I/O error during the upgrade");
+}
+
+#[test]
+fn an_untrusted_certificate_chains_to_the_io_error() {
+    let identity = Identity::new(&["127.0.0.1"]);
+    let server = MockServer::start_tls(Mode::Record, &identity);
+    let url = format!("wss://{}/", server.addr);
+    let stranger = Identity::new(&["127.0.0.1"]);
+    let builder = WsClientBuilder::new().tls_connector(connector(&[&stranger]));
+    let error = block_on(builder.connect(&url)).err().unwrap();
+    assert!(
+        matches!(error, WsError::Tls(TlsErr::Rustls(_))),
+        "{error:?}"
+    );
+    let chain = chain(&error);
+    let root = chain.last().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(root.kind(), ErrorKind::InvalidData);
+    let rustls = root.get_ref().unwrap().downcast_ref::<rustls::Error>();
+    assert!(
+        matches!(rustls, Some(rustls::Error::InvalidCertificate(_))),
+        "{root:?}"
+    );
+    let messages = messages(&chain);
+    assert_eq!(messages[0], "TLS handshake failed");
+    assert!(
+        messages
+            .last()
+            .unwrap()
+            .contains("invalid peer certificate")
+    );
+}
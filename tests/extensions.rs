@@ -0,0 +1,69 @@
+mod common;
+
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+use common::block_on;
+use websockets_monoio::WsClientBuilder;
+
+fn offer(name: &str, params: &[(&str, Option<&str>)]) -> common::Extension {
+    let params = params
+        .iter()
+        .map(|(param, value)| (param.to_string(), value.map(str::to_owned)))
+        .collect();
+    (name.to_owned(), params)
+}
+
+#[test]
+fn extension_offers_parse_as_sent() {
+    // Captures the offered extensions and accepts the first one.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let (offered, captured) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let request = common::read_request(&mut socket).unwrap();
+        let value = common::header(&request, "sec-websocket-extensions").unwrap();
+        let offers = common::parse_extensions(value);
+        let accepted = value.split(',').next().unwrap().trim().to_owned();
+        offered.send(offers).unwrap();
+        let headers = [("Sec-WebSocket-Extensions", accepted.as_str())];
+        common::answer_with(&mut socket, &request, &headers).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .with_extension(
+                "permessage-deflate",
+                &[
+                    ("client_max_window_bits", None),
+                    ("server_max_window_bits", Some("10")),
+                ],
+            )
+            .with_extension("x-meta", &[("note", Some(r#"a, b; "c" \ d"#))])
+            .with_extension_raw("x-raw; v=1")
+            .with_extension("x-bare", &[])
+            .connect(&url)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.info().extensions(),
+            Some("permessage-deflate; client_max_window_bits; server_max_window_bits=10")
+        );
+    });
+    assert_eq!(
+        captured.recv().unwrap(),
+        [
+            offer(
+                "permessage-deflate",
+                &[
+                    ("client_max_window_bits", None),
+                    ("server_max_window_bits", Some("10")),
+                ],
+            ),
+            offer("x-meta", &[("note", Some(r#"a, b; "c" \ d"#))]),
+            offer("x-raw", &[("v", Some("1"))]),
+            offer("x-bare", &[]),
+        ]
+    );
+}
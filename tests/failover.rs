@@ -0,0 +1,68 @@
+mod common;
+
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::url::OwnedWsUrl;
+use websockets_monoio::{Backoff, Message, RetryConfig, WsClientBuilder, WsError};
+
+/// A `ws://` URL on a port nothing listens on.
+fn unreachable() -> OwnedWsUrl {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    OwnedWsUrl::parse(&url).unwrap()
+}
+
+fn retry_twice() -> RetryConfig {
+    RetryConfig {
+        attempts_per_url: 2,
+        backoff: Backoff {
+            initial: Duration::from_millis(20),
+            multiplier: 1.0,
+            max_interval: Duration::from_millis(20),
+            max_attempts: None,
+        },
+    }
+}
+
+#[test]
+fn the_first_reachable_server_in_the_list_is_used() {
+    let server = MockServer::start(Mode::Echo);
+    let echo = OwnedWsUrl::parse(&server.url()).unwrap();
+    let urls = [unreachable(), unreachable(), echo.clone()];
+    block_on(async {
+        let started = Instant::now();
+        let (mut client, url) = WsClientBuilder::new()
+            .connect_list_with_retry(&urls, retry_twice())
+            .await
+            .unwrap();
+        // Each unreachable server was retried once after the backoff.
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert_eq!(url.to_string(), echo.to_string());
+        client.send(Message::Text("hello".into())).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+    });
+    assert_eq!(server.accepted(), 1);
+}
+
+#[test]
+fn every_url_failing_reports_each_one() {
+    let urls = [unreachable(), unreachable()];
+    block_on(async {
+        let result = WsClientBuilder::new()
+            .connect_list_with_retry(&urls, retry_twice())
+            .await;
+        let Err(WsError::AllFailed(failures)) = result else {
+            panic!("expected AllFailed, got {:?}", result.map(|_| ()));
+        };
+        assert_eq!(failures.len(), 2);
+        for ((url, error), expected) in failures.iter().zip(&urls) {
+            assert_eq!(url.to_string(), expected.to_string());
+            assert!(
+                matches!(error, WsError::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused),
+                "{error:?}"
+            );
+        }
+    });
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use common::{CLOSE, PING, PONG, TEXT, block_on};
+use websockets_monoio::{ContextMap, Message, WsClientBuilder, WsError};
+
+const WAIT: Duration = Duration::from_millis(200);
+
+/// A server that sends `greeting` frames after the upgrade, then reports
+/// every frame the client sends until it closes.
+fn greeting(greeting: &'static [(u8, &'static [u8])]) -> (String, Receiver<(u8, Vec<u8>)>) {
+    let (tx, rx) = mpsc::channel();
+    let url = common::scripted(move |_, mut socket| {
+        for (opcode, payload) in greeting {
+            common::write_frame(&mut socket, *opcode, payload).unwrap();
+        }
+        while let Ok(frame) = common::read_frame(&mut socket) {
+            let close = frame.0 == CLOSE;
+            let _ = tx.send(frame);
+            if close {
+                return;
+            }
+        }
+    });
+    (url, rx)
+}
+
+fn welcome_only(message: &Message, _: &ContextMap) -> Result<(), String> {
+    match message {
+        Message::Text(text) if text.contains("\"welcome\"") => Ok(()),
+        other => Err(format!("expected a welcome, got {other:?}")),
+    }
+}
+
+#[test]
+fn an_accepted_welcome_is_kept_and_not_delivered_again() {
+    let (url, frames) = greeting(&[
+        (PING, b"hi"),
+        (TEXT, br#"{"event":"welcome","version":2}"#),
+        (TEXT, b"tick"),
+    ]);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .await_first_message(WAIT, welcome_only)
+            .connect(&url)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.first_message(),
+            Some(&Message::Text(r#"{"event":"welcome","version":2}"#.into()))
+        );
+        assert_eq!(client.recv().await.unwrap(), Message::Text("tick".into()));
+        client
+            .send(Message::Text("subscribe".into()))
+            .await
+            .unwrap();
+        client.flush().await.unwrap();
+    });
+    // The Ping ahead of the welcome was answered while connecting.
+    let wait = Duration::from_secs(5);
+    assert_eq!(frames.recv_timeout(wait).unwrap(), (PONG, b"hi".to_vec()));
+    assert_eq!(
+        frames.recv_timeout(wait).unwrap(),
+        (TEXT, b"subscribe".to_vec())
+    );
+}
+
+#[test]
+fn a_rejected_welcome_fails_the_connect_and_closes_with_1008() {
+    let (url, frames) = greeting(&[(TEXT, br#"{"event":"maintenance"}"#)]);
+    block_on(async {
+        let result = WsClientBuilder::new()
+            .await_first_message(WAIT, welcome_only)
+            .connect(&url)
+            .await;
+        let Err(WsError::FirstMessageRejected { reason, message }) = result else {
+            panic!("expected a rejection, got {:?}", result.map(|_| ()));
+        };
+        assert_eq!(message, Message::Text(r#"{"event":"maintenance"}"#.into()));
+        assert!(reason.starts_with("expected a welcome"), "{reason}");
+    });
+    let (opcode, payload) = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload, 1008u16.to_be_bytes());
+}
+
+#[test]
+fn no_welcome_times_out_and_closes_with_1000() {
+    let (url, frames) = greeting(&[]);
+    block_on(async {
+        let result = WsClientBuilder::new()
+            .await_first_message(WAIT, welcome_only)
+            .connect(&url)
+            .await;
+        assert!(
+            matches!(result, Err(WsError::FirstMessageTimeout(limit)) if limit == WAIT),
+            "{:?}",
+            result.map(|_| ())
+        );
+    });
+    let (opcode, payload) = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload, 1000u16.to_be_bytes());
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{CLOSE, TEXT};
+use websockets_monoio::handoff::HandoffDescriptor;
+use websockets_monoio::{Message, WsClient, WsClientBuilder};
+
+/// Upgrade requests and Text payloads, by connection.
+type Log = Arc<Mutex<Vec<(String, Vec<String>)>>>;
+
+/// A server that selects `feed.v2` when offered and logs what each
+/// connection sends.
+fn feed_server() -> (String, Log) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/feed", listener.local_addr().unwrap());
+    let log = Log::default();
+    let connections = log.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let log = connections.clone();
+            thread::spawn(move || {
+                let request = common::read_request(&mut socket).unwrap();
+                let offered = common::header(&request, "sec-websocket-protocol")
+                    .is_some_and(|offer| offer.split(',').any(|p| p.trim() == "feed.v2"));
+                let selected: &[_] = if offered {
+                    &[("Sec-WebSocket-Protocol", "feed.v2")]
+                } else {
+                    &[]
+                };
+                common::answer_with(&mut socket, &request, selected).unwrap();
+                let conn = {
+                    let mut log = log.lock().unwrap();
+                    log.push((request, Vec::new()));
+                    log.len() - 1
+                };
+                while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+                    match opcode {
+                        TEXT => log.lock().unwrap()[conn]
+                            .1
+                            .push(String::from_utf8(payload).unwrap()),
+                        CLOSE => return,
+                        _ => {}
+                    }
+                }
+            });
+        }
+    });
+    (url, log)
+}
+
+/// Run `f` on a new thread with a runtime of its own.
+fn on_runtime<T: Send + 'static, F: Future<Output = T> + 'static>(
+    f: impl FnOnce() -> F + Send + 'static,
+) -> thread::JoinHandle<T> {
+    thread::spawn(move || common::block_on(f()))
+}
+
+fn subscribe(from: u64) -> Message {
+    Message::Text(format!("subscribe from {from}"))
+}
+
+#[test]
+fn a_session_moves_to_another_runtime_thread_and_back() {
+    let (url, log) = feed_server();
+    let (to_b, from_a) = mpsc::channel::<HandoffDescriptor>();
+    let (to_a, from_b) = mpsc::channel::<HandoffDescriptor>();
+    let wait = Duration::from_secs(5);
+
+    let b = on_runtime(move || async move {
+        let desc = from_a.recv_timeout(wait).unwrap();
+        let mut client = WsClient::connect_from_descriptor(&desc).await.unwrap();
+        assert_eq!(client.info().protocol(), Some("feed.v2"));
+        client.flush().await.unwrap();
+        // Hand the session back with where it got to.
+        let back = client
+            .handoff_descriptor()
+            .with_resubscribe(vec![subscribe(2000)]);
+        to_a.send(back).unwrap();
+        client.send(Message::Close(None)).await.unwrap();
+    });
+
+    let a = on_runtime(move || async move {
+        let mut client = WsClientBuilder::new()
+            .subprotocol("feed.v1")
+            .subprotocol("feed.v2")
+            .header("X-Tenant", "acme")
+            .tcp_nodelay(true)
+            .connect(&url)
+            .await
+            .unwrap();
+        client.send(subscribe(0)).await.unwrap();
+        client.flush().await.unwrap();
+        let desc = client
+            .handoff_descriptor()
+            .with_resubscribe(vec![subscribe(1042)]);
+        assert_eq!(desc.protocol.as_deref(), Some("feed.v2"));
+        assert_eq!(desc.options.tcp_nodelay, Some(true));
+        to_b.send(desc).unwrap();
+        let back = from_b.recv_timeout(wait).unwrap();
+        assert_eq!(back.url, url);
+        let mut returned = WsClient::connect_from_descriptor(&back).await.unwrap();
+        returned.flush().await.unwrap();
+        client.send(Message::Close(None)).await.unwrap();
+        assert_eq!(returned.info().protocol(), Some("feed.v2"));
+        returned.send(Message::Close(None)).await.unwrap();
+    });
+    a.join().unwrap();
+    b.join().unwrap();
+
+    let expected = [
+        ["subscribe from 0"],
+        ["subscribe from 1042"],
+        ["subscribe from 2000"],
+    ];
+    let sent = || -> Vec<_> {
+        log.lock()
+            .unwrap()
+            .iter()
+            .map(|(_, texts)| texts.clone())
+            .collect()
+    };
+    let deadline = Instant::now() + wait;
+    while sent() != expected && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(sent(), expected);
+    let log = log.lock().unwrap();
+    // Every connection asked for the same things as the first.
+    for (request, _) in log.iter() {
+        assert!(request.starts_with("GET /feed HTTP/1.1\r\n"), "{request}");
+        assert_eq!(common::header(request, "x-tenant"), Some("acme"));
+        assert_eq!(
+            common::header(request, "sec-websocket-protocol"),
+            Some("feed.v1, feed.v2")
+        );
+    }
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::io;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use common::proxy::MockProxy;
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode, block_on};
+use monoio_compat::{AsyncRead, AsyncWrite};
+use tokio::io::DuplexStream;
+use websockets_monoio::proxy::Proxy;
+use websockets_monoio::transport::Transport;
+use websockets_monoio::{Message, WsClientBuilder};
+
+#[test]
+fn plain_connection() {
+    // Selects a subprotocol and echoes the offered extension back.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let request = common::read_request(&mut socket).unwrap();
+        let extensions = common::header(&request, "sec-websocket-extensions").unwrap();
+        let headers = [
+            ("Sec-WebSocket-Protocol", "feed.v2"),
+            ("Sec-WebSocket-Extensions", extensions),
+        ];
+        common::answer_with(&mut socket, &request, &headers).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    let handshake_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let url = format!("ws://{addr}/feed?token=secret");
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .subprotocol("feed.v1")
+            .subprotocol("feed.v2")
+            .with_extension("x-trace", &[("id", Some("7"))])
+            .clock(move || handshake_at)
+            .connect(&url)
+            .await
+            .unwrap();
+        let info = client.info();
+        assert_eq!(info.url(), url);
+        assert_eq!(info.protocol(), Some("feed.v2"));
+        assert_eq!(info.extensions(), Some("x-trace; id=7"));
+        assert_eq!(info.tls(), None);
+        assert_eq!(info.peer_addr(), Some(addr));
+        assert_eq!(info.proxy_addr(), None);
+        assert!(info.local_addr().unwrap().ip().is_loopback());
+        assert_eq!(info.peer_label(), None);
+        assert_eq!(info.timings().tls, None);
+        assert!(info.timings().total() >= info.timings().tcp);
+        assert_eq!(info.handshake_at(), handshake_at);
+        assert!(!format!("{info:?}").contains("secret"));
+    });
+}
+
+#[test]
+fn tls_connection() {
+    let identity = Identity::new(&["localhost"]);
+    let server = MockServer::start_tls(Mode::Record, &identity);
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .tls_connector(connector(&[&identity]))
+            .connect(&server.url())
+            .await
+            .unwrap();
+        let info = client.info();
+        assert_eq!(info.url(), server.url());
+        assert_eq!(info.peer_addr(), Some(server.addr));
+        let tls = info.tls().unwrap();
+        assert_eq!(tls.version.as_deref(), Some("TLSv1_3"));
+        assert!(tls.cipher_suite.as_deref().unwrap().starts_with("TLS13_"));
+        assert_eq!(tls.alpn, None);
+        assert!(!tls.resumed);
+        assert!(info.timings().tls.is_some());
+        assert_eq!(info.protocol(), None);
+    });
+}
+
+#[test]
+fn proxied_connection() {
+    let server = MockServer::start(Mode::Record);
+    let proxy = MockProxy::start();
+    block_on(async {
+        let client = WsClientBuilder::new()
+            .proxy(Proxy::parse(&proxy.url).unwrap())
+            .connect(&server.url())
+            .await
+            .unwrap();
+        let info = client.info();
+        assert_eq!(info.url(), server.url());
+        assert_eq!(info.tls(), None);
+        assert_eq!(info.peer_addr(), Some(server.addr));
+        assert_eq!(info.proxy_addr(), Some(proxy.addr));
+        assert_eq!(info.peer_label(), None);
+        assert!(info.local_addr().unwrap().ip().is_loopback());
+    });
+    assert_eq!(proxy.requests(), [server.addr.to_string()]);
+}
+
+/// An in-memory pipe with a name for its other end.
+struct Pipe(DuplexStream);
+
+impl Transport for Pipe {
+    fn peer_label(&self) -> Option<String> {
+        Some("pipe:echo".into())
+    }
+}
+
+impl AsyncRead for Pipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Pipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[test]
+fn custom_transport() {
+    block_on(async {
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        monoio::spawn(common::echo_over_pipe(server_end));
+        let mut client = WsClientBuilder::new()
+            .connect_over("ws://echo.internal/feed", Pipe(client_end))
+            .await
+            .unwrap();
+        let info = client.info();
+        assert_eq!(info.url(), "ws://echo.internal/feed");
+        assert_eq!(info.peer_label(), Some("pipe:echo"));
+        assert_eq!(info.peer_addr(), None);
+        assert_eq!(info.local_addr(), None);
+        assert_eq!(info.tls(), None);
+        client.send(Message::Text("hello".into())).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+    });
+}
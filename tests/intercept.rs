@@ -0,0 +1,179 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use common::{MockServer, Mode, TEXT, block_on};
+use websockets_monoio::middleware::{Direction, InterceptAction};
+use websockets_monoio::{ContextMap, Message, WsClientBuilder, WsError};
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+/// An interceptor that logs `name` and appends `|name` to Text messages.
+fn tag(
+    name: &'static str,
+    log: &Log,
+) -> impl Fn(&mut Message, &ContextMap) -> Result<InterceptAction, String> + 'static {
+    let log = log.clone();
+    move |message, _| {
+        log.borrow_mut().push(name.to_owned());
+        if let Message::Text(text) = message {
+            text.push('|');
+            text.push_str(name);
+        }
+        Ok(InterceptAction::Continue)
+    }
+}
+
+#[test]
+fn interceptors_run_in_registration_order_per_direction() {
+    let server = MockServer::start(Mode::Echo);
+    let log = Log::default();
+    let builder = WsClientBuilder::new()
+        .intercept_outbound("seq", tag("seq", &log))
+        .intercept_inbound("verify", tag("verify", &log))
+        .intercept_outbound("sign", tag("sign", &log))
+        .intercept_inbound("decode", tag("decode", &log));
+    block_on(async {
+        let mut client = builder.connect(&server.url()).await.unwrap();
+        client.send(Message::Text("quote".into())).await.unwrap();
+        assert_eq!(*log.borrow(), ["seq", "sign"]);
+        // Pings are control messages and pass untouched.
+        client.send(Message::Ping(b"p".to_vec())).await.unwrap();
+        let echoed = client.recv().await.unwrap();
+        assert_eq!(echoed, Message::Text("quote|seq|sign|verify|decode".into()));
+        assert_eq!(*log.borrow(), ["seq", "sign", "verify", "decode"]);
+        let names: Vec<_> = client
+            .interceptor_stats()
+            .into_iter()
+            .map(|stats| (stats.name, stats.direction, stats.calls))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("seq".to_owned(), Direction::Outbound, 1),
+                ("verify".to_owned(), Direction::Inbound, 1),
+                ("sign".to_owned(), Direction::Outbound, 1),
+                ("decode".to_owned(), Direction::Inbound, 1),
+            ]
+        );
+    });
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(
+        (received[0].opcode, &received[0].payload[..]),
+        (TEXT, &b"quote|seq|sign"[..])
+    );
+}
+
+#[test]
+fn a_replacement_goes_to_the_next_interceptor_only_and_calls_never_nest() {
+    let server = MockServer::start(Mode::Record);
+    let depth = Rc::new(Cell::new(0));
+    let seen = Log::default();
+    // Each interceptor checks that it is the only one running.
+    let guarded = |name: &'static str, action: fn(&Message) -> InterceptAction| {
+        let (depth, seen) = (depth.clone(), seen.clone());
+        move |message: &mut Message, _: &ContextMap| {
+            depth.set(depth.get() + 1);
+            assert_eq!(depth.get(), 1, "{name} ran inside another interceptor");
+            seen.borrow_mut().push(format!("{name}:{message:?}"));
+            let action = action(message);
+            depth.set(depth.get() - 1);
+            Ok(action)
+        }
+    };
+    let builder = WsClientBuilder::new()
+        .intercept_outbound(
+            "wrap",
+            guarded("wrap", |message| match message {
+                Message::Text(text) => {
+                    InterceptAction::Replace(Message::Binary(text.clone().into_bytes()))
+                }
+                _ => InterceptAction::Continue,
+            }),
+        )
+        .intercept_outbound(
+            "filter",
+            guarded("filter", |message| match message {
+                Message::Binary(bytes) if bytes == b"noise" => InterceptAction::Drop("noise"),
+                _ => InterceptAction::Continue,
+            }),
+        );
+    block_on(async {
+        let mut client = builder.connect(&server.url()).await.unwrap();
+        client.send(Message::Text("quote".into())).await.unwrap();
+        client.send(Message::Text("noise".into())).await.unwrap();
+        client.send(Message::Text("trade".into())).await.unwrap();
+        client.flush().await.unwrap();
+        let stats = client.interceptor_stats();
+        assert_eq!((stats[0].calls, stats[0].replaced), (3, 3));
+        assert_eq!(stats[1].calls, 3);
+        assert_eq!(stats[1].dropped.get("noise"), Some(&1));
+    });
+    // `wrap` saw each message once, as sent; `filter` saw its replacement.
+    assert_eq!(
+        *seen.borrow(),
+        [
+            r#"wrap:Text("quote")"#,
+            "filter:Binary([113, 117, 111, 116, 101])",
+            r#"wrap:Text("noise")"#,
+            "filter:Binary([110, 111, 105, 115, 101])",
+            r#"wrap:Text("trade")"#,
+            "filter:Binary([116, 114, 97, 100, 101])",
+        ]
+    );
+    assert!(server.wait_for(Duration::from_secs(5), |received| received.len() == 2));
+    let payloads: Vec<_> = server.received().into_iter().map(|r| r.payload).collect();
+    assert_eq!(payloads, [b"quote".to_vec(), b"trade".to_vec()]);
+}
+
+#[test]
+fn stats_count_calls_errors_and_time_per_interceptor() {
+    let server = MockServer::start(Mode::Echo);
+    let slow = Duration::from_millis(20);
+    let builder = WsClientBuilder::new()
+        .intercept_outbound("schema", |message, _| match message {
+            Message::Text(text) if text.starts_with('{') => Ok(InterceptAction::Continue),
+            _ => Err("not a JSON object".into()),
+        })
+        .intercept_outbound("sign", move |_, _| {
+            std::thread::sleep(slow);
+            Ok(InterceptAction::Continue)
+        });
+    block_on(async {
+        let mut client = builder.connect(&server.url()).await.unwrap();
+        client.send(Message::Text("{}".into())).await.unwrap();
+        let result = client.send(Message::Text("nope".into())).await;
+        let Err(WsError::Intercept {
+            name,
+            direction,
+            reason,
+        }) = result
+        else {
+            panic!("expected an interceptor error, got {result:?}");
+        };
+        assert_eq!(
+            (name.as_str(), direction, reason.as_str()),
+            ("schema", Direction::Outbound, "not a JSON object")
+        );
+        // The failed send left the connection usable.
+        client
+            .send(Message::Text("{\"n\":2}".into()))
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("{}".into()));
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Text("{\"n\":2}".into())
+        );
+
+        let stats = client.interceptor_stats();
+        let (schema, sign) = (&stats[0], &stats[1]);
+        assert_eq!((schema.calls, schema.errors), (3, 1));
+        assert_eq!((sign.calls, sign.errors), (2, 0));
+        assert!(sign.time >= slow * 2, "{:?}", sign.time);
+        assert!(schema.time < slow, "{:?}", schema.time);
+    });
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::{MockServer, Mode, PING, TEXT, block_on};
+use websockets_monoio::keepalive::Keepalive;
+use websockets_monoio::{Message, WsClientBuilder, WsError};
+
+const INTERVAL: Duration = Duration::from_millis(20);
+const TIMEOUT: Duration = Duration::from_millis(80);
+
+/// The exchange-style heartbeat, numbered so replies can be matched.
+fn json_heartbeat() -> Keepalive {
+    let sent = Cell::new(0);
+    Keepalive::ping(INTERVAL, TIMEOUT)
+        .heartbeat(move || {
+            sent.set(sent.get() + 1);
+            Message::Text(format!(r#"{{"op":"ping","id":{}}}"#, sent.get()))
+        })
+        .liveness(|msg| matches!(msg, Message::Text(t) if t.starts_with(r#"{"op":"pong""#)))
+}
+
+#[test]
+fn json_heartbeats_answered_by_the_server_keep_the_connection_alive() {
+    // Answers `{"op":"ping","id":N}` with `{"op":"pong","id":N}` and ignores
+    // WS Pings, like an exchange that only speaks its own heartbeat.
+    let pings = Arc::new(Mutex::new(0));
+    let seen = pings.clone();
+    let url = common::scripted(move |_, mut socket| {
+        while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+            let text = String::from_utf8_lossy(&payload);
+            match opcode {
+                PING => *seen.lock().unwrap() += 1,
+                TEXT if text.starts_with(r#"{"op":"ping""#) => {
+                    let pong = text.replace(r#""op":"ping""#, r#""op":"pong""#);
+                    common::write_frame(&mut socket, TEXT, pong.as_bytes()).unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .keepalive(json_heartbeat())
+            .connect(&url)
+            .await
+            .unwrap();
+        // Several timeouts' worth of receiving: only pongs arrive.
+        let until = Instant::now() + 4 * TIMEOUT;
+        let mut pongs = Vec::new();
+        while Instant::now() < until {
+            match client.recv().await.unwrap() {
+                Message::Text(text) => pongs.push(text),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        assert!(pongs.len() >= 5, "{pongs:?}");
+        for (i, pong) in pongs.iter().enumerate() {
+            assert_eq!(pong, &format!(r#"{{"op":"pong","id":{}}}"#, i + 1));
+        }
+    });
+    assert_eq!(*pings.lock().unwrap(), 0, "sent a WS Ping");
+}
+
+#[test]
+fn unanswered_json_heartbeats_time_out() {
+    // Records the heartbeats and never replies.
+    let server = MockServer::start(Mode::Record);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .keepalive(json_heartbeat())
+            .connect(&server.url())
+            .await
+            .unwrap();
+        let started = Instant::now();
+        let result = client.recv().await;
+        assert!(
+            matches!(
+                result,
+                Err(WsError::KeepaliveTimeout { timeout, local_stall: None }) if timeout == TIMEOUT
+            ),
+            "{result:?}"
+        );
+        assert!(started.elapsed() >= TIMEOUT);
+    });
+    let heartbeats = server.received();
+    assert!(heartbeats.len() >= 3, "{heartbeats:?}");
+    assert_eq!(heartbeats[0].text(), r#"{"op":"ping","id":1}"#);
+}
+
+#[test]
+fn any_message_counts_as_liveness() {
+    // Never answers a heartbeat, but streams an update every 10 ms.
+    let url = common::scripted(|_, mut socket| {
+        for i in 0.. {
+            std::thread::sleep(Duration::from_millis(10));
+            if common::write_frame(&mut socket, TEXT, format!("tick {i}").as_bytes()).is_err() {
+                return;
+            }
+        }
+    });
+    let heartbeats = Rc::new(Cell::new(0));
+    let sent = heartbeats.clone();
+    block_on(async {
+        let keepalive = Keepalive::ping(INTERVAL, TIMEOUT)
+            .heartbeat(move || {
+                sent.set(sent.get() + 1);
+                Message::Text(r#"{"op":"ping"}"#.into())
+            })
+            .any_message();
+        let mut client = WsClientBuilder::new()
+            .keepalive(keepalive)
+            .connect(&url)
+            .await
+            .unwrap();
+        let until = Instant::now() + 4 * TIMEOUT;
+        while Instant::now() < until {
+            assert!(matches!(client.recv().await.unwrap(), Message::Text(_)));
+        }
+    });
+    assert!(heartbeats.get() >= 5);
+}
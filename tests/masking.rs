@@ -0,0 +1,72 @@
+mod common;
+
+use std::io::Read;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use common::{CLOSE, TEXT, block_on};
+use websockets_monoio::{CloseFrame, Message, WsClientBuilder};
+
+/// A server that echoes Text frames. A `strict` one follows RFC 6455 §5.1
+/// and fails the connection with 1002 on an unmasked client frame; each
+/// frame's mask bit is reported on the returned channel.
+fn server(strict: bool) -> (String, mpsc::Receiver<bool>) {
+    let (tx, rx) = mpsc::channel();
+    let url = common::scripted(move |_, mut socket| {
+        let mut head = [0u8; 2];
+        while socket.read_exact(&mut head).is_ok() {
+            let masked = head[1] & 0x80 != 0;
+            let _ = tx.send(masked);
+            let Ok((opcode, payload)) = common::read_frame(&mut (&head[..]).chain(&socket)) else {
+                return;
+            };
+            if strict && !masked {
+                let mut close = CloseFrame::PROTOCOL_ERROR.to_be_bytes().to_vec();
+                close.extend_from_slice(b"unmasked client frame");
+                let _ = common::write_frame(&mut socket, CLOSE, &close);
+                return;
+            }
+            if opcode == TEXT {
+                common::write_frame(&mut socket, TEXT, &payload).unwrap();
+            }
+        }
+    });
+    (url, rx)
+}
+
+fn echo(unmasked: bool, url: &str) -> Message {
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .unmasked_client_frames(unmasked)
+            .connect(url)
+            .await
+            .unwrap();
+        client.send(Message::Text("quote".into())).await.unwrap();
+        client.recv().await.unwrap()
+    })
+}
+
+#[test]
+fn a_strict_server_accepts_masked_frames() {
+    let (url, masks) = server(true);
+    assert_eq!(echo(false, &url), Message::Text("quote".into()));
+    assert!(masks.recv_timeout(Duration::from_secs(5)).unwrap());
+}
+
+#[test]
+fn a_strict_server_rejects_unmasked_frames() {
+    let (url, masks) = server(true);
+    let Message::Close(Some(close)) = echo(true, &url) else {
+        panic!("expected the server's Close");
+    };
+    assert_eq!(close.code, CloseFrame::PROTOCOL_ERROR);
+    assert_eq!(close.reason, "unmasked client frame");
+    assert!(!masks.recv_timeout(Duration::from_secs(5)).unwrap());
+}
+
+#[test]
+fn a_lenient_server_echoes_unmasked_frames() {
+    let (url, masks) = server(false);
+    assert_eq!(echo(true, &url), Message::Text("quote".into()));
+    assert!(!masks.recv_timeout(Duration::from_secs(5)).unwrap());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::cell::RefCell;
+use std::net::TcpListener;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{PING, TEXT, block_on};
+use websockets_monoio::{Message, WsClientBuilder, WsEvent, WsReconnectClient};
+
+const STALL: Duration = Duration::from_millis(300);
+
+#[test]
+fn messages_that_outlive_their_ttl_while_reconnecting_are_dropped() {
+    // Upgrades the first connection at once and stalls the upgrade of the
+    // next one; records the frames that arrive on it.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let received = frames.clone();
+    std::thread::spawn(move || {
+        for (conn, socket) in listener.incoming().enumerate() {
+            let mut socket = socket.unwrap();
+            let frames = frames.clone();
+            std::thread::spawn(move || {
+                if conn == 1 {
+                    std::thread::sleep(STALL);
+                }
+                common::accept(&mut socket).unwrap();
+                while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+                    if conn == 1 {
+                        frames.lock().unwrap().push((opcode, payload));
+                    }
+                }
+            });
+        }
+    });
+    let expired = Rc::new(RefCell::new(Vec::new()));
+    let seen = expired.clone();
+    let builder = WsClientBuilder::new()
+        .outbound_ttl(Duration::from_millis(100))
+        .on_event(move |event, _| {
+            if let WsEvent::OutboundExpired { queued_for } = event {
+                seen.borrow_mut().push(*queued_for);
+            }
+        });
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &url).await.unwrap();
+        let short = Some(Duration::from_millis(50));
+        client.enqueue(Message::Text("default ttl".into())).unwrap();
+        client
+            .enqueue_with_ttl(Message::Text("short ttl".into()), short)
+            .unwrap();
+        client
+            .enqueue_with_ttl(
+                Message::Text("long ttl".into()),
+                Some(Duration::from_secs(30)),
+            )
+            .unwrap();
+        client
+            .enqueue_with_ttl(Message::Text("no ttl".into()), None)
+            .unwrap();
+        client
+            .enqueue_with_ttl(Message::Ping(b"exempt".to_vec()), short)
+            .unwrap();
+
+        client.reconnect().await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!((client.expired(), client.queued()), (2, 0));
+        client.client().unwrap().flush().await.unwrap();
+    });
+    let expired = expired.borrow();
+    assert_eq!(expired.len(), 2);
+    assert!(expired.iter().all(|&queued_for| queued_for >= STALL));
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while received.lock().unwrap().len() < 3 && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let received: Vec<_> = received.lock().unwrap().iter().take(3).cloned().collect();
+    assert_eq!(
+        received,
+        [
+            (TEXT, b"long ttl".to_vec()),
+            (TEXT, b"no ttl".to_vec()),
+            (PING, b"exempt".to_vec()),
+        ]
+    );
+}
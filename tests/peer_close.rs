@@ -0,0 +1,86 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use common::policy::Recording;
+use common::{CLOSE, TEXT, block_on};
+use websockets_monoio::{
+    Backoff, CloseFrame, DefaultReconnectPolicy, Jitter, Message, ReconnectDecision,
+    WsClientBuilder, WsError, WsEvent, WsReconnectClient,
+};
+
+const TRY_AGAIN: Duration = Duration::from_millis(200);
+
+#[test]
+fn a_close_mid_burst_reaches_the_writer_and_the_reconnect_backoff() {
+    // The first connection reads a little of the burst, then closes with
+    // 1013 and leaves; the second reports what it receives.
+    let (texts, received) = mpsc::channel();
+    let texts = Mutex::new(texts);
+    let url = common::scripted(move |conn, mut socket| {
+        if conn == 0 {
+            for _ in 0..3 {
+                common::read_frame(&mut socket).unwrap();
+            }
+            let mut close = 1013u16.to_be_bytes().to_vec();
+            close.extend_from_slice(b"overloaded");
+            common::write_frame(&mut socket, CLOSE, &close).unwrap();
+            return;
+        }
+        while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+            if opcode == TEXT {
+                texts.lock().unwrap().send(payload).unwrap();
+            }
+        }
+    });
+    let scheduled = Rc::new(RefCell::new(Vec::new()));
+    let seen = scheduled.clone();
+    let (policy, decisions) = Recording::new(DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: Duration::from_millis(10),
+            ..Backoff::default()
+        },
+        try_again_delay: TRY_AGAIN,
+    });
+    let builder = WsClientBuilder::new()
+        .reconnect_policy(policy)
+        .reconnect_jitter(Jitter::None)
+        .on_event(move |event, _| {
+            if let WsEvent::ReconnectScheduled { delay, wait, .. } = event {
+                seen.borrow_mut().push((*delay, *wait));
+            }
+        });
+    let overloaded = CloseFrame {
+        code: 1013,
+        reason: "overloaded".into(),
+    };
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &url).await.unwrap();
+        let error = loop {
+            if let Err(e) = client.send(Message::Binary(vec![0; 64 * 1024])).await {
+                break e;
+            }
+        };
+        match error {
+            WsError::ClosedByPeer(close) => assert_eq!(close, overloaded),
+            other => panic!("expected ClosedByPeer, got {other:?}"),
+        }
+
+        let started = Instant::now();
+        client.send(Message::Text("after".into())).await.unwrap();
+        assert!(started.elapsed() >= TRY_AGAIN);
+        assert_eq!(client.session(), 1);
+    });
+    let decisions = decisions.borrow();
+    let [decided] = &decisions[..] else {
+        panic!("expected one decision, got {decisions:?}");
+    };
+    assert_eq!(decided.disconnect.close.as_ref(), Some(&overloaded));
+    assert_eq!(decided.disconnect.code(), 1013);
+    assert_eq!(decided.decision, ReconnectDecision::RetryAfter(TRY_AGAIN));
+    assert_eq!(scheduled.borrow()[..], [(TRY_AGAIN, TRY_AGAIN)]);
+    assert_eq!(received.recv().unwrap(), b"after");
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::sync::{Mutex, mpsc};
+
+use common::{PING, PONG, TEXT, block_on};
+use websockets_monoio::{Message, WsClientBuilder, WsError};
+
+/// The reply a venue expects: the nonce reversed, behind a tag.
+fn sign(nonce: &[u8]) -> Vec<u8> {
+    let mut reply = b"ack:".to_vec();
+    reply.extend(nonce.iter().rev());
+    reply
+}
+
+#[test]
+fn pings_are_answered_with_the_transformed_payload() {
+    // Pings a nonce, then one the client declines to answer, then asks for
+    // a Text; reports every frame the client sends back.
+    let (frames, received) = mpsc::channel();
+    let frames = Mutex::new(frames);
+    let url = common::scripted(move |_, mut socket| {
+        common::write_frame(&mut socket, PING, b"nonce-1").unwrap();
+        common::write_frame(&mut socket, PING, b"skip").unwrap();
+        common::write_frame(&mut socket, TEXT, b"reply").unwrap();
+        for _ in 0..2 {
+            let frame = common::read_frame(&mut socket).unwrap();
+            frames.lock().unwrap().send(frame).unwrap();
+        }
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .on_ping_reply(|nonce, _| (nonce != b"skip").then(|| sign(nonce)))
+            .connect(&url)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Ping(b"nonce-1".to_vec())
+        );
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Ping(b"skip".to_vec())
+        );
+        assert_eq!(client.recv().await.unwrap(), Message::Text("reply".into()));
+        client.send(Message::Text("done".into())).await.unwrap();
+        client.flush().await.unwrap();
+    });
+    assert_eq!(received.recv().unwrap(), (PONG, b"ack:1-ecnon".to_vec()));
+    // Nothing answers the declined Ping.
+    assert_eq!(received.recv().unwrap(), (TEXT, b"done".to_vec()));
+}
+
+#[test]
+fn a_reply_over_the_control_frame_limit_fails_the_receive() {
+    let url = common::scripted(|_, mut socket| {
+        common::write_frame(&mut socket, PING, b"nonce").unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .on_ping_reply(|_, _| Some(vec![0; 126]))
+            .connect(&url)
+            .await
+            .unwrap();
+        let result = client.recv().await;
+        assert!(
+            matches!(result, Err(WsError::PingReplyTooLarge { .. })),
+            "{result:?}"
+        );
+    });
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::proxy::MockProxy;
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::http_upgrade::UpgradeErr;
+use websockets_monoio::policy::{AddressRange, DenyPrivateNetworks};
+use websockets_monoio::proxy::Proxy;
+use websockets_monoio::{WsClientBuilder, WsError};
+
+fn deny_private() -> WsClientBuilder {
+    WsClientBuilder::new().endpoint_policy(DenyPrivateNetworks::new())
+}
+
+fn denied(result: Result<websockets_monoio::WsClient, WsError>) -> String {
+    match result {
+        Err(WsError::Policy(e)) => e.reason,
+        other => panic!("expected a policy error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn metadata_service_addresses_are_denied() {
+    let proxy = MockProxy::start();
+    block_on(async {
+        for url in ["ws://169.254.169.254/latest", "wss://[fe80::1]/"] {
+            let reason = denied(deny_private().connect(url).await);
+            assert!(reason.contains("link-local"), "{reason}");
+            let through_proxy = deny_private().proxy(Proxy::parse(&proxy.url).unwrap());
+            denied(through_proxy.connect(url).await);
+        }
+    });
+    assert!(proxy.requests().is_empty());
+}
+
+#[test]
+fn names_resolving_to_private_addresses_are_denied() {
+    let server = MockServer::start(Mode::Record);
+    let proxy = MockProxy::start();
+    let url = format!("ws://localhost:{}/", server.addr.port());
+    block_on(async {
+        let reason = denied(deny_private().connect(&url).await);
+        assert!(reason.contains("loopback"), "{reason}");
+        // The proxy would resolve the name itself; the client resolves and
+        // vets it first, and never reaches the proxy.
+        let through_proxy = deny_private().proxy(Proxy::parse(&proxy.url).unwrap());
+        denied(through_proxy.connect(&url).await);
+    });
+    assert_eq!(server.accepted(), 0);
+    assert!(proxy.requests().is_empty());
+}
+
+#[test]
+fn a_proxy_is_asked_for_the_vetted_address() {
+    let server = MockServer::start(Mode::Record);
+    let proxy = MockProxy::start();
+    let url = format!("ws://localhost:{}/", server.addr.port());
+    block_on(async {
+        let builder = WsClientBuilder::new()
+            .endpoint_policy(DenyPrivateNetworks::new().allow(AddressRange::Loopback))
+            .proxy(Proxy::parse(&proxy.url).unwrap());
+        builder.connect(&url).await.unwrap();
+    });
+    assert_eq!(server.accepted(), 1);
+    let requests = proxy.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(
+        requests[0] == format!("127.0.0.1:{}", server.addr.port())
+            || requests[0] == format!("[::1]:{}", server.addr.port()),
+        "{requests:?}"
+    );
+}
+
+#[test]
+fn redirects_to_private_addresses_are_not_followed() {
+    // Answers every upgrade with a redirect to the metadata service.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let redirected = Arc::new(Mutex::new(0));
+    let count = redirected.clone();
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0u8];
+            while !head.ends_with(b"\r\n\r\n") && socket.read_exact(&mut byte).is_ok() {
+                head.push(byte[0]);
+            }
+            *count.lock().unwrap() += 1;
+            let _ = socket.write_all(
+                b"HTTP/1.1 302 Found\r\nLocation: ws://169.254.169.254/\r\n\
+                  Content-Length: 0\r\n\r\n",
+            );
+        }
+    });
+    block_on(async {
+        let builder = WsClientBuilder::new()
+            .endpoint_policy(DenyPrivateNetworks::new().allow(AddressRange::Loopback));
+        let result = monoio::time::timeout(Duration::from_secs(5), builder.connect(&url))
+            .await
+            .unwrap();
+        assert!(
+            matches!(result, Err(WsError::Upgrade(UpgradeErr::Status(302)))),
+            "{:?}",
+            result.map(|_| ())
+        );
+    });
+    assert_eq!(*redirected.lock().unwrap(), 1);
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::pool::EvictionReason;
+use websockets_monoio::{Message, PoolConfig, WsClientBuilder, WsError, WsEvent, WsPool};
+
+fn recording_builder() -> (WsClientBuilder, Rc<RefCell<Vec<WsEvent>>>) {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    let builder = WsClientBuilder::new().on_event(move |event, _| {
+        if matches!(
+            event,
+            WsEvent::PoolCheckout { .. } | WsEvent::PoolEviction { .. }
+        ) {
+            seen.borrow_mut().push(event.clone());
+        }
+    });
+    (builder, events)
+}
+
+#[test]
+fn returned_connections_are_reused() {
+    let server = MockServer::start(Mode::Record);
+    let (builder, events) = recording_builder();
+    block_on(async {
+        let pool = WsPool::connect(builder, &server.url(), PoolConfig::default())
+            .await
+            .unwrap();
+        drop(pool.checkout().await.unwrap());
+        drop(pool.checkout().await.unwrap());
+        let stats = pool.stats();
+        assert_eq!(
+            (stats.misses, stats.hits, stats.open, stats.idle),
+            (1, 1, 1, 1)
+        );
+    });
+    assert_eq!(server.accepted(), 1);
+    assert!(matches!(
+        events.borrow()[..],
+        [
+            WsEvent::PoolCheckout { reused: false },
+            WsEvent::PoolCheckout { reused: true },
+        ]
+    ));
+}
+
+#[test]
+fn pooled_connections_report_their_average_frame_size() {
+    let server = MockServer::start(Mode::Record);
+    let config = PoolConfig {
+        health_check_after: Duration::ZERO,
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(WsClientBuilder::new(), &server.url(), config)
+            .await
+            .unwrap();
+        let mut client = pool.checkout().await.unwrap();
+        for i in 0..100u8 {
+            client.send(Message::Binary(vec![i; 100])).await.unwrap();
+        }
+        client.flush().await.unwrap();
+        let snapshot = client.stats_snapshot();
+        assert_eq!((snapshot.frames_sent, snapshot.bytes_sent), (100, 10_000));
+        assert!((snapshot.average_frame_size - 100.0).abs() < f64::EPSILON);
+        drop(client);
+
+        // The health check's empty Ping and Pong count as frames too.
+        let client = pool.checkout().await.unwrap();
+        let snapshot = client.stats_snapshot();
+        assert_eq!((snapshot.frames_sent, snapshot.frames_received), (101, 1));
+        assert!((snapshot.average_frame_size - 10_000.0 / 102.0).abs() < 1e-9);
+    });
+    let received = server.received();
+    let data: Vec<_> = received
+        .iter()
+        .filter(|r| r.opcode == common::BINARY)
+        .collect();
+    assert_eq!(data.len(), 100);
+    assert!(data.iter().all(|r| r.payload.len() == 100));
+}
+
+#[test]
+fn idle_connections_past_the_timeout_are_evicted() {
+    let server = MockServer::start(Mode::Record);
+    let (builder, events) = recording_builder();
+    let config = PoolConfig {
+        idle_timeout: Duration::from_millis(50),
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(builder, &server.url(), config)
+            .await
+            .unwrap();
+        drop(pool.checkout().await.unwrap());
+        monoio::time::sleep(Duration::from_millis(100)).await;
+        let _client = pool.checkout().await.unwrap();
+        let stats = pool.stats();
+        assert_eq!(
+            (stats.misses, stats.hits, stats.evictions, stats.open),
+            (2, 0, 1, 1)
+        );
+    });
+    assert_eq!(server.accepted(), 2);
+    assert!(events.borrow().iter().any(|event| matches!(
+        event,
+        WsEvent::PoolEviction {
+            reason: EvictionReason::IdleTimeout
+        }
+    )));
+}
+
+#[test]
+fn a_connection_broken_while_idle_is_replaced_at_checkout() {
+    let server = MockServer::start(Mode::Record);
+    let (builder, events) = recording_builder();
+    let config = PoolConfig {
+        health_check_after: Duration::ZERO,
+        health_check_timeout: Duration::from_secs(2),
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(builder, &server.url(), config)
+            .await
+            .unwrap();
+        drop(pool.checkout().await.unwrap());
+        server.kill_connections();
+        monoio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut client = pool.checkout().await.unwrap();
+        assert!(client.is_usable());
+        client
+            .send(websockets_monoio::Message::Text("after".into()))
+            .await
+            .unwrap();
+        client.flush().await.unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.misses, stats.evictions, stats.open), (2, 1, 1));
+    });
+    assert_eq!(server.accepted(), 2);
+    assert!(server.wait_for(Duration::from_secs(2), |frames| {
+        frames.iter().any(|f| f.conn == 1 && f.text() == "after")
+    }));
+    assert!(events.borrow().iter().any(|event| matches!(
+        event,
+        WsEvent::PoolEviction {
+            reason: EvictionReason::HealthCheckFailed
+        }
+    )));
+}
+
+#[test]
+fn a_checkout_cancelled_during_the_health_check_frees_its_slot() {
+    let server = MockServer::start(Mode::Silent);
+    let config = PoolConfig {
+        max_connections: 1,
+        health_check_after: Duration::ZERO,
+        health_check_timeout: Duration::from_secs(60),
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(WsClientBuilder::new(), &server.url(), config)
+            .await
+            .unwrap();
+        drop(pool.checkout().await.unwrap());
+        // The silent server never answers the checkout ping.
+        let cancelled = monoio::time::timeout(Duration::from_millis(50), pool.checkout()).await;
+        assert!(cancelled.is_err());
+        assert_eq!((pool.stats().open, pool.stats().idle), (0, 0));
+        let _client = pool.checkout().await.unwrap();
+        assert_eq!(pool.stats().open, 1);
+    });
+}
+
+#[test]
+fn a_checkout_cancelled_while_dialing_frees_its_slot() {
+    // Accepts TCP connections (the kernel completes them) but never answers
+    // the upgrade.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let config = PoolConfig {
+        max_connections: 1,
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(WsClientBuilder::new(), &url, config)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            let cancelled = monoio::time::timeout(Duration::from_millis(50), pool.checkout()).await;
+            assert!(cancelled.is_err());
+            assert_eq!(pool.stats().open, 0);
+        }
+    });
+    drop(listener);
+}
+
+#[test]
+fn checkouts_beyond_the_limit_fail() {
+    let server = MockServer::start(Mode::Record);
+    let config = PoolConfig {
+        max_connections: 1,
+        ..PoolConfig::default()
+    };
+    block_on(async {
+        let pool = WsPool::connect(WsClientBuilder::new(), &server.url(), config)
+            .await
+            .unwrap();
+        let held = pool.checkout().await.unwrap();
+        assert!(matches!(
+            pool.checkout().await,
+            Err(WsError::PoolExhausted { max: 1 })
+        ));
+        drop(held);
+        assert!(pool.checkout().await.is_ok());
+    });
+}
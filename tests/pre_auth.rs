@@ -0,0 +1,127 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use common::{TEXT, block_on};
+use websockets_monoio::http_upgrade::{SimpleRequest, UpgradeErr};
+use websockets_monoio::{Message, WsClientBuilder, WsError};
+
+const TOKEN: &str = "t-5f2a";
+
+/// What the venue saw on one connection: each request line, in order.
+type Seen = Arc<Mutex<Vec<Vec<String>>>>;
+
+/// A venue that only upgrades a connection that first POSTed a signed body
+/// to `/auth`, and then presents the token it got back.
+fn venue() -> (String, Seen) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/stream", listener.local_addr().unwrap());
+    let seen = Seen::default();
+    let log = seen.clone();
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            let log = log.clone();
+            std::thread::spawn(move || serve(socket.unwrap(), &log));
+        }
+    });
+    (url, seen)
+}
+
+fn serve(mut socket: TcpStream, log: &Mutex<Vec<Vec<String>>>) {
+    let conn = {
+        let mut log = log.lock().unwrap();
+        log.push(Vec::new());
+        log.len() - 1
+    };
+    let mut authorized = false;
+    while let Ok(request) = common::read_request(&mut socket) {
+        let line = request.lines().next().unwrap_or_default().to_owned();
+        log.lock().unwrap()[conn].push(line.clone());
+        if line.starts_with("POST /auth ") {
+            let length =
+                common::header(&request, "content-length").map_or(0, |n| n.parse().unwrap());
+            let mut body = vec![0; length];
+            socket.read_exact(&mut body).unwrap();
+            let (status, reply) = if body == br#"{"key":"k1","sig":"good"}"# {
+                authorized = true;
+                ("200 OK", TOKEN)
+            } else {
+                ("403 Forbidden", "bad signature")
+            };
+            write!(
+                socket,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n{reply}",
+                reply.len()
+            )
+            .unwrap();
+        } else if authorized && common::header(&request, "x-auth-token") == Some(TOKEN) {
+            common::answer(&mut socket, &request).unwrap();
+            while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+                if opcode == TEXT {
+                    common::write_frame(&mut socket, TEXT, &payload).unwrap();
+                }
+            }
+            return;
+        } else {
+            let _ = socket.write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n");
+            return;
+        }
+    }
+}
+
+/// A builder that authenticates with `sig` before the upgrade.
+fn signing(sig: &'static str) -> WsClientBuilder {
+    WsClientBuilder::new().pre_upgrade(move |conn| async move {
+        let auth = SimpleRequest::new("POST", "/auth")
+            .header("Content-Type", "application/json")
+            .body(format!(r#"{{"key":"k1","sig":"{sig}"}}"#));
+        let response = conn.exchange(&auth).await?;
+        if response.status != 200 {
+            return Err(UpgradeErr::Status(response.status).into());
+        }
+        let token = String::from_utf8(response.body).unwrap();
+        Ok(vec![("X-Auth-Token".to_owned(), token)])
+    })
+}
+
+#[test]
+fn auth_and_upgrade_share_one_connection() {
+    let (url, seen) = venue();
+    block_on(async {
+        let mut client = signing("good").connect(&url).await.unwrap();
+        client.send(Message::Text("hello".into())).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+    });
+    let seen = seen.lock().unwrap();
+    assert_eq!(*seen, [["POST /auth HTTP/1.1", "GET /stream HTTP/1.1"]]);
+}
+
+#[test]
+fn a_rejected_signature_fails_the_connect_before_the_upgrade() {
+    let (url, seen) = venue();
+    block_on(async {
+        let result = signing("forged").connect(&url).await;
+        assert!(
+            matches!(result, Err(WsError::Upgrade(UpgradeErr::Status(403)))),
+            "{:?}",
+            result.map(|_| ())
+        );
+    });
+    assert_eq!(*seen.lock().unwrap(), [["POST /auth HTTP/1.1"]]);
+}
+
+#[test]
+fn an_upgrade_without_auth_is_refused() {
+    let (url, seen) = venue();
+    block_on(async {
+        let result = WsClientBuilder::new().connect(&url).await;
+        assert!(
+            matches!(result, Err(WsError::Upgrade(UpgradeErr::Status(401)))),
+            "{:?}",
+            result.map(|_| ())
+        );
+    });
+    assert_eq!(*seen.lock().unwrap(), [["GET /stream HTTP/1.1"]]);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::io::Write;
+use std::net::TcpListener;
+
+use common::proxy::MockProxy;
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::proxy::Proxy;
+use websockets_monoio::{ProbeFailure, WsClient, WsClientBuilder};
+
+/// A server that answers every upgrade request with `response`.
+fn answering(response: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            if common::read_request(&mut socket).is_ok() {
+                let _ = socket.write_all(response.as_bytes());
+            }
+        }
+    });
+    url
+}
+
+fn failure(url: &str, opts: &WsClientBuilder) -> ProbeFailure {
+    match block_on(WsClient::probe(url, opts)) {
+        Ok(report) => panic!("probe of {url} succeeded: {report:?}"),
+        Err(e) => e.kind,
+    }
+}
+
+#[test]
+fn a_reachable_server_is_probed_and_closed() {
+    let server = MockServer::start(Mode::Record);
+    let opts = WsClientBuilder::new().header("Authorization", "Bearer t");
+    let report = block_on(WsClient::probe(&server.url(), &opts)).unwrap();
+    assert_eq!(report.info.url(), server.url());
+    // The server echoes the probe's Close.
+    assert_eq!(report.close.map(|close| close.code), Some(1000));
+    let request = &server.requests()[0];
+    assert_eq!(common::header(request, "authorization"), Some("Bearer t"));
+    assert!(server.received().is_empty());
+}
+
+#[test]
+fn an_unresolvable_name_is_a_dns_failure() {
+    let kind = failure("ws://probe.invalid/", &WsClientBuilder::new());
+    assert_eq!(kind, ProbeFailure::Dns);
+    assert_eq!(kind.as_str(), "dns");
+}
+
+#[test]
+fn a_refused_connection_is_a_tcp_failure() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    drop(listener);
+    assert_eq!(failure(&url, &WsClientBuilder::new()), ProbeFailure::Tcp);
+}
+
+#[test]
+fn an_untrusted_certificate_is_a_tls_failure() {
+    let identity = Identity::new(&["127.0.0.1"]);
+    let server = MockServer::start_tls(Mode::Record, &identity);
+    let url = format!("wss://{}/", server.addr);
+    let stranger = Identity::new(&["127.0.0.1"]);
+    let opts = WsClientBuilder::new().tls_connector(connector(&[&stranger]));
+    assert_eq!(failure(&url, &opts), ProbeFailure::Tls);
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn upgrade_answers_are_classified_by_status() {
+    let opts = WsClientBuilder::new();
+    let forbidden = answering("HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+    let unauthorized = answering("HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n");
+    let unavailable = answering("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+    let garbled = answering("SSH-2.0-OpenSSH_9.6\r\n\r\n");
+    assert_eq!(failure(&forbidden, &opts), ProbeFailure::Auth(403));
+    assert_eq!(failure(&unauthorized, &opts), ProbeFailure::Auth(401));
+    let kind = failure(&unavailable, &opts);
+    assert_eq!(kind, ProbeFailure::Status(503));
+    assert_eq!(kind.to_string(), "status (503)");
+    assert_eq!(failure(&garbled, &opts), ProbeFailure::Protocol);
+}
+
+#[test]
+fn a_refusing_proxy_is_a_proxy_failure() {
+    // The proxy's dial of the closed port fails, and it answers `502`.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    drop(listener);
+    let proxy = MockProxy::start();
+    let opts = WsClientBuilder::new().proxy(Proxy::parse(&proxy.url).unwrap());
+    assert_eq!(failure(&url, &opts), ProbeFailure::Proxy);
+    assert_eq!(proxy.requests().len(), 1);
+}
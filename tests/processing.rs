@@ -0,0 +1,86 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use common::{TEXT, block_on};
+use websockets_monoio::fairness::ProcessingBudget;
+use websockets_monoio::{Message, WsClientBuilder};
+
+const CONTINUATION: u8 = 0x0;
+const FIN: u8 = 0x80;
+
+const TICK: Duration = Duration::from_millis(5);
+
+/// How late a tick may fire on a loaded machine. Yielding to other tasks
+/// alone leaves the timer waiting about 100 ms, until the whole message is
+/// validated.
+const LATE: Duration = Duration::from_millis(40);
+
+#[test]
+fn a_timer_keeps_its_schedule_while_a_large_text_message_is_validated() {
+    // 4 MiB of two-byte characters in 1 MiB fragments, sent once the client
+    // is waiting. fastwebsockets validates a single-frame Text message as it
+    // reads it; fragments are left to the client.
+    let text = "é".repeat(2 * 1024 * 1024);
+    let sent = text.clone();
+    let url = common::scripted(move |_, mut socket| {
+        let mut frames = Vec::new();
+        for (i, fragment) in sent.as_bytes().chunks(1024 * 1024).enumerate() {
+            let start = frames.len();
+            let opcode = if i == 0 { TEXT } else { CONTINUATION };
+            common::write_frame(&mut frames, opcode, fragment).unwrap();
+            frames[start] &= !FIN;
+        }
+        common::write_frame(&mut frames, CONTINUATION, &[]).unwrap();
+        sleep(Duration::from_millis(50));
+        socket.write_all(&frames).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            // Tiny chunks stretch the validation over many yields.
+            .processing_budget(ProcessingBudget {
+                threshold: 1024 * 1024,
+                chunk: 64,
+                drive_timers: true,
+            })
+            .connect(&url)
+            .await
+            .unwrap();
+
+        let done = Rc::new(Cell::new(false));
+        let lateness = Rc::new(RefCell::new(Vec::new()));
+        let timer = monoio::spawn({
+            let done = done.clone();
+            let lateness = lateness.clone();
+            async move {
+                while !done.get() {
+                    let due = Instant::now() + TICK;
+                    monoio::time::sleep(TICK).await;
+                    lateness
+                        .borrow_mut()
+                        .push(Instant::now().saturating_duration_since(due));
+                }
+            }
+        });
+
+        let received = client.recv().await.unwrap();
+        done.set(true);
+        timer.await;
+        assert!(
+            received == Message::Text(text),
+            "the message arrived changed"
+        );
+
+        let lateness = lateness.borrow();
+        // The timer ran through the wait for the message and its validation.
+        assert!(lateness.len() >= 10, "only {} ticks", lateness.len());
+        let worst = lateness.iter().max().unwrap();
+        assert!(*worst <= LATE, "a tick fired {worst:?} late");
+        assert!(client.stats().utf8_validation > Duration::ZERO);
+    });
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::{
+    Backoff, Message, ReliableSend, WsClientBuilder, WsError, WsEvent, WsReconnectClient,
+};
+
+fn fast_reconnects() -> WsClientBuilder {
+    WsClientBuilder::new().reconnect_backoff(Backoff {
+        initial: Duration::from_millis(10),
+        multiplier: 1.0,
+        max_interval: Duration::from_millis(10),
+        max_attempts: Some(20),
+    })
+}
+
+#[test]
+fn replays_once_on_the_new_session_after_the_server_dies() {
+    let server = MockServer::start(Mode::Record);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    let builder = fast_reconnects().on_event(move |event, _| {
+        if let WsEvent::ReliableSend {
+            dedupe_key,
+            session,
+            attempt,
+        } = event
+        {
+            seen.borrow_mut()
+                .push((dedupe_key.clone(), *session, *attempt));
+        }
+    });
+
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &server.url())
+            .await
+            .unwrap();
+        let send = ReliableSend::new(Message::Text("subscribe:btc-usd".into()))
+            .dedupe_key("sub-1")
+            .deadline(Duration::from_secs(5));
+
+        server.kill_connections();
+        monoio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = Instant::now();
+        client.send_reliable(send).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(client.session(), 1);
+    });
+
+    assert!(server.wait_for(Duration::from_secs(2), |frames| !frames.is_empty()));
+    std::thread::sleep(Duration::from_millis(50));
+    let received = server.received();
+    assert_eq!(received.len(), 1, "{received:?}");
+    assert_eq!(
+        (received[0].conn, received[0].text()),
+        (1, "subscribe:btc-usd")
+    );
+    assert_eq!(
+        *events.borrow(),
+        [
+            (Some("sub-1".to_owned()), 0, 1),
+            (Some("sub-1".to_owned()), 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn a_timed_out_send_drops_the_session() {
+    let server = MockServer::start(Mode::Silent);
+    block_on(async {
+        let mut client = WsReconnectClient::connect(fast_reconnects(), &server.url())
+            .await
+            .unwrap();
+        // Far more than the socket buffers take from a peer that never
+        // reads, so the write is cut off partway through the frame.
+        let send = ReliableSend::new(Message::Binary(vec![0; 32 << 20]))
+            .deadline(Duration::from_millis(200));
+        let result = client.send_reliable(send).await;
+        assert!(matches!(result, Err(WsError::Timeout(_))), "{result:?}");
+        assert!(client.client().is_none());
+    });
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{CLOSE, TEXT, block_on};
+use websockets_monoio::{Message, ReplaceOptions, WsClientBuilder, WsReconnectClient};
+
+/// A feed every subscribed connection gets: `seq:N` every 2 ms, the same
+/// N on all of them. A connection subscribes by sending any Text frame and
+/// is answered with `snapshot:N` first. Returns the URL and the count of
+/// Closes the server received.
+fn start_feed() -> (String, Arc<AtomicU64>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let subscribers: Arc<Mutex<Vec<TcpStream>>> = Arc::default();
+    let seq = Arc::new(AtomicU64::new(0));
+    let closes = Arc::new(AtomicU64::new(0));
+
+    std::thread::spawn({
+        let (subscribers, seq) = (subscribers.clone(), seq.clone());
+        move || {
+            loop {
+                std::thread::sleep(Duration::from_millis(2));
+                // Sent under the lock, so a new subscriber's snapshot is
+                // never overtaken by an update it missed.
+                let mut subscribers = subscribers.lock().unwrap();
+                let n = seq.fetch_add(1, Ordering::SeqCst) + 1;
+                let update = format!("seq:{n}");
+                subscribers.retain_mut(|s| common::write_frame(s, TEXT, update.as_bytes()).is_ok());
+            }
+        }
+    });
+
+    let feed = (subscribers, seq, closes.clone());
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let (subscribers, seq, closes) = (feed.0.clone(), feed.1.clone(), feed.2.clone());
+            std::thread::spawn(move || {
+                common::accept(&mut socket).unwrap();
+                while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+                    match opcode {
+                        TEXT => {
+                            let mut subscribers = subscribers.lock().unwrap();
+                            let snapshot = format!("snapshot:{}", seq.load(Ordering::SeqCst));
+                            common::write_frame(&mut socket, TEXT, snapshot.as_bytes()).unwrap();
+                            subscribers.push(socket.try_clone().unwrap());
+                        }
+                        CLOSE => {
+                            closes.fetch_add(1, Ordering::SeqCst);
+                            let _subscribers = subscribers.lock().unwrap();
+                            let _ = common::write_frame(&mut socket, CLOSE, &payload);
+                            let _ = socket.shutdown(std::net::Shutdown::Both);
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+    });
+    (url, closes)
+}
+
+/// What the application does with tagged messages: deliver each update
+/// once, dropping the copy from the other session.
+#[derive(Default)]
+struct Seen {
+    delivered: BTreeSet<u64>,
+    duplicates: usize,
+    sessions: BTreeSet<u64>,
+}
+
+impl Seen {
+    fn take(&mut self, session: u64, message: &Message) {
+        self.sessions.insert(session);
+        if let Some(n) = seq(message)
+            && !self.delivered.insert(n)
+        {
+            self.duplicates += 1;
+        }
+    }
+}
+
+fn seq(message: &Message) -> Option<u64> {
+    match message {
+        Message::Text(text) => text.strip_prefix("seq:")?.parse().ok(),
+        _ => None,
+    }
+}
+
+#[test]
+fn replace_overlaps_sessions_without_a_gap() {
+    let (url, closes) = start_feed();
+    block_on(async {
+        let mut client = WsReconnectClient::connect(WsClientBuilder::new(), &url)
+            .await
+            .unwrap();
+        client.resume_with(|_| None::<()>, |_| vec![Message::Text("subscribe".into())]);
+        client
+            .send(Message::Text("subscribe".into()))
+            .await
+            .unwrap();
+
+        let mut seen = Seen::default();
+        while seen.delivered.len() < 20 {
+            let (session, message) = client.recv_tagged().await.unwrap();
+            seen.take(session, &message);
+        }
+        // Ready once the new session has its snapshot and five updates, so
+        // both sessions deliver those five.
+        let warmed = Cell::new(0);
+        client.replace(ReplaceOptions::new().ready_when(move |message| {
+            if seq(message).is_some() {
+                warmed.set(warmed.get() + 1);
+            }
+            warmed.get() == 5
+        }));
+        let mut after_swap = 0;
+        while client.is_replacing() || after_swap < 20 {
+            let (session, message) = client.recv_tagged().await.unwrap();
+            if !client.is_replacing() && session == client.session() {
+                after_swap += 1;
+            }
+            seen.take(session, &message);
+        }
+
+        assert_eq!(client.session(), 1);
+        assert_eq!(seen.sessions, BTreeSet::from([0, 1]));
+        // Both sessions carried the updates of the overlap, and together
+        // they missed none.
+        assert!(seen.duplicates > 0, "the sessions did not overlap");
+        let delivered = &seen.delivered;
+        let (first, last) = (*delivered.first().unwrap(), *delivered.last().unwrap());
+        assert_eq!(delivered.len() as u64, last - first + 1, "{delivered:?}");
+    });
+    // The old session was closed once the new one took over.
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    while closes.load(Ordering::SeqCst) == 0 && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(closes.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use common::{CLOSE, PONG, TEXT, block_on};
+use websockets_monoio::{Message, WsClientBuilder, WsError, WsEvent};
+
+/// A server that sends an unsolicited Pong, a frame with reserved opcode
+/// `0x3` and a Text, then reports the first frame the client sends back.
+fn maintenance_server() -> (String, Receiver<(u8, Vec<u8>)>) {
+    let (tx, rx) = mpsc::channel();
+    let url = common::scripted(move |_, mut socket| {
+        common::write_frame(&mut socket, PONG, b"server keepalive").unwrap();
+        common::write_frame(&mut socket, 0x3, b"maintenance").unwrap();
+        common::write_frame(&mut socket, TEXT, b"after").unwrap();
+        if let Ok(frame) = common::read_frame(&mut socket) {
+            let _ = tx.send(frame);
+        }
+    });
+    (url, rx)
+}
+
+/// What a [`recording`] builder's hooks saw.
+#[derive(Default)]
+struct Seen {
+    pongs: Vec<Vec<u8>>,
+    events: Vec<WsEvent>,
+}
+
+/// A builder recording the Pongs its hook sees and the events it emits.
+fn recording() -> (WsClientBuilder, Rc<RefCell<Seen>>) {
+    let seen = Rc::new(RefCell::new(Seen::default()));
+    let (pongs, events) = (seen.clone(), seen.clone());
+    let builder = WsClientBuilder::new()
+        .on_pong(move |payload, _| pongs.borrow_mut().pongs.push(payload.to_vec()))
+        .on_event(move |event, _| events.borrow_mut().events.push(event.clone()));
+    (builder, seen)
+}
+
+#[test]
+fn a_reserved_opcode_fails_the_connection_with_1002_by_default() {
+    let (url, frames) = maintenance_server();
+    let (builder, seen) = recording();
+    block_on(async {
+        let mut client = builder.connect(&url).await.unwrap();
+        assert!(client.stats().last_received.is_none());
+        let pong = client.recv().await.unwrap();
+        assert_eq!(pong, Message::Pong(b"server keepalive".to_vec()));
+        assert!(client.stats().last_received.is_some());
+        let result = client.recv().await;
+        assert!(
+            matches!(result, Err(WsError::ReservedOpcode(0x3))),
+            "{result:?}"
+        );
+        assert_eq!(client.reserved_frames_skipped(), 0);
+    });
+    let (opcode, payload) = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload[..2], 1002u16.to_be_bytes());
+    assert_eq!(&payload[2..], b"reserved opcode");
+    assert_eq!(seen.borrow().pongs, [b"server keepalive".to_vec()]);
+    assert!(
+        !seen
+            .borrow()
+            .events
+            .iter()
+            .any(|event| matches!(event, WsEvent::ReservedOpcodeSkipped { .. }))
+    );
+}
+
+#[test]
+fn a_lenient_client_skips_reserved_opcodes_and_counts_them() {
+    let (url, frames) = maintenance_server();
+    let (builder, seen) = recording();
+    block_on(async {
+        let mut client = builder
+            .skip_reserved_opcodes(true)
+            .connect(&url)
+            .await
+            .unwrap();
+        let pong = client.recv().await.unwrap();
+        assert_eq!(pong, Message::Pong(b"server keepalive".to_vec()));
+        assert_eq!(client.recv().await.unwrap(), Message::Text("after".into()));
+        assert_eq!(client.reserved_frames_skipped(), 1);
+        client
+            .send(Message::Text("still open".into()))
+            .await
+            .unwrap();
+        client.flush().await.unwrap();
+    });
+    let frame = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(frame, (TEXT, b"still open".to_vec()));
+    assert_eq!(seen.borrow().pongs, [b"server keepalive".to_vec()]);
+    let skipped: Vec<_> = seen
+        .borrow()
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            WsEvent::ReservedOpcodeSkipped { opcode } => Some(*opcode),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(skipped, [0x3]);
+}
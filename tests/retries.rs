@@ -0,0 +1,75 @@
+mod common;
+
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use common::block_on;
+use websockets_monoio::http_upgrade::UpgradeErr;
+use websockets_monoio::{Message, WsClient, WsError};
+
+/// A server that closes its first `failures` connections right after
+/// accepting them, then echoes Text frames.
+fn flaky(failures: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for (conn, socket) in listener.incoming().enumerate() {
+            let mut socket = socket.unwrap();
+            if conn < failures {
+                continue;
+            }
+            std::thread::spawn(move || {
+                common::accept(&mut socket).unwrap();
+                while let Ok((opcode, payload)) = common::read_frame(&mut socket) {
+                    if opcode != common::TEXT {
+                        return;
+                    }
+                    common::write_frame(&mut socket, opcode, &payload).unwrap();
+                }
+            });
+        }
+    });
+    url
+}
+
+/// A connection closed before the upgrade response.
+fn dropped(e: &WsError) -> bool {
+    matches!(
+        e,
+        WsError::Upgrade(UpgradeErr::Eof | UpgradeErr::Io(_)) | WsError::Io(_)
+    )
+}
+
+#[test]
+fn the_third_attempt_connects_after_two_dropped_connections() {
+    let url = flaky(2);
+    let backoff = Duration::from_millis(20);
+    block_on(async {
+        let started = Instant::now();
+        let mut client = WsClient::connect_with_retries(&url, &[], 3, backoff)
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= backoff * 2);
+        client.send(Message::Text("hello".into())).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+    });
+}
+
+#[test]
+fn every_attempt_error_is_reported() {
+    let url = flaky(usize::MAX);
+    block_on(async {
+        let result = WsClient::connect_with_retries(&url, &[], 3, Duration::ZERO).await;
+        let Err(WsError::MaxRetriesExceeded {
+            attempts,
+            earlier_errors,
+            last_error,
+        }) = result
+        else {
+            panic!("expected MaxRetriesExceeded, got {:?}", result.map(|_| ()));
+        };
+        assert_eq!((attempts, earlier_errors.len()), (3, 2));
+        assert!(earlier_errors.iter().all(dropped), "{earlier_errors:?}");
+        assert!(dropped(&last_error), "{last_error:?}");
+    });
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::sync::{Mutex, mpsc};
+
+use common::block_on;
+use fastwebsockets::Role;
+use websockets_monoio::{Message, WsClientBuilder};
+
+/// Read one frame of up to 125 bytes, returning whether it was masked and
+/// its unmasked payload.
+fn read_small_frame(socket: &mut impl Read) -> (bool, Vec<u8>) {
+    let mut head = [0u8; 2];
+    socket.read_exact(&mut head).unwrap();
+    let masked = head[1] & 0x80 != 0;
+    let mut mask = [0u8; 4];
+    if masked {
+        socket.read_exact(&mut mask).unwrap();
+    }
+    let mut payload = vec![0u8; (head[1] & 0x7F) as usize];
+    socket.read_exact(&mut payload).unwrap();
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    (masked, payload)
+}
+
+#[test]
+fn set_role_flips_the_mask_bit_of_the_next_frame() {
+    // Reports each of three frames; after the second, sends a masked frame
+    // as a client would.
+    let (frames, received) = mpsc::channel();
+    let frames = Mutex::new(frames);
+    let url = common::scripted(move |_, mut socket| {
+        for i in 0..3 {
+            let frame = read_small_frame(&mut socket);
+            frames.lock().unwrap().send(frame).unwrap();
+            if i == 1 {
+                let mask = [0x12, 0x34, 0x56, 0x78];
+                let mut frame = vec![0x81, 0x80 | 6];
+                frame.extend(mask);
+                frame.extend(b"masked".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+                socket.write_all(&frame).unwrap();
+            }
+        }
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new().connect(&url).await.unwrap();
+        client.send(Message::Text("client".into())).await.unwrap();
+        client.flush().await.unwrap();
+
+        client.set_role(Role::Server);
+        assert!(client.role() == Role::Server);
+        client.send(Message::Text("server".into())).await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("masked".into()));
+
+        client.set_role(Role::Client);
+        client
+            .send(Message::Text("client again".into()))
+            .await
+            .unwrap();
+        client.flush().await.unwrap();
+    });
+    let frames: Vec<_> = received.iter().take(3).collect();
+    assert_eq!(
+        frames,
+        [
+            (true, b"client".to_vec()),
+            (false, b"server".to_vec()),
+            (true, b"client again".to_vec()),
+        ]
+    );
+}
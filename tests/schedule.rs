@@ -0,0 +1,174 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::schedule::ScheduledJob;
+use websockets_monoio::{
+    Backoff, DefaultReconnectPolicy, Message, WsClientBuilder, WsError, WsEvent, WsReconnectClient,
+};
+
+const fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+/// How late a run may start on a loaded machine.
+const TOLERANCE: Duration = ms(20);
+
+/// When each job ran, by name.
+type Runs = Rc<RefCell<Vec<(&'static str, Instant)>>>;
+
+/// A job that records its runs and sends its name.
+fn job(
+    runs: &Runs,
+    name: &'static str,
+) -> impl Fn() -> std::future::Ready<Result<Vec<Message>, WsError>> + 'static {
+    let runs = runs.clone();
+    move || {
+        runs.borrow_mut().push((name, Instant::now()));
+        std::future::ready(Ok(vec![Message::Text(name.into())]))
+    }
+}
+
+/// Drive the client's jobs for `period`; nothing arrives in the meantime.
+async fn drive(client: &mut WsReconnectClient, period: Duration) {
+    let result = monoio::time::timeout(period, client.recv()).await;
+    assert!(result.is_err(), "{result:?}");
+}
+
+#[test]
+fn jobs_run_on_their_schedules_in_order() {
+    let server = MockServer::start(Mode::Record);
+    let runs = Runs::default();
+    let started = Instant::now();
+    block_on(async {
+        let mut client = WsReconnectClient::connect(WsClientBuilder::new(), &server.url())
+            .await
+            .unwrap();
+        client.schedule(ScheduledJob::every("a", ms(40), job(&runs, "a")));
+        client.schedule(ScheduledJob::at("b", |now| now + ms(70), job(&runs, "b")));
+        drive(&mut client, ms(260)).await;
+    });
+    // a runs at 40, 80, ..., 240 ms and b at 70, 140 and 210 ms.
+    let expected = ["a", "b", "a", "a", "b", "a", "a", "b", "a"];
+    let runs = runs.borrow();
+    let names: Vec<_> = runs.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, expected);
+    for name in ["a", "b"] {
+        let interval = if name == "a" { ms(40) } else { ms(70) };
+        let times = runs.iter().filter(|(n, _)| *n == name).map(|(_, at)| *at);
+        for (k, at) in times.enumerate() {
+            let due = interval * (k as u32 + 1);
+            let offset = at - started;
+            assert!(
+                offset >= due && offset <= due + TOLERANCE,
+                "run {k} of {name} at {offset:?}, due at {due:?}"
+            );
+        }
+    }
+    // Each run's message went out, in the order the runs finished.
+    assert!(server.wait_for(Duration::from_secs(2), |frames| frames.len() == 9));
+    let sent: Vec<_> = server
+        .received()
+        .iter()
+        .map(|f| f.text().to_owned())
+        .collect();
+    assert_eq!(sent, expected);
+}
+
+#[test]
+fn jitter_delays_each_run_by_up_to_its_bound() {
+    let server = MockServer::start(Mode::Record);
+    let runs = Runs::default();
+    block_on(async {
+        let mut client = WsReconnectClient::connect(WsClientBuilder::new(), &server.url())
+            .await
+            .unwrap();
+        client.schedule(ScheduledJob::every("j", ms(20), job(&runs, "j")).jitter(ms(30)));
+        drive(&mut client, ms(800)).await;
+    });
+    let times: Vec<_> = runs.borrow().iter().map(|(_, at)| *at).collect();
+    assert!(times.len() >= 10, "only {} runs", times.len());
+    let gaps: Vec<_> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    for gap in &gaps {
+        // A run is never early: the interval plus a jitter of 0 to 30 ms.
+        assert!(
+            *gap >= ms(19) && *gap <= ms(50) + TOLERANCE,
+            "runs {gap:?} apart: {gaps:?}"
+        );
+    }
+    let spread = *gaps.iter().max().unwrap() - *gaps.iter().min().unwrap();
+    assert!(spread >= ms(5), "no jitter: {gaps:?}");
+}
+
+#[test]
+fn repeated_failures_reconnect_and_a_success_resets_the_count() {
+    let server = MockServer::start(Mode::Record);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    let policy = DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: ms(10),
+            multiplier: 2.0,
+            max_interval: Duration::from_secs(1),
+            max_attempts: None,
+        },
+        try_again_delay: ms(10),
+    };
+    let builder = WsClientBuilder::new()
+        .reconnect_policy(policy)
+        .on_event(move |event, _| {
+            if let WsEvent::ScheduledJobFailed {
+                name,
+                failures,
+                reconnect,
+                code,
+                ..
+            } = event
+            {
+                seen.borrow_mut()
+                    .push((name.clone(), *failures, *reconnect, *code));
+            }
+        });
+    // Fails, succeeds, then fails from the third run on.
+    let run = Rc::new(Cell::new(0));
+    let count = run.clone();
+    let reauth = ScheduledJob::every("reauth", ms(30), move || {
+        count.set(count.get() + 1);
+        let result = match count.get() {
+            2 => Ok(vec![Message::Text("auth".into())]),
+            _ => Err(WsError::Io(std::io::Error::other("token service down"))),
+        };
+        std::future::ready(result)
+    })
+    .reconnect_after(2);
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &server.url())
+            .await
+            .unwrap();
+        client.schedule(reauth);
+        while run.get() < 6 {
+            let _ = monoio::time::timeout(ms(50), client.recv()).await;
+        }
+    });
+    let failed = |failures, reconnect| ("reauth".to_owned(), failures, reconnect, "io.other");
+    assert_eq!(
+        events.borrow()[..4],
+        [
+            failed(1, false),
+            failed(1, false),
+            failed(2, true),
+            failed(1, false)
+        ]
+    );
+    // The second failure in a row dropped the first session.
+    assert!(server.accepted() >= 2, "{} connections", server.accepted());
+    let sent: Vec<_> = server
+        .received()
+        .iter()
+        .map(|f| f.text().to_owned())
+        .collect();
+    assert_eq!(sent, ["auth"]);
+}
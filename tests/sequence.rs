@@ -0,0 +1,150 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use common::{TEXT, block_on};
+use websockets_monoio::sequence::{RecoveryPolicy, SequenceGap, SequencedClient};
+use websockets_monoio::{Message, WsClientBuilder, WsEvent};
+
+/// A frame of the feed: a sequence number, or a pause long enough for a
+/// recovery to finish.
+#[derive(Clone, Copy)]
+enum Step {
+    Seq(u64),
+    Pause,
+}
+use Step::{Pause, Seq};
+
+/// How long a recovery takes; pauses are four times as long.
+const RECOVERY: Duration = Duration::from_millis(50);
+
+fn feed(steps: &'static [Step]) -> String {
+    common::scripted(move |_, mut socket| {
+        for step in steps {
+            match step {
+                Seq(n) => {
+                    let text = format!("seq {n}");
+                    common::write_frame(&mut socket, TEXT, text.as_bytes()).unwrap();
+                }
+                Pause => std::thread::sleep(RECOVERY * 4),
+            }
+        }
+        let _ = common::read_frame(&mut socket);
+    })
+}
+
+struct Run {
+    feed: SequencedClient,
+    /// The gaps `recover` was called with.
+    recoveries: Rc<RefCell<Vec<SequenceGap>>>,
+    events: Rc<RefCell<Vec<WsEvent>>>,
+}
+
+/// Connect to `steps` and track the sequence with `policy`; each recovery
+/// takes [`RECOVERY`] and resolves to `recovered`.
+async fn track(steps: &'static [Step], policy: RecoveryPolicy, recovered: Option<u64>) -> Run {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    let client = WsClientBuilder::new()
+        .on_event(move |event, _| seen.borrow_mut().push(event.clone()))
+        .connect(&feed(steps))
+        .await
+        .unwrap();
+    let recoveries = Rc::new(RefCell::new(Vec::new()));
+    let calls = recoveries.clone();
+    let feed = client.track_sequence(
+        policy,
+        |message| match message {
+            Message::Text(text) => text.strip_prefix("seq ")?.parse().ok(),
+            _ => None,
+        },
+        move |gap| {
+            calls.borrow_mut().push(gap);
+            async move {
+                monoio::time::sleep(RECOVERY).await;
+                Ok(recovered)
+            }
+        },
+    );
+    Run {
+        feed,
+        recoveries,
+        events,
+    }
+}
+
+impl Run {
+    /// Receive `n` messages, as their numbers and whether they arrived
+    /// during a recovery.
+    async fn take(&mut self, n: usize) -> Vec<(u64, bool)> {
+        let mut delivered = Vec::new();
+        for _ in 0..n {
+            let next = self.feed.recv().await.unwrap();
+            delivered.push((next.seq.unwrap(), next.recovering));
+        }
+        delivered
+    }
+
+    fn gap_events(&self) -> Vec<(u64, u64)> {
+        let events = self.events.borrow();
+        let gaps = events.iter().filter_map(|event| match event {
+            WsEvent::SequenceGap { expected, got } => Some((*expected, *got)),
+            _ => None,
+        });
+        gaps.collect()
+    }
+}
+
+#[test]
+fn a_gap_is_recovered_and_buffered_messages_replayed() {
+    // 3 and 4 are missing; the snapshot covers up to 6.
+    const STEPS: &[Step] = &[Seq(1), Seq(2), Seq(5), Seq(6), Seq(7), Pause, Seq(8)];
+    block_on(async {
+        let mut run = track(STEPS, RecoveryPolicy::Buffer, Some(6)).await;
+        let delivered = run.take(4).await;
+        assert_eq!(delivered, [(1, false), (2, false), (7, false), (8, false)]);
+        let gap = SequenceGap {
+            expected: 3,
+            got: 5,
+        };
+        assert_eq!(*run.recoveries.borrow(), [gap]);
+        assert_eq!(run.gap_events(), [(3, 5)]);
+        // 5 and 6 were held and then found to be covered by the snapshot.
+        assert_eq!((run.feed.gaps(), run.feed.dropped()), (1, 2));
+        assert_eq!(run.feed.last_sequence(), Some(8));
+        assert!(!run.feed.is_recovering());
+    });
+}
+
+#[test]
+fn a_duplicate_starts_a_recovery_that_drops_what_arrives_meanwhile() {
+    const STEPS: &[Step] = &[Seq(1), Seq(2), Seq(2), Seq(3), Pause, Seq(4)];
+    block_on(async {
+        // Recovering to `None` accepts whatever number comes next.
+        let mut run = track(STEPS, RecoveryPolicy::Drop, None).await;
+        let delivered = run.take(3).await;
+        assert_eq!(delivered, [(1, false), (2, false), (4, false)]);
+        assert_eq!(run.gap_events(), [(3, 2)]);
+        assert_eq!((run.feed.gaps(), run.feed.dropped()), (1, 2));
+        assert_eq!(run.feed.last_sequence(), Some(4));
+    });
+}
+
+#[test]
+fn a_reset_to_zero_passes_through_flagged_until_recovered() {
+    // The venue restarted its numbering.
+    const STEPS: &[Step] = &[Seq(5), Seq(6), Seq(0), Seq(1), Pause, Seq(2)];
+    block_on(async {
+        let mut run = track(STEPS, RecoveryPolicy::PassThrough, Some(1)).await;
+        let delivered = run.take(5).await;
+        assert_eq!(
+            delivered,
+            [(5, false), (6, false), (0, true), (1, true), (2, false)]
+        );
+        assert_eq!(run.gap_events(), [(7, 0)]);
+        assert_eq!((run.feed.gaps(), run.feed.dropped()), (1, 0));
+        assert_eq!(run.feed.last_sequence(), Some(2));
+    });
+}
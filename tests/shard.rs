@@ -0,0 +1,66 @@
+mod common;
+
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use common::{MockServer, Mode};
+use websockets_monoio::Message;
+use websockets_monoio::shard::{ConnectSpec, ShardReport, ShardedRunner};
+
+#[test]
+fn two_shards_receive_their_streams_and_stop_together() {
+    let server = MockServer::start(Mode::Echo);
+    // The echo server sends each subscribe message back, so every stream
+    // gets one message, on the shard its key picks.
+    let specs = (0..4u64).map(|i| {
+        ConnectSpec::new(format!("stream-{i}"), server.url())
+            .shard_key(i)
+            .subscribe(vec![Message::Text(format!("subscribe-{i}"))])
+    });
+    let (delivered, messages) = mpsc::channel();
+    let delivered = Mutex::new(delivered);
+    let supervisor = ShardedRunner::new(specs)
+        .shards(2)
+        .stats_interval(Duration::from_secs(3600))
+        .handler(move |shard| {
+            let delivered = delivered.lock().unwrap().clone();
+            move |spec: &ConnectSpec, message: Message| {
+                delivered.send((shard, spec.id.clone(), message)).unwrap();
+            }
+        })
+        .start()
+        .unwrap();
+
+    let mut received: Vec<_> = (0..4)
+        .map(|_| messages.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    received.sort_by(|a, b| a.1.cmp(&b.1));
+    let expected: Vec<_> = (0..4)
+        .map(|i| {
+            let text = format!("subscribe-{i}");
+            (i % 2, format!("stream-{i}"), Message::Text(text))
+        })
+        .collect();
+    assert_eq!(received, expected);
+    assert_eq!(server.accepted(), 4);
+
+    // Every shard is idle in `recv` and a long stats sleep; stopping wakes
+    // them instead of waiting for either.
+    let started = Instant::now();
+    supervisor.stop();
+    let mut finished = Vec::new();
+    while finished.len() < 2 {
+        let report = supervisor
+            .reports()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        if let ShardReport::Finished(stats) = report {
+            finished.push((stats.shard, stats.streams, stats.messages));
+        }
+    }
+    assert!(started.elapsed() < Duration::from_secs(1));
+    finished.sort();
+    assert_eq!(finished, [(0, 2, 2), (1, 2, 2)]);
+    supervisor.shutdown().unwrap();
+}
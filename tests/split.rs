@@ -0,0 +1,35 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::WsClientBuilder;
+use websockets_monoio::split::ConnState;
+
+#[test]
+fn closed_polled_in_a_select_loop_still_resolves() {
+    let server = MockServer::start(Mode::Record);
+    let state = block_on(async {
+        let client = WsClientBuilder::new().connect(&server.url()).await.unwrap();
+        let (mut reader, writer) = client.split();
+        let reading = monoio::spawn(async move { while reader.recv().await.is_ok() {} });
+
+        // A fresh `closed()` future per turn, each dropped when the sleep
+        // wins, as in the module example.
+        let mut turns = 0;
+        let state = loop {
+            monoio::select! {
+                state = writer.closed() => break state,
+                _ = monoio::time::sleep(Duration::from_millis(1)) => {}
+            }
+            turns += 1;
+            if turns == 200 {
+                server.kill_connections();
+            }
+        };
+        reading.await;
+        assert!(turns >= 200);
+        state
+    });
+    assert_eq!(state, ConnState::Failed);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use common::{BINARY, CLOSE, TEXT, block_on};
+use websockets_monoio::summary::SessionSummary;
+use websockets_monoio::{
+    Backoff, CloseFrame, DefaultReconnectPolicy, Message, WsClientBuilder, WsEvent,
+    WsReconnectClient,
+};
+
+const fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn each_session_of_a_reconnecting_client_is_summarized() {
+    // The first session trades a few messages and is restarted by the
+    // server; the second is dropped by the client.
+    let url = common::scripted(|conn, mut socket| {
+        if conn == 0 {
+            assert_eq!(
+                common::read_frame(&mut socket).unwrap(),
+                (TEXT, b"hello".to_vec())
+            );
+            common::write_frame(&mut socket, TEXT, b"welcome").unwrap();
+            common::write_frame(&mut socket, BINARY, &[1, 2, 3]).unwrap();
+            sleep(ms(100));
+            let mut close = 1012u16.to_be_bytes().to_vec();
+            close.extend_from_slice(b"restart");
+            common::write_frame(&mut socket, CLOSE, &close).unwrap();
+        } else {
+            common::write_frame(&mut socket, TEXT, b"back").unwrap();
+        }
+        while common::read_frame(&mut socket).is_ok() {}
+    });
+    let url = format!("{url}feed?token=secret");
+    let ended = Rc::new(RefCell::new(Vec::<SessionSummary>::new()));
+    let seen = ended.clone();
+    let policy = DefaultReconnectPolicy {
+        backoff: Backoff {
+            initial: ms(10),
+            multiplier: 2.0,
+            max_interval: Duration::from_secs(1),
+            max_attempts: None,
+        },
+        try_again_delay: ms(10),
+    };
+    let builder = WsClientBuilder::new()
+        .reconnect_policy(policy)
+        .on_event(move |event, _| {
+            if let WsEvent::SessionEnded { summary } = event {
+                seen.borrow_mut().push((**summary).clone());
+            }
+        });
+    block_on(async {
+        let mut client = WsReconnectClient::connect(builder, &url).await.unwrap();
+        client.send(Message::Text("hello".into())).await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Text("welcome".into())
+        );
+        assert_eq!(client.recv().await.unwrap(), Message::Binary(vec![1, 2, 3]));
+        let restart = CloseFrame {
+            code: 1012,
+            reason: "restart".into(),
+        };
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Close(Some(restart.clone()))
+        );
+        assert_eq!(client.recv().await.unwrap(), Message::Text("back".into()));
+        assert_eq!(client.session(), 1);
+        // The first summary was delivered when the server's Close was read.
+        assert_eq!(client.session_history(), *ended.borrow());
+        client.send(Message::Text("bye".into())).await.unwrap();
+    });
+
+    let ended = ended.borrow();
+    assert_eq!(ended.len(), 2, "{ended:?}");
+    let (first, second) = (&ended[0], &ended[1]);
+    for summary in [first, second] {
+        assert!(summary.endpoint.ends_with("/feed?<redacted>"), "{summary}");
+        assert!(!summary.endpoint.contains("secret"), "{summary}");
+        assert_eq!(summary.rtt, None);
+        assert_eq!(summary.error_code, None);
+    }
+
+    assert_eq!(first.generation, 0);
+    assert_eq!((first.frames_sent, first.bytes_sent), (1, 5));
+    // "welcome", the three bytes and the Close with its code and reason.
+    assert_eq!(
+        (first.frames_received, first.bytes_received),
+        (3, 7 + 3 + 9)
+    );
+    let restart = CloseFrame {
+        code: 1012,
+        reason: "restart".into(),
+    };
+    assert_eq!(first.close, Some(restart));
+    assert!(first.duration >= ms(100), "{first}");
+
+    assert_eq!(second.generation, 1);
+    assert!(second.started_at > first.started_at);
+    assert_eq!((second.frames_sent, second.bytes_sent), (1, 3));
+    assert_eq!((second.frames_received, second.bytes_received), (1, 4));
+    assert_eq!(second.close, None);
+
+    let line = first.to_string();
+    assert!(line.starts_with("endpoint=ws://127.0.0.1:"), "{line}");
+    for field in [
+        "generation=0 ",
+        "sent=1f/5B",
+        "received=3f/19B",
+        "close=1012 reason=\"restart\"",
+    ] {
+        assert!(line.contains(field), "{field} missing from {line}");
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::net::TcpListener;
+use std::time::Duration;
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::{ConnectPhase, Message, WsClientBuilder, WsError};
+
+#[test]
+fn connect_and_receive_time_out_with_the_same_variant() {
+    // Accepts connections and never answers the upgrade.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let wedged = format!("ws://{}/", listener.local_addr().unwrap());
+    let silent = MockServer::start(Mode::Silent);
+    let limit = Duration::from_millis(100);
+    block_on(async {
+        let result = WsClientBuilder::new()
+            .connect_timeout(limit)
+            .connect(&wedged)
+            .await;
+        let Err(WsError::Timeout(e)) = result else {
+            panic!("expected a timeout, got {:?}", result.map(|_| ()));
+        };
+        assert_eq!(e.phase(), Some(ConnectPhase::Upgrade));
+        assert!(e.limit() <= limit);
+        assert_eq!(WsError::Timeout(e).code(), "timeout.deadline.upgrade");
+
+        let mut client = WsClientBuilder::new().connect(&silent.url()).await.unwrap();
+        client.send(Message::Text("hello".into())).await.unwrap();
+        let result = client.consume_until_close_with_timeout(limit).await;
+        let Err(WsError::Timeout(e)) = result else {
+            panic!("expected a timeout, got {result:?}");
+        };
+        assert_eq!((e.phase(), e.limit()), (None, limit));
+        assert_eq!(e.to_string(), "timed out after 100ms");
+        assert_eq!(WsError::Timeout(e).code(), "timeout");
+    });
+    drop(listener);
+}
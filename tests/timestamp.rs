@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{BINARY, TEXT, block_on};
+use websockets_monoio::middleware::TimestampMiddleware;
+use websockets_monoio::{Message, WsClientBuilder};
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[test]
+fn receive_timestamps_strictly_increase() {
+    // A burst of frames in one write, so several are read within the same
+    // clock tick, then a pause and a message in two fragments.
+    let url = common::scripted(|_, mut socket| {
+        let mut burst = Vec::new();
+        for i in 0..50u8 {
+            common::write_frame(&mut burst, BINARY, &[i]).unwrap();
+        }
+        socket.write_all(&burst).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        socket.write_all(&[TEXT, 4]).unwrap();
+        socket.write_all(b"last").unwrap();
+        socket.write_all(&[0x80, 0]).unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    let timestamps = TimestampMiddleware::new();
+    let started = now_ns();
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .middleware(timestamps.clone())
+            .connect(&url)
+            .await
+            .unwrap();
+        let mut previous = 0;
+        for i in 0..50u8 {
+            assert_eq!(client.recv().await.unwrap(), Message::Binary(vec![i]));
+            let received_at = timestamps.message_received_at().unwrap();
+            assert!(received_at > previous, "{received_at} <= {previous}");
+            previous = received_at;
+        }
+        assert_eq!(client.recv().await.unwrap(), Message::Text("last".into()));
+        // Stamped when its first fragment was read; the last one came after.
+        let received_at = timestamps.message_received_at().unwrap();
+        assert!(received_at > previous);
+        assert!(timestamps.last_received_at().unwrap() > received_at);
+    });
+    let last = timestamps.last_received_at().unwrap();
+    assert!(started <= last && last <= now_ns());
+    assert!(timestamps.avg_latency_ns() < 1_000_000_000);
+}
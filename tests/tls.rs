@@ -0,0 +1,95 @@
+mod common;
+
+use common::proxy::MockProxy;
+use common::tls::{Identity, connector};
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::proxy::Proxy;
+use websockets_monoio::{Message, WsClient, WsClientBuilder};
+
+async fn echo(client: &mut WsClient) {
+    client.send(Message::Text("hello".into())).await.unwrap();
+    client.flush().await.unwrap();
+    assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+}
+
+#[test]
+fn a_tls_terminator_is_dialed_by_address_with_the_backend_name() {
+    // The terminator holds the origin's certificate; the backend's name does
+    // not resolve, so only the given address can be dialed.
+    let identity = Identity::new(&["origin.test"]);
+    let terminator = MockServer::start_tls(Mode::Echo, &identity);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .tls_connector(connector(&[&identity]))
+            .header("X-Route", "backend")
+            .connect_via_tls_terminator("ws://origin.test/feed?depth=10", terminator.addr)
+            .await
+            .unwrap();
+        client.send(Message::Text("hello".into())).await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+        assert_eq!(client.info().peer_addr(), Some(terminator.addr));
+        assert!(client.info().tls().is_some());
+    });
+    assert_eq!(terminator.sni(), [Some("origin.test".to_owned())]);
+    let request = &terminator.requests()[0];
+    assert!(
+        request.starts_with("GET /feed?depth=10 HTTP/1.1\r\n"),
+        "{request}"
+    );
+    assert_eq!(common::header(request, "host"), Some("origin.test"));
+    assert_eq!(common::header(request, "x-route"), Some("backend"));
+}
+
+#[test]
+fn wss_runs_inside_a_plain_proxy_tunnel() {
+    let identity = Identity::new(&["127.0.0.1"]);
+    let origin = MockServer::start_tls(Mode::Echo, &identity);
+    let proxy = MockProxy::start();
+    let url = format!("wss://{}/", origin.addr);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .tls_connector(connector(&[&identity]))
+            .proxy(Proxy::parse(&proxy.url).unwrap())
+            .connect(&url)
+            .await
+            .unwrap();
+        echo(&mut client).await;
+        let info = client.info();
+        assert!(info.tls().is_some());
+        // The tunnel was asked for an address, which is the peer.
+        assert_eq!(info.peer_addr(), Some(origin.addr));
+        assert_eq!(info.proxy_addr(), Some(proxy.addr));
+    });
+    assert_eq!(proxy.requests(), [origin.addr.to_string()]);
+    // No SNI for an IP address; the handshake reached the origin.
+    assert_eq!(origin.sni(), [None]);
+}
+
+#[test]
+fn wss_runs_inside_tls_to_an_https_proxy() {
+    let proxy_identity = Identity::new(&["127.0.0.1"]);
+    let origin_identity = Identity::new(&["localhost"]);
+    let origin = MockServer::start_tls(Mode::Echo, &origin_identity);
+    let proxy = MockProxy::start_tls(&proxy_identity);
+    block_on(async {
+        let mut client = WsClientBuilder::new()
+            .tls_connector(connector(&[&proxy_identity, &origin_identity]))
+            .proxy(Proxy::parse(&proxy.url).unwrap())
+            .connect(&origin.url())
+            .await
+            .unwrap();
+        echo(&mut client).await;
+        let info = client.info();
+        assert!(info.tls().is_some());
+        assert!(info.timings().tls.is_some());
+        // The proxy resolved the name, so the origin's address is unknown.
+        assert_eq!(info.peer_addr(), None);
+        assert_eq!(info.proxy_addr(), Some(proxy.addr));
+    });
+    assert_eq!(
+        proxy.requests(),
+        [format!("localhost:{}", origin.addr.port())]
+    );
+    assert_eq!(origin.sni(), [Some("localhost".to_owned())]);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::error::Error;
+use std::time::Duration;
+
+use common::{BINARY, CLOSE, MockServer, Mode, TEXT, block_on};
+use fastwebsockets::OpCode;
+use websockets_monoio::middleware::Direction;
+use websockets_monoio::transform::{OnTransformError, TransformError};
+use websockets_monoio::{Message, WsClientBuilder, WsError};
+
+/// A stand-in for a real cipher.
+fn xor(mut payload: Vec<u8>) -> Vec<u8> {
+    payload.iter_mut().for_each(|b| *b ^= 0x5A);
+    payload
+}
+
+/// Encrypts outbound messages into Binary frames and decrypts Binary
+/// frames into Text, rejecting anything that arrives unencrypted.
+fn encrypted(on_error: OnTransformError) -> WsClientBuilder {
+    WsClientBuilder::new()
+        .transform_outbound(|_, payload| Ok((OpCode::Binary, xor(payload))))
+        .transform_inbound(
+            |opcode, payload| match opcode {
+                OpCode::Binary => Ok((OpCode::Text, xor(payload))),
+                _ => Err(TransformError::new("expected an encrypted binary frame")),
+            },
+            on_error,
+        )
+}
+
+/// A server that sends a plaintext Text message, then an encrypted one, and
+/// reports the frames it reads back.
+fn plaintext_then_encrypted() -> (String, std::sync::mpsc::Receiver<(u8, Vec<u8>)>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let url = common::scripted(move |_, mut socket| {
+        common::write_frame(&mut socket, TEXT, b"plain").unwrap();
+        common::write_frame(&mut socket, BINARY, &xor(b"secret".to_vec())).unwrap();
+        while let Ok(frame) = common::read_frame(&mut socket) {
+            let _ = tx.send(frame);
+        }
+    });
+    (url, rx)
+}
+
+#[test]
+fn the_wire_carries_the_transformed_payload() {
+    let server = MockServer::start(Mode::Echo);
+    block_on(async {
+        let mut client = encrypted(OnTransformError::Fail)
+            .connect(&server.url())
+            .await
+            .unwrap();
+        client.send(Message::Text("hello".into())).await.unwrap();
+        // The echo comes back encrypted and is decrypted on the way in.
+        assert_eq!(client.recv().await.unwrap(), Message::Text("hello".into()));
+        assert_eq!(client.transform_drops(), 0);
+    });
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].opcode, BINARY);
+    assert_eq!(received[0].payload, xor(b"hello".to_vec()));
+}
+
+#[test]
+fn a_message_the_transform_rejects_is_dropped_and_counted() {
+    let (url, frames) = plaintext_then_encrypted();
+    block_on(async {
+        let mut client = encrypted(OnTransformError::Drop)
+            .connect(&url)
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Text("secret".into()));
+        assert_eq!(client.transform_drops(), 1);
+        assert!(client.is_usable());
+        client.send(Message::Text("ack".into())).await.unwrap();
+    });
+    let frame = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(frame, (BINARY, xor(b"ack".to_vec())));
+}
+
+#[test]
+fn a_failed_transform_closes_with_1007() {
+    let (url, frames) = plaintext_then_encrypted();
+    block_on(async {
+        let mut client = encrypted(OnTransformError::Fail)
+            .connect(&url)
+            .await
+            .unwrap();
+        let e = client.recv().await.err().unwrap();
+        let WsError::Transform { direction, error } = &e else {
+            panic!("expected a transform error, got {e:?}");
+        };
+        assert_eq!(*direction, Direction::Inbound);
+        assert_eq!(error.0, "expected an encrypted binary frame");
+        let source = e.source().and_then(|s| s.downcast_ref::<TransformError>());
+        assert_eq!(source, Some(error));
+        assert!(!client.is_usable());
+        assert_eq!(client.transform_drops(), 0);
+    });
+    let (opcode, payload) = frames.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(opcode, CLOSE);
+    assert_eq!(payload[..2], 1007u16.to_be_bytes());
+}
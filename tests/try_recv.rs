@@ -0,0 +1,72 @@
+mod common;
+
+use std::future::poll_fn;
+use std::io::Write;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use common::{TEXT, block_on};
+use websockets_monoio::{Message, WsClientBuilder};
+
+/// Let the runtime drive I/O once before polling again.
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+#[test]
+fn try_recv_interleaves_with_delayed_sends() {
+    // One message, a pause, a burst of two, a pause, then a message whose
+    // two fragments are split by a pause.
+    let url = common::scripted(|_, mut socket| {
+        socket.set_nodelay(true).unwrap();
+        let pause = || std::thread::sleep(Duration::from_millis(100));
+        common::write_frame(&mut socket, TEXT, b"first").unwrap();
+        pause();
+        common::write_frame(&mut socket, TEXT, b"second").unwrap();
+        common::write_frame(&mut socket, TEXT, b"third").unwrap();
+        pause();
+        socket.write_all(&[TEXT, 4]).unwrap();
+        socket.write_all(b"four").unwrap();
+        pause();
+        socket.write_all(&[0x80, 3]).unwrap();
+        socket.write_all(b"th!").unwrap();
+        let _ = common::read_frame(&mut socket);
+    });
+    block_on(async {
+        let mut client = WsClientBuilder::new().connect(&url).await.unwrap();
+        let spin = monoio::spawn(async move {
+            // Each message with the number of empty polls before it.
+            let mut received = Vec::new();
+            let mut empty = 0;
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while received.len() < 4 && Instant::now() < deadline {
+                match client.try_recv().unwrap() {
+                    Some(message) => received.push((std::mem::take(&mut empty), message)),
+                    None => empty += 1,
+                }
+                yield_now().await;
+            }
+            client.send(Message::Close(None)).await.unwrap();
+            received
+        });
+        let received = spin.await;
+        let texts: Vec<_> = received.iter().map(|(_, m)| m.clone()).collect();
+        assert_eq!(
+            texts,
+            ["first", "second", "third", "fourth!"].map(|t| Message::Text(t.into()))
+        );
+        // Each pause is spent polling empty, including the one between the
+        // fragments of the last message.
+        assert!(received[1].0 > 0, "{received:?}");
+        assert!(received[3].0 > 0, "{received:?}");
+    });
+}
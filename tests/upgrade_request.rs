@@ -0,0 +1,137 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use common::{MockServer, Mode, block_on};
+use websockets_monoio::http_upgrade::UpgradeErr;
+use websockets_monoio::{WsClient, WsClientBuilder, WsError};
+
+#[test]
+fn a_custom_upgrade_request_is_sent_verbatim() {
+    let server = MockServer::start(Mode::Record);
+    let url = format!("ws://127.0.0.1:{}/feed/v2?depth=10", server.addr.port());
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let capture = sent.clone();
+    block_on(async {
+        WsClientBuilder::new()
+            .header("X-Ignored", "1")
+            .clock(move || now)
+            .with_custom_http_request(Box::new(move |url, key, now| {
+                let stamp = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+                let request = format!(
+                    "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+                     Sec-WebSocket-Key: {key}\r\nX-Path-Route: {}\r\n\
+                     X-Signed-At: {}\r\n\r\n",
+                    url.path_and_query,
+                    url.host,
+                    url.port,
+                    url.path_and_query.split('/').nth(1).unwrap(),
+                    stamp.as_secs(),
+                );
+                *capture.lock().unwrap() = request.clone().into_bytes();
+                request.into_bytes()
+            }))
+            .connect(&url)
+            .await
+            .unwrap();
+    });
+    let sent = String::from_utf8(sent.lock().unwrap().clone()).unwrap();
+    let key = common::header(&sent, "sec-websocket-key").unwrap();
+    assert_eq!(
+        sent,
+        format!(
+            "GET /feed/v2?depth=10 HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\
+             Upgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: {key}\r\n\
+             X-Path-Route: feed\r\nX-Signed-At: 1700000000\r\n\r\n",
+            server.addr.port()
+        )
+    );
+    assert_eq!(server.requests(), [sent]);
+}
+
+#[test]
+fn the_response_to_a_custom_request_is_validated_against_the_generated_key() {
+    let server = MockServer::start(Mode::Record);
+    block_on(async {
+        let result = WsClientBuilder::new()
+            .with_custom_http_request(Box::new(|url, _key, _now| {
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+                     Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+                    url.path_and_query, url.host,
+                )
+                .into_bytes()
+            }))
+            .connect(&server.url())
+            .await;
+        assert!(
+            matches!(result, Err(WsError::Upgrade(UpgradeErr::Accept(_)))),
+            "{:?}",
+            result.map(|_| ())
+        );
+    });
+}
+
+/// The upgrade request `server` received, with its random key replaced by
+/// `KEY`.
+fn request_with_fixed_key(server: &MockServer, conn: usize) -> String {
+    let request = server.requests()[conn].clone();
+    let key = common::header(&request, "sec-websocket-key").unwrap();
+    request.replace(key, "KEY")
+}
+
+#[test]
+fn repeated_headers_are_sent_once_per_occurrence_in_order() {
+    let server = MockServer::start(Mode::Record);
+    let signature = format!("sig-{}", 42);
+    block_on(async {
+        WsClientBuilder::new()
+            .extra_headers(&[("Cookie", "a=1"), ("X-Trace", "7")])
+            .header("Cookie", "b=2")
+            .extra_headers(vec![("X-Signature".to_owned(), signature)])
+            .extra_headers([("Cookie", String::from("c=3"))])
+            .connect(&server.url())
+            .await
+            .unwrap();
+        WsClient::connect(&server.url(), &[("Cookie", "a=1"), ("Cookie", "b=2")])
+            .await
+            .unwrap();
+    });
+    let host = format!("127.0.0.1:{}", server.addr.port());
+    assert_eq!(
+        request_with_fixed_key(&server, 0),
+        format!(
+            "GET / HTTP/1.1\r\n\
+             Host: {host}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: KEY\r\n\
+             Cookie: a=1\r\n\
+             X-Trace: 7\r\n\
+             Cookie: b=2\r\n\
+             X-Signature: sig-42\r\n\
+             Cookie: c=3\r\n\
+             \r\n"
+        )
+    );
+    assert_eq!(
+        request_with_fixed_key(&server, 1),
+        format!(
+            "GET / HTTP/1.1\r\n\
+             Host: {host}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: KEY\r\n\
+             Cookie: a=1\r\n\
+             Cookie: b=2\r\n\
+             \r\n"
+        )
+    );
+}
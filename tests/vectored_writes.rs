@@ -0,0 +1,136 @@
+mod common;
+
+use std::cell::Cell;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use monoio_compat::{AsyncRead, AsyncWrite};
+use tokio::io::DuplexStream;
+use websockets_monoio::transport::Transport;
+use websockets_monoio::{Message, WsClientBuilder};
+
+/// One end of an in-memory pipe that gathers vectored writes into a single
+/// write and counts them.
+struct GatheringPipe {
+    io: DuplexStream,
+    vectored: Rc<Cell<usize>>,
+}
+
+impl Transport for GatheringPipe {}
+
+impl AsyncRead for GatheringPipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GatheringPipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let gathered: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        let poll = Pin::new(&mut self.io).poll_write(cx, &gathered);
+        if poll.is_ready() {
+            self.vectored.set(self.vectored.get() + 1);
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+/// Send a message over the pipe and return how many vectored writes the
+/// upgrade request and the send took.
+fn vectored_writes(builder: WsClientBuilder, payload: Vec<u8>) -> (usize, usize) {
+    common::block_on(async {
+        let (client_end, server_end) = tokio::io::duplex(256 * 1024);
+        monoio::spawn(common::echo_over_pipe(server_end));
+        let vectored = Rc::new(Cell::new(0));
+        let pipe = GatheringPipe {
+            io: client_end,
+            vectored: vectored.clone(),
+        };
+        let mut client = builder
+            .connect_over("ws://pipe.internal/", pipe)
+            .await
+            .unwrap();
+        let upgrade = vectored.get();
+        client.send(Message::Binary(payload.clone())).await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Message::Binary(payload));
+        (upgrade, vectored.get() - upgrade)
+    })
+}
+
+#[test]
+fn gathering_transports_get_vectored_upgrade_and_frames() {
+    let (upgrade, frames) = vectored_writes(WsClientBuilder::new(), vec![7; 64 * 1024]);
+    assert!(upgrade > 0, "the upgrade request was not gathered");
+    assert!(frames > 0, "the frame was not gathered");
+}
+
+#[test]
+fn small_frames_and_disabled_writev_are_copied() {
+    let (_, frames) = vectored_writes(WsClientBuilder::new(), vec![7; 512]);
+    assert_eq!(frames, 0);
+    let builder = WsClientBuilder::new().vectored_writes(false);
+    let (upgrade, frames) = vectored_writes(builder, vec![7; 64 * 1024]);
+    assert!(upgrade > 0);
+    assert_eq!(frames, 0);
+}
+
+#[cfg(feature = "raw-frames")]
+#[test]
+fn write_frame_vectored_gathers_on_a_gathering_transport() {
+    common::block_on(async {
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        monoio::spawn(common::echo_over_pipe(server_end));
+        let vectored = Rc::new(Cell::new(0));
+        let pipe = GatheringPipe {
+            io: client_end,
+            vectored: vectored.clone(),
+        };
+        let mut client = WsClientBuilder::new()
+            .connect_over("ws://pipe.internal/", pipe)
+            .await
+            .unwrap();
+        let before = vectored.get();
+        // A masked Text frame with an all-zero key, so the payload is sent
+        // as is.
+        let payload = b"raw frame";
+        let header = [0x81, 0x80 | payload.len() as u8, 0, 0, 0, 0];
+        client.write_frame_vectored(&header, payload).await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(vectored.get() - before, 1);
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Message::Text("raw frame".into())
+        );
+    });
+}
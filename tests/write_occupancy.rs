@@ -0,0 +1,60 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockServer, Mode, block_on};
+use monoio::time::timeout;
+use websockets_monoio::{Message, WsClientBuilder};
+
+const FRAME: usize = 64 * 1024;
+
+#[test]
+fn occupancy_climbs_against_a_server_that_never_reads() {
+    // Upgrades and then never reads, so the socket buffers fill up.
+    let server = MockServer::start(Mode::Silent);
+    block_on(async {
+        let mut client = WsClientBuilder::new().connect(&server.url()).await.unwrap();
+        assert!(!client.needs_flush());
+        let mut samples = Vec::new();
+        // Send until the socket buffers are full and a send can no longer
+        // complete; loopback buffers hold a few MiB at most.
+        for _ in 0..1024 {
+            let send = client.send(Message::Binary(vec![7; FRAME]));
+            if timeout(Duration::from_millis(200), send).await.is_err() {
+                break;
+            }
+            samples.push(client.pending_write_bytes());
+        }
+        assert!(samples.len() < 1024, "every send completed");
+        assert!(samples.len() > 1);
+        for pair in samples.windows(2) {
+            assert!(pair[1] >= pair[0] + FRAME, "{samples:?}");
+        }
+        // A sender checking the signal would conflate now: the bytes of
+        // every completed send are still unconfirmed.
+        assert!(client.needs_flush());
+        assert!(client.pending_write_bytes() >= samples.len() * FRAME);
+        let flush = timeout(Duration::from_millis(100), client.flush()).await;
+        assert!(flush.is_err());
+        assert!(client.is_flushing());
+        let high_water = client.stats_snapshot().pending_write_high_water;
+        assert!(high_water >= client.pending_write_bytes());
+        assert!(high_water >= *samples.last().unwrap());
+    });
+}
+
+#[test]
+fn a_flush_to_a_reading_server_clears_the_signal() {
+    let server = MockServer::start(Mode::Record);
+    block_on(async {
+        let mut client = WsClientBuilder::new().connect(&server.url()).await.unwrap();
+        client.send(Message::Binary(vec![7; FRAME])).await.unwrap();
+        assert!(client.needs_flush());
+        assert!(client.pending_write_bytes() >= FRAME);
+        client.flush().await.unwrap();
+        assert!(!client.needs_flush());
+        assert!(!client.is_flushing());
+        assert_eq!(client.pending_write_bytes(), 0);
+        assert!(client.stats_snapshot().pending_write_high_water >= FRAME);
+    });
+}